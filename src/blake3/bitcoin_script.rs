@@ -0,0 +1,426 @@
+use crate::blake3::{CHUNK_END, CHUNK_START, G_SCHEDULE, IV, MSG_PERMUTATION, N_ROUNDS, ROOT};
+use crate::channel::Sha256ChannelGadget;
+use crate::treepp::*;
+
+/// Gadget for the Blake3 hash.
+///
+/// A 32-bit word is kept on the stack as its eight 4-bit nibbles, the most significant one
+/// deepest, so that additions are carried nibble by nibble within the range of Bitcoin integers,
+/// and XORs are computed bit by bit on the nibbles.
+pub struct Blake3Gadget;
+
+impl Blake3Gadget {
+    /// Compute the Blake3 hash of a || b, as in `blake3_two`, which can replace `mix_digest` for a
+    /// transcript over Blake3.
+    ///
+    /// hint:
+    ///  the nibbles of a || b, as pushed by `push_hash_two_hint`
+    ///
+    /// input:
+    ///  a (32 bytes)
+    ///  b (32 bytes)
+    ///
+    /// output:
+    ///  blake3(a || b) (32 bytes)
+    pub fn hash_two() -> Script {
+        // the words on the stack from the bottom, m_i is i and v_i is 16 + i
+        let mut words = (0..32).collect::<Vec<usize>>();
+
+        let mut schedule: [usize; 16] = std::array::from_fn(|i| i);
+        let mut rounds = vec![];
+        for _ in 0..N_ROUNDS {
+            let mut round = vec![];
+            for (i, &(a, b, c, d)) in G_SCHEDULE.iter().enumerate() {
+                round.push(g(
+                    &mut words,
+                    (16 + a, 16 + b, 16 + c, 16 + d),
+                    schedule[2 * i],
+                    schedule[2 * i + 1],
+                ));
+            }
+            rounds.push(round);
+            schedule = MSG_PERMUTATION.map(|i| schedule[i]);
+        }
+
+        let mut finalization = vec![];
+        for i in 0..8 {
+            finalization.push(roll_word(&mut words, 16 + i));
+            finalization.push(roll_word(&mut words, 24 + i));
+            words.truncate(words.len() - 2);
+        }
+
+        script! {
+            OP_CAT OP_TOALTSTACK
+
+            // pull the message words and check them against a || b
+            for _ in 0..16 {
+                for _ in 0..8 {
+                    OP_DEPTH OP_1SUB OP_ROLL
+                    OP_DUP 0 16 OP_WITHIN OP_VERIFY
+                }
+                word_to_bytes
+                OP_TOALTSTACK
+            }
+            OP_FROMALTSTACK
+            for _ in 1..16 {
+                OP_FROMALTSTACK OP_SWAP OP_CAT
+            }
+            OP_FROMALTSTACK
+            OP_EQUALVERIFY
+
+            // the state, for a block of 64 bytes that is the only block of the only chunk
+            for v in IV.iter() {
+                { push_word(*v) }
+            }
+            for v in IV[..4].iter() {
+                { push_word(*v) }
+            }
+            { push_word(0) }
+            { push_word(0) }
+            { push_word(64) }
+            { push_word(CHUNK_START | CHUNK_END | ROOT) }
+
+            for round in rounds.iter() {
+                for g in round.iter() {
+                    { g.clone() }
+                }
+            }
+
+            // the output is v_i ^ v_{i + 8} for i = 0, ..., 7, each in little-endian
+            for roll in finalization.chunks(2) {
+                { roll[0].clone() }
+                { roll[1].clone() }
+                xor_words
+                word_to_bytes
+                OP_TOALTSTACK
+                for _ in 0..4 {
+                    OP_2DROP
+                }
+            }
+
+            // drop the message words
+            for _ in 0..64 {
+                OP_2DROP
+            }
+
+            OP_FROMALTSTACK
+            for _ in 1..8 {
+                OP_FROMALTSTACK OP_SWAP OP_CAT
+            }
+        }
+    }
+
+    /// Push the hint for `hash_two`, which is the nibbles of a || b, with the four bytes of each
+    /// message word in little-endian, from the most significant nibble.
+    pub fn push_hash_two_hint(a: &[u8; 32], b: &[u8; 32]) -> Script {
+        let bytes = a.iter().chain(b.iter()).copied().collect::<Vec<u8>>();
+        script! {
+            for word in bytes.chunks_exact(4) {
+                for byte in word.iter().rev() {
+                    { (*byte >> 4) as u32 }
+                    { (*byte & 15) as u32 }
+                }
+            }
+        }
+    }
+}
+
+/// The G function, as in `blake3_compress`, which tracks the words on the stack.
+fn g(
+    words: &mut Vec<usize>,
+    (a, b, c, d): (usize, usize, usize, usize),
+    mx: usize,
+    my: usize,
+) -> Script {
+    let steps = vec![
+        roll_word(words, a),
+        copy_word(words, b),
+        add_words(words, a),
+        copy_word(words, mx),
+        add_words(words, a),
+        // d = (d ^ a) >>> 16
+        roll_word(words, d),
+        copy_word(words, a),
+        xor_words_into(words, d),
+        rotr_nibbles(4),
+        roll_word(words, c),
+        copy_word(words, d),
+        add_words(words, c),
+        // b = (b ^ c) >>> 12
+        roll_word(words, b),
+        copy_word(words, c),
+        xor_words_into(words, b),
+        rotr_nibbles(3),
+        roll_word(words, a),
+        copy_word(words, b),
+        add_words(words, a),
+        copy_word(words, my),
+        add_words(words, a),
+        // d = (d ^ a) >>> 8
+        roll_word(words, d),
+        copy_word(words, a),
+        xor_words_into(words, d),
+        rotr_nibbles(2),
+        roll_word(words, c),
+        copy_word(words, d),
+        add_words(words, c),
+        // b = (b ^ c) >>> 7, which is >>> 8 and then <<< 1
+        roll_word(words, b),
+        copy_word(words, c),
+        xor_words_into(words, b),
+        rotr_nibbles(2),
+        rotl_one_bit(),
+    ];
+
+    script! {
+        for step in steps.iter() {
+            { step.clone() }
+        }
+    }
+}
+
+/// The depth, in words, of the word `id` on the stack.
+fn word_depth(words: &[usize], id: usize) -> usize {
+    words.len() - 1 - words.iter().position(|&w| w == id).unwrap()
+}
+
+/// Move the word `id` to the top of the stack.
+fn roll_word(words: &mut Vec<usize>, id: usize) -> Script {
+    let depth = word_depth(words, id);
+    words.retain(|&w| w != id);
+    words.push(id);
+    script! {
+        if depth > 0 {
+            for _ in 0..8 {
+                { 8 * depth + 7 } OP_ROLL
+            }
+        }
+    }
+}
+
+/// Copy the word `id` to the top of the stack.
+fn copy_word(words: &mut Vec<usize>, id: usize) -> Script {
+    let depth = word_depth(words, id);
+    words.push(usize::MAX);
+    script! {
+        for _ in 0..8 {
+            { 8 * depth + 7 } OP_PICK
+        }
+    }
+}
+
+/// Add the top two words, and name the result `id`.
+fn add_words(words: &mut Vec<usize>, id: usize) -> Script {
+    words.pop();
+    words.pop();
+    words.push(id);
+    script! {
+        for i in 0..8 {
+            if i > 0 {
+                OP_ADD
+            }
+            { 8 - i } OP_ROLL OP_ADD
+            OP_DUP 16 OP_GREATERTHANOREQUAL OP_TUCK
+            OP_IF 16 OP_SUB OP_ENDIF
+            OP_TOALTSTACK
+        }
+        OP_DROP
+        for _ in 0..8 {
+            OP_FROMALTSTACK
+        }
+    }
+}
+
+/// XOR the top two words, and name the result `id`.
+fn xor_words_into(words: &mut Vec<usize>, id: usize) -> Script {
+    words.pop();
+    words.pop();
+    words.push(id);
+    xor_words()
+}
+
+/// XOR the top two words.
+///
+/// input:
+///  a (word)
+///  b (word)
+///
+/// output:
+///  a ^ b (word)
+fn xor_words() -> Script {
+    script! {
+        for i in 0..8 {
+            { 8 - i } OP_ROLL
+            xor_nibbles
+            OP_TOALTSTACK
+        }
+        for _ in 0..8 {
+            OP_FROMALTSTACK
+        }
+    }
+}
+
+/// XOR the top two nibbles.
+fn xor_nibbles() -> Script {
+    script! {
+        OP_TOALTSTACK
+        nibble_to_bits
+        OP_FROMALTSTACK
+        nibble_to_bits
+
+        // x3 x2 x1 x0 y3 y2 y1 y0
+        4 OP_ROLL OP_NUMNOTEQUAL OP_TOALTSTACK
+        3 OP_ROLL OP_NUMNOTEQUAL
+        OP_DUP OP_ADD OP_FROMALTSTACK OP_ADD OP_TOALTSTACK
+        2 OP_ROLL OP_NUMNOTEQUAL
+        OP_DUP OP_ADD OP_DUP OP_ADD OP_FROMALTSTACK OP_ADD OP_TOALTSTACK
+        OP_NUMNOTEQUAL
+        OP_DUP OP_ADD OP_DUP OP_ADD OP_DUP OP_ADD OP_FROMALTSTACK OP_ADD
+    }
+}
+
+/// Split a nibble into its bits, the most significant one deepest.
+fn nibble_to_bits() -> Script {
+    script! {
+        for w in [8, 4, 2] {
+            OP_DUP { w } OP_GREATERTHANOREQUAL OP_TUCK
+            OP_IF { w } OP_SUB OP_ENDIF
+        }
+    }
+}
+
+/// Rotate the top word right by k nibbles.
+fn rotr_nibbles(k: usize) -> Script {
+    script! {
+        for _ in 0..(8 - k) {
+            7 OP_ROLL
+        }
+    }
+}
+
+/// Rotate the top word left by one bit.
+fn rotl_one_bit() -> Script {
+    script! {
+        // split each nibble into its top bit and twice the other bits
+        for i in 0..8 {
+            { 7 + i } OP_ROLL
+            OP_DUP 8 OP_GREATERTHANOREQUAL OP_TUCK
+            OP_IF 8 OP_SUB OP_ENDIF
+            OP_DUP OP_ADD
+        }
+
+        // the top bit of each nibble goes to the next more significant nibble
+        15 OP_ROLL OP_ADD OP_TOALTSTACK
+        for _ in 1..8 {
+            OP_ADD OP_TOALTSTACK
+        }
+        for _ in 0..8 {
+            OP_FROMALTSTACK
+        }
+    }
+}
+
+/// Push a word as a script constant.
+fn push_word(v: u32) -> Script {
+    script! {
+        for k in (0..8).rev() {
+            { (v >> (4 * k)) & 15 }
+        }
+    }
+}
+
+/// Copy the bytes of the top word in little-endian into a 4-byte string.
+///
+/// input:
+///  v (word)
+///
+/// output:
+///  v (word)
+///  v as 4 bytes, little-endian
+fn word_to_bytes() -> Script {
+    script! {
+        for k in 0..4 {
+            { 2 * k + 1 + usize::from(k > 0) } OP_PICK
+            for _ in 0..4 {
+                OP_DUP OP_ADD
+            }
+            { 2 * k + 1 + usize::from(k > 0) } OP_PICK
+            OP_ADD
+            { Sha256ChannelGadget::byte_to_bytes() }
+            if k > 0 {
+                OP_CAT
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::blake3::{blake3_two, Blake3Gadget};
+    use crate::tests_utils::report::report_bitcoin_script_size;
+    use crate::treepp::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_blake3_two() {
+        // the official test vector for the 64-byte input 0, 1, ..., 63
+        let input: [u8; 64] = std::array::from_fn(|i| i as u8);
+        let a: [u8; 32] = input[..32].try_into().unwrap();
+        let b: [u8; 32] = input[32..].try_into().unwrap();
+
+        let expected: [u8; 32] = [
+            0x4e, 0xed, 0x71, 0x41, 0xea, 0x4a, 0x5c, 0xd4, 0xb7, 0x88, 0x60, 0x6b, 0xd2, 0x3f,
+            0x46, 0xe2, 0x12, 0xaf, 0x9c, 0xac, 0xeb, 0xac, 0xdc, 0x7d, 0x1f, 0x4c, 0x6d, 0xc7,
+            0xf2, 0x51, 0x1b, 0x98,
+        ];
+        assert_eq!(blake3_two(&a, &b), expected);
+    }
+
+    #[test]
+    fn test_hash_two() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let hash_script = Blake3Gadget::hash_two();
+        report_bitcoin_script_size("Blake3", "hash_two", hash_script.len());
+
+        for _ in 0..3 {
+            let mut a = [0u8; 32];
+            a.iter_mut().for_each(|v| *v = prng.gen());
+            let mut b = [0u8; 32];
+            b.iter_mut().for_each(|v| *v = prng.gen());
+
+            let expected = blake3_two(&a, &b);
+
+            let script = script! {
+                { Blake3Gadget::push_hash_two_hint(&a, &b) }
+                { a.to_vec() }
+                { b.to_vec() }
+                { hash_script.clone() }
+                { expected.to_vec() }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        // a hint that is not the nibbles of a || b is rejected
+        let mut a = [0u8; 32];
+        a.iter_mut().for_each(|v| *v = prng.gen());
+        let mut b = [0u8; 32];
+        b.iter_mut().for_each(|v| *v = prng.gen());
+        let mut other_b = b;
+        other_b[0] ^= 1;
+
+        let script = script! {
+            { Blake3Gadget::push_hash_two_hint(&a, &other_b) }
+            { a.to_vec() }
+            { b.to_vec() }
+            { hash_script.clone() }
+            OP_DROP
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+}
@@ -0,0 +1,86 @@
+mod bitcoin_script;
+pub use bitcoin_script::*;
+
+/// The initialization vector of Blake3, which is that of SHA-256.
+pub const IV: [u32; 8] = [
+    0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
+];
+
+/// The permutation of the message words between two rounds.
+pub const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+/// The number of rounds of the compression function.
+pub const N_ROUNDS: usize = 7;
+
+/// The flags of a block that is the first block of a chunk.
+pub const CHUNK_START: u32 = 1;
+
+/// The flags of a block that is the last block of a chunk.
+pub const CHUNK_END: u32 = 2;
+
+/// The flags of a block whose compression gives the root of the hash.
+pub const ROOT: u32 = 8;
+
+/// The state words that each G function of a round updates, first the four columns and then the
+/// four diagonals, where the i-th one mixes the message words 2i and 2i + 1.
+pub(crate) const G_SCHEDULE: [(usize, usize, usize, usize); 8] = [
+    (0, 4, 8, 12),
+    (1, 5, 9, 13),
+    (2, 6, 10, 14),
+    (3, 7, 11, 15),
+    (0, 5, 10, 15),
+    (1, 6, 11, 12),
+    (2, 7, 8, 13),
+    (3, 4, 9, 14),
+];
+
+/// The G function, which mixes the message words mx and my into four state words.
+fn g(state: &mut [u32; 16], (a, b, c, d): (usize, usize, usize, usize), mx: u32, my: u32) {
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+    state[d] = (state[d] ^ state[a]).rotate_right(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(12);
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+    state[d] = (state[d] ^ state[a]).rotate_right(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(7);
+}
+
+/// The Blake3 compression function from the initialization vector, which gives the first 8 words
+/// of its output, i.e., the chaining value.
+pub fn blake3_compress(block: &[u32; 16], counter: u64, block_len: u32, flags: u32) -> [u32; 8] {
+    let mut state = [0u32; 16];
+    state[..8].copy_from_slice(&IV);
+    state[8..12].copy_from_slice(&IV[..4]);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    state[14] = block_len;
+    state[15] = flags;
+
+    let mut m = *block;
+    for _ in 0..N_ROUNDS {
+        for (i, &abcd) in G_SCHEDULE.iter().enumerate() {
+            g(&mut state, abcd, m[2 * i], m[2 * i + 1]);
+        }
+        m = MSG_PERMUTATION.map(|i| m[i]);
+    }
+
+    std::array::from_fn(|i| state[i] ^ state[i + 8])
+}
+
+/// Compute the Blake3 hash of a || b, for two 32-byte strings, which is a single block that is
+/// both the first and the last block of the only chunk, as `Blake3Gadget::hash_two` does.
+pub fn blake3_two(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let block: [u32; 16] = std::array::from_fn(|i| {
+        let bytes = if i < 8 { a } else { b };
+        u32::from_le_bytes(bytes[(i % 8) * 4..(i % 8) * 4 + 4].try_into().unwrap())
+    });
+
+    let cv = blake3_compress(&block, 0, 64, CHUNK_START | CHUNK_END | ROOT);
+
+    let mut res = [0u8; 32];
+    for (chunk, word) in res.chunks_exact_mut(4).zip(cv.iter()) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    res
+}
@@ -1,6 +1,9 @@
-use crate::channel::DrawHints;
+use crate::channel::{DrawHints, DrawQM31Hints, Endianness};
 use crate::treepp::*;
-use crate::utils::{hash_felt_gadget, trim_m31_gadget};
+use crate::utils::{hash_felt_gadget, m31_to_le_bytes_gadget, trim_m31_gadget};
+use rust_bitcoin_m31::{qm31_fromaltstack, qm31_toaltstack};
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
 
 /// Gadget for a channel.
 pub struct Sha256ChannelGadget;
@@ -13,6 +16,22 @@ impl Sha256ChannelGadget {
         }
     }
 
+    /// Absorb a commitment, but keep a copy of it below the channel for later use.
+    ///
+    /// input:
+    ///  commitment
+    ///  channel
+    ///
+    /// output:
+    ///  commitment
+    ///  channel'
+    pub fn mix_digest_keep() -> Script {
+        script! {
+            OP_OVER OP_SWAP
+            OP_CAT OP_SHA256
+        }
+    }
+
     /// Absorb a qm31 element.
     pub fn mix_felt() -> Script {
         script! {
@@ -22,12 +41,98 @@ impl Sha256ChannelGadget {
         }
     }
 
+    /// Absorb a m31 element, as in `ChannelMixM31::mix_m31`.
+    ///
+    /// input:
+    ///  m31
+    ///  channel
+    ///
+    /// output:
+    ///  channel'
+    pub fn mix_m31() -> Script {
+        script! {
+            OP_SWAP m31_to_le_bytes_gadget OP_SHA256
+            OP_SWAP OP_CAT OP_SHA256
+        }
+    }
+
+    /// Absorb n qm31 elements one at a time, as in `mix_felts`, so that every concatenation is of
+    /// two 32-byte hashes no matter how many elements are absorbed.
+    ///
+    /// input:
+    ///  felt_{n-1}, ..., felt_0 (qm31 -- felt_0 is absorbed first)
+    ///  channel
+    ///
+    /// output:
+    ///  channel'
+    pub fn mix_felts(n: usize) -> Script {
+        script! {
+            for _ in 0..n {
+                { Self::mix_felt() }
+            }
+        }
+    }
+
     /// Squeeze a qm31 element using hints.
     pub fn draw_felt_with_hint() -> Script {
+        Self::draw_felt_with_hint_with_endianness(Endianness::default())
+    }
+
+    /// Squeeze a qm31 element using hints, with the hash sliced into limbs in the given byte order.
+    pub fn draw_felt_with_hint_with_endianness(endianness: Endianness) -> Script {
+        script! {
+            OP_DUP OP_SHA256 OP_SWAP
+            OP_PUSHBYTES_1 OP_PUSHBYTES_0 OP_CAT OP_SHA256
+            { Self::unpack_multi_m31_with_endianness::<4>(endianness) }
+        }
+    }
+
+    /// Squeeze a qm31 element using hints without the reduction bias, by rejecting any draw that
+    /// has a limb equal to 2^31-1 and drawing again, as in `draw_felt_unbiased_and_hints`.
+    ///
+    /// Whether a draw is rejected is computed from the draw itself, so the prover cannot skip or
+    /// force a redraw. Only one redraw is supported, as two rejections in a row happen with a
+    /// probability of about 2^-58.
+    pub fn draw_felt_unbiased_with_hint() -> Script {
+        script! {
+            { Self::draw_raw_felt_with_hint() }
+            { Self::is_rejected() }
+            OP_IF
+                OP_2DROP OP_2DROP
+                { Self::draw_raw_felt_with_hint() }
+                { Self::is_rejected() }
+                OP_NOT OP_VERIFY
+            OP_ENDIF
+        }
+    }
+
+    /// Squeeze four m31 limbs in [0, 2^31-1] using hints, without reducing them.
+    fn draw_raw_felt_with_hint() -> Script {
         script! {
             OP_DUP OP_SHA256 OP_SWAP
             OP_PUSHBYTES_1 OP_PUSHBYTES_0 OP_CAT OP_SHA256
-            { Self::unpack_multi_m31::<4>() }
+            { Self::unpack_multi_m31_internal::<4>(Endianness::Le, false) }
+        }
+    }
+
+    /// Check if any of the four limbs on the top of the stack is 2^31-1, which keeps the limbs.
+    fn is_rejected() -> Script {
+        script! {
+            // the results of the previous comparisons are on the top of the limbs
+            for i in 0..4 {
+                { 2 * i } OP_PICK { 0x7fffffff } OP_EQUAL
+            }
+            OP_BOOLOR OP_BOOLOR OP_BOOLOR
+        }
+    }
+
+    /// Push the hints for `draw_felt_unbiased_with_hint`, one for each draw.
+    pub fn push_draw_unbiased_hint(hints: &[DrawQM31Hints]) -> Script {
+        assert!(!hints.is_empty() && hints.len() <= 2);
+        script! {
+            for hint in hints.iter() {
+                { Self::push_draw_hint(hint) }
+            }
         }
     }
 
@@ -45,6 +150,70 @@ impl Sha256ChannelGadget {
         }
     }
 
+    /// Squeeze n queries from the channel, each of logn bits, using hints, as in
+    /// `draw_n_queries`: each hash gives 8 m31 elements, and those of the last hash beyond the
+    /// n-th query are dropped.
+    ///
+    /// hint:
+    ///  as pushed by `push_draw_n_numbers_hint`
+    ///
+    /// input:
+    ///  channel
+    ///
+    /// output:
+    ///  channel'
+    ///  queries (n elements)
+    pub fn draw_n_numbers_with_hint(n: usize, logn: usize) -> Script {
+        assert!(n > 0);
+
+        let mut chunk_sizes = vec![8; n / 8];
+        if n % 8 != 0 {
+            chunk_sizes.push(n % 8);
+        }
+
+        script! {
+            for (i, k) in chunk_sizes.iter().enumerate() {
+                // the channel is below the 8 queries of the previous hash
+                if i > 0 {
+                    8 OP_ROLL
+                }
+
+                OP_DUP OP_SHA256 OP_SWAP
+                OP_PUSHBYTES_1 OP_PUSHBYTES_0 OP_CAT OP_SHA256
+                { Self::unpack_multi_m31::<8>() }
+
+                // v_0 is on the top, so drop the deepest limbs, v_7, ..., v_k, each of which is
+                // at the depth of its index, above the channel
+                for j in (*k..8).rev() {
+                    { j } OP_ROLL OP_DROP
+                }
+
+                // trim the limbs and put them in order, with v_{k-1} on the top
+                { trim_m31_gadget(logn) }
+                for i in 1..*k {
+                    { i } OP_ROLL { trim_m31_gadget(logn) }
+                }
+            }
+
+            // move the channel below all the queries
+            if chunk_sizes.len() > 1 {
+                { *chunk_sizes.last().unwrap() } OP_ROLL
+                for _ in 0..n {
+                    { n } OP_ROLL
+                }
+            }
+        }
+    }
+
+    /// Push the hints for `draw_n_numbers_with_hint`.
+    pub fn push_draw_n_numbers_hint(hints: &[DrawHints<8>]) -> Script {
+        script! {
+            for hint in hints.iter() {
+                { Self::push_draw_hint(hint) }
+            }
+        }
+    }
+
     /// Push the hint for drawing m31 elements from a hash.
     pub fn push_draw_hint<const N: usize>(e: &DrawHints<N>) -> Script {
         if N % 8 == 0 {
@@ -62,6 +231,36 @@ impl Sha256ChannelGadget {
         }
     }
 
+    /// Push the hint for drawing m31 elements from a hash, which is sliced into limbs in the given
+    /// byte order.
+    pub fn push_draw_hint_with_endianness<const N: usize>(
+        e: &DrawHints<N>,
+        endianness: Endianness,
+    ) -> Script {
+        match endianness {
+            Endianness::Le => Self::push_draw_hint(e),
+            Endianness::Be => {
+                let bytes =
+                    e.0.iter()
+                        .flat_map(|v| v.to_le_bytes())
+                        .collect::<Vec<u8>>();
+                if N % 8 == 0 {
+                    assert!(e.1.is_empty());
+                } else {
+                    assert_eq!(e.1.len(), 32 - (N % 8) * 4);
+                }
+                script! {
+                    for byte in bytes.iter() {
+                        { *byte as u32 }
+                    }
+                    if N % 8 != 0 {
+                        { e.1.clone() }
+                    }
+                }
+            }
+        }
+    }
+
     /// Reconstruct a 4-byte representation from a Bitcoin integer.
     ///
     /// Idea: extract the positive/negative symbol and pad it accordingly.
@@ -102,16 +301,96 @@ impl Sha256ChannelGadget {
         }
     }
 
+    /// Convert a byte, given as a Bitcoin integer, into a 1-byte string.
+    pub(crate) fn byte_to_bytes() -> Script {
+        script! {
+            OP_DUP 0 256 OP_WITHIN OP_VERIFY
+
+            OP_DUP 0 OP_EQUAL
+            OP_IF
+                OP_DROP
+                OP_PUSHBYTES_1 OP_PUSHBYTES_0
+            OP_ELSE
+                OP_DUP 128 OP_GREATERTHANOREQUAL
+                OP_IF
+                    // 0x80 is the "negative zero", and any larger byte is a negative number of 1 byte
+                    OP_DUP 128 OP_EQUAL
+                    OP_IF
+                        OP_DROP
+                        OP_PUSHBYTES_1 OP_LEFT
+                    OP_ELSE
+                        128 OP_SWAP OP_SUB
+                    OP_ENDIF
+                OP_ENDIF
+            OP_ENDIF
+        }
+    }
+
+    /// Reconstruct a 4-byte big-endian representation from its bytes.
+    ///
+    /// input:
+    ///   b0, b1, b2, b3 (as Bitcoin integers)
+    ///
+    /// output:
+    ///   b0 || b1 || b2 || b3
+    ///
+    /// altstack:
+    ///   the 31-bit value, with the highest bit of b0 removed
+    fn reconstruct_be() -> Script {
+        script! {
+            3 OP_PICK
+            OP_DUP 128 OP_GREATERTHANOREQUAL OP_IF 128 OP_SUB OP_ENDIF
+            for _ in 0..8 {
+                OP_DUP OP_ADD
+            }
+            3 OP_PICK OP_ADD
+            for _ in 0..8 {
+                OP_DUP OP_ADD
+            }
+            2 OP_PICK OP_ADD
+            for _ in 0..8 {
+                OP_DUP OP_ADD
+            }
+            OP_OVER OP_ADD
+            OP_TOALTSTACK
+
+            3 OP_ROLL { Self::byte_to_bytes() }
+            3 OP_ROLL { Self::byte_to_bytes() } OP_CAT
+            2 OP_ROLL { Self::byte_to_bytes() } OP_CAT
+            OP_SWAP { Self::byte_to_bytes() } OP_CAT
+        }
+    }
+
     /// Unpack multiple m31 and put them on the stack.
     pub fn unpack_multi_m31<const N: usize>() -> Script {
+        Self::unpack_multi_m31_with_endianness::<N>(Endianness::default())
+    }
+
+    /// Unpack multiple m31 and put them on the stack, with the hash sliced into limbs in the given
+    /// byte order.
+    pub fn unpack_multi_m31_with_endianness<const N: usize>(endianness: Endianness) -> Script {
+        Self::unpack_multi_m31_internal::<N>(endianness, true)
+    }
+
+    /// Unpack multiple m31 and put them on the stack, and, if `reduce` is set, reduce them.
+    fn unpack_multi_m31_internal<const N: usize>(endianness: Endianness, reduce: bool) -> Script {
         script! {
-            for _ in 0..N {
-                OP_DEPTH OP_1SUB OP_ROLL
-            }
+            if endianness == Endianness::Le {
+                for _ in 0..N {
+                    OP_DEPTH OP_1SUB OP_ROLL
+                }
 
-            for _ in 0..N {
-                { N - 1 } OP_ROLL
-                { Self::reconstruct() }
+                for _ in 0..N {
+                    { N - 1 } OP_ROLL
+                    { Self::reconstruct() }
+                }
+            } else {
+                for _ in 0..N {
+                    for _ in 0..4 {
+                        OP_DEPTH OP_1SUB OP_ROLL
+                    }
+                    { Self::reconstruct_be() }
+                }
             }
 
             for _ in 0..N-1 {
@@ -130,7 +409,251 @@ impl Sha256ChannelGadget {
                 // Reduce the number from [0, 2^31-1] to [0, 2^31-2] by subtracting 1 from any element that is not zero.
                 // This is because 2^31-1 is the modulus and a reduced element should be smaller than it.
                 // The sampling, therefore, has a small bias.
-                OP_DUP OP_NOT OP_NOTIF OP_1SUB OP_ENDIF
+                if reduce {
+                    OP_DUP OP_NOT OP_NOTIF OP_1SUB OP_ENDIF
+                }
+            }
+        }
+    }
+}
+
+/// Fail the execution if the channel state is not the expected one, which allows a script to check
+/// that it continues from the channel state that a previous script ended with.
+///
+/// input:
+///  channel (32 bytes)
+///  expected channel (32 bytes)
+///
+/// output:
+///  channel (32 bytes)
+pub fn assert_state_equals_gadget() -> Script {
+    script! {
+        OP_OVER OP_EQUALVERIFY
+    }
+}
+
+/// Fail the execution if the channel state at the end of the verifier is not the final digest
+/// recorded in the proof, which catches a transcript in which a mix or a draw is skipped or
+/// reordered.
+///
+/// hint:
+///  final digest (32 bytes)
+///
+/// input:
+///  channel (32 bytes)
+///
+/// output:
+///  none
+pub fn assert_final_digest_gadget() -> Script {
+    script! {
+        OP_DEPTH OP_1SUB OP_ROLL
+        OP_EQUALVERIFY
+    }
+}
+
+/// Fail the execution if the channel state is not the committed split digest, and otherwise fork
+/// it into two copies, as in `ChannelFork::fork`, so that two verification branches descend from
+/// the same transcript.
+///
+/// input:
+///  channel (32 bytes)
+///
+/// output:
+///  channel (32 bytes)
+///  channel (32 bytes)
+pub fn assert_shared_prefix_gadget(split_digest: BWSSha256Hash) -> Script {
+    script! {
+        OP_DUP { split_digest } OP_EQUALVERIFY
+        OP_DUP
+    }
+}
+
+/// Absorb a Merkle cap of `cap_size` entries, as in `ChannelMixCap::mix_cap`.
+///
+/// The entries are concatenated into a single stack element before hashing, so there can be at
+/// most 16 of them.
+///
+/// input:
+///  cap_0
+///  ...
+///  cap_{cap_size - 1}
+///  channel
+///
+/// output:
+///  channel'
+pub fn mix_cap_gadget(cap_size: usize) -> Script {
+    assert!(
+        (1..=16).contains(&cap_size),
+        "a cap of {} entries does not fit in a stack element",
+        cap_size
+    );
+
+    script! {
+        OP_TOALTSTACK
+        for _ in 1..cap_size {
+            OP_CAT
+        }
+        OP_SHA256
+        OP_FROMALTSTACK
+        { Sha256ChannelGadget::mix_digest() }
+    }
+}
+
+/// Absorb the verifier key hash into the channel, as in `ChannelMixVerifierKey::mix_verifier_key`,
+/// where `vk_bytes` are the verifier key bytes, see `VerifierParams::to_bytes`, which are hashed
+/// in the script.
+///
+/// input:
+///  channel
+///
+/// output:
+///  channel'
+pub fn mix_verifier_key_gadget(vk_bytes: &[u8]) -> Script {
+    assert!(
+        vk_bytes.len() <= 520,
+        "a verifier key of {} bytes does not fit in a stack element",
+        vk_bytes.len()
+    );
+
+    script! {
+        { vk_bytes.to_vec() }
+        OP_SHA256
+        OP_SWAP
+        { Sha256ChannelGadget::mix_digest() }
+    }
+}
+
+/// Compute the initial channel state from the public inputs, as in
+/// `ChannelFromPublicInputs::init_from_public_inputs`.
+///
+/// input:
+///  none
+///
+/// output:
+///  channel
+pub fn init_from_public_inputs_gadget(public_inputs: &[QM31]) -> Script {
+    script! {
+        { vec![0u8; 32] }
+        for public_input in public_inputs.iter() {
+            { *public_input }
+            4 OP_ROLL
+            { Sha256ChannelGadget::mix_felt() }
+        }
+    }
+}
+
+/// Squeeze n qm31 challenges one after another, each as in `draw_felt_with_hint`, and leave them
+/// with the first one on the top, as in `ChannelWithHint::draw_challenge_vector`.
+///
+/// hint:
+///  the draw hints of the n challenges, see `push_draw_challenge_vector_hint`
+///
+/// input:
+///  channel
+///
+/// output:
+///  channel'
+///  c_{n-1} (qm31)
+///  ...
+///  c_0 (qm31)
+pub fn draw_challenge_vector_gadget(n: usize) -> Script {
+    script! {
+        for _ in 0..n {
+            { Sha256ChannelGadget::draw_felt_with_hint() }
+            qm31_toaltstack
+        }
+        for _ in 0..n {
+            qm31_fromaltstack
+        }
+    }
+}
+
+/// Push the hints for `draw_challenge_vector_gadget`.
+pub fn push_draw_challenge_vector_hint(hints: &[DrawQM31Hints]) -> Script {
+    script! {
+        for hint in hints.iter() {
+            { Sha256ChannelGadget::push_draw_hint(hint) }
+        }
+    }
+}
+
+/// Squeeze n queries from the channel, each of logn bits, as in `draw_n_numbers_with_hint`, and
+/// fail the execution if two of them are the same.
+///
+/// It requires `n <= 2^logn`, as otherwise the queries cannot be distinct.
+///
+/// hint:
+///  same as `draw_n_numbers_with_hint`
+///
+/// input:
+///  channel
+///
+/// output:
+///  channel'
+///  queries (n elements)
+pub fn draw_distinct_queries_gadget(n: usize, logn: usize) -> Script {
+    assert!(
+        n <= 1 << logn,
+        "{} queries of {} bits cannot be distinct",
+        n,
+        logn
+    );
+
+    script! {
+        { Sha256ChannelGadget::draw_n_numbers_with_hint(n, logn) }
+        { assert_distinct_gadget(n) }
+    }
+}
+
+/// Squeeze n queries from the channel, each of logn bits, as in `draw_n_numbers_with_hint`, and
+/// map them into a subdomain of size 2^logn of a larger evaluation domain, as in
+/// `ChannelWithHint::draw_subdomain_queries`.
+///
+/// The evaluations are laid out in bit-reversed order, see `permute_eval`, in which every aligned
+/// block of 2^logn positions holds a circle domain of size 2^logn, i.e., a half coset c +
+/// <G_{logn - 1}> and its conjugate. A query q is mapped to the position `coset_offset` + q,
+/// which is in the block that starts at `coset_offset`, so the offset must be a multiple of
+/// 2^logn and the queries need no further range check.
+///
+/// hint:
+///  same as `draw_n_numbers_with_hint`
+///
+/// input:
+///  channel
+///
+/// output:
+///  channel'
+///  queries (n elements)
+pub fn draw_subdomain_queries_gadget(n: usize, logn: usize, coset_offset: usize) -> Script {
+    assert_eq!(
+        coset_offset % (1 << logn),
+        0,
+        "the subdomain must start at a multiple of its size"
+    );
+
+    script! {
+        { Sha256ChannelGadget::draw_n_numbers_with_hint(n, logn) }
+        if coset_offset > 0 {
+            for _ in 0..n {
+                { n - 1 } OP_ROLL { coset_offset } OP_ADD
+            }
+        }
+    }
+}
+
+/// Fail the execution if two of the top n stack elements are the same.
+///
+/// input:
+///  a_1, ..., a_n
+///
+/// output:
+///  a_1, ..., a_n
+pub fn assert_distinct_gadget(n: usize) -> Script {
+    script! {
+        for i in 0..n {
+            for j in (i + 1)..n {
+                { i } OP_PICK { j + 1 } OP_PICK
+                OP_NUMNOTEQUAL OP_VERIFY
             }
         }
     }
@@ -138,17 +661,27 @@ impl Sha256ChannelGadget {
 
 #[cfg(test)]
 mod test {
-    use crate::channel::{generate_hints, ChannelWithHint, Sha256Channel, Sha256ChannelGadget};
+    use crate::channel::{
+        assert_shared_prefix_gadget, assert_state_equals_gadget, draw_challenge_vector_gadget,
+        draw_distinct_queries_gadget, draw_subdomain_queries_gadget, generate_hints,
+        init_from_public_inputs_gadget, mix_cap_gadget, mix_verifier_key_gadget,
+        push_draw_challenge_vector_hint, verifier_key_hash, ChannelFork, ChannelFromPublicInputs,
+        ChannelMixCap, ChannelMixM31, ChannelMixVerifierKey, ChannelWithHint, Endianness,
+        Sha256Channel, Sha256ChannelGadget, VerifierParams,
+    };
+    use crate::circle::CircleDomain;
+    use crate::fri::N_QUERIES;
+    use crate::tests_utils::fixtures::{random_m31, random_qm31};
     use crate::tests_utils::report::report_bitcoin_script_size;
     use crate::treepp::*;
-    use crate::utils::{hash_felt_gadget, hash_qm31};
+    use crate::utils::{bit_reverse_index, hash_felt_gadget, hash_qm31};
     use bitcoin_script::script;
+    use num_traits::{One, Zero};
     use rand::{Rng, RngCore, SeedableRng};
     use rand_chacha::ChaCha20Rng;
     use rust_bitcoin_m31::qm31_equalverify;
     use stwo_prover::core::channel::Channel;
-    use stwo_prover::core::fields::cm31::CM31;
-    use stwo_prover::core::fields::m31::M31;
+    use stwo_prover::core::fields::m31::{M31, P};
     use stwo_prover::core::fields::qm31::QM31;
     use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
 
@@ -184,23 +717,74 @@ mod test {
     }
 
     #[test]
-    fn test_mix_felt() {
+    fn test_mix_cap() {
         let mut prng = ChaCha20Rng::seed_from_u64(0);
 
-        let channel_script = Sha256ChannelGadget::mix_felt();
-        report_bitcoin_script_size("Channel", "mix_felt", channel_script.len());
+        let cap_size = 4;
+
+        let channel_script = mix_cap_gadget(cap_size);
+        report_bitcoin_script_size(
+            "Channel",
+            format!("mix_cap({} entries)", cap_size).as_str(),
+            channel_script.len(),
+        );
 
         let mut init_state = [0u8; 32];
         init_state.iter_mut().for_each(|v| *v = prng.gen());
         let init_state = BWSSha256Hash::from(init_state.to_vec());
 
-        let elem = QM31(
-            CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64())),
-            CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64())),
-        );
+        let cap = (0..cap_size)
+            .map(|_| {
+                let mut entry = [0u8; 32];
+                entry.iter_mut().for_each(|v| *v = prng.gen());
+                BWSSha256Hash::from(entry.to_vec())
+            })
+            .collect::<Vec<_>>();
 
         let mut channel = Sha256Channel::new(init_state);
-        channel.mix_felts(&[elem]);
+        channel.mix_cap(&cap);
+        let final_state = channel.digest;
+
+        // the transcript binds the order of the entries
+        let mut reordered = cap.clone();
+        reordered.swap(0, 1);
+
+        let mut reordered_channel = Sha256Channel::new(init_state);
+        reordered_channel.mix_cap(&reordered);
+        assert_ne!(reordered_channel.digest, final_state);
+
+        for (cap, expected) in [(&cap, true), (&reordered, false)] {
+            let script = script! {
+                for entry in cap.iter() {
+                    { *entry }
+                }
+                { init_state }
+                { channel_script.clone() }
+                { final_state }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
+
+    #[test]
+    fn test_mix_digest_keep() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let channel_script = Sha256ChannelGadget::mix_digest_keep();
+        report_bitcoin_script_size("Channel", "mix_digest_keep", channel_script.len());
+
+        let mut init_state = [0u8; 32];
+        init_state.iter_mut().for_each(|v| *v = prng.gen());
+        let init_state = BWSSha256Hash::from(init_state.to_vec());
+
+        let mut elem = [0u8; 32];
+        elem.iter_mut().for_each(|v| *v = prng.gen());
+        let elem = BWSSha256Hash::from(elem.to_vec());
+
+        let mut channel = Sha256Channel::new(init_state);
+        channel.mix_digest(elem);
 
         let final_state = channel.digest;
 
@@ -209,6 +793,8 @@ mod test {
             { init_state }
             { channel_script.clone() }
             { final_state }
+            OP_EQUALVERIFY
+            { elem }
             OP_EQUAL
         };
         let exec_result = execute_script(script);
@@ -216,57 +802,414 @@ mod test {
     }
 
     #[test]
-    fn test_draw_8_elements() {
+    fn test_shared_prefix() {
         let mut prng = ChaCha20Rng::seed_from_u64(0);
 
-        for _ in 0..100 {
-            let mut a = [0u8; 32];
-            a.iter_mut().for_each(|v| *v = prng.gen());
-            let a = BWSSha256Hash::from(a.to_vec());
+        let mut random_hash = || {
+            let mut v = [0u8; 32];
+            v.iter_mut().for_each(|v| *v = prng.gen());
+            BWSSha256Hash::from(v.to_vec())
+        };
 
-            let mut channel = Sha256Channel::new(a);
-            let (b, hint) = channel.draw_m31_and_hints::<8>();
+        let init_state = random_hash();
+        let prefix = random_hash();
+        let elem_a = random_hash();
+        let elem_b = random_hash();
 
-            let c = channel.digest;
+        let mut channel = Sha256Channel::new(init_state);
+        channel.mix_digest(prefix);
+        let split_digest = channel.digest;
 
-            let script = script! {
-                { Sha256ChannelGadget::push_draw_hint(&hint) }
-                { a }
-                OP_DUP OP_SHA256 OP_SWAP
-                OP_PUSHBYTES_1 OP_PUSHBYTES_0 OP_CAT OP_SHA256
-                { Sha256ChannelGadget::unpack_multi_m31::<8>() }
-                for i in 0..8 {
-                    { b[i] }
-                    OP_EQUALVERIFY
-                }
-                { c }
-                OP_EQUAL
-            };
-            let exec_result = execute_script(script);
-            assert!(exec_result.success);
-        }
+        // identical data after the fork gives identical draws
+        let mut branch_1 = channel.fork();
+        let mut branch_2 = channel.fork();
+        branch_1.mix_digest(elem_a);
+        branch_2.mix_digest(elem_a);
+        assert_eq!(branch_1.draw_felt(), branch_2.draw_felt());
+
+        // diverging data after the fork gives different draws
+        let mut branch_a = channel.fork();
+        let mut branch_b = channel.fork();
+        branch_a.mix_digest(elem_a);
+        branch_b.mix_digest(elem_b);
+        let (felt_a, hint_a) = branch_a.draw_felt_and_hints();
+        let (felt_b, hint_b) = branch_b.draw_felt_and_hints();
+        assert_ne!(felt_a, felt_b);
+
+        let gadget = assert_shared_prefix_gadget(split_digest);
+        report_bitcoin_script_size("Channel", "assert_shared_prefix", gadget.len());
+
+        let script = script! {
+            { Sha256ChannelGadget::push_draw_hint(&hint_b) }
+            { Sha256ChannelGadget::push_draw_hint(&hint_a) }
+            { elem_a }
+            { elem_b }
+            { prefix }
+            { init_state }
+            { Sha256ChannelGadget::mix_digest() }
+            { gadget.clone() }
+            OP_ROT OP_SWAP
+            { Sha256ChannelGadget::mix_digest() }
+            { Sha256ChannelGadget::draw_felt_with_hint() }
+            { felt_b }
+            qm31_equalverify
+            { branch_b.digest }
+            OP_EQUALVERIFY
+            { Sha256ChannelGadget::mix_digest() }
+            { Sha256ChannelGadget::draw_felt_with_hint() }
+            { felt_a }
+            qm31_equalverify
+            { branch_a.digest }
+            OP_EQUAL
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+
+        // a channel that does not end at the split digest is rejected
+        let script = script! {
+            { init_state }
+            { gadget }
+            OP_2DROP
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
     }
 
     #[test]
-    fn test_draw_felt_with_hint() {
+    fn test_assert_state_equals() {
         let mut prng = ChaCha20Rng::seed_from_u64(0);
 
-        let channel_script = Sha256ChannelGadget::draw_felt_with_hint();
-        report_bitcoin_script_size("Channel", "draw_felt_with_hint", channel_script.len());
+        let assert_script = assert_state_equals_gadget();
+        report_bitcoin_script_size("Channel", "assert_state_equals", assert_script.len());
 
-        for _ in 0..100 {
-            let mut a = [0u8; 32];
-            a.iter_mut().for_each(|v| *v = prng.gen());
-            let a = BWSSha256Hash::from(a.to_vec());
+        let mut init_state = [0u8; 32];
+        init_state.iter_mut().for_each(|v| *v = prng.gen());
+        let init_state = BWSSha256Hash::from(init_state.to_vec());
 
-            let mut channel = Sha256Channel::new(a);
-            let (b, hint) = channel.draw_felt_and_hints();
+        let mut elem = [0u8; 32];
+        elem.iter_mut().for_each(|v| *v = prng.gen());
+        let elem = BWSSha256Hash::from(elem.to_vec());
+
+        let mut channel = Sha256Channel::new(init_state);
+        channel.mix_digest(elem);
+
+        let final_state = channel.digest;
+
+        let script = script! {
+            { init_state }
+            { init_state }
+            { assert_script.clone() }
+            { elem }
+            OP_SWAP
+            { Sha256ChannelGadget::mix_digest() }
+            { final_state }
+            OP_EQUAL
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+
+        let script = script! {
+            { init_state }
+            { final_state }
+            { assert_script.clone() }
+            OP_DROP
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_mix_felt() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let channel_script = Sha256ChannelGadget::mix_felt();
+        report_bitcoin_script_size("Channel", "mix_felt", channel_script.len());
+
+        let mut init_state = [0u8; 32];
+        init_state.iter_mut().for_each(|v| *v = prng.gen());
+        let init_state = BWSSha256Hash::from(init_state.to_vec());
+
+        let elem = random_qm31(&mut prng);
+
+        let mut channel = Sha256Channel::new(init_state);
+        channel.mix_felts(&[elem]);
+
+        let final_state = channel.digest;
+
+        let script = script! {
+            { elem }
+            { init_state }
+            { channel_script.clone() }
+            { final_state }
+            OP_EQUAL
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+    }
+
+    #[test]
+    fn test_init_from_public_inputs() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let public_inputs = (0..3)
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<QM31>>();
+
+        let channel_script = init_from_public_inputs_gadget(&public_inputs);
+        report_bitcoin_script_size(
+            "Channel",
+            "init_from_public_inputs(3 elements)",
+            channel_script.len(),
+        );
+
+        let channel = Sha256Channel::init_from_public_inputs(&public_inputs);
+
+        let script = script! {
+            { channel_script.clone() }
+            { channel.digest }
+            OP_EQUAL
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+
+        // a different statement leads to a different transcript
+        let mut other_public_inputs = public_inputs.clone();
+        other_public_inputs[2] += QM31::one();
+        let other_channel = Sha256Channel::init_from_public_inputs(&other_public_inputs);
+        assert_ne!(channel.digest, other_channel.digest);
+
+        let script = script! {
+            { init_from_public_inputs_gadget(&other_public_inputs) }
+            { channel.digest }
+            OP_EQUAL
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_draw_8_elements() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        for _ in 0..100 {
+            let mut a = [0u8; 32];
+            a.iter_mut().for_each(|v| *v = prng.gen());
+            let a = BWSSha256Hash::from(a.to_vec());
+
+            let mut channel = Sha256Channel::new(a);
+            let (b, hint) = channel.draw_m31_and_hints::<8>();
 
             let c = channel.digest;
 
             let script = script! {
                 { Sha256ChannelGadget::push_draw_hint(&hint) }
                 { a }
+                OP_DUP OP_SHA256 OP_SWAP
+                OP_PUSHBYTES_1 OP_PUSHBYTES_0 OP_CAT OP_SHA256
+                { Sha256ChannelGadget::unpack_multi_m31::<8>() }
+                for i in 0..8 {
+                    { b[i] }
+                    OP_EQUALVERIFY
+                }
+                { c }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_mix_m31() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let channel_script = Sha256ChannelGadget::mix_m31();
+        report_bitcoin_script_size("Channel", "mix_m31", channel_script.len());
+
+        // the elements whose minimal Bitcoin integer encodings have 0 to 4 bytes
+        let edge_cases = [0, 0x80, 0x8000, 0x800000, P - 1].map(M31::from_u32_unchecked);
+
+        for i in 0..100 {
+            let mut init_state = [0u8; 32];
+            init_state.iter_mut().for_each(|v| *v = prng.gen());
+            let init_state = BWSSha256Hash::from(init_state.to_vec());
+
+            let elem = match edge_cases.get(i) {
+                Some(elem) => *elem,
+                None => random_m31(&mut prng),
+            };
+
+            let mut channel = Sha256Channel::new(init_state);
+            channel.mix_m31(elem);
+            let final_state = channel.digest;
+
+            // absorbing the zero-padded qm31 element gives a different state
+            let mut channel = Sha256Channel::new(init_state);
+            channel.mix_felts(&[QM31::from_m31(elem, M31::zero(), M31::zero(), M31::zero())]);
+            assert_ne!(channel.digest, final_state);
+
+            let script = script! {
+                { elem }
+                { init_state }
+                { channel_script.clone() }
+                { final_state }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_mix_felts() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let n = 100;
+
+        let channel_script = Sha256ChannelGadget::mix_felts(n);
+        report_bitcoin_script_size(
+            "Channel",
+            format!("mix_felts({})", n).as_str(),
+            channel_script.len(),
+        );
+
+        let mut init_state = [0u8; 32];
+        init_state.iter_mut().for_each(|v| *v = prng.gen());
+        let init_state = BWSSha256Hash::from(init_state.to_vec());
+
+        let elems = (0..n)
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<QM31>>();
+
+        let mut channel = Sha256Channel::new(init_state);
+        channel.mix_felts(&elems);
+        let final_state = channel.digest;
+
+        // absorbing the elements in chunks threads the same digest
+        let mut chunked_channel = Sha256Channel::new(init_state);
+        for chunk in elems.chunks(7) {
+            chunked_channel.mix_felts(chunk);
+        }
+        assert_eq!(chunked_channel.digest, final_state);
+
+        let script = script! {
+            for elem in elems.iter().rev() {
+                { *elem }
+            }
+            { init_state }
+            { channel_script.clone() }
+            { final_state }
+            OP_EQUAL
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+    }
+
+    #[test]
+    fn test_draw_felt_with_hint() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let channel_script = Sha256ChannelGadget::draw_felt_with_hint();
+        report_bitcoin_script_size("Channel", "draw_felt_with_hint", channel_script.len());
+
+        for _ in 0..100 {
+            let mut a = [0u8; 32];
+            a.iter_mut().for_each(|v| *v = prng.gen());
+            let a = BWSSha256Hash::from(a.to_vec());
+
+            let mut channel = Sha256Channel::new(a);
+            let (b, hint) = channel.draw_felt_and_hints();
+
+            let c = channel.digest;
+
+            let script = script! {
+                { Sha256ChannelGadget::push_draw_hint(&hint) }
+                { a }
+                { channel_script.clone() }
+                { b }
+                qm31_equalverify
+                { c }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_draw_challenge_vector() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let n = 3;
+
+        let channel_script = draw_challenge_vector_gadget(n);
+        report_bitcoin_script_size(
+            "Channel",
+            format!("draw_challenge_vector({})", n).as_str(),
+            channel_script.len(),
+        );
+
+        for _ in 0..20 {
+            let mut a = [0u8; 32];
+            a.iter_mut().for_each(|v| *v = prng.gen());
+            let a = BWSSha256Hash::from(a.to_vec());
+
+            let mut channel = Sha256Channel::new(a);
+            let (challenges, hints) = channel.draw_challenge_vector(n);
+            assert_eq!(challenges.len(), n);
+
+            // the same as drawing the challenges one by one, with exactly n draws
+            let mut expected_channel = Sha256Channel::new(a);
+            for challenge in challenges.iter() {
+                assert_eq!(expected_channel.draw_felt_and_hints().0, *challenge);
+            }
+            assert_eq!(expected_channel.digest, channel.digest);
+
+            let c = channel.digest;
+
+            let script = script! {
+                { push_draw_challenge_vector_hint(&hints) }
+                { a }
+                { channel_script.clone() }
+                for challenge in challenges.iter() {
+                    { *challenge }
+                    qm31_equalverify
+                }
+                { c }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_draw_felt_unbiased_with_hint() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let channel_script = Sha256ChannelGadget::draw_felt_unbiased_with_hint();
+        report_bitcoin_script_size(
+            "Channel",
+            "draw_felt_unbiased_with_hint",
+            channel_script.len(),
+        );
+
+        for _ in 0..100 {
+            let mut a = [0u8; 32];
+            a.iter_mut().for_each(|v| *v = prng.gen());
+            let a = BWSSha256Hash::from(a.to_vec());
+
+            let mut channel = Sha256Channel::new(a);
+            let (b, hints) = channel.draw_felt_unbiased_and_hints();
+            assert_eq!(hints.len(), 1);
+
+            let c = channel.digest;
+
+            let script = script! {
+                { Sha256ChannelGadget::push_draw_unbiased_hint(&hints) }
+                { a }
                 { channel_script.clone() }
                 { b }
                 qm31_equalverify
@@ -278,6 +1221,208 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_is_rejected() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let limbs = (0..4)
+            .map(|_| prng.gen_range(0..0x7fffffffu32))
+            .collect::<Vec<_>>();
+
+        // no limb is 2^31-1, and then each of the four limbs in turn
+        for rejected_limb in [None, Some(0), Some(1), Some(2), Some(3)] {
+            let mut limbs = limbs.clone();
+            if let Some(i) = rejected_limb {
+                limbs[i] = 0x7fffffff;
+            }
+
+            let script = script! {
+                for limb in limbs.iter() {
+                    { *limb }
+                }
+                { Sha256ChannelGadget::is_rejected() }
+                if rejected_limb.is_some() {
+                    OP_VERIFY
+                } else {
+                    OP_NOT OP_VERIFY
+                }
+                for limb in limbs.iter().rev() {
+                    { *limb } OP_EQUALVERIFY
+                }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_unbiased_rejection() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        // A crafted hash whose third limb is 2^31-1, which must be rejected.
+        let mut rejected = [0u8; 32];
+        rejected.iter_mut().for_each(|v| *v = prng.gen());
+        rejected[8..12].copy_from_slice(&0x7fffffffu32.to_le_bytes());
+
+        // The same hash with the top bit set, which is the same limb in a negative encoding.
+        let mut rejected_neg = rejected;
+        rejected_neg[11] = 0xff;
+
+        let mut accepted = rejected;
+        accepted[8] = 0xfe;
+
+        for (hash, expected) in [(rejected, 1), (rejected_neg, 1), (accepted, 0)] {
+            let (_, hint) = generate_hints::<4>(&hash);
+
+            let script = script! {
+                { Sha256ChannelGadget::push_draw_hint(&hint) }
+                { hash.to_vec() }
+                { Sha256ChannelGadget::unpack_multi_m31_internal::<4>(Endianness::Le, false) }
+                { Sha256ChannelGadget::is_rejected() }
+                { expected }
+                OP_EQUALVERIFY
+                OP_2DROP OP_2DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_draw_felt_with_endianness() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        for endianness in [Endianness::Le, Endianness::Be] {
+            let channel_script =
+                Sha256ChannelGadget::draw_felt_with_hint_with_endianness(endianness);
+            report_bitcoin_script_size(
+                "Channel",
+                format!("draw_felt_with_hint({:?})", endianness).as_str(),
+                channel_script.len(),
+            );
+
+            for _ in 0..100 {
+                let mut a = [0u8; 32];
+                a.iter_mut().for_each(|v| *v = prng.gen());
+                let a = BWSSha256Hash::from(a.to_vec());
+
+                let mut channel = Sha256Channel::new(a);
+                let (b, hint) = channel.draw_felt_and_hints_with_endianness(endianness);
+
+                // the hint is the same for both orders, only the limbs differ
+                let (_, le_hint) = Sha256Channel::new(a).draw_felt_and_hints();
+                for (limb, le_limb) in hint.0.iter().zip(le_hint.0.iter()) {
+                    assert_eq!(limb.to_le_bytes(), le_limb.to_le_bytes());
+                }
+                assert_eq!(hint.1, le_hint.1);
+
+                let c = channel.digest;
+
+                let script = script! {
+                    { Sha256ChannelGadget::push_draw_hint_with_endianness(&hint, endianness) }
+                    { a }
+                    { channel_script.clone() }
+                    { b }
+                    qm31_equalverify
+                    { c }
+                    OP_EQUAL
+                };
+                let exec_result = execute_script(script);
+                assert!(exec_result.success);
+            }
+        }
+
+        // the two orders draw different elements from the same channel
+        let mut a = [0u8; 32];
+        a.iter_mut().for_each(|v| *v = prng.gen());
+        let a = BWSSha256Hash::from(a.to_vec());
+
+        let (le, _) = Sha256Channel::new(a).draw_felt_and_hints_with_endianness(Endianness::Le);
+        let (be, _) = Sha256Channel::new(a).draw_felt_and_hints_with_endianness(Endianness::Be);
+        assert_ne!(le, be);
+    }
+
+    #[test]
+    fn test_draw_n_numbers_with_hint() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+        let logn = 15;
+
+        for n in [1, 5, 8, 12, 17] {
+            let channel_script = Sha256ChannelGadget::draw_n_numbers_with_hint(n, logn);
+            report_bitcoin_script_size(
+                "Channel",
+                format!("draw_n_numbers_with_hint(n={})", n).as_str(),
+                channel_script.len(),
+            );
+
+            for _ in 0..10 {
+                let mut a = [0u8; 32];
+                a.iter_mut().for_each(|v| *v = prng.gen());
+                let a = BWSSha256Hash::from(a.to_vec());
+
+                let mut channel = Sha256Channel::new(a);
+                let (b, hints) = channel.draw_n_queries(n, logn);
+                assert_eq!(b.len(), n);
+                assert_eq!(hints.len(), n.div_ceil(8));
+
+                // the first five queries are those of `draw_5queries`
+                if n >= 5 {
+                    let mut channel_5 = Sha256Channel::new(a);
+                    assert_eq!(channel_5.draw_5queries(logn).0, b[..5]);
+                    if n <= 8 {
+                        assert_eq!(channel_5.digest, channel.digest);
+                    }
+                }
+
+                let script = script! {
+                    { Sha256ChannelGadget::push_draw_n_numbers_hint(&hints) }
+                    { a }
+                    { channel_script.clone() }
+                    for v in b.iter().rev() {
+                        { *v } OP_EQUALVERIFY
+                    }
+                    { channel.digest }
+                    OP_EQUAL
+                };
+                let exec_result = execute_script(script);
+                assert!(exec_result.success);
+            }
+        }
+    }
+
+    #[test]
+    fn test_draw_n_numbers_partial_hash() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+        let logn = 10;
+
+        // n is not a multiple of 8, so that the last hash is only partially used
+        for n in [2, 3, 7, 9, 13] {
+            let channel_script = Sha256ChannelGadget::draw_n_numbers_with_hint(n, logn);
+
+            let mut a = [0u8; 32];
+            a.iter_mut().for_each(|v| *v = prng.gen());
+            let a = BWSSha256Hash::from(a.to_vec());
+
+            let mut channel = Sha256Channel::new(a);
+            let (queries, hints) = channel.draw_n_queries(n, logn);
+
+            let script = script! {
+                { Sha256ChannelGadget::push_draw_n_numbers_hint(&hints) }
+                { a }
+                { channel_script.clone() }
+                for q in queries.iter().rev() {
+                    { *q } OP_EQUALVERIFY
+                }
+                { channel.digest }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
     #[test]
     fn test_draw_5numbers_with_hint() {
         let mut prng = ChaCha20Rng::seed_from_u64(0);
@@ -313,6 +1458,133 @@ mod test {
         }
     }
 
+    fn check_draw_distinct_queries(n: usize, logn: usize) {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let channel_script = draw_distinct_queries_gadget(n, logn);
+        report_bitcoin_script_size(
+            "Channel",
+            format!("draw_distinct_queries({} queries, logn={})", n, logn).as_str(),
+            channel_script.len(),
+        );
+
+        let mut n_distinct = 0;
+        let mut n_duplicate = 0;
+        for _ in 0..20 {
+            let mut a = [0u8; 32];
+            a.iter_mut().for_each(|v| *v = prng.gen());
+            let a = BWSSha256Hash::from(a.to_vec());
+
+            let mut channel = Sha256Channel::new(a);
+            let (b, hints) = channel.draw_n_queries(n, logn);
+
+            let c = channel.digest;
+
+            let is_distinct = (0..n).all(|i| ((i + 1)..n).all(|j| b[i] != b[j]));
+            if is_distinct {
+                n_distinct += 1;
+            } else {
+                n_duplicate += 1;
+            }
+
+            let script = script! {
+                { Sha256ChannelGadget::push_draw_n_numbers_hint(&hints) }
+                { a }
+                { channel_script.clone() }
+                for v in b.iter().rev() {
+                    { *v } OP_EQUALVERIFY
+                }
+                { c }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, is_distinct);
+        }
+
+        // make sure that both cases have been exercised
+        assert!(n_distinct > 0);
+        assert!(n_duplicate > 0);
+    }
+
+    #[test]
+    fn test_draw_distinct_queries() {
+        // with 4 bits, a good fraction of the draws have duplicates
+        check_draw_distinct_queries(5, 4);
+    }
+
+    #[test]
+    fn test_draw_distinct_queries_from_several_hashes() {
+        // more queries than the 8 of a hash
+        check_draw_distinct_queries(12, 6);
+    }
+
+    #[test]
+    fn test_draw_distinct_queries_whole_domain() {
+        // as many queries as positions, which are distinct only if they are a permutation
+        check_draw_distinct_queries(2, 1);
+    }
+
+    #[test]
+    fn test_draw_subdomain_queries() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 6;
+        let log_domain_size = 9;
+        let coset_offset = 3 << logn;
+
+        let channel_script = draw_subdomain_queries_gadget(N_QUERIES, logn, coset_offset);
+        report_bitcoin_script_size(
+            "Channel",
+            format!("draw_subdomain_queries(logn={})", logn).as_str(),
+            channel_script.len(),
+        );
+
+        // the points of the subdomain all double to the same x-coordinate after logn - 1 doublings
+        let domain = CircleDomain::new(log_domain_size as u32);
+        let doubled_x = |index: usize| {
+            let p = domain.point_at(bit_reverse_index(index, log_domain_size));
+            (1..logn).fold(p, |p, _| p + p).x
+        };
+        let subdomain_x = doubled_x(coset_offset);
+        let n_in_subdomain = (0..1 << log_domain_size)
+            .filter(|&index| doubled_x(index) == subdomain_x)
+            .count();
+        assert_eq!(n_in_subdomain, 1 << logn);
+
+        // the queries of more than one hash are also in the subdomain, with the channel below them
+        for n in [N_QUERIES, 12] {
+            let channel_script = draw_subdomain_queries_gadget(n, logn, coset_offset);
+
+            for _ in 0..20 {
+                let mut a = [0u8; 32];
+                a.iter_mut().for_each(|v| *v = prng.gen());
+                let a = BWSSha256Hash::from(a.to_vec());
+
+                let mut channel = Sha256Channel::new(a);
+                let (queries, hints) = channel.draw_subdomain_queries(n, logn, coset_offset);
+                assert_eq!(queries.len(), n);
+
+                for &query in queries.iter() {
+                    assert!((coset_offset..coset_offset + (1 << logn)).contains(&query));
+                    assert_eq!(doubled_x(query), subdomain_x);
+                }
+
+                let script = script! {
+                    { Sha256ChannelGadget::push_draw_n_numbers_hint(&hints) }
+                    { a }
+                    { channel_script.clone() }
+                    for v in queries.iter().rev() {
+                        { *v } OP_EQUALVERIFY
+                    }
+                    { channel.digest }
+                    OP_EQUAL
+                };
+                let exec_result = execute_script(script);
+                assert!(exec_result.success);
+            }
+        }
+    }
+
     #[test]
     fn test_hash_felt() {
         let mut prng = ChaCha20Rng::seed_from_u64(0);
@@ -321,10 +1593,7 @@ mod test {
         report_bitcoin_script_size("QM31", "hash", commit_script.len());
 
         for _ in 0..100 {
-            let a = QM31(
-                CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64())),
-                CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64())),
-            );
+            let a = random_qm31(&mut prng);
             let b = hash_qm31(&a);
 
             let script = script! {
@@ -366,4 +1635,55 @@ mod test {
         let exec_result = execute_script(script);
         assert!(!exec_result.success);
     }
+
+    #[test]
+    fn test_mix_verifier_key() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let mut init_state = [0u8; 32];
+        init_state.iter_mut().for_each(|v| *v = prng.gen());
+        let init_state = BWSSha256Hash::from(init_state.to_vec());
+
+        let params = VerifierParams {
+            logn: 5,
+            n_queries: 8,
+            constraint_degrees: vec![1, 2],
+        };
+        let other_params = VerifierParams {
+            n_queries: 9,
+            ..params.clone()
+        };
+
+        // different parameters yield different verifier keys and different transcripts
+        assert_ne!(verifier_key_hash(&params), verifier_key_hash(&other_params));
+
+        let mut channel = Sha256Channel::new(init_state);
+        channel.mix_verifier_key(&params);
+
+        let mut other_channel = Sha256Channel::new(init_state);
+        other_channel.mix_verifier_key(&other_params);
+        assert_ne!(channel.digest, other_channel.digest);
+
+        let channel_script = mix_verifier_key_gadget(&params.to_bytes());
+        report_bitcoin_script_size(
+            "Channel",
+            format!(
+                "mix_verifier_key({} constraints)",
+                params.constraint_degrees.len()
+            )
+            .as_str(),
+            channel_script.len(),
+        );
+
+        for (vk_bytes, expected) in [(params.to_bytes(), true), (other_params.to_bytes(), false)] {
+            let script = script! {
+                { init_state }
+                { mix_verifier_key_gadget(&vk_bytes) }
+                { channel.digest }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
 }
@@ -0,0 +1,193 @@
+use crate::channel::{FriChannel, Sha256Channel};
+use crate::utils::hash_qm31;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use stwo_prover::core::channel::Channel;
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+
+/// A channel for debugging, in which the challenges are popped from a predetermined queue instead of
+/// being derived from the transcript through SHA256.
+///
+/// `Sha256Channel` is the channel from stwo, so this is a separate type rather than a constructor of it.
+pub struct FixedChallengeChannel {
+    /// A dummy state, which is still advanced when absorbing digests and elements.
+    pub digest: BWSSha256Hash,
+    challenges: VecDeque<QM31>,
+}
+
+impl FixedChallengeChannel {
+    /// Create a channel that outputs the given challenges, in order.
+    pub fn with_fixed_challenges(challenges: Vec<QM31>) -> Self {
+        Self {
+            digest: BWSSha256Hash::from(vec![0u8; 32]),
+            challenges: challenges.into(),
+        }
+    }
+
+    /// Absorb a commitment, which only advances the dummy state.
+    pub fn mix_digest(&mut self, digest: BWSSha256Hash) {
+        let mut hasher = Sha256::new();
+        Digest::update(&mut hasher, digest);
+        Digest::update(&mut hasher, self.digest);
+        self.digest = BWSSha256Hash::from(hasher.finalize().to_vec());
+    }
+
+    /// Absorb qm31 elements, which only advances the dummy state.
+    pub fn mix_felts(&mut self, felts: &[QM31]) {
+        for felt in felts.iter() {
+            let mut hasher = Sha256::new();
+            Digest::update(&mut hasher, hash_qm31(felt));
+            Digest::update(&mut hasher, self.digest);
+            self.digest = BWSSha256Hash::from(hasher.finalize().to_vec());
+        }
+    }
+
+    /// Pop the next challenge from the queue.
+    ///
+    /// Panics if all the challenges have been consumed.
+    pub fn draw_felt(&mut self) -> QM31 {
+        self.challenges
+            .pop_front()
+            .expect("the fixed challenges have all been consumed")
+    }
+
+    /// The number of challenges that have not been consumed.
+    pub fn remaining(&self) -> usize {
+        self.challenges.len()
+    }
+}
+
+/// The folding factors are the fixed challenges, while the queries are still drawn from the dummy
+/// state, as `Sha256Channel` draws them, so that a run only differs in the chosen factors.
+impl FriChannel for FixedChallengeChannel {
+    fn absorb_digest(&mut self, digest: BWSSha256Hash) {
+        self.mix_digest(digest);
+    }
+
+    fn absorb_felts(&mut self, felts: &[QM31]) {
+        self.mix_felts(felts);
+    }
+
+    fn draw_challenge(&mut self) -> QM31 {
+        self.draw_felt()
+    }
+
+    fn draw_queries(&mut self, n: usize, logn: usize) -> Vec<usize> {
+        let mut channel = Sha256Channel::new(self.digest);
+        let queries = channel.draw_queries(n, logn);
+        self.digest = channel.digest;
+        queries
+    }
+
+    fn state(&self) -> BWSSha256Hash {
+        self.digest
+    }
+
+    fn set_state(&mut self, state: BWSSha256Hash) {
+        self.digest = state;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::channel::FixedChallengeChannel;
+    use crate::fri::{fri_prove, fri_verify, num_fri_layers, FriError, N_QUERIES};
+    use crate::tests_utils::fixtures::random_qm31;
+    use crate::twiddle_merkle_tree::TWIDDLE_MERKLE_TREE_ROOT_4;
+    use crate::utils::permute_eval;
+    use num_traits::One;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use stwo_prover::core::circle::CirclePointIndex;
+    use stwo_prover::core::fields::m31::M31;
+    use stwo_prover::core::fields::qm31::QM31;
+    use stwo_prover::core::fields::FieldExpOps;
+    use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+
+    fn random_challenges(prng: &mut ChaCha20Rng, n: usize) -> Vec<QM31> {
+        (0..n).map(|_| random_qm31(prng)).collect()
+    }
+
+    #[test]
+    fn test_fixed_challenges_in_order() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let challenges = random_challenges(&mut prng, 5);
+
+        let mut channel = FixedChallengeChannel::with_fixed_challenges(challenges.clone());
+
+        for (i, challenge) in challenges.iter().enumerate() {
+            let state = channel.digest;
+
+            let mut elem = [0u8; 32];
+            elem.iter_mut().for_each(|v| *v = prng.gen());
+            channel.mix_digest(BWSSha256Hash::from(elem.to_vec()));
+            assert_ne!(channel.digest, state);
+
+            assert_eq!(channel.draw_felt(), *challenge);
+            assert_eq!(channel.remaining(), challenges.len() - 1 - i);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_fixed_challenges_underflow() {
+        let mut channel = FixedChallengeChannel::with_fixed_challenges(vec![QM31::from_m31(
+            M31::from(1),
+            M31::from(2),
+            M31::from(3),
+            M31::from(4),
+        )]);
+        channel.draw_felt();
+        channel.draw_felt();
+    }
+
+    #[test]
+    fn test_fri_with_fixed_challenges() {
+        let logn = 5;
+        let n_layers = num_fri_layers(logn);
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let p = CirclePointIndex::subgroup_gen(logn as u32 + 1).to_point();
+        let evaluation = (0..(1 << logn))
+            .map(|i| (p.mul(i * 2 + 1).x.square().square() + M31::one()).into())
+            .collect::<Vec<QM31>>();
+        let evaluation = permute_eval(evaluation);
+
+        let challenges = random_challenges(&mut prng, n_layers);
+
+        let mut channel = FixedChallengeChannel::with_fixed_challenges(challenges.clone());
+        let proof = fri_prove(&mut channel, evaluation, N_QUERIES, 0);
+        assert_eq!(channel.remaining(), 0);
+
+        // the verifier folds with the same factors as the prover
+        let mut channel = FixedChallengeChannel::with_fixed_challenges(challenges);
+        fri_verify(
+            &mut channel,
+            logn,
+            proof.clone(),
+            N_QUERIES,
+            0,
+            TWIDDLE_MERKLE_TREE_ROOT_4,
+        )
+        .unwrap();
+        assert_eq!(channel.remaining(), 0);
+
+        // while the same proof does not fold consistently under other factors
+        let mut channel =
+            FixedChallengeChannel::with_fixed_challenges(random_challenges(&mut prng, n_layers));
+        assert_eq!(
+            fri_verify(
+                &mut channel,
+                logn,
+                proof,
+                N_QUERIES,
+                0,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Err(FriError::FoldMismatch)
+        );
+    }
+}
@@ -3,27 +3,206 @@ use bitcoin::script::PushBytesBuf;
 use sha2::{Digest, Sha256};
 use std::ops::Neg;
 use stwo_prover::core::channel::Channel;
-use stwo_prover::core::fields::m31::M31;
+use stwo_prover::core::fields::m31::{M31, P};
 use stwo_prover::core::fields::qm31::QM31;
 
 mod bitcoin_script;
 use crate::treepp::pushable::{Builder, Pushable};
 pub use bitcoin_script::*;
 
+#[cfg(feature = "debug-channel")]
+mod debug;
+#[cfg(feature = "debug-channel")]
+pub use debug::*;
+
 pub use stwo_prover::core::channel::BWSSha256Channel as Sha256Channel;
 use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
 
+/// The byte order in which a hash is sliced into m31 limbs when drawing from the channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Endianness {
+    /// Each limb is read from four bytes in little-endian.
+    #[default]
+    Le,
+    /// Each limb is read from four bytes in big-endian.
+    Be,
+}
+
+/// A wrapper trait to initialize a channel from the public inputs of the statement.
+pub trait ChannelFromPublicInputs: Channel {
+    /// Start from the all-zero state and absorb the public inputs, as in `mix_felts`, so that the
+    /// whole transcript is bound to the statement.
+    fn init_from_public_inputs(public_inputs: &[QM31]) -> Self;
+}
+
+impl ChannelFromPublicInputs for Sha256Channel {
+    fn init_from_public_inputs(public_inputs: &[QM31]) -> Self {
+        let mut channel = Sha256Channel::new(BWSSha256Hash::from(vec![0u8; 32]));
+        channel.mix_felts(public_inputs);
+        channel
+    }
+}
+
+/// A wrapper trait to fork a channel into independent branches.
+pub trait ChannelFork: Channel {
+    /// Snapshot the channel state as a new channel, which continues from the same transcript
+    /// independently of this one.
+    fn fork(&self) -> Self;
+}
+
+impl ChannelFork for Sha256Channel {
+    fn fork(&self) -> Self {
+        Sha256Channel::new(self.digest)
+    }
+}
+
+/// A wrapper trait to absorb single m31 elements into a channel.
+pub trait ChannelMixM31: Channel {
+    /// Absorb a m31 element, by hashing its fixed 4-byte little-endian encoding and folding the
+    /// hash into the state, as `mix_digest` does.
+    ///
+    /// This is not equivalent to `mix_felts` on the qm31 element with the other limbs set to zero,
+    /// which hashes each of the four limbs in turn.
+    fn mix_m31(&mut self, v: M31);
+}
+
+impl ChannelMixM31 for Sha256Channel {
+    fn mix_m31(&mut self, v: M31) {
+        let mut hasher = Sha256::new();
+        Digest::update(&mut hasher, v.0.to_le_bytes());
+        let hash = hasher.finalize();
+
+        let mut hasher = Sha256::new();
+        Digest::update(&mut hasher, hash);
+        Digest::update(&mut hasher, self.digest);
+        self.digest = BWSSha256Hash::from(hasher.finalize().to_vec());
+    }
+}
+
+/// A wrapper trait to absorb a Merkle cap, i.e., the roots of the subtrees at a given height,
+/// into a channel.
+pub trait ChannelMixCap: Channel {
+    /// Absorb the whole cap, by hashing the concatenation of its entries, cap_0 first, and
+    /// absorbing the hash as `mix_digest` does, so that the transcript binds every entry and
+    /// their order.
+    fn mix_cap(&mut self, cap: &[BWSSha256Hash]);
+}
+
+impl ChannelMixCap for Sha256Channel {
+    fn mix_cap(&mut self, cap: &[BWSSha256Hash]) {
+        let mut hasher = Sha256::new();
+        for entry in cap.iter() {
+            Digest::update(&mut hasher, entry.as_ref());
+        }
+        self.mix_digest(BWSSha256Hash::from(hasher.finalize().to_vec()));
+    }
+}
+
+/// The parameters of a verifier, which a proof is bound to through the verifier key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifierParams {
+    /// The log size of the evaluation domain.
+    pub logn: usize,
+    /// The number of queries.
+    pub n_queries: usize,
+    /// The degree of each constraint, in the order of the constraint coefficients.
+    pub constraint_degrees: Vec<usize>,
+}
+
+impl VerifierParams {
+    /// Serialize the parameters as the verifier key bytes, which are logn, n_queries, the number
+    /// of constraints, and the constraint degrees, each as four bytes in little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for v in [self.logn, self.n_queries, self.constraint_degrees.len()]
+            .iter()
+            .chain(self.constraint_degrees.iter())
+        {
+            bytes.extend_from_slice(&(*v as u32).to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// Compute the verifier key hash, i.e., the sha256 hash of the verifier key bytes, see
+/// `VerifierParams::to_bytes`.
+pub fn verifier_key_hash(params: &VerifierParams) -> BWSSha256Hash {
+    let mut hasher = Sha256::new();
+    Digest::update(&mut hasher, params.to_bytes());
+    BWSSha256Hash::from(hasher.finalize().to_vec())
+}
+
+/// A wrapper trait to bind a channel to the verifier configuration.
+pub trait ChannelMixVerifierKey: Channel {
+    /// Absorb the verifier key hash as `mix_digest` does, so that the transcript, and hence every
+    /// challenge, depends on the exact verifier parameters.
+    fn mix_verifier_key(&mut self, params: &VerifierParams);
+}
+
+impl ChannelMixVerifierKey for Sha256Channel {
+    fn mix_verifier_key(&mut self, params: &VerifierParams) {
+        self.mix_digest(verifier_key_hash(params));
+    }
+}
+
 /// A wrapper trait to implement hint-related method for channels.
 pub trait ChannelWithHint: Channel {
+    /// Draw raw m31 elements, with the hash sliced into limbs in the given byte order.
+    fn draw_m31_and_hints_with_endianness<const N: usize>(
+        &mut self,
+        endianness: Endianness,
+    ) -> ([M31; N], DrawHints<N>);
+
     /// Draw raw m31 elements.
-    fn draw_m31_and_hints<const N: usize>(&mut self) -> ([M31; N], DrawHints<N>);
+    fn draw_m31_and_hints<const N: usize>(&mut self) -> ([M31; N], DrawHints<N>) {
+        self.draw_m31_and_hints_with_endianness::<N>(Endianness::default())
+    }
 
     /// Draw one qm31 and compute the hints.
     fn draw_felt_and_hints(&mut self) -> (QM31, DrawHints<4>) {
-        let res = self.draw_m31_and_hints::<4>();
+        self.draw_felt_and_hints_with_endianness(Endianness::default())
+    }
+
+    /// Draw one qm31 and compute the hints, with the hash sliced into limbs in the given byte order.
+    fn draw_felt_and_hints_with_endianness(
+        &mut self,
+        endianness: Endianness,
+    ) -> (QM31, DrawHints<4>) {
+        let res = self.draw_m31_and_hints_with_endianness::<4>(endianness);
         (QM31::from_m31_array(res.0), res.1)
     }
 
+    /// Draw n qm31 challenges one after another, such as the challenges (gamma, beta, ...) of a
+    /// permutation argument, and compute the hints, one for each challenge.
+    fn draw_challenge_vector(&mut self, n: usize) -> (Vec<QM31>, Vec<DrawQM31Hints>) {
+        (0..n).map(|_| self.draw_felt_and_hints()).unzip()
+    }
+
+    /// Draw one qm31 without the reduction bias and compute the hints, one for each draw.
+    ///
+    /// Instead of reducing the limbs, a draw with a limb equal to 2^31-1 is rejected and the
+    /// channel draws again. Panics if two draws in a row are rejected, which happens with a
+    /// probability of about 2^-58, as the script only supports one redraw.
+    fn draw_felt_unbiased_and_hints(&mut self) -> (QM31, Vec<DrawQM31Hints>) {
+        let mut hints = vec![];
+        loop {
+            let (_, hint) = self.draw_m31_and_hints::<4>();
+            let limbs = hint.0.map(|v| match v {
+                BitcoinIntegerEncodedData::NegativeZero => 0,
+                BitcoinIntegerEncodedData::Other(v) => v.unsigned_abs() as u32,
+            });
+            hints.push(hint);
+
+            if limbs.iter().all(|&v| v != P) {
+                return (
+                    QM31::from_m31_array(limbs.map(M31::from_u32_unchecked)),
+                    hints,
+                );
+            }
+            assert!(hints.len() < 2, "two draws in a row are rejected");
+        }
+    }
+
     /// Draw five queries and compute the hints.
     fn draw_5queries(&mut self, logn: usize) -> ([usize; 5], DrawHints<5>) {
         let res = self.draw_m31_and_hints::<5>();
@@ -35,10 +214,50 @@ pub trait ChannelWithHint: Channel {
 
         (trimmed_results, res.1)
     }
+
+    /// Draw n queries and compute the hints, one for each hash that is squeezed.
+    ///
+    /// Each hash gives 8 m31 elements, and those of the last hash beyond the n-th query are
+    /// discarded, so the first five queries and the channel state after them are the same as in
+    /// `draw_5queries`.
+    fn draw_n_queries(&mut self, n: usize, logn: usize) -> (Vec<usize>, Vec<DrawHints<8>>) {
+        let mut queries = Vec::with_capacity(n);
+        let mut hints = Vec::with_capacity(n.div_ceil(8));
+
+        while queries.len() < n {
+            let (res, hint) = self.draw_m31_and_hints::<8>();
+            let remaining = n - queries.len();
+            queries.extend(
+                res.iter()
+                    .take(remaining)
+                    .map(|v| trim_m31(v.0, logn) as usize),
+            );
+            hints.push(hint);
+        }
+
+        (queries, hints)
+    }
+
+    /// Draw n queries of logn bits, as in `draw_n_queries`, and map them into the subdomain of
+    /// size 2^logn that starts at position `coset_offset` of a larger evaluation domain, by adding
+    /// the offset, see `draw_subdomain_queries_gadget`.
+    fn draw_subdomain_queries(
+        &mut self,
+        n: usize,
+        logn: usize,
+        coset_offset: usize,
+    ) -> (Vec<usize>, Vec<DrawHints<8>>) {
+        assert_eq!(coset_offset % (1 << logn), 0);
+        let (queries, hints) = self.draw_n_queries(n, logn);
+        (queries.iter().map(|q| q + coset_offset).collect(), hints)
+    }
 }
 
 impl ChannelWithHint for Sha256Channel {
-    fn draw_m31_and_hints<const N: usize>(&mut self) -> ([M31; N], DrawHints<N>) {
+    fn draw_m31_and_hints_with_endianness<const N: usize>(
+        &mut self,
+        endianness: Endianness,
+    ) -> ([M31; N], DrawHints<N>) {
         let mut extract = vec![];
         let mut count = 0;
 
@@ -55,17 +274,75 @@ impl ChannelWithHint for Sha256Channel {
             count += 8;
         }
 
-        generate_hints(&extract)
+        generate_hints_with_endianness(&extract, endianness)
+    }
+}
+
+/// The channel operations that the FRI prover and verifier use, so that they can run on a channel
+/// other than `Sha256Channel`, such as the `FixedChallengeChannel` for debugging.
+///
+/// The methods are named apart from those of `Channel`, so that calls on `Sha256Channel` stay
+/// unambiguous where both traits are in scope.
+pub trait FriChannel {
+    /// Absorb the commitment of a layer.
+    fn absorb_digest(&mut self, digest: BWSSha256Hash);
+
+    /// Absorb the elements of the last layer.
+    fn absorb_felts(&mut self, felts: &[QM31]);
+
+    /// Draw a folding factor.
+    fn draw_challenge(&mut self) -> QM31;
+
+    /// Draw `n` queries of `logn` bits.
+    fn draw_queries(&mut self, n: usize, logn: usize) -> Vec<usize>;
+
+    /// The state of the channel, which the proof-of-work is ground on.
+    fn state(&self) -> BWSSha256Hash;
+
+    /// Replace the state of the channel, as the proof-of-work does.
+    fn set_state(&mut self, state: BWSSha256Hash);
+}
+
+impl FriChannel for Sha256Channel {
+    fn absorb_digest(&mut self, digest: BWSSha256Hash) {
+        self.mix_digest(digest);
+    }
+
+    fn absorb_felts(&mut self, felts: &[QM31]) {
+        self.mix_felts(felts);
+    }
+
+    fn draw_challenge(&mut self) -> QM31 {
+        self.draw_felt_and_hints().0
+    }
+
+    fn draw_queries(&mut self, n: usize, logn: usize) -> Vec<usize> {
+        self.draw_n_queries(n, logn).0
+    }
+
+    fn state(&self) -> BWSSha256Hash {
+        self.digest
+    }
+
+    fn set_state(&mut self, state: BWSSha256Hash) {
+        self.digest = state;
     }
 }
 
 fn generate_hints<const N: usize>(extract: &[u8]) -> ([M31; N], DrawHints<N>) {
+    generate_hints_with_endianness(extract, Endianness::default())
+}
+
+fn generate_hints_with_endianness<const N: usize>(
+    extract: &[u8],
+    endianness: Endianness,
+) -> ([M31; N], DrawHints<N>) {
     let mut res_m31 = [M31::default(); N];
     let mut res_hints = DrawHints::<N>::default();
 
     for i in 0..N {
-        let res = u32::from_le_bytes(<[u8; 4]>::try_from(&extract[i * 4..(i + 1) * 4]).unwrap())
-            & 0x7fffffff;
+        let bytes = <[u8; 4]>::try_from(&extract[i * 4..(i + 1) * 4]).unwrap();
+        let res = u32::from_le_bytes(bytes) & 0x7fffffff;
 
         res_hints.0[i] = if extract[(i + 1) * 4 - 1] & 0x80 != 0 {
             if res == 0 {
@@ -77,6 +354,10 @@ fn generate_hints<const N: usize>(extract: &[u8]) -> ([M31; N], DrawHints<N>) {
             BitcoinIntegerEncodedData::Other(res as i64)
         };
 
+        let res = match endianness {
+            Endianness::Le => res,
+            Endianness::Be => u32::from_be_bytes(bytes) & 0x7fffffff,
+        };
         res_m31[i] = M31::from(res.saturating_sub(1));
     }
 
@@ -96,6 +377,20 @@ pub enum BitcoinIntegerEncodedData {
     Other(i64),
 }
 
+impl BitcoinIntegerEncodedData {
+    /// The four bytes of the hash that the Bitcoin integer stands for, which are the absolute value
+    /// in little-endian with the sign in the highest bit.
+    pub fn to_le_bytes(&self) -> [u8; 4] {
+        match self {
+            BitcoinIntegerEncodedData::NegativeZero => [0, 0, 0, 0x80],
+            BitcoinIntegerEncodedData::Other(v) => {
+                let sign = if *v < 0 { 0x80000000 } else { 0 };
+                (v.unsigned_abs() as u32 | sign).to_le_bytes()
+            }
+        }
+    }
+}
+
 impl Default for BitcoinIntegerEncodedData {
     fn default() -> Self {
         Self::Other(0)
@@ -114,7 +409,9 @@ impl Pushable for BitcoinIntegerEncodedData {
 }
 
 #[derive(Clone)]
-/// Hints for drawing m31 elements.
+/// Hints for drawing m31 elements, which are the four-byte chunks of the hash as Bitcoin integers
+/// (read in little-endian, whatever the byte order of the limbs), and the remaining bytes of the
+/// hash.
 pub struct DrawHints<const N: usize>(pub [BitcoinIntegerEncodedData; N], pub Vec<u8>);
 
 impl<const N: usize> Default for DrawHints<N> {
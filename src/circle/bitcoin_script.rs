@@ -1,8 +1,12 @@
+use crate::circle::CircleDomain;
 use crate::treepp::*;
+use crate::utils::push_qm31_one;
 use rust_bitcoin_m31::{
-    push_qm31_one, qm31_add, qm31_copy, qm31_double, qm31_equalverify, qm31_fromaltstack, qm31_mul,
-    qm31_roll, qm31_square, qm31_sub, qm31_swap, qm31_toaltstack,
+    m31_mul, m31_sub, qm31_add, qm31_copy, qm31_double, qm31_equalverify, qm31_fromaltstack,
+    qm31_mul, qm31_mul_m31, qm31_over, qm31_roll, qm31_square, qm31_sub, qm31_swap,
+    qm31_toaltstack,
 };
+use stwo_prover::core::fields::m31::P;
 
 /// Gadget for points on the circle curve in the qm31 field.
 pub struct CirclePointGadget;
@@ -77,21 +81,98 @@ impl CirclePointGadget {
     }
 }
 
+/// Shift a point by the generator of the subgroup of size 2^logn, which moves it to the next row
+/// of a trace over a domain of that size.
+///
+/// The generator (a, b) is a constant embedded in the script, and the point is rotated by:
+///   x' = x * a - y * b
+///   y' = x * b + y * a
+///
+/// input:
+///  x (QM31)
+///  y (QM31)
+///
+/// output:
+///  x' (QM31)
+///  y' (QM31)
+pub fn coset_shift_gadget(logn: u32) -> Script {
+    let step = CircleDomain::new(logn).generator();
+
+    script! {
+        qm31_over
+        { step.y }
+        qm31_mul_m31
+        qm31_over
+        { step.x }
+        qm31_mul_m31
+        qm31_add
+        qm31_toaltstack
+        { step.y }
+        qm31_mul_m31
+        qm31_swap
+        { step.x }
+        qm31_mul_m31
+        qm31_swap
+        qm31_sub
+        qm31_fromaltstack
+    }
+}
+
+/// Compute the y-coordinate of a point on the circle over m31 from its x-coordinate, with the
+/// root as a hint, which is checked to satisfy y^2 = 1 - x^2, and a sign bit, which selects the
+/// root larger than (P - 1) / 2, as in `y_from_x`.
+///
+/// If 1 - x^2 is not a square, or if x = 1 or -1 and the sign bit is set, no hint passes the check,
+/// so the execution fails.
+///
+/// hint:
+///  y (m31)
+///
+/// input:
+///  x (m31)
+///  sign (0 or 1)
+///
+/// output:
+///  y (m31)
+pub fn y_from_x_gadget() -> Script {
+    script! {
+        OP_TOALTSTACK
+
+        // compute 1 - x^2
+        OP_DUP m31_mul
+        OP_1 OP_SWAP m31_sub
+
+        // pull the hint y, which must be reduced, and check that y^2 = 1 - x^2
+        OP_DEPTH OP_1SUB OP_ROLL
+        OP_DUP 0 { P } OP_WITHIN OP_VERIFY
+        OP_DUP OP_DUP m31_mul
+        OP_ROT OP_EQUALVERIFY
+
+        // check the sign
+        OP_DUP { (P - 1) / 2 } OP_GREATERTHAN
+        OP_FROMALTSTACK OP_NUMEQUALVERIFY
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use num_traits::One;
+    use crate::tests_utils::fixtures::random_qm31;
+    use num_traits::{One, Zero};
     use std::ops::{Add, Neg};
-    use stwo_prover::core::circle::CirclePoint;
+    use stwo_prover::core::circle::{CirclePoint, CirclePointIndex};
 
     use crate::{tests_utils::report::report_bitcoin_script_size, treepp::*};
     use rand::{RngCore, SeedableRng};
     use rand_chacha::ChaCha20Rng;
     use rust_bitcoin_m31::qm31_equalverify;
-    use stwo_prover::core::fields::m31::M31;
+    use stwo_prover::core::fields::m31::{self, M31};
     use stwo_prover::core::fields::qm31::QM31;
     use stwo_prover::core::fields::{Field, FieldExpOps};
 
-    use crate::circle::CirclePointGadget;
+    use crate::circle::{
+        coset_shift_gadget, y_from_x, y_from_x_gadget, CircleDomain, CirclePointGadget,
+    };
+    use stwo_prover::core::poly::circle::CanonicCoset;
 
     #[test]
     fn test_add() {
@@ -105,33 +186,13 @@ mod test {
 
         for _ in 0..100 {
             let a = CirclePoint {
-                x: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
-                y: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
             };
 
             let b = CirclePoint {
-                x: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
-                y: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
             };
             let c = a + b;
 
@@ -173,12 +234,7 @@ mod test {
         for seed in 0..20 {
             let mut prng = ChaCha20Rng::seed_from_u64(seed);
 
-            let a = QM31::from_m31(
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-            );
+            let a = random_qm31(&mut prng);
             let double_a = a.square().double().add(QM31::one().neg());
 
             let script = script! {
@@ -192,4 +248,165 @@ mod test {
             assert!(exec_result.success);
         }
     }
+
+    #[test]
+    fn test_coset_shift() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        for logn in 1..=5 {
+            let coset_shift_script = coset_shift_gadget(logn);
+            report_bitcoin_script_size(
+                "CirclePoint",
+                format!("coset_shift({})", logn).as_str(),
+                coset_shift_script.len(),
+            );
+
+            let a = CirclePoint {
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
+            };
+
+            let step = CirclePointIndex::subgroup_gen(logn).to_point();
+            let b = a + CirclePoint {
+                x: QM31::from(step.x),
+                y: QM31::from(step.y),
+            };
+
+            let script = script! {
+                { a.x }
+                { a.y }
+                { coset_shift_script.clone() }
+                { b.x }
+                { b.y }
+                { CirclePointGadget::equalverify() }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+
+            let script = script! {
+                { a.x }
+                { a.y }
+                for _ in 0..(1 << logn) {
+                    { coset_shift_script.clone() }
+                }
+                { a.x }
+                { a.y }
+                { CirclePointGadget::equalverify() }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_y_from_x() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let y_from_x_script = y_from_x_gadget();
+        report_bitcoin_script_size("CirclePoint", "y_from_x", y_from_x_script.len());
+
+        // the points of a subgroup are on the circle
+        let log_size = 5;
+        let step = CirclePointIndex::subgroup_gen(log_size).to_point();
+        let mut p = step;
+        for _ in 0..(1 << log_size) {
+            let sign = p.y.0 > (m31::P - 1) / 2;
+            assert_eq!(y_from_x(p.x, sign), Some(p.y));
+
+            let script = script! {
+                { p.y }
+                { p.x }
+                { sign as u32 }
+                { y_from_x_script.clone() }
+                { p.y }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+
+            // the other root does not match the sign
+            let script = script! {
+                { -p.y }
+                { p.x }
+                { sign as u32 }
+                { y_from_x_script.clone() }
+                OP_DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, p.y.0 == 0);
+
+            p = p + step;
+        }
+
+        // for x = 1 and x = -1, the only root is 0, which goes with the sign bit unset
+        for x in [M31::one(), -M31::one()] {
+            assert_eq!(y_from_x(x, false), Some(M31::zero()));
+            assert_eq!(y_from_x(x, true), None);
+
+            for sign in [false, true] {
+                let script = script! {
+                    { M31::zero() }
+                    { x }
+                    { sign as u32 }
+                    { y_from_x_script.clone() }
+                    OP_DROP
+                    OP_TRUE
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, !sign);
+            }
+        }
+
+        // an x for which 1 - x^2 is not a square is not on the circle
+        let mut n_invalid = 0;
+        while n_invalid < 10 {
+            let x = M31::reduce(prng.next_u64());
+            if y_from_x(x, false).is_some() {
+                continue;
+            }
+            n_invalid += 1;
+
+            let y = M31::reduce(prng.next_u64());
+            let script = script! {
+                { y }
+                { x }
+                { (y.0 > (m31::P - 1) / 2) as u32 }
+                { y_from_x_script.clone() }
+                OP_DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_circle_domain() {
+        let log_size = 6;
+
+        let domain = CircleDomain::new(log_size);
+        let expected = CanonicCoset::new(log_size).circle_domain();
+
+        assert_eq!(domain.size(), expected.size());
+        assert_eq!(domain.initial(), expected.half_coset.initial);
+        assert_eq!(domain.half_coset_step(), expected.half_coset.step);
+        assert_eq!(domain.generator(), CanonicCoset::new(log_size).coset().step);
+
+        for (i, p) in expected.iter().enumerate() {
+            assert_eq!(domain.point_at(i), p);
+
+            let script = script! {
+                { domain.push_point_at(i) }
+                { p.y }
+                OP_EQUALVERIFY
+                { p.x }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
 }
@@ -1,2 +1,104 @@
 mod bitcoin_script;
 pub use bitcoin_script::*;
+
+use crate::treepp::*;
+use num_traits::One;
+use stwo_prover::core::circle::{CirclePoint, CirclePointIndex, Coset};
+use stwo_prover::core::fields::m31::{M31, P};
+use stwo_prover::core::fields::FieldExpOps;
+
+/// The canonic circle domain of size 2^log_size, as in stwo's
+/// `CanonicCoset::new(log_size).circle_domain()`, which is the half coset
+///   G_{log_size + 1} + <G_{log_size - 1}>
+/// followed by its conjugate, where G_k is the generator of the subgroup of size 2^k.
+///
+/// This is where the domain parameters used by the FRI twiddles and the gadgets are derived.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircleDomain {
+    /// The log of the size of the domain.
+    pub log_size: u32,
+}
+
+impl CircleDomain {
+    /// Create the canonic circle domain of size 2^log_size.
+    pub fn new(log_size: u32) -> Self {
+        assert!(log_size >= 1);
+        Self { log_size }
+    }
+
+    /// The size of the domain.
+    pub fn size(&self) -> usize {
+        1 << self.log_size
+    }
+
+    /// The generator of the subgroup of size 2^log_size, by which the domain is shifted onto
+    /// itself.
+    pub fn generator(&self) -> CirclePoint<M31> {
+        CirclePointIndex::subgroup_gen(self.log_size).to_point()
+    }
+
+    /// The first point of the half coset, G_{log_size + 1}.
+    pub fn initial(&self) -> CirclePoint<M31> {
+        CirclePointIndex::subgroup_gen(self.log_size + 1).to_point()
+    }
+
+    /// The step between consecutive points of the half coset, G_{log_size - 1}.
+    pub fn half_coset_step(&self) -> CirclePoint<M31> {
+        CirclePointIndex::subgroup_gen(self.log_size - 1).to_point()
+    }
+
+    /// The half coset, of size 2^(log_size - 1).
+    pub fn half_coset(&self) -> Coset {
+        Coset::half_odds(self.log_size - 1)
+    }
+
+    /// The point at the given index, where the first half of the indices are the points of the
+    /// half coset, and the second half are their conjugates, in the same order.
+    pub fn point_at(&self, index: usize) -> CirclePoint<M31> {
+        assert!(index < self.size());
+        let half_size = self.size() / 2;
+        if index < half_size {
+            self.initial() + self.half_coset_step().mul(index as u128)
+        } else {
+            -(self.initial() + self.half_coset_step().mul((index - half_size) as u128))
+        }
+    }
+
+    /// Push the point at the given index as script constants.
+    ///
+    /// output:
+    ///  x (m31)
+    ///  y (m31)
+    pub fn push_point_at(&self, index: usize) -> Script {
+        let p = self.point_at(index);
+        script! {
+            { p.x }
+            { p.y }
+        }
+    }
+}
+
+/// Recover the y-coordinate of a point on the circle over m31 from its x-coordinate, where `sign`
+/// selects the root larger than (P - 1) / 2, as checked by `y_from_x_gadget`.
+///
+/// Returns `None` if 1 - x^2 is not a square, i.e., x is not the x-coordinate of any point, or if
+/// x = 1 or -1 and `sign` is set, since the only root, 0, is not larger than (P - 1) / 2.
+pub fn y_from_x(x: M31, sign: bool) -> Option<M31> {
+    let y2 = M31::one() - x.square();
+
+    // since P = 3 mod 4, a square root of a square a is a^((P + 1) / 4)
+    let y = y2.pow((P as u128 + 1) / 4);
+    if y.square() != y2 {
+        return None;
+    }
+
+    if y.0 == 0 && sign {
+        return None;
+    }
+
+    if (y.0 > (P - 1) / 2) == sign {
+        Some(y)
+    } else {
+        Some(-y)
+    }
+}
@@ -1,8 +1,22 @@
-use crate::{circle::CirclePointGadget, treepp::*};
-use rust_bitcoin_m31::{qm31_add, qm31_mul, qm31_swap};
+use crate::{
+    channel::{DrawQM31Hints, Sha256ChannelGadget},
+    circle::CirclePointGadget,
+    constraints::ConstraintCoeffsMode,
+    treepp::*,
+    utils::{
+        hash_felt_gadget, hash_qm31, push_qm31_constant, push_qm31_one, qm31_horner_gadget,
+        qm31_is_one_verify, qm31_is_zero_verify,
+    },
+};
+use num_traits::{One, Zero};
+use rust_bitcoin_m31::{
+    m31_add, m31_sub, qm31_add, qm31_copy, qm31_double, qm31_dup, qm31_equalverify,
+    qm31_from_bottom, qm31_fromaltstack, qm31_mul, qm31_mul_m31, qm31_neg, qm31_over, qm31_roll,
+    qm31_square, qm31_sub, qm31_swap, qm31_toaltstack,
+};
 use stwo_prover::core::{
     circle::{CirclePoint, Coset},
-    fields::qm31::QM31,
+    fields::{m31::M31, qm31::QM31, FieldExpOps},
 };
 
 /// Gadget for constraints over the circle curve
@@ -24,8 +38,8 @@ impl ConstraintsGadget {
             -coset.initial.into_ef::<QM31>() + coset.step_size.half().to_point().into_ef::<QM31>();
 
         script! {
-            { shift.x }
-            { shift.y }
+            { push_qm31_constant(shift.x) }
+            { push_qm31_constant(shift.y) }
             { CirclePointGadget::add_x_only() }
             for _ in 1..coset.log_size {
                 { CirclePointGadget::double_x() }
@@ -43,15 +57,15 @@ impl ConstraintsGadget {
     ///  P(z)
     pub fn pair_vanishing(excluded0: CirclePoint<QM31>, excluded1: CirclePoint<QM31>) -> Script {
         script! {
-            { excluded1.x - excluded0.x }
+            { push_qm31_constant(excluded1.x - excluded0.x) }
             qm31_mul    //(excluded1.x - excluded0.x) * z.y
 
             qm31_swap
-            { excluded0.y - excluded1.y }
+            { push_qm31_constant(excluded0.y - excluded1.y) }
             qm31_mul    //(excluded0.y - excluded1.y) * z.x
 
             qm31_add
-            { excluded0.x * excluded1.y - excluded0.y * excluded1.x }
+            { push_qm31_constant(excluded0.x * excluded1.y - excluded0.y * excluded1.x) }
             qm31_add
             //(excluded0.y - excluded1.y) * z.x
             //    + (excluded1.x - excluded0.x) * z.y
@@ -60,19 +74,744 @@ impl ConstraintsGadget {
     }
 }
 
+/// Evaluate the derivative of the vanishing polynomial of the canonic circle domain of size 2^logn
+/// with respect to z.x, as in `vanishing_derivative`.
+///
+/// input:
+///  z.x (QM31)
+///  z.y (QM31)
+///
+/// output:
+///  V'(z) (QM31)
+pub fn vanishing_derivative_gadget(logn: u32) -> Script {
+    assert!(logn >= 1);
+
+    script! {
+        // drop z.y, on which V does not depend
+        OP_2DROP OP_2DROP
+
+        if logn == 1 {
+            OP_2DROP OP_2DROP
+            push_qm31_one
+        } else {
+            // stack: 4 x_0, x_0
+            qm31_dup
+            qm31_double
+            qm31_double
+            qm31_swap
+
+            for _ in 2..logn {
+                // x_j = 2 x_{j - 1}^2 - 1
+                qm31_square
+                qm31_double
+                push_qm31_one
+                qm31_sub
+
+                qm31_dup
+                qm31_double
+                qm31_double
+                { qm31_roll(2) }
+                qm31_mul
+                qm31_swap
+            }
+
+            OP_2DROP OP_2DROP
+        }
+    }
+}
+
+/// Push the hints for `verify_composition_at_oods_gadget`, which are the quotients of the
+/// constraint evaluations by the vanishing evaluations.
+pub fn push_composition_at_oods_hint(constraints: &[QM31], vanishings: &[QM31]) -> Script {
+    assert_eq!(constraints.len(), vanishings.len());
+    script! {
+        for (constraint, vanishing) in constraints.iter().zip(vanishings.iter()) {
+            { *constraint * vanishing.inverse() }
+        }
+    }
+}
+
+/// Check the composition polynomial evaluation at the OODS point against the constraint
+/// evaluations at the same point, i.e.,
+///   composition == sum(coeff_i * constraint_i / vanishing_i)
+///
+/// hint:
+///  constraint_i / vanishing_i for i = 0, ..., num_constraints - 1
+///
+/// input:
+///  composition (QM31)
+///  coeff_{num_constraints - 1}, constraint_{num_constraints - 1}, vanishing_{num_constraints - 1} (QM31)
+///  ...
+///  coeff_0, constraint_0, vanishing_0 (QM31)
+///
+/// output:
+///  none
+/// mark the transaction as invalid if the check fails
+pub fn verify_composition_at_oods_gadget(num_constraints: usize) -> Script {
+    assert!(num_constraints > 0);
+    script! {
+        for i in 0..num_constraints {
+            qm31_from_bottom // constraint / vanishing
+            qm31_dup
+            qm31_toaltstack
+
+            qm31_mul
+            qm31_equalverify // check that constraint == (constraint / vanishing) * vanishing
+
+            qm31_fromaltstack
+            qm31_mul // coeff * (constraint / vanishing)
+
+            if i > 0 {
+                qm31_fromaltstack
+                qm31_add
+            }
+            qm31_toaltstack
+        }
+
+        qm31_fromaltstack
+        qm31_equalverify
+    }
+}
+
+/// Open a polynomial committed by `commit_poly_coefficients` by revealing all its coefficients,
+/// which are hashed into the Merkle tree again to check the root, and evaluate it at x with
+/// `qm31_horner_gadget`.
+///
+/// input:
+///  root
+///  x (QM31)
+///  c_0 (QM31)
+///  ...
+///  c_degree (QM31)
+///
+/// output:
+///  c_0 + c_1 * x + ... + c_degree * x^degree (QM31)
+pub fn verify_poly_commitment_gadget(degree: usize) -> Script {
+    let n = degree + 1;
+    let num_leaves = n.next_power_of_two().max(2);
+    let zero_leaf_hash = hash_qm31(&QM31::zero()).to_vec();
+
+    script! {
+        // copy the coefficients and hash them, with the hash of c_0 ending on the top of the
+        // altstack
+        for _ in 0..n {
+            { qm31_copy(degree) }
+        }
+        for _ in 0..n {
+            hash_felt_gadget
+            OP_TOALTSTACK
+        }
+
+        // the leaves, with the last one on the top
+        for _ in 0..n {
+            OP_FROMALTSTACK
+        }
+        for _ in n..num_leaves {
+            { zero_leaf_hash.clone() }
+        }
+
+        // hash each layer into the next one, which keeps the last node on the top
+        for layer in 1..=num_leaves.ilog2() {
+            for _ in 0..(num_leaves >> layer) {
+                OP_CAT OP_SHA256
+                OP_TOALTSTACK
+            }
+            for _ in 0..(num_leaves >> layer) {
+                OP_FROMALTSTACK
+            }
+        }
+
+        { 4 * n + 5 } OP_ROLL
+        OP_EQUALVERIFY
+
+        { qm31_horner_gadget(degree) }
+    }
+}
+
+/// Run one round of a sum-check reduction, as in `sumcheck_round`: check that the evaluations of
+/// the linear round polynomial p at 0 and 1 sum to the claim, and compute the next claim p(r).
+///
+/// input:
+///  claim (QM31)
+///  p(0) (QM31)
+///  p(1) (QM31)
+///  r (QM31)
+///
+/// output:
+///  p(r) (QM31)
+/// mark the transaction as invalid if p(0) + p(1) is not the claim
+pub fn sumcheck_round_gadget() -> Script {
+    script! {
+        qm31_toaltstack
+
+        // check p(0) + p(1) == claim
+        qm31_over
+        qm31_over
+        qm31_add
+        { qm31_roll(3) }
+        qm31_equalverify
+
+        // p(r) = p(0) + r * (p(1) - p(0))
+        qm31_over
+        qm31_sub
+        qm31_fromaltstack
+        qm31_mul
+        qm31_add
+    }
+}
+
+/// Recombine the evaluations at z of the parts of a split composition polynomial, as in
+/// `recombine_composition`, i.e., sum_i z^(i * part_degree) * comp_i(z), where z^part_degree is
+/// computed by square-and-multiply and the sum with `qm31_horner_gadget`.
+///
+/// input:
+///  z (QM31)
+///  comp_0(z), ..., comp_{num_parts - 1}(z) (QM31)
+///
+/// output:
+///  comp(z) (QM31)
+pub fn recombine_composition_gadget(num_parts: usize, part_degree: usize) -> Script {
+    assert!(num_parts > 0);
+    assert!(part_degree > 0);
+
+    let num_bits = usize::BITS - part_degree.leading_zeros();
+    script! {
+        // z^part_degree, from the highest bit
+        { qm31_roll(num_parts) }
+        qm31_dup
+        for i in (0..num_bits - 1).rev() {
+            qm31_square
+            if (part_degree >> i) & 1 == 1 {
+                qm31_over
+                qm31_mul
+            }
+        }
+        qm31_swap
+        OP_2DROP OP_2DROP
+
+        // move z^part_degree below the parts
+        for _ in 0..num_parts {
+            { qm31_roll(num_parts) }
+        }
+
+        { qm31_horner_gadget(num_parts - 1) }
+    }
+}
+
+/// Check that two trace columns agree at the OODS point, i.e., f(z) == g(z), which is the
+/// building block of copy constraints, since two polynomials of low degree that agree at a random
+/// point are equal except with a negligible probability.
+///
+/// input:
+///  f(z) (QM31)
+///  g(z) (QM31)
+///
+/// output:
+///  none
+/// mark the transaction as invalid if the check fails
+pub fn assert_columns_equal_at_point_gadget() -> Script {
+    script! {
+        qm31_equalverify
+    }
+}
+
+/// Check that the columns g_0, ..., g_{n-1} are the columns f_0, ..., f_{n-1} under the index
+/// map, i.e., f_i(z) == g_{map[i]}(z) for all i, at the OODS point.
+///
+/// input:
+///  f_0(z), ..., f_{n-1}(z) (QM31)
+///  g_0(z), ..., g_{n-1}(z) (QM31)
+///
+/// output:
+///  none
+/// mark the transaction as invalid if the check fails
+pub fn assert_columns_permuted_at_point_gadget(map: &[usize]) -> Script {
+    let n = map.len();
+    assert!(map.iter().all(|&j| j < n));
+
+    script! {
+        for (i, &j) in map.iter().enumerate() {
+            { qm31_copy(2 * n - 1 - i) }
+            { qm31_copy(n - j) }
+            { assert_columns_equal_at_point_gadget() }
+        }
+        for _ in 0..2 * n {
+            OP_2DROP OP_2DROP
+        }
+    }
+}
+
+/// Compute the booleanity constraint of a selector at the OODS point, as in `selector_constraint`,
+/// to be checked by `verify_composition_at_oods_gadget` with the vanishing polynomial of the trace
+/// domain, which enforces that the selector is boolean on every row.
+///
+/// input:
+///  s(z) (QM31)
+///
+/// output:
+///  s(z)^2 - s(z) (QM31)
+pub fn verify_selector_gadget() -> Script {
+    script! {
+        qm31_dup
+        qm31_square
+        qm31_swap
+        qm31_sub
+    }
+}
+
+/// Gate a constraint value at the OODS point by a selector, as in `gate_by_selector`.
+///
+/// input:
+///  value (QM31)
+///  s(z) (QM31)
+///
+/// output:
+///  value * s(z) (QM31)
+pub fn gate_by_selector_gadget() -> Script {
+    script! {
+        qm31_mul
+    }
+}
+
+/// Push the hints for `draw_constraint_coeffs_gadget`, which are the hints of the draws returned by
+/// `draw_constraint_coeffs`.
+pub fn push_draw_constraint_coeffs_hint(hints: &[DrawQM31Hints]) -> Script {
+    script! {
+        for hint in hints.iter() {
+            { Sha256ChannelGadget::push_draw_hint(hint) }
+        }
+    }
+}
+
+/// Draw the random coefficients of the constraints, as in `draw_constraint_coeffs`.
+///
+/// hint:
+///  the hints of the draws (one for `PowersOfAlpha`, num_constraints for `Independent`)
+///
+/// input:
+///  channel
+///
+/// output:
+///  channel'
+///
+/// altstack output:
+///  coeff_0, ..., coeff_{num_constraints - 1} (QM31 -- coeff_{num_constraints - 1} on top)
+pub fn draw_constraint_coeffs_gadget(num_constraints: usize, mode: ConstraintCoeffsMode) -> Script {
+    assert!(num_constraints > 0);
+    match mode {
+        ConstraintCoeffsMode::PowersOfAlpha => script! {
+            { Sha256ChannelGadget::draw_felt_with_hint() }
+            { push_qm31_constant(QM31::one()) }
+            qm31_toaltstack
+
+            qm31_dup
+            for i in 1..num_constraints {
+                if i > 1 {
+                    qm31_over
+                    qm31_mul
+                }
+                qm31_dup
+                qm31_toaltstack
+            }
+            OP_2DROP OP_2DROP OP_2DROP OP_2DROP
+        },
+        ConstraintCoeffsMode::Independent => script! {
+            for _ in 0..num_constraints {
+                { Sha256ChannelGadget::draw_felt_with_hint() }
+                qm31_toaltstack
+            }
+        },
+    }
+}
+
+/// Push the hint for `batch_denominator_inverse_gadget`, which is the inverse of the product of
+/// all the denominators.
+pub fn push_batch_denominator_inverse_hint(denominators: &[QM31]) -> Script {
+    script! {
+        { denominators.iter().fold(QM31::one(), |acc, d| acc * *d).inverse() }
+    }
+}
+
+/// Compute the inverses of the constraint denominators with Montgomery's batch inversion, which
+/// only needs the inverse of their product as a hint.
+///
+/// hint:
+///  (d_0 * ... * d_{num_constraints - 1})^{-1} (QM31)
+///
+/// input:
+///  d_0, ..., d_{num_constraints - 1} (QM31)
+///
+/// output:
+///  d_0^{-1}, ..., d_{num_constraints - 1}^{-1} (QM31)
+pub fn batch_denominator_inverse_gadget(num_constraints: usize) -> Script {
+    assert!(num_constraints > 0);
+    let n = num_constraints;
+    script! {
+        // compute the prefix products p_i = d_0 * ... * d_i
+        { qm31_copy(n - 1) }
+        for _ in 1..n {
+            { qm31_copy(n - 1) }
+            { qm31_copy(1) }
+            qm31_mul
+        }
+
+        // check the hint against p_{n - 1}, which makes it the running inverse
+        qm31_from_bottom
+        { qm31_copy(1) }
+        { qm31_copy(1) }
+        qm31_mul
+        qm31_is_one_verify
+        qm31_swap
+        OP_2DROP OP_2DROP
+
+        // stack: d_0, ..., d_i, p_0, ..., p_{i - 1}, (d_0 * ... * d_i)^{-1}
+        for i in (1..n).rev() {
+            qm31_swap
+            { qm31_copy(1) }
+            qm31_mul
+            qm31_toaltstack // d_i^{-1} = (d_0 * ... * d_i)^{-1} * p_{i - 1}
+
+            { qm31_roll(i) }
+            qm31_mul
+        }
+
+        // stack: d_0, d_0^{-1}
+        qm31_swap
+        OP_2DROP OP_2DROP
+
+        for _ in 1..n {
+            qm31_fromaltstack
+        }
+    }
+}
+
+/// Check a claimed evaluation f(z) of the trace interpolant at the OODS point against the trace
+/// evaluation f(p) at a domain point p and the quotient q(p) from `trace_quotient_at_point`, i.e.,
+///   (f(p) - f(z)) * d_y - d_f * (p.y - z.y) == q(p) * (d_y * (z.x - p.x) - d_x * (z.y - p.y))
+/// where d_x, d_y, and d_f are conj(z.x) - z.x, conj(z.y) - z.y, and conj(f(z)) - f(z).
+///
+/// Evaluating f(z) from the whole domain is linear in the size of the domain, so instead, the
+/// prover supplies f(z), and the check at the queried points ties it to the trace as long as FRI
+/// shows that q is of low degree.
+///
+/// input:
+///  z.x, z.y (QM31)
+///  f(z) (QM31)
+///  p.x, p.y (QM31)
+///  f(p) (QM31)
+///  q(p) (QM31)
+///
+/// output:
+///  none
+/// mark the transaction as invalid if the check fails
+pub fn verify_trace_value_at_oods_gadget() -> Script {
+    script! {
+        qm31_toaltstack
+
+        // d_y, d_f, d_x
+        { qm31_copy(4) }
+        { conj_minus_self() }
+        { qm31_copy(4) }
+        { conj_minus_self() }
+        { qm31_copy(7) }
+        { conj_minus_self() }
+
+        // stack: z.x, z.y, f(z), p.x, p.y, f(p), d_y, d_f, d_x
+        // altstack: q(p)
+
+        // d_y * (z.x - p.x)
+        { qm31_copy(8) }
+        { qm31_copy(6) }
+        qm31_neg qm31_add
+        { qm31_copy(3) }
+        qm31_mul
+
+        // d_x * (z.y - p.y)
+        { qm31_copy(8) }
+        { qm31_copy(6) }
+        qm31_neg qm31_add
+        { qm31_roll(2) }
+        qm31_mul
+
+        qm31_neg qm31_add
+        qm31_fromaltstack
+        qm31_mul
+        qm31_toaltstack
+
+        // stack: z.x, z.y, f(z), p.x, p.y, f(p), d_y, d_f
+        // altstack: q(p) * V(p)
+
+        // d_f * (p.y - z.y)
+        { qm31_roll(3) }
+        { qm31_roll(6) }
+        qm31_neg qm31_add
+        qm31_mul
+
+        // (f(p) - f(z)) * d_y
+        { qm31_roll(2) }
+        { qm31_roll(4) }
+        qm31_neg qm31_add
+        { qm31_roll(2) }
+        qm31_mul
+
+        qm31_swap qm31_neg qm31_add
+        qm31_fromaltstack
+        qm31_equalverify
+
+        // drop z.x and p.x
+        OP_2DROP OP_2DROP
+        OP_2DROP OP_2DROP
+    }
+}
+
+/// Push the hints for `verify_quotient_degree_gadget`, which are the hint of drawing r and the
+/// claimed evaluations f(r) and q(r).
+pub fn push_quotient_degree_hint(draw_hint: &DrawQM31Hints, fr: QM31, qr: QM31) -> Script {
+    script! {
+        { Sha256ChannelGadget::push_draw_hint(draw_hint) }
+        { fr }
+        { qr }
+    }
+}
+
+/// Check a claimed quotient q(x) = (f(x) - f(z)) / (x - z) at a random point r drawn from the
+/// channel, in the same way as the OODS point, i.e.,
+///   q(r) * (r - z) == f(r) - f(z)
+/// which is checked without a division.
+///
+/// A quotient of the wrong degree, such as one with an extra high-degree term, satisfies this
+/// relation at r only with negligible probability, because r is drawn after f(z) is absorbed. The
+/// caller must still tie f(r) and q(r) to their commitments, e.g., with
+/// `verify_poly_commitment_gadget`.
+///
+/// hint:
+///  the hint of drawing r
+///  f(r) (QM31)
+///  q(r) (QM31)
+///
+/// input:
+///  channel
+///  z (QM31)
+///  f(z) (QM31)
+///
+/// output:
+///  channel'
+///  r (QM31)
+///  f(r) (QM31)
+///  q(r) (QM31)
+/// mark the transaction as invalid if the check fails
+pub fn verify_quotient_degree_gadget() -> Script {
+    script! {
+        qm31_toaltstack
+        qm31_toaltstack
+
+        { Sha256ChannelGadget::draw_felt_with_hint() }
+        qm31_from_bottom
+        qm31_from_bottom
+
+        // stack: channel', r, f(r), q(r)
+        // altstack: f(z), z
+
+        // q(r) * (r - z)
+        { qm31_copy(2) }
+        qm31_fromaltstack
+        qm31_neg qm31_add
+        { qm31_copy(1) }
+        qm31_mul
+
+        // f(r) - f(z)
+        { qm31_copy(2) }
+        qm31_fromaltstack
+        qm31_neg qm31_add
+
+        qm31_equalverify
+    }
+}
+
+/// Check that the trace takes a public value v at the boundary point b, through the quotient of
+/// the boundary constraint from `boundary_quotient_at_point`, i.e.,
+///   (f(z) - v) * (1 + h.x) == q(z) * h.y
+/// where h = z - b, so h.x = z.x * b.x + z.y * b.y and h.y = z.y * b.x - z.x * b.y.
+///
+/// The public value is an input, so it can be either a script constant or an element that has
+/// been absorbed into the channel. The caller must tie q(z) to the committed quotient, which is
+/// only of low degree if f(b) = v.
+///
+/// hint:
+///  q(z) (QM31)
+///
+/// input:
+///  z.x, z.y (QM31)
+///  f(z) (QM31)
+///  v (M31)
+///
+/// output:
+///  none
+/// mark the transaction as invalid if the check fails
+pub fn verify_public_boundary_gadget(boundary: CirclePoint<M31>) -> Script {
+    script! {
+        // lift v to qm31 and compute f(z) - v
+        OP_TOALTSTACK
+        OP_0 OP_0 OP_0
+        OP_FROMALTSTACK
+        qm31_neg qm31_add
+
+        // (f(z) - v) * (1 + h.x)
+        { qm31_copy(2) }
+        { boundary.x }
+        qm31_mul_m31
+        { qm31_copy(2) }
+        { boundary.y }
+        qm31_mul_m31
+        qm31_add
+        push_qm31_one
+        qm31_add
+        qm31_mul
+        qm31_toaltstack
+
+        // q(z) * h.y
+        { boundary.x }
+        qm31_mul_m31
+        qm31_swap
+        { boundary.y }
+        qm31_mul_m31
+        qm31_neg qm31_add
+        qm31_from_bottom
+        qm31_mul
+
+        qm31_fromaltstack
+        qm31_equalverify
+    }
+}
+
+/// Push the hints for `verify_logup_gadget`, which are the values of the running-sum column from
+/// `logup_running_sum`, from the last one to the first one.
+pub fn push_logup_hint(running_sum: &[QM31]) -> Script {
+    script! {
+        for v in running_sum.iter().rev() {
+            { *v }
+        }
+    }
+}
+
+/// Check a logup argument through its running-sum column S_0, ..., S_N, as in
+/// `logup_running_sum`, i.e., that both boundary values S_0 and S_N are zero, and that for each
+/// row the accumulation
+///   (S_{k+1} - S_k) * (alpha - a) == 1, for a looked-up value a, or
+///   (S_{k+1} - S_k) * (alpha - t) == -m, for a table value t with multiplicity m
+/// holds, which is checked without divisions.
+///
+/// The rows are checked from the last one, so the column is pulled from S_N to S_0.
+///
+/// hint:
+///  S_N, ..., S_0 (QM31)
+///
+/// input:
+///  alpha (QM31)
+///  a_0, ..., a_{num_lookups - 1} (m31)
+///  t_0, m_0, ..., t_{table_size - 1}, m_{table_size - 1} (m31)
+///
+/// output:
+///  none
+/// mark the transaction as invalid if any check fails
+pub fn verify_logup_gadget(num_lookups: usize, table_size: usize) -> Script {
+    // the number of stack elements of the rows below the k-th row, and of the k-th row itself
+    let row_sizes = [vec![1; num_lookups], vec![2; table_size]].concat();
+    let mut rows_below = Vec::with_capacity(row_sizes.len());
+    let mut total = 0;
+    for size in row_sizes.iter() {
+        rows_below.push(total);
+        total += size;
+    }
+
+    script! {
+        // S_N
+        qm31_from_bottom
+        qm31_dup
+        qm31_is_zero_verify
+
+        for k in (0..row_sizes.len()).rev() {
+            // S_{k+1} - S_k, keeping S_k for the next row
+            qm31_from_bottom
+            qm31_dup
+            qm31_toaltstack
+            qm31_neg
+            qm31_add
+
+            // alpha - v
+            for _ in 0..4 {
+                { rows_below[k] + row_sizes[k] + 7 } OP_PICK
+            }
+            { 4 + 4 + row_sizes[k] - 1 } OP_ROLL
+            m31_sub
+
+            qm31_mul
+            if k < num_lookups {
+                qm31_is_one_verify
+            } else {
+                4 OP_ROLL
+                m31_add
+                qm31_is_zero_verify
+            }
+
+            qm31_fromaltstack
+        }
+
+        // S_0
+        qm31_is_zero_verify
+
+        // drop alpha
+        OP_2DROP OP_2DROP
+    }
+}
+
+/// Compute conj(a) - a, which is zero in the real part and -2 times the u-part of a.
+fn conj_minus_self() -> Script {
+    script! {
+        OP_2DROP
+        OP_0 OP_0
+        qm31_double
+        qm31_neg
+    }
+}
+
 #[cfg(test)]
 mod test {
 
+    use crate::tests_utils::fixtures::random_qm31;
     use crate::{
-        constraints::ConstraintsGadget, tests_utils::report::report_bitcoin_script_size, treepp::*,
+        channel::{ChannelWithHint, Sha256Channel},
+        constraints::{
+            assert_columns_equal_at_point_gadget, assert_columns_permuted_at_point_gadget,
+            batch_denominator_inverse_gadget, boundary_quotient_at_point, commit_poly_coefficients,
+            draw_constraint_coeffs, draw_constraint_coeffs_gadget, gate_by_selector,
+            gate_by_selector_gadget, logup_running_sum, push_batch_denominator_inverse_hint,
+            push_composition_at_oods_hint, push_draw_constraint_coeffs_hint, push_logup_hint,
+            push_quotient_degree_hint, recombine_composition, recombine_composition_gadget,
+            selector_constraint, sumcheck_round, sumcheck_round_gadget, trace_quotient_at_point,
+            vanishing_derivative, vanishing_derivative_gadget, verify_composition_at_oods_gadget,
+            verify_logup_gadget, verify_poly_commitment_gadget, verify_public_boundary_gadget,
+            verify_quotient_degree_gadget, verify_selector_gadget,
+            verify_trace_value_at_oods_gadget, ConstraintCoeffsMode, ConstraintsGadget,
+        },
+        tests_utils::report::report_bitcoin_script_size,
+        treepp::*,
+        utils::{m31_point_to_qm31_gadget, push_qm31_one},
     };
-    use rand::{RngCore, SeedableRng};
+    use num_traits::{One, Zero};
+    use rand::{Rng, RngCore, SeedableRng};
     use rand_chacha::ChaCha20Rng;
-    use rust_bitcoin_m31::qm31_equalverify;
+    use rust_bitcoin_m31::{qm31_equalverify, qm31_fromaltstack, qm31_sub};
+    use stwo_prover::core::air::AirExt;
+    use stwo_prover::core::channel::Channel;
     use stwo_prover::core::circle::{CirclePoint, Coset};
     use stwo_prover::core::constraints::{coset_vanishing, pair_vanishing};
     use stwo_prover::core::fields::m31::M31;
     use stwo_prover::core::fields::qm31::QM31;
+    use stwo_prover::core::fields::FieldExpOps;
+    use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+    use stwo_prover::core::ComponentVec;
+    use stwo_prover::examples::fibonacci::Fibonacci;
 
     #[test]
     fn test_coset_vanishing() {
@@ -93,18 +832,8 @@ mod test {
             );
 
             let z = CirclePoint {
-                x: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
-                y: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
             };
 
             let res = coset_vanishing(coset, z);
@@ -128,48 +857,18 @@ mod test {
             let mut prng = ChaCha20Rng::seed_from_u64(seed);
 
             let z = CirclePoint {
-                x: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
-                y: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
             };
 
             let excluded0 = CirclePoint {
-                x: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
-                y: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
             };
 
             let excluded1 = CirclePoint {
-                x: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
-                y: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
             };
 
             let res = pair_vanishing(excluded0, excluded1, z);
@@ -195,4 +894,780 @@ mod test {
             assert!(exec_result.success);
         }
     }
+
+    #[test]
+    fn test_verify_composition_at_oods() {
+        let log_size = 5;
+        let claim = M31::reduce(443693538);
+        let fib = Fibonacci::new(log_size, claim);
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let verify_script = verify_composition_at_oods_gadget(2);
+        report_bitcoin_script_size(
+            "Constraints",
+            "verify_composition_at_oods(num_constraints=2)",
+            verify_script.len(),
+        );
+
+        for _ in 0..20 {
+            let z = CirclePoint {
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
+            };
+            let (fz, fgz, fggz) = (
+                random_qm31(&mut prng),
+                random_qm31(&mut prng),
+                random_qm31(&mut prng),
+            );
+            let random_coeff = random_qm31(&mut prng);
+
+            // the boundary constraint f(0) = 1, f(end) = claim
+            let constraint_zero_domain = Coset::subgroup(log_size);
+            let p = constraint_zero_domain.at(constraint_zero_domain.size() - 1);
+            let linear = QM31::one() + z.y * (claim - M31::one()) * p.y.inverse();
+            let boundary_constraint = fz - linear;
+            let boundary_vanishing = pair_vanishing(p.into_ef(), CirclePoint::zero(), z);
+
+            // the step constraint f(z)^2 + f(G z)^2 - f(G^2 z)
+            let step_constraint = (fz.square() + fgz.square() - fggz)
+                * pair_vanishing(
+                    constraint_zero_domain
+                        .at(constraint_zero_domain.size() - 2)
+                        .into_ef(),
+                    p.into_ef(),
+                    z,
+                );
+            let step_vanishing = coset_vanishing(constraint_zero_domain, z);
+
+            let composition = fib.air.eval_composition_polynomial_at_point(
+                z,
+                &ComponentVec(vec![vec![vec![fz, fgz, fggz]]]),
+                random_coeff,
+            );
+
+            let constraints = [boundary_constraint, step_constraint];
+            let vanishings = [boundary_vanishing, step_vanishing];
+            let coeffs = [QM31::one(), random_coeff];
+
+            for (claimed, expected) in [(composition, true), (composition + QM31::one(), false)] {
+                let script = script! {
+                    { push_composition_at_oods_hint(&constraints, &vanishings) }
+                    { claimed }
+                    for i in (0..2).rev() {
+                        { coeffs[i] }
+                        { constraints[i] }
+                        { vanishings[i] }
+                    }
+                    { verify_script.clone() }
+                    OP_TRUE
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_trace_value_at_oods() {
+        let log_size = 5;
+        let claim = M31::reduce(443693538);
+
+        let fib = Fibonacci::new(log_size, claim);
+        let trace_poly = fib.get_trace().interpolate();
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let verify_script = verify_trace_value_at_oods_gadget();
+        report_bitcoin_script_size(
+            "Constraints",
+            "verify_trace_value_at_oods",
+            verify_script.len(),
+        );
+
+        let z = CirclePoint {
+            x: random_qm31(&mut prng),
+            y: random_qm31(&mut prng),
+        };
+        let fz = trace_poly.eval_at_point(z);
+
+        let domain = Coset::odds(log_size + 1);
+        for i in 0..4 {
+            let p_m31 = domain.at(i);
+            let p = p_m31.into_ef::<QM31>();
+            let fp = trace_poly.eval_at_point(p);
+
+            let q = trace_quotient_at_point(z, fz, p, fp);
+
+            let script = script! {
+                { z.x }
+                { z.y }
+                { fz }
+                { p_m31.x }
+                { p_m31.y }
+                m31_point_to_qm31_gadget
+                { fp }
+                { q }
+                { verify_script.clone() }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+
+            // a wrong f(z) does not match the quotient
+            let script = script! {
+                { z.x }
+                { z.y }
+                { fz + QM31::one() }
+                { p_m31.x }
+                { p_m31.y }
+                m31_point_to_qm31_gadget
+                { fp }
+                { q }
+                { verify_script.clone() }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_batch_denominator_inverse() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let num_constraints = 3;
+
+        let batch_inverse_script = batch_denominator_inverse_gadget(num_constraints);
+        report_bitcoin_script_size(
+            "Constraints",
+            format!("batch_denominator_inverse({})", num_constraints).as_str(),
+            batch_inverse_script.len(),
+        );
+
+        let denominators = (0..num_constraints)
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<QM31>>();
+
+        let script = script! {
+            { push_batch_denominator_inverse_hint(&denominators) }
+            for d in denominators.iter() {
+                { *d }
+            }
+            { batch_inverse_script.clone() }
+            for d in denominators.iter().rev() {
+                { d.inverse() }
+                qm31_equalverify
+            }
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+
+        // a wrong hint is rejected
+        let script = script! {
+            { push_batch_denominator_inverse_hint(&denominators[1..]) }
+            for d in denominators.iter() {
+                { *d }
+            }
+            { batch_inverse_script }
+            for _ in 0..num_constraints {
+                OP_2DROP OP_2DROP
+            }
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_draw_constraint_coeffs() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let num_constraints = 5;
+
+        let mut init_state = [0u8; 32];
+        init_state.iter_mut().for_each(|v| *v = prng.gen());
+        let init_state = BWSSha256Hash::from(init_state.to_vec());
+
+        let mut all_coeffs = vec![];
+        for (mode, name) in [
+            (ConstraintCoeffsMode::PowersOfAlpha, "powers_of_alpha"),
+            (ConstraintCoeffsMode::Independent, "independent"),
+        ] {
+            let gadget = draw_constraint_coeffs_gadget(num_constraints, mode);
+            report_bitcoin_script_size(
+                "Constraints",
+                &format!("draw_constraint_coeffs({})", name),
+                gadget.len(),
+            );
+
+            let mut channel = Sha256Channel::new(init_state);
+            let (coeffs, hints) = draw_constraint_coeffs(&mut channel, num_constraints, mode);
+            assert_eq!(coeffs.len(), num_constraints);
+
+            // coeff_0 comes from the first draw of the channel
+            let (first_draw, _) = Sha256Channel::new(init_state).draw_felt_and_hints();
+            match mode {
+                ConstraintCoeffsMode::PowersOfAlpha => {
+                    assert_eq!(coeffs[0], QM31::one());
+                    assert_eq!(coeffs[1], first_draw);
+                    assert_eq!(coeffs[4], first_draw.square().square());
+                }
+                ConstraintCoeffsMode::Independent => {
+                    assert_eq!(coeffs[0], first_draw);
+                }
+            }
+
+            let script = script! {
+                { push_draw_constraint_coeffs_hint(&hints) }
+                { init_state }
+                { gadget.clone() }
+                { channel.digest }
+                OP_EQUALVERIFY
+                for coeff in coeffs.iter().rev() {
+                    qm31_fromaltstack
+                    { *coeff }
+                    qm31_equalverify
+                }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+
+            all_coeffs.push(coeffs);
+        }
+
+        // the two modes draw different coefficients
+        assert_ne!(all_coeffs[0], all_coeffs[1]);
+    }
+
+    #[test]
+    fn test_poly_commitment() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        for degree in [0, 1, 3, 4, 7] {
+            let commitment_script = verify_poly_commitment_gadget(degree);
+            report_bitcoin_script_size(
+                "Constraints",
+                format!("verify_poly_commitment(degree={})", degree).as_str(),
+                commitment_script.len(),
+            );
+
+            let coeffs = (0..=degree)
+                .map(|_| random_qm31(&mut prng))
+                .collect::<Vec<QM31>>();
+            let x = random_qm31(&mut prng);
+
+            let root = commit_poly_coefficients(&coeffs);
+            let expected = coeffs
+                .iter()
+                .rev()
+                .fold(QM31::zero(), |acc, c| acc * x + *c);
+
+            let script = script! {
+                { root }
+                { x }
+                for c in coeffs.iter() {
+                    { *c }
+                }
+                { commitment_script.clone() }
+                { expected }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+
+            // opening a different polynomial against the root fails
+            let mut tampered = coeffs.clone();
+            tampered[degree] += QM31::one();
+
+            let script = script! {
+                { root }
+                { x }
+                for c in tampered.iter() {
+                    { *c }
+                }
+                { commitment_script.clone() }
+                OP_2DROP OP_2DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_verify_quotient_degree() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let gadget = verify_quotient_degree_gadget();
+        report_bitcoin_script_size("Constraints", "verify_quotient_degree", gadget.len());
+
+        let eval = |coeffs: &[QM31], x: QM31| {
+            coeffs
+                .iter()
+                .rev()
+                .fold(QM31::zero(), |acc, c| acc * x + *c)
+        };
+
+        let degree = 7;
+        let f = (0..=degree)
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<QM31>>();
+        let z = random_qm31(&mut prng);
+        let fz = eval(&f, z);
+
+        // q = (f - f(z)) / (x - z) by synthetic division
+        let mut q = vec![QM31::zero(); degree];
+        let mut acc = QM31::zero();
+        for i in (1..=degree).rev() {
+            acc = acc * z + f[i];
+            q[i - 1] = acc;
+        }
+
+        // q with an extra high-degree term
+        let mut bad_q = q.clone();
+        bad_q.resize(2 * degree, QM31::zero());
+        bad_q[2 * degree - 1] = random_qm31(&mut prng);
+
+        let mut init_state = [0u8; 32];
+        init_state.iter_mut().for_each(|v| *v = prng.gen());
+        let init_state = BWSSha256Hash::from(init_state.to_vec());
+
+        let mut channel = Sha256Channel::new(init_state);
+        let (r, hint) = channel.draw_felt_and_hints();
+        let fr = eval(&f, r);
+
+        for (quotient, expected) in [(&q, true), (&bad_q, false)] {
+            let qr = eval(quotient, r);
+            let script = script! {
+                { push_quotient_degree_hint(&hint, fr, qr) }
+                { init_state }
+                { z }
+                { fz }
+                { gadget.clone() }
+                { qr }
+                qm31_equalverify
+                { fr }
+                qm31_equalverify
+                { r }
+                qm31_equalverify
+                { channel.digest }
+                OP_EQUALVERIFY
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
+
+    #[test]
+    fn test_verify_public_boundary() {
+        let log_size = 5;
+        let claim = M31::reduce(443693538);
+
+        let fib = Fibonacci::new(log_size, claim);
+        let trace_poly = fib.get_trace().interpolate();
+
+        // the claim is the value of the trace at the last point of the constraint domain
+        let constraint_zero_domain = Coset::subgroup(log_size);
+        let boundary = constraint_zero_domain.at(constraint_zero_domain.size() - 1);
+        assert_eq!(
+            trace_poly.eval_at_point(boundary.into_ef::<QM31>()),
+            claim.into()
+        );
+
+        let gadget = verify_public_boundary_gadget(boundary);
+        report_bitcoin_script_size("Constraints", "verify_public_boundary", gadget.len());
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+        let z = CirclePoint {
+            x: random_qm31(&mut prng),
+            y: random_qm31(&mut prng),
+        };
+        let fz = trace_poly.eval_at_point(z);
+
+        // the quotient in the proof is for the real claim
+        let qz = boundary_quotient_at_point(z, fz, boundary, claim);
+
+        for (value, expected) in [(claim, true), (claim + M31::one(), false)] {
+            let script = script! {
+                { qz }
+                { z.x }
+                { z.y }
+                { fz }
+                { value }
+                { gadget.clone() }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
+
+    #[test]
+    fn test_recombine_composition() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let num_parts = 2;
+        for part_degree in [1, 4, 5, 16] {
+            let gadget = recombine_composition_gadget(num_parts, part_degree);
+            report_bitcoin_script_size(
+                "Constraints",
+                format!(
+                    "recombine_composition(num_parts={}, part_degree={})",
+                    num_parts, part_degree
+                )
+                .as_str(),
+                gadget.len(),
+            );
+
+            // split a polynomial into two parts of part_degree coefficients
+            let coeffs = (0..num_parts * part_degree)
+                .map(|_| random_qm31(&mut prng))
+                .collect::<Vec<QM31>>();
+            let z = random_qm31(&mut prng);
+            let eval = |coeffs: &[QM31]| {
+                coeffs
+                    .iter()
+                    .rev()
+                    .fold(QM31::zero(), |acc, c| acc * z + *c)
+            };
+            let parts = coeffs
+                .chunks_exact(part_degree)
+                .map(eval)
+                .collect::<Vec<QM31>>();
+
+            let expected = recombine_composition(z, &parts, part_degree);
+            assert_eq!(expected, eval(&coeffs));
+
+            let script = script! {
+                { z }
+                for part in parts.iter() {
+                    { *part }
+                }
+                { gadget.clone() }
+                { expected }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_verify_logup() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        // a table of four values, looked up six times
+        let table = (0..4)
+            .map(|_| M31::reduce(prng.next_u64()))
+            .collect::<Vec<M31>>();
+        let lookups = [0, 2, 2, 3, 0, 2].map(|i| table[i]);
+        let multiplicities = [2, 0, 3, 1].map(M31::reduce);
+
+        let gadget = verify_logup_gadget(lookups.len(), table.len());
+        report_bitcoin_script_size(
+            "Constraints",
+            format!("verify_logup({}, {})", lookups.len(), table.len()).as_str(),
+            gadget.len(),
+        );
+
+        let alpha = random_qm31(&mut prng);
+
+        let run = |multiplicities: &[M31], running_sum: &[QM31]| {
+            let script = script! {
+                { push_logup_hint(running_sum) }
+                { alpha }
+                for a in lookups.iter() {
+                    { *a }
+                }
+                for (t, m) in table.iter().zip(multiplicities.iter()) {
+                    { *t }
+                    { *m }
+                }
+                { gadget.clone() }
+                OP_TRUE
+            };
+            execute_script(script).success
+        };
+
+        let running_sum = logup_running_sum(alpha, &lookups, &table, &multiplicities);
+        assert_eq!(*running_sum.last().unwrap(), QM31::zero());
+        assert!(run(&multiplicities, &running_sum));
+
+        // a tampered multiplicity fails the accumulation with the honest column
+        let mut tampered = multiplicities;
+        tampered[1] = M31::one();
+        assert!(!run(&tampered, &running_sum));
+
+        // and the final sum with a column that accumulates it
+        let tampered_running_sum = logup_running_sum(alpha, &lookups, &table, &tampered);
+        assert_ne!(*tampered_running_sum.last().unwrap(), QM31::zero());
+        assert!(!run(&tampered, &tampered_running_sum));
+    }
+
+    #[test]
+    fn test_assert_columns_equal_at_point() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let equal_script = assert_columns_equal_at_point_gadget();
+        report_bitcoin_script_size(
+            "Constraints",
+            "assert_columns_equal_at_point",
+            equal_script.len(),
+        );
+
+        // the columns are given by their coefficients
+        let eval = |coeffs: &[QM31], z: QM31| {
+            coeffs
+                .iter()
+                .rev()
+                .fold(QM31::zero(), |acc, c| acc * z + *c)
+        };
+
+        let n = 4;
+        let degree = 8;
+        let columns = (0..n)
+            .map(|_| {
+                (0..degree)
+                    .map(|_| random_qm31(&mut prng))
+                    .collect::<Vec<QM31>>()
+            })
+            .collect::<Vec<_>>();
+
+        // g_{map[i]} = f_i
+        let map = [2, 0, 3, 1];
+        let mut permuted = vec![vec![]; n];
+        for (i, &j) in map.iter().enumerate() {
+            permuted[j] = columns[i].clone();
+        }
+
+        let mut different = columns[0].clone();
+        different[degree - 1] += QM31::one();
+
+        let permuted_script = assert_columns_permuted_at_point_gadget(&map);
+        report_bitcoin_script_size(
+            "Constraints",
+            format!("assert_columns_permuted_at_point({})", n).as_str(),
+            permuted_script.len(),
+        );
+
+        for _ in 0..4 {
+            let z = random_qm31(&mut prng);
+
+            for (g, expected) in [(&columns[0], true), (&different, false)] {
+                let script = script! {
+                    { eval(&columns[0], z) }
+                    { eval(g, z) }
+                    { equal_script.clone() }
+                    OP_TRUE
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, expected);
+            }
+
+            let mut tampered = permuted.clone();
+            tampered[map[1]] = different.clone();
+
+            for (gs, expected) in [(&permuted, true), (&columns, false), (&tampered, false)] {
+                let script = script! {
+                    for f in columns.iter() {
+                        { eval(f, z) }
+                    }
+                    for g in gs.iter() {
+                        { eval(g, z) }
+                    }
+                    { permuted_script.clone() }
+                    OP_TRUE
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sumcheck_round() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let sumcheck_script = sumcheck_round_gadget();
+        report_bitcoin_script_size("Constraints", "sumcheck_round", sumcheck_script.len());
+
+        for _ in 0..10 {
+            let p0 = random_qm31(&mut prng);
+            let p1 = random_qm31(&mut prng);
+            let r = random_qm31(&mut prng);
+            let claim = p0 + p1;
+
+            let next_claim = sumcheck_round(claim, p0, p1, r).unwrap();
+            // the round polynomial is linear, so p(r) = (1 - r) * p(0) + r * p(1)
+            assert_eq!(next_claim, (QM31::one() - r) * p0 + r * p1);
+
+            // a round polynomial that does not sum to the claim
+            let bad_p1 = p1 + QM31::one();
+            assert_eq!(sumcheck_round(claim, p0, bad_p1, r), None);
+
+            for (p1, expected) in [(p1, true), (bad_p1, false)] {
+                let script = script! {
+                    { claim }
+                    { p0 }
+                    { p1 }
+                    { r }
+                    { sumcheck_script.clone() }
+                    { next_claim }
+                    qm31_equalverify
+                    OP_TRUE
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_selector() {
+        let log_size = 5;
+        let two = QM31::from(M31::from_u32_unchecked(2));
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        // a non-boolean value of the selector on a row violates the constraint there
+        assert_eq!(selector_constraint(QM31::zero()), QM31::zero());
+        assert_eq!(selector_constraint(QM31::one()), QM31::zero());
+        assert_ne!(selector_constraint(two), QM31::zero());
+
+        let selector_script = verify_selector_gadget();
+        report_bitcoin_script_size("Constraints", "verify_selector", selector_script.len());
+
+        let gate_script = gate_by_selector_gadget();
+        report_bitcoin_script_size("Constraints", "gate_by_selector", gate_script.len());
+
+        let verify_script = verify_composition_at_oods_gadget(3);
+
+        for _ in 0..10 {
+            let z = CirclePoint {
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
+            };
+            let (fz, sz) = (random_qm31(&mut prng), random_qm31(&mut prng));
+            let coeffs = [QM31::one(), random_qm31(&mut prng), random_qm31(&mut prng)];
+
+            // a two-phase AIR: f = 1 on the rows where s = 1, and f = 2 on the rows where s = 0
+            let vanishing = coset_vanishing(Coset::subgroup(log_size), z);
+            let constraints = |sz: QM31| {
+                [
+                    selector_constraint(sz),
+                    gate_by_selector(fz - QM31::one(), sz),
+                    gate_by_selector(fz - two, QM31::one() - sz),
+                ]
+            };
+            let vanishings = [vanishing; 3];
+            let composition = constraints(sz)
+                .iter()
+                .zip(coeffs.iter())
+                .fold(QM31::zero(), |acc, (constraint, coeff)| {
+                    acc + *coeff * *constraint * vanishing.inverse()
+                });
+
+            // the prover claims a selector evaluation that does not match the composition
+            for (sz, expected) in [(sz, true), (sz + QM31::one(), false)] {
+                let script = script! {
+                    { push_composition_at_oods_hint(&constraints(sz), &vanishings) }
+                    { composition }
+                    { coeffs[2] }
+                    { fz - two }
+                    { push_qm31_one() }
+                    { sz }
+                    qm31_sub
+                    { gate_script.clone() }
+                    { vanishing }
+                    { coeffs[1] }
+                    { fz - QM31::one() }
+                    { sz }
+                    { gate_script.clone() }
+                    { vanishing }
+                    { coeffs[0] }
+                    { sz }
+                    { selector_script.clone() }
+                    { vanishing }
+                    { verify_script.clone() }
+                    OP_TRUE
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_vanishing_derivative() {
+        let logn = 4;
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let derivative_script = vanishing_derivative_gadget(logn);
+        report_bitcoin_script_size(
+            "Constraints",
+            format!("vanishing_derivative(logn={})", logn).as_str(),
+            derivative_script.len(),
+        );
+
+        // the coefficients of V as a polynomial in x, by composing T(x) = 2x^2 - 1
+        let mut coeffs = vec![QM31::zero(), QM31::one()];
+        for _ in 1..logn {
+            let mut square = vec![QM31::zero(); 2 * coeffs.len() - 1];
+            for (i, a) in coeffs.iter().enumerate() {
+                for (j, b) in coeffs.iter().enumerate() {
+                    square[i + j] += *a * *b;
+                }
+            }
+            coeffs = square.iter().map(|c| *c + *c).collect();
+            coeffs[0] -= QM31::one();
+        }
+        let eval = |coeffs: &[QM31], x: QM31| {
+            coeffs
+                .iter()
+                .rev()
+                .fold(QM31::zero(), |acc, c| acc * x + *c)
+        };
+        let derivative_coeffs = coeffs
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, c)| *c * M31::from_u32_unchecked(i as u32))
+            .collect::<Vec<_>>();
+
+        let domain = Coset::odds(logn);
+        let random_points = (0..10).map(|_| CirclePoint {
+            x: random_qm31(&mut prng),
+            y: random_qm31(&mut prng),
+        });
+        let domain_points = (0..domain.size()).map(|i| domain.at(i).into_ef::<QM31>());
+
+        for z in random_points
+            .collect::<Vec<_>>()
+            .into_iter()
+            .chain(domain_points)
+        {
+            assert_eq!(eval(&coeffs, z.x), coset_vanishing(domain, z));
+
+            let expected = vanishing_derivative(logn, z);
+            assert_eq!(expected, eval(&derivative_coeffs, z.x));
+            // the roots of V are simple
+            assert_ne!(expected, QM31::zero());
+
+            let script = script! {
+                { z.x }
+                { z.y }
+                { derivative_script.clone() }
+                { expected }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
 }
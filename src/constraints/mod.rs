@@ -1,2 +1,189 @@
 mod bitcoin_script;
 pub use bitcoin_script::*;
+
+use crate::channel::{ChannelWithHint, DrawQM31Hints};
+use crate::merkle_tree::MerkleTree;
+use num_traits::{One, Zero};
+use stwo_prover::core::circle::CirclePoint;
+use stwo_prover::core::fields::m31::M31;
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::fields::FieldExpOps;
+use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+
+/// Compute the quotient that binds a claimed evaluation f(z) at the OODS point z to the trace
+/// evaluation f(p) at a domain point p, i.e.,
+///   (f(p) - L(p)) * (conj(z).y - z.y) / V(p)
+/// where L is the line through (z, f(z)) and (conj(z), conj(f(z))), and V vanishes at z and conj(z).
+///
+/// The quotient is a low-degree polynomial in p only if f(z) is correct, which FRI then checks.
+pub fn trace_quotient_at_point(
+    z: CirclePoint<QM31>,
+    fz: QM31,
+    p: CirclePoint<QM31>,
+    fp: QM31,
+) -> QM31 {
+    let d_x = conj(z.x) - z.x;
+    let d_y = conj(z.y) - z.y;
+    let d_f = conj(fz) - fz;
+
+    let numerator = (fp - fz) * d_y - d_f * (p.y - z.y);
+    let vanishing = d_y * (z.x - p.x) - d_x * (z.y - p.y);
+
+    numerator * vanishing.inverse()
+}
+
+/// Compute the quotient of the boundary constraint f(b) = v at the OODS point z, i.e.,
+///   (f(z) - v) / V_b(z)
+/// where V_b(z) = h.y / (1 + h.x) with h = z - b is the vanishing fraction of the single point b,
+/// as in stwo's `point_vanishing`.
+pub fn boundary_quotient_at_point(
+    z: CirclePoint<QM31>,
+    fz: QM31,
+    boundary: CirclePoint<M31>,
+    value: M31,
+) -> QM31 {
+    let h = z - boundary.into_ef();
+    (fz - value) * (QM31::one() + h.x) * h.y.inverse()
+}
+
+/// Compute the derivative with respect to z.x of the vanishing polynomial of the canonic circle
+/// domain of size 2^logn, i.e., of V(z) = T^(logn - 1)(z.x) with the doubling map
+/// T(x) = 2x^2 - 1, as in stwo's `coset_vanishing` for `Coset::odds(logn)`, which is
+///   V'(z) = 4 x_0 * 4 x_1 * ... * 4 x_{logn - 2}
+/// where x_0 = z.x and x_{j + 1} = T(x_j).
+///
+/// The roots of V are simple, so V' is nonzero on the domain, which quotients by a vanishing
+/// polynomial with a repeated root use.
+pub fn vanishing_derivative(logn: u32, z: CirclePoint<QM31>) -> QM31 {
+    assert!(logn >= 1);
+    let mut x = z.x;
+    let mut derivative = QM31::one();
+    for _ in 1..logn {
+        derivative *= x * M31::from_u32_unchecked(4);
+        x = x.square() * M31::from_u32_unchecked(2) - QM31::one();
+    }
+    derivative
+}
+
+/// The log of the degree bound of the composition polynomial of constraints of degree at most
+/// `max_constraint_degree` over a trace of size 2^trace_log_size, i.e.,
+///   trace_log_size + ceil(log2(max_constraint_degree))
+/// since such a constraint has degree up to max_constraint_degree * 2^trace_log_size.
+pub fn composition_log_degree_bound(trace_log_size: usize, max_constraint_degree: usize) -> usize {
+    assert!(max_constraint_degree > 0);
+    trace_log_size + max_constraint_degree.next_power_of_two().ilog2() as usize
+}
+
+/// Commit to the coefficients c_0, ..., c_degree of a small polynomial, as the root of a Merkle
+/// tree whose leaves are the coefficients, padded with zeros to a power of two (at least 2), which
+/// `verify_poly_commitment_gadget` recomputes from all the coefficients.
+pub fn commit_poly_coefficients(coeffs: &[QM31]) -> BWSSha256Hash {
+    assert!(!coeffs.is_empty());
+    let mut leaves = coeffs.to_vec();
+    leaves.resize(coeffs.len().next_power_of_two().max(2), QM31::zero());
+    MerkleTree::new(leaves).root_hash
+}
+
+/// Recombine the evaluations at z of the parts of a composition polynomial that is split into
+/// parts of `part_degree` coefficients each, i.e., sum_i z^(i * part_degree) * comp_i(z).
+pub fn recombine_composition(z: QM31, parts: &[QM31], part_degree: usize) -> QM31 {
+    let shift = z.pow(part_degree as u128);
+    parts
+        .iter()
+        .rev()
+        .fold(QM31::zero(), |acc, part| acc * shift + *part)
+}
+
+/// Run one round of a sum-check reduction with a linear round polynomial p, given by p(0) and
+/// p(1): the round is valid if p(0) + p(1) is the claimed sum, and the next claim is p(r) at the
+/// challenge r. Return None if the round is not valid.
+pub fn sumcheck_round(claim: QM31, p0: QM31, p1: QM31, r: QM31) -> Option<QM31> {
+    if p0 + p1 != claim {
+        return None;
+    }
+    Some(p0 + r * (p1 - p0))
+}
+
+/// The booleanity constraint s^2 - s of a selector column s, which vanishes exactly on the rows
+/// where the selector is 0 or 1.
+///
+/// Note that the evaluation of the selector at the OODS point is not boolean in general: the
+/// constraint is enforced over the trace domain by dividing it by the vanishing polynomial of the
+/// trace domain in the composition polynomial, as with any other constraint.
+pub fn selector_constraint(s: QM31) -> QM31 {
+    s.square() - s
+}
+
+/// Gate a constraint value by a selector, i.e., value * s, so that the constraint only applies to
+/// the rows where the selector is 1.
+pub fn gate_by_selector(value: QM31, s: QM31) -> QM31 {
+    value * s
+}
+
+/// Compute the running-sum column S_0, ..., S_N of a logup argument, with N = lookups.len() +
+/// table.len(), where S_0 = 0, each looked-up value a adds 1 / (alpha - a), and each table value t
+/// with multiplicity m adds -m / (alpha - t), in the order of the lookups and then the table.
+///
+/// The lookups are all in the table with the right multiplicities if and only if S_N = 0, except
+/// with a negligible probability over alpha.
+pub fn logup_running_sum(
+    alpha: QM31,
+    lookups: &[M31],
+    table: &[M31],
+    multiplicities: &[M31],
+) -> Vec<QM31> {
+    assert_eq!(table.len(), multiplicities.len());
+
+    let mut running_sum = Vec::with_capacity(lookups.len() + table.len() + 1);
+    let mut cur = QM31::zero();
+    running_sum.push(cur);
+    for a in lookups.iter() {
+        cur += (alpha - *a).inverse();
+        running_sum.push(cur);
+    }
+    for (t, m) in table.iter().zip(multiplicities.iter()) {
+        cur -= (alpha - *t).inverse() * *m;
+        running_sum.push(cur);
+    }
+    running_sum
+}
+
+/// The complex conjugate of a qm31 element, which negates the u-part.
+fn conj(v: QM31) -> QM31 {
+    QM31(v.0, -v.1)
+}
+
+/// How the random coefficients of the constraints are drawn from the channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConstraintCoeffsMode {
+    /// A single alpha is drawn, and coeff_i = alpha^i.
+    #[default]
+    PowersOfAlpha,
+    /// Each coefficient is drawn independently, and coeff_i is the i-th draw.
+    Independent,
+}
+
+/// Draw the random coefficients of the constraints, coeff_0 first, and return them together with
+/// the hints of the draws in the order that they are drawn.
+pub fn draw_constraint_coeffs(
+    channel: &mut impl ChannelWithHint,
+    num_constraints: usize,
+    mode: ConstraintCoeffsMode,
+) -> (Vec<QM31>, Vec<DrawQM31Hints>) {
+    match mode {
+        ConstraintCoeffsMode::PowersOfAlpha => {
+            let (alpha, hint) = channel.draw_felt_and_hints();
+
+            let mut coeffs = Vec::with_capacity(num_constraints);
+            let mut cur = QM31::one();
+            for _ in 0..num_constraints {
+                coeffs.push(cur);
+                cur *= alpha;
+            }
+            (coeffs, vec![hint])
+        }
+        ConstraintCoeffsMode::Independent => (0..num_constraints)
+            .map(|_| channel.draw_felt_and_hints())
+            .unzip(),
+    }
+}
@@ -1,5 +1,24 @@
+use crate::channel::{ChannelWithHint, Sha256ChannelGadget};
+use crate::circle::{coset_shift_gadget, CircleDomain};
+use crate::constraints::trace_quotient_at_point;
+use crate::fibonacci::{
+    fibonacci_channel_init_state, fibonacci_claim, FibonacciAir, TraceCommitmentMode,
+};
+use crate::fri::{
+    bind_queries_to_openings_gadget, num_fri_layers, verify_first_layer_binding_gadget, FRIGadget,
+    N_QUERIES,
+};
+use crate::merkle_tree::{verify_columnar_leaf_gadget, MerkleTreeGadget};
+use crate::oods::{verify_all_column_oods_gadget, OODSGadget, OODS};
+use crate::prover::{self, evaluation_point_tree, trace_point, trace_step, POW_BITS};
+use crate::twiddle_merkle_tree::TwiddleMerkleTree;
+use crate::utils::{
+    alpha_powers_batch_gadget, bit_reverse_index, m31_point_to_qm31_gadget, push_qm31_one,
+};
 use crate::{constraints::ConstraintsGadget, treepp::*};
-use num_traits::One;
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+use num_traits::{One, Zero};
+use rust_bitcoin_m31::m31_add;
 use rust_bitcoin_m31::qm31_add;
 use rust_bitcoin_m31::qm31_copy;
 use rust_bitcoin_m31::qm31_dup;
@@ -10,18 +29,485 @@ use rust_bitcoin_m31::qm31_swap;
 use rust_bitcoin_m31::{
     qm31_fromaltstack, qm31_mul, qm31_mul_m31, qm31_roll, qm31_sub, qm31_toaltstack,
 };
+use stwo_prover::core::channel::{BWSSha256Channel, Channel};
+use stwo_prover::core::poly::circle::CanonicCoset;
 use stwo_prover::core::{
     circle::{CirclePoint, Coset},
     fields::{m31::M31, qm31::QM31, FieldExpOps},
 };
 use stwo_prover::examples::fibonacci::Fibonacci;
 
+/// Absorb the roots of a trace commitment of `num_columns` columns, in the same order as
+/// `absorb_trace_commitment`.
+///
+/// input:
+///   root_{k - 1}, ..., root_0 (root_0 is absorbed first)
+///   channel
+///
+/// output:
+///   channel'
+///
+/// where k is `num_columns` for `TraceCommitmentMode::SeparateRoots` and 1 for
+/// `TraceCommitmentMode::CombinedRoot`.
+pub fn absorb_trace_commitment_gadget(num_columns: usize, mode: TraceCommitmentMode) -> Script {
+    assert!(num_columns > 0);
+    let num_roots = match mode {
+        TraceCommitmentMode::SeparateRoots => num_columns,
+        TraceCommitmentMode::CombinedRoot => 1,
+    };
+    script! {
+        for _ in 0..num_roots {
+            { Sha256ChannelGadget::mix_digest() }
+        }
+    }
+}
+
+/// Assemble the verifier for a proof from `prove_fibonacci` of the Fibonacci trace of size
+/// 2^log_size and its claim `fibonacci_claim(log_size)`, which does what `prover::verify` does
+/// for `FibonacciAir`:
+/// - it replays the channel, i.e., it absorbs the trace commitment, draws the random coefficient,
+///   absorbs the composition commitment, and draws the OODS point z,
+/// - it checks the composition evaluations at z against the constraints at the mask values,
+/// - it absorbs the mask values and the composition evaluations, and draws the DEEP coefficient,
+/// - for each query, it opens the trace, the composition polynomial, and the domain point p
+///   against their roots, checks the quotient of each mask value and each composition evaluation
+///   at p, and checks that the DEEP quotient that batches them is the leaf of FRI,
+/// - it verifies the FRI proof on the channel after the DEEP coefficient, and checks that the
+///   positions of the openings are the queries of FRI.
+///
+/// The point p is opened from `evaluation_point_tree`, whose root is a constant of the script.
+///
+/// input (produced by the witness builder):
+///   hints
+///   FRI proof body (leaves, last layer, commitments)
+///   positions of the openings, one per query
+///   trace commitment
+///   composition commitment
+///   composition evaluations at z, the last one first (4 qm31)
+///   f(z + 2G), f(z + G), f(z) (qm31)
+///
+/// output:
+///   true
+///
+/// The trace size 2^log_size is supported for log_size from 4 to 20. The witness is pushed before
+/// the script runs, so the stack stays within the limit of Bitcoin only for small traces.
+pub fn build_full_verifier(log_size: u32) -> (Script, WitnessBuilder) {
+    assert!((4..=20).contains(&log_size));
+
+    let claim = fibonacci_claim(log_size);
+    let logn = prover::evaluation_logn(&FibonacciAir::new(log_size, claim));
+    let n_layers = num_fri_layers(logn);
+    let twiddle_merkle_tree_root = TwiddleMerkleTree::new(n_layers).root_hash;
+    let point_root = evaluation_point_tree(logn).root_hash;
+
+    // the verifier state, which is the two commitments and then 14 qm31 elements:
+    //   z.x, z.y, the composition evaluations, the mask values, the DEEP coefficient, and the
+    //   mask points z + G and z + 2G
+    // below it are the positions, and then the FRI proof body
+    let state_len = 2 + 14 * 4;
+    let position_depth = |i: usize| state_len + (N_QUERIES - 1 - i);
+    let leaf_depth = |i: usize| state_len + N_QUERIES + n_layers + 2 * 4 + 4 * i;
+
+    // the qm31 index of the mask points z, z + G, and z + 2G, with the openings of a query above
+    // the state
+    let mask_point_x = [20, 10, 8];
+    let mask_point_y = [19, 9, 7];
+
+    // the twiddle factors of each query and the folding factors (qm31), and then the queries,
+    // the commitments, and the last layer (2 qm31), which are left by the FRI verifier
+    let num_fri_factors = N_QUERIES * n_layers + n_layers * 4;
+    let num_fri_body = N_QUERIES + n_layers + 2 * 4;
+
+    let script = script! {
+        // absorb the trace commitment and draw the random coefficient
+        for _ in 0..7 * 4 {
+            OP_TOALTSTACK
+        }
+        OP_OVER
+        { fibonacci_channel_init_state(claim) }
+        { Sha256ChannelGadget::mix_digest() }
+        { Sha256ChannelGadget::draw_felt_with_hint() }
+        qm31_toaltstack
+
+        // absorb the composition commitment and draw z
+        OP_OVER OP_SWAP
+        { OODSGadget::get_random_point_after_composition() }
+
+        // stack: trace commitment, composition commitment, channel, z.x, z.y, random_coeff,
+        //   composition evaluations, f(z + 2G), f(z + G), f(z)
+        qm31_fromaltstack
+        for _ in 0..7 * 4 {
+            OP_FROMALTSTACK
+        }
+
+        // check the composition polynomial at z
+        { qm31_copy(7) }
+        { qm31_copy(3) }
+        { qm31_copy(3) }
+        { qm31_copy(3) }
+        { qm31_copy(13) }
+        { qm31_copy(13) }
+        { FibonacciAirGadget::eval_constraints(log_size, claim) }
+        { qm31_copy(4) }
+        { qm31_copy(6) }
+        { qm31_copy(8) }
+        { qm31_copy(10) }
+        { FibonacciCompositionGadget::combine_partial_evals() }
+        qm31_equalverify
+
+        // drop random_coeff
+        { qm31_roll(7) }
+        OP_2DROP OP_2DROP
+
+        // absorb the mask values and then the composition evaluations, and draw the DEEP
+        // coefficient, after which the channel is kept in the altstack
+        for _ in 0..7 {
+            { qm31_copy(6) }
+        }
+        { 9 * 4 + 7 * 4 } OP_ROLL
+        { Sha256ChannelGadget::mix_felts(3) }
+        { Sha256ChannelGadget::mix_felts(4) }
+        { Sha256ChannelGadget::draw_felt_with_hint() }
+        qm31_toaltstack
+        OP_TOALTSTACK
+        qm31_fromaltstack
+
+        // compute the mask points z + G and z + 2G
+        { qm31_copy(9) }
+        { qm31_copy(9) }
+        { coset_shift_gadget(log_size) }
+        { qm31_copy(1) }
+        { qm31_copy(1) }
+        { coset_shift_gadget(log_size) }
+
+        for i in 0..N_QUERIES {
+            // open the trace, the composition polynomial, and the domain point at the position
+            { state_len - 1 } OP_PICK
+            { position_depth(i) + 1 } OP_PICK
+            { verify_columnar_leaf_gadget(1, logn) }
+            OP_TOALTSTACK
+            OP_0 OP_0 OP_0
+            OP_FROMALTSTACK
+
+            { state_len - 2 + 4 } OP_PICK
+            { position_depth(i) + 4 + 1 } OP_PICK
+            { verify_columnar_leaf_gadget(4, logn) }
+            for _ in 0..4 {
+                OP_TOALTSTACK
+            }
+            for _ in 0..4 {
+                OP_0 OP_0 OP_0
+                OP_FROMALTSTACK
+            }
+
+            { point_root }
+            { position_depth(i) + 5 * 4 + 1 } OP_PICK
+            { verify_columnar_leaf_gadget(2, logn) }
+            m31_point_to_qm31_gadget
+
+            // stack: state, t(p), c_0(p), c_1(p), c_2(p), c_3(p), p.x, p.y (qm31)
+            // check the quotient of each mask value at p, which are pulled from the hints
+            for k in 0..3 {
+                { qm31_copy(11 + k) }
+                { qm31_copy(mask_point_x[k] + k + 1) }
+                { qm31_copy(mask_point_y[k] + k + 2) }
+                { qm31_copy(k + 4) }
+                { qm31_copy(k + 4) }
+                { qm31_copy(17 + 2 * k) }
+                { qm31_copy(12 + k) }
+                qm31_from_bottom
+                { verify_all_column_oods_gadget(1) }
+            }
+
+            // check the quotient of each composition evaluation at p, and batch them
+            { qm31_copy(14) }
+            { qm31_copy(24) }
+            { qm31_copy(24) }
+            { qm31_copy(7) }
+            { qm31_copy(7) }
+            for k in 0..4 {
+                { qm31_copy(23 + 4 * k) }
+                { qm31_copy(14 + 2 * k) }
+                qm31_from_bottom
+            }
+            { verify_all_column_oods_gadget(4) }
+
+            // batch all the quotients into the DEEP quotient, which must be the leaf of FRI
+            { qm31_copy(15) }
+            for _ in 0..4 {
+                { qm31_roll(4) }
+            }
+            { alpha_powers_batch_gadget(4) }
+            for _ in 0..4 {
+                { leaf_depth(i) + 8 * 4 + 3 } OP_PICK
+            }
+            verify_first_layer_binding_gadget
+            OP_2DROP OP_2DROP
+
+            // drop the openings
+            for _ in 0..7 * 2 {
+                OP_2DROP
+            }
+        }
+
+        // drop the state, and keep the positions in the altstack
+        for _ in 0..state_len / 2 {
+            OP_2DROP
+        }
+        OP_FROMALTSTACK
+        for _ in 0..N_QUERIES {
+            OP_SWAP OP_TOALTSTACK
+        }
+
+        // stack: FRI proof body, channel
+        {
+            FRIGadget::check_fri_on_channel(
+                logn,
+                N_QUERIES,
+                POW_BITS,
+                twiddle_merkle_tree_root,
+            )
+        }
+
+        for _ in 0..num_fri_factors / 2 {
+            OP_2DROP
+        }
+        if num_fri_factors % 2 == 1 {
+            OP_DROP
+        }
+
+        // the openings are at the queries of FRI
+        for _ in 0..N_QUERIES {
+            OP_FROMALTSTACK
+        }
+        { bind_queries_to_openings_gadget(N_QUERIES) }
+
+        for _ in 0..num_fri_body / 2 {
+            OP_2DROP
+        }
+        if num_fri_body % 2 == 1 {
+            OP_DROP
+        }
+        OP_TRUE
+    };
+
+    (script, WitnessBuilder { log_size })
+}
+
+/// Compute the TapLeaf hash of the verifier assembled by `build_full_verifier` for the Fibonacci
+/// trace of size 2^log_size and its claim `fibonacci_claim(log_size)`.
+///
+/// The verifier depends only on log_size and not on the proof, so the leaf, and therefore the
+/// Taproot output committing to it, can be computed before any proof is generated.
+pub fn verifier_taproot_leaf(log_size: u32) -> TapLeafHash {
+    let (script, _) = build_full_verifier(log_size);
+    TapLeafHash::from_script(&script, LeafVersion::TapScript)
+}
+
+/// Builder of the witness for the verifier assembled by `build_full_verifier`.
+pub struct WitnessBuilder {
+    log_size: u32,
+}
+
+impl WitnessBuilder {
+    /// Produce the witness (the hints followed by the proof) for a proof from `prove_fibonacci`.
+    pub fn build(&self, proof: prover::Proof) -> Script {
+        let claim = fibonacci_claim(self.log_size);
+        let air = FibonacciAir::new(self.log_size, claim);
+        let logn = prover::evaluation_logn(&air);
+
+        // replay the channel, as `prover::verify` does
+        let channel = &mut BWSSha256Channel::new(fibonacci_channel_init_state(claim));
+        channel.mix_digest(proof.trace_root);
+        let (_, random_coeff_hint) = channel.draw_felt_and_hints();
+        channel.mix_digest(proof.composition_root);
+        let (oods_point, oods_point_hint) =
+            CirclePoint::<QM31>::get_random_point_with_hint(channel);
+        channel.mix_felts(&proof.trace_sampled_values.concat());
+        channel.mix_felts(&proof.composition_sampled_values);
+        let (_, deep_coeff_hint) = channel.draw_felt_and_hints();
+
+        let queries = proof.fri_proof.draw_queries(
+            &mut BWSSha256Channel::new(channel.digest),
+            logn,
+            POW_BITS,
+        );
+
+        let mask = &proof.trace_sampled_values[0];
+        let step = trace_step(self.log_size);
+        let mask_points = (0..3)
+            .map(|k| oods_point + step.mul(k as u128).into_ef::<QM31>())
+            .collect::<Vec<CirclePoint<QM31>>>();
+
+        let domain = CircleDomain::new(logn as u32);
+        let point_tree = evaluation_point_tree(logn);
+        let query_hints = queries
+            .iter()
+            .enumerate()
+            .map(|(i, &query)| {
+                let trace_opening = &proof.trace_openings[i];
+                let composition_opening = &proof.composition_openings[i];
+                let p = domain
+                    .point_at(bit_reverse_index(query, logn))
+                    .into_ef::<QM31>();
+
+                let quotients = mask_points
+                    .iter()
+                    .zip(mask.iter())
+                    .map(|(&z, &fz)| {
+                        trace_quotient_at_point(z, fz, p, trace_opening.leaf[0].into())
+                    })
+                    .chain(
+                        proof
+                            .composition_sampled_values
+                            .iter()
+                            .zip(composition_opening.leaf.iter())
+                            .map(|(&c, &cp)| trace_quotient_at_point(oods_point, c, p, cp.into())),
+                    )
+                    .collect::<Vec<QM31>>();
+
+                script! {
+                    { MerkleTreeGadget::push_columnar_merkle_tree_proof(trace_opening) }
+                    { MerkleTreeGadget::push_columnar_merkle_tree_proof(composition_opening) }
+                    { MerkleTreeGadget::push_columnar_merkle_tree_proof(&point_tree.query(query)) }
+                    for quotient in quotients.iter() {
+                        { *quotient }
+                    }
+                }
+            })
+            .collect::<Vec<Script>>();
+
+        script! {
+            { Sha256ChannelGadget::push_draw_hint(&random_coeff_hint) }
+            { Sha256ChannelGadget::push_draw_hint(&oods_point_hint) }
+            { OODSGadget::push_random_point_hint(&oods_point) }
+            { FibonacciAirGadget::push_eval_constraints_hint(&air, oods_point, mask) }
+            { Sha256ChannelGadget::push_draw_hint(&deep_coeff_hint) }
+            for hint in query_hints {
+                { hint }
+            }
+            { FRIGadget::push_fri_witness(channel.digest, logn, &proof.fri_proof, POW_BITS) }
+            for query in queries.iter() {
+                { *query as u32 }
+            }
+            { proof.trace_root }
+            { proof.composition_root }
+            for eval in proof.composition_sampled_values.iter().rev() {
+                { *eval }
+            }
+            for value in mask.iter().rev() {
+                { *value }
+            }
+        }
+    }
+}
+
+/// Gadget for the constraints of `FibonacciAir`, whose trace is over the canonic coset, unlike
+/// stwo's Fibonacci component, which `FibonacciCompositionGadget` follows.
+pub struct FibonacciAirGadget;
+
+impl FibonacciAirGadget {
+    /// Push the hints for `eval_constraints`, which are the boundary quotient and then the step
+    /// quotient at z, given the mask values f(z), f(z + G), f(z + 2G).
+    pub fn push_eval_constraints_hint(
+        air: &FibonacciAir,
+        z: CirclePoint<QM31>,
+        mask: &[QM31],
+    ) -> Script {
+        script! {
+            { air.boundary_quotient(z, mask[0]) }
+            { air.step_quotient(z, mask[0], mask[1], mask[2]) }
+        }
+    }
+
+    /// Compute the boundary quotient of `FibonacciAir`, i.e., (f(z) - L(z)) / V(z), where L is the
+    /// line from `boundary_line` that is 1 at the first row and the claim at the last row, which
+    /// are conjugate points, so that L only depends on z.y, and V is the pair vanishing polynomial
+    /// of the two rows.
+    ///
+    /// hint:
+    ///  the boundary quotient
+    ///
+    /// input:
+    ///  f(z)
+    ///  z.x
+    ///  z.y
+    ///
+    /// output:
+    ///  the boundary quotient
+    fn boundary_quotient(log_size: u32, claim: M31) -> Script {
+        let first = trace_point(log_size, 0);
+        let last = trace_point(log_size, (1 << log_size) - 1);
+        assert_ne!(first.y, last.y);
+        let slope = (claim - M31::one()) * (last.y - first.y).inverse();
+
+        script! {
+            qm31_dup
+            qm31_toaltstack
+            { qm31_roll(1) }
+            qm31_toaltstack //stack: f(z), z.y; altstack: z.y, z.x
+
+            { slope }
+            qm31_mul_m31
+            { M31::one() - first.y * slope }
+            m31_add // L(z) = z.y * slope + 1 - first.y * slope
+
+            qm31_sub //num = f(z) - L(z)
+
+            qm31_fromaltstack
+            qm31_fromaltstack
+            { ConstraintsGadget::pair_vanishing(first.into_ef(), last.into_ef()) } //denom
+
+            qm31_from_bottom //num/denom
+            qm31_dup
+            qm31_toaltstack
+            qm31_mul
+            qm31_equalverify
+            qm31_fromaltstack
+        }
+    }
+
+    /// Evaluate the composition polynomial of `FibonacciAir` at z, as in `eval_constraints`.
+    ///
+    /// hint:
+    ///  as pushed by `push_eval_constraints_hint`
+    ///
+    /// input:
+    ///  alpha
+    ///  f(z + 2G)
+    ///  f(z + G)
+    ///  f(z) (QM31)
+    ///  z.x
+    ///  z.y
+    ///
+    /// output:
+    ///  alpha * step quotient + boundary quotient
+    pub fn eval_constraints(log_size: u32, claim: M31) -> Script {
+        script! {
+            { qm31_copy(2) }
+            { qm31_copy(2) }
+            { qm31_copy(2) }
+            { Self::boundary_quotient(log_size, claim) }
+            qm31_toaltstack
+
+            {
+                FibonacciCompositionGadget::step_constraint_eval_quotient_on_coset(
+                    CanonicCoset::new(log_size).coset()
+                )
+            }
+            qm31_mul
+
+            qm31_fromaltstack
+            qm31_add
+        }
+    }
+}
+
 /// Gadget for Fibonacci composition polynomial-related operations.
 pub struct FibonacciCompositionGadget;
 
 impl FibonacciCompositionGadget {
     ///Hint
-    #[allow(dead_code)]
     fn step_constraint_eval_quotient_by_mask_hint(
         log_size: u32,
         claim: M31,
@@ -48,10 +534,13 @@ impl FibonacciCompositionGadget {
     /// z.y
     ///output:
     /// num/denom
-    #[allow(dead_code)]
     fn step_constraint_eval_quotient_by_mask(log_size: u32) -> Script {
-        let constraint_zero_domain = Coset::subgroup(log_size);
+        Self::step_constraint_eval_quotient_on_coset(Coset::subgroup(log_size))
+    }
 
+    /// Computes the step constraint as `step_constraint_eval_quotient_by_mask` does, for a trace
+    /// over the coset, whose last two points are excluded from the constraint.
+    fn step_constraint_eval_quotient_on_coset(constraint_zero_domain: Coset) -> Script {
         script! {
             { qm31_copy(1) }
             { qm31_copy(1) }
@@ -99,7 +588,6 @@ impl FibonacciCompositionGadget {
     }
 
     ///Hint
-    #[allow(dead_code)]
     fn boundary_constraint_eval_quotient_by_mask_hint(
         log_size: u32,
         claim: M31,
@@ -127,7 +615,6 @@ impl FibonacciCompositionGadget {
     /// z.y
     ///output:
     /// num/denom
-    #[allow(dead_code)]
     fn boundary_constraint_eval_quotient_by_mask(log_size: u32, claim: M31) -> Script {
         let constraint_zero_domain = Coset::subgroup(log_size);
         let p = constraint_zero_domain.at(constraint_zero_domain.size() - 1);
@@ -140,7 +627,7 @@ impl FibonacciCompositionGadget {
             { (claim - M31::one()) * p.y.inverse() }
             qm31_mul_m31
 
-            { QM31::one() }
+            push_qm31_one
             qm31_add //linear = QM31::one() + z.y * (self.claim - M31::one()) * p.y.inverse();
 
             qm31_sub //num = f(z) - linear
@@ -163,7 +650,6 @@ impl FibonacciCompositionGadget {
     }
 
     ///Hint
-    #[allow(dead_code)]
     fn eval_composition_polynomial_at_point_hint(
         log_size: u32,
         claim: M31,
@@ -178,6 +664,32 @@ impl FibonacciCompositionGadget {
         }
     }
 
+    /// Combine the evaluations of the four coordinate polynomials of the composition polynomial
+    /// into the qm31 evaluation, i.e., e0 + e1 * i + e2 * u + e3 * iu.
+    ///
+    /// input:
+    ///  e0, e1, e2, e3 (QM31)
+    ///
+    /// output:
+    ///  e0 + e1 * i + e2 * u + e3 * iu
+    fn combine_partial_evals() -> Script {
+        let zero = M31::zero();
+        let one = M31::one();
+        script! {
+            { QM31::from_m31(zero, zero, zero, one) }
+            qm31_mul
+            qm31_swap
+            { QM31::from_m31(zero, zero, one, zero) }
+            qm31_mul
+            qm31_add
+            qm31_swap
+            { QM31::from_m31(zero, one, zero, zero) }
+            qm31_mul
+            qm31_add
+            qm31_add
+        }
+    }
+
     ///Computes the composition polynomial of Fibonacci
     ///input:
     /// alpha
@@ -188,7 +700,6 @@ impl FibonacciCompositionGadget {
     /// z.y
     ///output:
     /// alpha*step_constraint(f(z),f(Gz),f(G^2 z),z) + boundary_constraint(f(z),z,claim)
-    #[allow(dead_code)]
     fn eval_composition_polynomial_at_point(log_size: u32, claim: M31) -> Script {
         script! {
             { qm31_copy(2) }
@@ -208,9 +719,12 @@ impl FibonacciCompositionGadget {
 
 #[cfg(test)]
 mod test {
+    use crate::tests_utils::fixtures::random_qm31;
     use std::iter::zip;
 
+    use bitcoin::taproot::{LeafVersion, TapLeafHash};
     use itertools::Itertools;
+    use num_traits::One;
 
     use rand::{RngCore, SeedableRng};
     use rand_chacha::ChaCha20Rng;
@@ -218,6 +732,7 @@ mod test {
     use stwo_prover::{
         core::{
             air::{AirExt, ComponentTrace},
+            channel::{BWSSha256Channel, Channel},
             circle::CirclePoint,
             fields::{
                 m31::{self, M31},
@@ -229,11 +744,250 @@ mod test {
         examples::fibonacci::Fibonacci,
     };
 
+    use crate::channel::{ChannelWithHint, Sha256ChannelGadget};
+    use crate::fibonacci::{
+        absorb_trace_commitment, absorb_trace_commitment_gadget, build_full_verifier,
+        commit_trace_columns, fibonacci_channel_init_state, fibonacci_claim, fibonacci_trace,
+        prove_fibonacci, verifier_taproot_leaf, verify_fibonacci, FibonacciAir, FibonacciAirGadget,
+        TraceCommitmentMode,
+    };
+    use crate::fri::{TamperKind, N_QUERIES};
+    use crate::prover::{self, Air};
+    use crate::tests_utils::run::run_script;
     use crate::treepp::*;
     use crate::{
-        fibonacci::FibonacciCompositionGadget, tests_utils::report::report_bitcoin_script_size,
+        fibonacci::FibonacciCompositionGadget,
+        tests_utils::report::{assert_max_stack_depth, report_bitcoin_script_size},
     };
 
+    /// The maximum stack depth of the full verifier, including the witness, which is kept 10%
+    /// below the 1000-element limit of Bitcoin, so that a change that brings the verifier close to
+    /// the limit fails this test before it fails on chain.
+    const FULL_VERIFIER_MAX_STACK_DEPTH: usize = 900;
+
+    fn check_full_verifier(log_size: u32, max_stack_depth: Option<usize>) {
+        let claim = fibonacci_claim(log_size);
+
+        let (verifier_script, witness_builder) = build_full_verifier(log_size);
+        report_bitcoin_script_size(
+            "Fibonacci",
+            format!("full_verifier(log_size={})", log_size).as_str(),
+            verifier_script.len(),
+        );
+
+        let run = |proof: prover::Proof| {
+            run_script(script! {
+                { witness_builder.build(proof) }
+                { verifier_script.clone() }
+            })
+        };
+
+        let proof = prove_fibonacci(log_size, claim);
+        assert_eq!(verify_fibonacci(log_size, claim, proof.clone()), Ok(()));
+
+        let script = script! {
+            { witness_builder.build(proof.clone()) }
+            { verifier_script.clone() }
+        };
+        assert!(run_script(script.clone()));
+
+        if let Some(limit) = max_stack_depth {
+            assert!(assert_max_stack_depth(script, limit));
+        }
+
+        // a proof of another claim is not accepted
+        assert!(!run(prove_fibonacci(log_size, claim + M31::one())));
+
+        // tamper with f(z + G), which makes the composition check fail
+        let mut tampered = proof.clone();
+        tampered.trace_sampled_values[0][1] += QM31::one();
+        assert!(!run(tampered));
+
+        // tamper with a composition evaluation at z
+        let mut tampered = proof.clone();
+        tampered.composition_sampled_values[2] += QM31::one();
+        assert!(!run(tampered));
+
+        // tamper with an opening of the trace, which is not under the trace commitment
+        let mut tampered = proof.clone();
+        tampered.trace_openings[0].leaf[0] += M31::one();
+        assert!(!run(tampered));
+
+        // tamper with an opening of the composition polynomial
+        let mut tampered = proof.clone();
+        tampered.composition_openings[N_QUERIES - 1].leaf[3] += M31::one();
+        assert!(!run(tampered));
+
+        // tamper with the FRI proof, whose first layer is no longer the DEEP quotient
+        let mut tampered = proof;
+        tampered.fri_proof = tampered.fri_proof.tamper(TamperKind::BadFoldValue);
+        assert!(!run(tampered));
+    }
+
+    #[test]
+    fn test_absorb_trace_commitment() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let num_columns = 3;
+        let log_size = 5;
+
+        let columns = (0..num_columns)
+            .map(|_| {
+                (0..(1 << log_size))
+                    .map(|_| M31::reduce(prng.next_u64()))
+                    .collect::<Vec<M31>>()
+            })
+            .collect::<Vec<Vec<M31>>>();
+        let init_state = fibonacci_channel_init_state(fibonacci_claim(log_size));
+
+        for mode in [
+            TraceCommitmentMode::SeparateRoots,
+            TraceCommitmentMode::CombinedRoot,
+        ] {
+            let absorb_script = absorb_trace_commitment_gadget(num_columns, mode);
+            report_bitcoin_script_size(
+                "Fibonacci",
+                format!("absorb_trace_commitment({}, {:?})", num_columns, mode).as_str(),
+                absorb_script.len(),
+            );
+
+            let roots = commit_trace_columns(&columns, mode);
+            assert_eq!(
+                roots.len(),
+                if mode == TraceCommitmentMode::SeparateRoots {
+                    num_columns
+                } else {
+                    1
+                }
+            );
+
+            let mut channel = BWSSha256Channel::new(init_state);
+            absorb_trace_commitment(&mut channel, &roots);
+            let (random_coeff, hint) = channel.draw_felt_and_hints();
+
+            let script = script! {
+                { Sha256ChannelGadget::push_draw_hint(&hint) }
+                for root in roots.iter().rev() {
+                    { *root }
+                }
+                { init_state }
+                { absorb_script.clone() }
+                { Sha256ChannelGadget::draw_felt_with_hint() }
+                { random_coeff }
+                qm31_equalverify
+                { channel.digest }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_build_full_verifier() {
+        check_full_verifier(5, Some(FULL_VERIFIER_MAX_STACK_DEPTH));
+    }
+
+    #[test]
+    fn test_build_full_verifier_large() {
+        // the witness of the FRI proof for this size exceeds the stack limit
+        check_full_verifier(12, None);
+    }
+
+    #[test]
+    fn test_build_full_verifier_bad_trace() {
+        let log_size = 5;
+        let claim = fibonacci_claim(log_size);
+        let air = FibonacciAir::new(log_size, claim);
+
+        let (verifier_script, witness_builder) = build_full_verifier(log_size);
+
+        // a trace that breaks the transition, whose composition polynomial is not of low degree
+        let mut trace = fibonacci_trace(1 << log_size);
+        trace[10] += M31::one();
+        let proof = prover::prove(
+            &air,
+            &[trace],
+            &mut BWSSha256Channel::new(fibonacci_channel_init_state(claim)),
+        );
+        assert!(verify_fibonacci(log_size, claim, proof.clone()).is_err());
+
+        let script = script! {
+            { witness_builder.build(proof) }
+            { verifier_script }
+        };
+        assert!(!run_script(script));
+    }
+
+    #[test]
+    fn test_verifier_taproot_leaf() {
+        let log_size = 5;
+        let claim = fibonacci_claim(log_size);
+
+        let leaf = verifier_taproot_leaf(log_size);
+        assert_eq!(leaf, verifier_taproot_leaf(log_size));
+        assert_ne!(leaf, verifier_taproot_leaf(log_size + 1));
+
+        // the proof is checked by the verifier committed in the leaf
+        let proof = prove_fibonacci(log_size, claim);
+
+        let (verifier_script, witness_builder) = build_full_verifier(log_size);
+        assert_eq!(
+            TapLeafHash::from_script(&verifier_script, LeafVersion::TapScript),
+            leaf
+        );
+
+        let script = script! {
+            { witness_builder.build(proof) }
+            { verifier_script }
+        };
+        assert!(run_script(script));
+    }
+
+    #[test]
+    fn test_fibonacci_air_eval_constraints() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let log_size = 5;
+        let claim = fibonacci_claim(log_size);
+        let air = FibonacciAir::new(log_size, claim);
+
+        let eval_script = FibonacciAirGadget::eval_constraints(log_size, claim);
+        report_bitcoin_script_size(
+            "Fibonacci",
+            format!("air_eval_constraints(log_size={})", log_size).as_str(),
+            eval_script.len(),
+        );
+
+        let z = CirclePoint {
+            x: random_qm31(&mut prng),
+            y: random_qm31(&mut prng),
+        };
+        let mask = vec![
+            random_qm31(&mut prng),
+            random_qm31(&mut prng),
+            random_qm31(&mut prng),
+        ];
+        let random_coeff = random_qm31(&mut prng);
+        let expected = air.eval_constraints(z, &[mask.clone()], random_coeff);
+
+        let script = script! {
+            { FibonacciAirGadget::push_eval_constraints_hint(&air, z, &mask) }
+            { random_coeff }
+            { mask[2] }
+            { mask[1] }
+            { mask[0] }
+            { z.x }
+            { z.y }
+            { eval_script.clone() }
+            { expected }
+            qm31_equalverify
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+    }
+
     #[test]
     fn test_eval_composition_polynomial_at_point() {
         let log_size = 5;
@@ -263,26 +1017,11 @@ mod test {
         );
 
         for _ in 0..20 {
-            let random_coeff = QM31::from_m31(
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-            );
+            let random_coeff = random_qm31(&mut prng);
 
             let z = CirclePoint {
-                x: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
-                y: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
             };
 
             let points = fib.air.mask_points(z);
@@ -342,26 +1081,11 @@ mod test {
 
         for _ in 0..20 {
             let z = CirclePoint {
-                x: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
-                y: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
             };
 
-            let fz = QM31::from_m31(
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-            );
+            let fz = random_qm31(&mut prng);
 
             let res = fib
                 .air
@@ -405,40 +1129,15 @@ mod test {
 
         for _ in 0..20 {
             let z = CirclePoint {
-                x: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
-                y: QM31::from_m31(
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                    M31::reduce(prng.next_u64()),
-                ),
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
             };
 
-            let fz = QM31::from_m31(
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-            );
+            let fz = random_qm31(&mut prng);
 
-            let fgz = QM31::from_m31(
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-            );
+            let fgz = random_qm31(&mut prng);
 
-            let fggz = QM31::from_m31(
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-                M31::reduce(prng.next_u64()),
-            );
+            let fggz = random_qm31(&mut prng);
 
             let res = fib
                 .air
@@ -2,11 +2,21 @@ mod bitcoin_script;
 pub use bitcoin_script::*;
 
 use crate::channel::{ChannelWithHint, DrawQM31Hints};
+use crate::merkle_tree::ColumnarMerkleTree;
+use crate::oods::OODS;
+use crate::prover::{self, boundary_line, trace_point, trace_vanishing};
+use num_traits::One;
 use stwo_prover::core::air::{Air, AirExt};
-use stwo_prover::core::channel::BWSSha256Channel;
+use stwo_prover::core::channel::{BWSSha256Channel, Channel};
+use stwo_prover::core::circle::CirclePoint;
+use stwo_prover::core::constraints::pair_vanishing;
+use stwo_prover::core::fields::m31::{BaseField, M31};
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::fields::{FieldExpOps, IntoSlice};
 use stwo_prover::core::pcs::CommitmentSchemeVerifier;
 use stwo_prover::core::prover::{StarkProof, VerificationError};
-use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+use stwo_prover::core::vcs::bws_sha256_hash::{BWSSha256Hash, BWSSha256Hasher};
+use stwo_prover::core::vcs::hasher::Hasher;
 
 /// All the hints for the verifier (note: proof is also provided as a hint).
 pub struct VerifierHints {
@@ -15,6 +25,334 @@ pub struct VerifierHints {
 
     /// random_coeff comes from adding `proof.commitments[0]` to the channel.
     pub random_coeff_hint: DrawQM31Hints,
+
+    /// The out-of-domain sampling point, drawn after adding `proof.commitments[1]` to the channel.
+    pub oods_point: CirclePoint<QM31>,
+
+    /// oods_point comes from drawing a qm31 element from the channel.
+    pub oods_point_hint: DrawQM31Hints,
+}
+
+/// The initial channel state for a Fibonacci proof, which is the hash of the claim.
+pub fn fibonacci_channel_init_state(claim: M31) -> BWSSha256Hash {
+    BWSSha256Hasher::hash(BaseField::into_slice(&[claim]))
+}
+
+/// The claim of a Fibonacci trace of size 2^log_size, which is its last element, where the trace
+/// starts with 1, 1 and follows a_{i+2} = a_i^2 + a_{i+1}^2.
+pub fn fibonacci_claim(log_size: u32) -> M31 {
+    fibonacci_claim_for_length(1 << log_size)
+}
+
+/// The claim of a Fibonacci trace with `len` real rows, which is its last real element.
+pub fn fibonacci_claim_for_length(len: usize) -> M31 {
+    *fibonacci_trace(len).last().unwrap()
+}
+
+/// The first `len` rows of the Fibonacci trace, which starts with 1, 1 and follows
+/// a_{i+2} = a_i^2 + a_{i+1}^2.
+pub fn fibonacci_trace(len: usize) -> Vec<M31> {
+    assert!(len > 0);
+    let mut trace = vec![M31::one(); len.min(2)];
+    while trace.len() < len {
+        let n = trace.len();
+        trace.push(trace[n - 2].square() + trace[n - 1].square());
+    }
+    trace
+}
+
+/// The statement of stwo's Fibonacci example as an AIR for `prover`: the trace of 2^log_size rows
+/// starts with 1, follows a_{i+2} = a_i^2 + a_{i+1}^2, and ends with the claim.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FibonacciAir {
+    /// The log of the number of rows.
+    pub log_size: u32,
+    /// The last row.
+    pub claim: M31,
+}
+
+impl FibonacciAir {
+    /// Create the AIR of a trace of 2^log_size rows that ends with the claim.
+    pub fn new(log_size: u32, claim: M31) -> Self {
+        assert!(log_size >= 2);
+        Self { log_size, claim }
+    }
+
+    /// The quotient of the transition constraint at the point, which does not apply to the last
+    /// two rows, whose next rows wrap around.
+    pub fn step_quotient(&self, point: CirclePoint<QM31>, f0: QM31, f1: QM31, f2: QM31) -> QM31 {
+        let n = 1 << self.log_size;
+        (f0.square() + f1.square() - f2)
+            * pair_vanishing(
+                trace_point(self.log_size, n - 2).into_ef(),
+                trace_point(self.log_size, n - 1).into_ef(),
+                point,
+            )
+            * trace_vanishing(self.log_size, point).inverse()
+    }
+
+    /// The quotient of the boundary constraints at the point, i.e., the first row is 1 and the
+    /// last row is the claim.
+    pub fn boundary_quotient(&self, point: CirclePoint<QM31>, f0: QM31) -> QM31 {
+        let first = trace_point(self.log_size, 0);
+        let last = trace_point(self.log_size, (1 << self.log_size) - 1);
+        (f0 - boundary_line(first, M31::one(), last, self.claim, point))
+            * pair_vanishing(first.into_ef(), last.into_ef(), point).inverse()
+    }
+}
+
+impl prover::Air for FibonacciAir {
+    fn log_size(&self) -> u32 {
+        self.log_size
+    }
+
+    fn n_columns(&self) -> usize {
+        1
+    }
+
+    fn mask_offsets(&self) -> Vec<usize> {
+        vec![0, 1, 2]
+    }
+
+    fn max_degree(&self) -> usize {
+        2
+    }
+
+    fn eval_constraints(
+        &self,
+        point: CirclePoint<QM31>,
+        mask: &[Vec<QM31>],
+        random_coeff: QM31,
+    ) -> QM31 {
+        let (f0, f1, f2) = (mask[0][0], mask[0][1], mask[0][2]);
+        self.step_quotient(point, f0, f1, f2) * random_coeff + self.boundary_quotient(point, f0)
+    }
+}
+
+/// Prove the Fibonacci trace of size 2^log_size with the given claim, see `FibonacciAir`, on the
+/// channel that starts from `fibonacci_channel_init_state(claim)`.
+pub fn prove_fibonacci(log_size: u32, claim: M31) -> prover::Proof {
+    prover::prove(
+        &FibonacciAir::new(log_size, claim),
+        &[fibonacci_trace(1 << log_size)],
+        &mut BWSSha256Channel::new(fibonacci_channel_init_state(claim)),
+    )
+}
+
+/// Verify a proof from `prove_fibonacci` of the Fibonacci trace of size 2^log_size with the given
+/// claim.
+pub fn verify_fibonacci(
+    log_size: u32,
+    claim: M31,
+    proof: prover::Proof,
+) -> Result<(), prover::VerificationError> {
+    prover::verify(
+        &FibonacciAir::new(log_size, claim),
+        proof,
+        &mut BWSSha256Channel::new(fibonacci_channel_init_state(claim)),
+    )
+}
+
+/// A trace column whose number of real rows is not necessarily a power of two, padded to the next
+/// power of two by repeating its last real row.
+///
+/// The padding rows do not follow the transition constraint, so the constraints only apply to the
+/// real rows, and the verifier must know `real_len` (as part of the claim) to place them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaddedColumn {
+    /// The real rows followed by the padding rows.
+    pub values: Vec<M31>,
+    /// The number of real rows.
+    pub real_len: usize,
+}
+
+impl PaddedColumn {
+    /// Pad the real rows of a column to the next power of two.
+    pub fn new(mut values: Vec<M31>) -> Self {
+        assert!(!values.is_empty());
+        let real_len = values.len();
+        let last = values[real_len - 1];
+        values.resize(real_len.next_power_of_two(), last);
+        Self { values, real_len }
+    }
+
+    /// The log size of the padded column.
+    pub fn log_size(&self) -> u32 {
+        self.values.len().ilog2()
+    }
+
+    /// Whether the row is a padding row.
+    pub fn is_padding(&self, row: usize) -> bool {
+        row >= self.real_len
+    }
+}
+
+/// Check the Fibonacci constraints on the real rows of a padded trace: the first two rows are 1, 1,
+/// the transition a_{i+2} = a_i^2 + a_{i+1}^2 holds whenever row i+2 is a real row, and the last
+/// real row equals the claim.
+pub fn check_padded_fibonacci_trace(column: &PaddedColumn, claim: M31) -> bool {
+    let real = &column.values[..column.real_len];
+    real.iter().take(2).all(|v| *v == M31::one())
+        && real
+            .windows(3)
+            .all(|w| w[2] == w[0].square() + w[1].square())
+        && real[real.len() - 1] == claim
+}
+
+/// The Fibonacci statement over a padded trace as an AIR for `prover`: the trace of `real_len`
+/// real rows, padded to 2^log_size rows, starts with 1, 1, follows a_{i+2} = a_i^2 + a_{i+1}^2
+/// whenever the row i+2 is a real row, and its last real row is the claim, as
+/// `check_padded_fibonacci_trace` checks.
+///
+/// The verifier must know `real_len`, which places the boundary constraint of the claim and the
+/// rows that the transition is gated off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PaddedFibonacciAir {
+    /// The log of the number of rows, including the padding rows.
+    pub log_size: u32,
+    /// The number of real rows.
+    pub real_len: usize,
+    /// The last real row.
+    pub claim: M31,
+}
+
+impl PaddedFibonacciAir {
+    /// Create the AIR of a trace of `real_len` real rows padded to 2^log_size rows.
+    pub fn new(log_size: u32, real_len: usize, claim: M31) -> Self {
+        let n = 1 << log_size;
+        assert!(real_len >= 3 && real_len <= n);
+        // the gate adds one degree per pair of excluded rows, on top of n / 2 for the transition
+        // quotient, which must stay below the degree bound n of the composition polynomial
+        assert!(n / 2 + (n - real_len + 3) / 2 < n);
+        Self {
+            log_size,
+            real_len,
+            claim,
+        }
+    }
+
+    /// The gate of the transition, which vanishes on the rows i whose row i + 2 is not a real row,
+    /// i.e., the last two real rows and the padding rows, as the product of the pair vanishing
+    /// polynomials of consecutive excluded rows.
+    fn transition_gate(&self, point: CirclePoint<QM31>) -> QM31 {
+        let n = 1 << self.log_size;
+        let excluded = (self.real_len - 2..n).collect::<Vec<usize>>();
+        excluded.chunks(2).fold(QM31::one(), |acc, rows| {
+            // an odd row out is paired with the excluded row before it
+            let (a, b) = if rows.len() == 2 {
+                (rows[0], rows[1])
+            } else {
+                (rows[0], rows[0] - 1)
+            };
+            acc * pair_vanishing(
+                trace_point(self.log_size, a).into_ef(),
+                trace_point(self.log_size, b).into_ef(),
+                point,
+            )
+        })
+    }
+}
+
+impl prover::Air for PaddedFibonacciAir {
+    fn log_size(&self) -> u32 {
+        self.log_size
+    }
+
+    fn n_columns(&self) -> usize {
+        1
+    }
+
+    fn mask_offsets(&self) -> Vec<usize> {
+        vec![0, 1, 2]
+    }
+
+    fn max_degree(&self) -> usize {
+        2
+    }
+
+    fn eval_constraints(
+        &self,
+        point: CirclePoint<QM31>,
+        mask: &[Vec<QM31>],
+        random_coeff: QM31,
+    ) -> QM31 {
+        let first = trace_point(self.log_size, 0);
+        let second = trace_point(self.log_size, 1);
+        let last = trace_point(self.log_size, self.real_len - 1);
+        let (f0, f1, f2) = (mask[0][0], mask[0][1], mask[0][2]);
+
+        let transition = (f0.square() + f1.square() - f2)
+            * self.transition_gate(point)
+            * trace_vanishing(self.log_size, point).inverse();
+        let start = (f0 - boundary_line(first, M31::one(), second, M31::one(), point))
+            * pair_vanishing(first.into_ef(), second.into_ef(), point).inverse();
+        let end = (f0 - boundary_line(first, M31::one(), last, self.claim, point))
+            * pair_vanishing(first.into_ef(), last.into_ef(), point).inverse();
+
+        (transition * random_coeff + start) * random_coeff + end
+    }
+}
+
+/// The initial channel state for a proof of a padded Fibonacci trace, which is the hash of the
+/// claim and the number of real rows, so that the proof is bound to both.
+pub fn padded_fibonacci_channel_init_state(real_len: usize, claim: M31) -> BWSSha256Hash {
+    BWSSha256Hasher::hash(BaseField::into_slice(&[claim, M31::from(real_len as u32)]))
+}
+
+/// Prove a padded Fibonacci trace with the given claim, see `PaddedFibonacciAir`.
+pub fn prove_padded_fibonacci(column: &PaddedColumn, claim: M31) -> prover::Proof {
+    let air = PaddedFibonacciAir::new(column.log_size(), column.real_len, claim);
+    prover::prove(
+        &air,
+        &[column.values.clone()],
+        &mut BWSSha256Channel::new(padded_fibonacci_channel_init_state(column.real_len, claim)),
+    )
+}
+
+/// Verify a proof from `prove_padded_fibonacci` of a trace with `real_len` real rows, padded to the
+/// next power of two, and the given claim.
+pub fn verify_padded_fibonacci(
+    real_len: usize,
+    claim: M31,
+    proof: prover::Proof,
+) -> Result<(), prover::VerificationError> {
+    let air = PaddedFibonacciAir::new(real_len.next_power_of_two().ilog2(), real_len, claim);
+    prover::verify(
+        &air,
+        proof,
+        &mut BWSSha256Channel::new(padded_fibonacci_channel_init_state(real_len, claim)),
+    )
+}
+
+/// How the columns of a trace are committed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraceCommitmentMode {
+    /// Each column has its own Merkle tree, and the roots are absorbed in the column order.
+    #[default]
+    SeparateRoots,
+    /// All the columns share one Merkle tree, whose leaves are the rows, and its root is absorbed.
+    CombinedRoot,
+}
+
+/// Commit to the columns of a trace, and return the roots in the order that they are absorbed.
+pub fn commit_trace_columns(columns: &[Vec<M31>], mode: TraceCommitmentMode) -> Vec<BWSSha256Hash> {
+    assert!(!columns.is_empty());
+    match mode {
+        TraceCommitmentMode::SeparateRoots => columns
+            .iter()
+            .map(|column| ColumnarMerkleTree::new(vec![column.clone()]).root_hash)
+            .collect(),
+        TraceCommitmentMode::CombinedRoot => {
+            vec![ColumnarMerkleTree::new(columns.to_vec()).root_hash]
+        }
+    }
+}
+
+/// Absorb the roots of a trace commitment, as returned by `commit_trace_columns`.
+pub fn absorb_trace_commitment(channel: &mut BWSSha256Channel, roots: &[BWSSha256Hash]) {
+    for root in roots.iter() {
+        channel.mix_digest(*root);
+    }
 }
 
 /// A verifier program that generates hints.
@@ -37,14 +375,25 @@ pub fn verify_with_hints(
 
     let _ = random_coeff;
 
+    // Draw the out-of-domain sampling point.
+    let (oods_point, oods_point_hint) = CirclePoint::<QM31>::get_random_point_with_hint(channel);
+
     Ok(VerifierHints {
         commitments: [proof.commitments[0], proof.commitments[1]],
         random_coeff_hint,
+        oods_point,
+        oods_point_hint,
     })
 }
 
 #[cfg(test)]
 mod test {
+    use crate::fibonacci::{
+        check_padded_fibonacci_trace, fibonacci_claim, fibonacci_claim_for_length, fibonacci_trace,
+        prove_fibonacci, prove_padded_fibonacci, verify_fibonacci, verify_padded_fibonacci,
+        PaddedColumn,
+    };
+    use num_traits::One;
     use stwo_prover::core::channel::{BWSSha256Channel, Channel};
     use stwo_prover::core::fields::m31::{BaseField, M31};
     use stwo_prover::core::fields::IntoSlice;
@@ -53,6 +402,101 @@ mod test {
     use stwo_prover::core::vcs::hasher::Hasher;
     use stwo_prover::examples::fibonacci::Fibonacci;
 
+    #[test]
+    fn test_fibonacci_claim() {
+        assert_eq!(fibonacci_claim(5), M31::reduce(443693538));
+    }
+
+    #[test]
+    fn test_prove_fibonacci() {
+        let log_size = 5;
+        let claim = fibonacci_claim(log_size);
+
+        let proof = prove_fibonacci(log_size, claim);
+        assert_eq!(verify_fibonacci(log_size, claim, proof.clone()), Ok(()));
+        assert!(verify_fibonacci(log_size, claim + M31::one(), proof).is_err());
+
+        // a claim that is not the last row of the trace cannot be proven
+        let proof = prove_fibonacci(log_size, claim + M31::one());
+        assert!(verify_fibonacci(log_size, claim + M31::one(), proof).is_err());
+    }
+
+    #[test]
+    fn test_padded_fibonacci_trace() {
+        let real_len = 20;
+        let claim = fibonacci_claim_for_length(real_len);
+
+        let column = PaddedColumn::new(fibonacci_trace(real_len));
+        assert_eq!(column.values.len(), 32);
+        assert_eq!(column.log_size(), 5);
+        assert!(!column.is_padding(19));
+        assert!(column.is_padding(20));
+        assert!(column.values[20..].iter().all(|v| *v == claim));
+
+        assert!(check_padded_fibonacci_trace(&column, claim));
+
+        // the padding rows break the transition constraint, so it must not apply to them
+        let unpadded = PaddedColumn {
+            values: column.values.clone(),
+            real_len: 32,
+        };
+        assert!(!check_padded_fibonacci_trace(&unpadded, claim));
+
+        // the claim is bound to the last real row
+        assert!(!check_padded_fibonacci_trace(
+            &column,
+            fibonacci_claim_for_length(real_len - 1)
+        ));
+
+        // a tampered real row is still caught
+        let mut tampered = column.clone();
+        tampered.values[10] += M31::one();
+        assert!(!check_padded_fibonacci_trace(&tampered, claim));
+
+        // a power-of-two length needs no padding
+        let exact = PaddedColumn::new(fibonacci_trace(32));
+        assert_eq!(exact.real_len, 32);
+        assert!(check_padded_fibonacci_trace(&exact, fibonacci_claim(5)));
+    }
+
+    #[test]
+    fn test_prove_padded_fibonacci() {
+        let real_len = 20;
+        let claim = fibonacci_claim_for_length(real_len);
+        let column = PaddedColumn::new(fibonacci_trace(real_len));
+        assert_eq!(column.values.len(), 32);
+
+        let proof = prove_padded_fibonacci(&column, claim);
+        assert_eq!(
+            verify_padded_fibonacci(real_len, claim, proof.clone()),
+            Ok(())
+        );
+
+        // the proof is bound to the number of real rows and to the claim
+        assert!(verify_padded_fibonacci(real_len - 1, claim, proof.clone()).is_err());
+        assert!(
+            verify_padded_fibonacci(real_len, fibonacci_claim_for_length(real_len - 1), proof)
+                .is_err()
+        );
+
+        // the padding rows break the transition, so the trace cannot be proven with the
+        // constraints applied to all of its rows
+        let unpadded = PaddedColumn {
+            values: column.values.clone(),
+            real_len: 32,
+        };
+        let proof = prove_padded_fibonacci(&unpadded, claim);
+        assert!(verify_padded_fibonacci(32, claim, proof).is_err());
+
+        // a power-of-two length needs no padding
+        let exact = PaddedColumn::new(fibonacci_trace(32));
+        let proof = prove_padded_fibonacci(&exact, fibonacci_claim(5));
+        assert_eq!(
+            verify_padded_fibonacci(32, fibonacci_claim(5), proof),
+            Ok(())
+        );
+    }
+
     #[test]
     fn test_fib_prove() {
         const FIB_LOG_SIZE: u32 = 5;
@@ -1,25 +1,39 @@
-use crate::channel::{ChannelWithHint, DrawQM31Hints, Sha256Channel, Sha256ChannelGadget};
-use crate::fri::{FriProof, N_QUERIES};
+use crate::channel::{
+    assert_final_digest_gadget, ChannelWithHint, DrawQM31Hints, Sha256Channel, Sha256ChannelGadget,
+};
+use crate::fri::{
+    composition_fri_logn, degree_correction_factor, layer_domain, num_fri_layers,
+    DeduplicatedWitness, FriProof, LAST_LAYER_LOG_SIZE, N_QUERIES,
+};
 use crate::merkle_tree::MerkleTreeGadget;
+use crate::pow::{hash_with_nonce, verify_multi_pow_gadget, PowGadget};
 use crate::treepp::*;
 use crate::twiddle_merkle_tree::TwiddleMerkleTreeGadget;
 use crate::utils::copy_to_altstack_top_item_first_in;
-use crate::utils::{limb_to_be_bits, limb_to_be_bits_toaltstack};
+use crate::utils::{
+    alpha_powers_batch_gadget, assert_index_in_range_gadget, get_twiddles, hash_felt_gadget,
+    limb_to_be_bits, limb_to_be_bits_toaltstack, pick_hint_gadget, pull_hint_gadget,
+    qm31_horner_gadget, qm31_is_zero_verify, qm31_restore, qm31_save,
+};
 use rust_bitcoin_m31::{
-    qm31_add, qm31_equalverify, qm31_fromaltstack, qm31_mul, qm31_mul_m31, qm31_over, qm31_roll,
-    qm31_sub, qm31_swap, qm31_toaltstack,
+    qm31_add, qm31_copy, qm31_equalverify, qm31_fromaltstack, qm31_mul, qm31_mul_m31, qm31_over,
+    qm31_roll, qm31_sub, qm31_swap, qm31_toaltstack,
 };
 use stwo_prover::core::channel::Channel;
+use stwo_prover::core::fields::FieldExpOps;
+use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
 
 /// Gadget for FRI.
 pub struct FRIGadget;
 
 impl FRIGadget {
-    /// Push the hints for Fiat-Shamir.
+    /// Push the hints for Fiat-Shamir, including the hint of the proof-of-work if `pow_bits` is
+    /// nonzero.
     pub fn push_fiat_shamir_hints(
         channel: &mut Sha256Channel,
         logn: usize,
         proof: &FriProof,
+        pow_bits: usize,
     ) -> Script {
         let mut factors_hints = Vec::<DrawQM31Hints>::new();
 
@@ -30,38 +44,138 @@ impl FRIGadget {
         }
         channel.mix_felts(&proof.last_layer);
 
-        let res = channel.draw_5queries(logn);
-        let queries_hint = res.1;
+        let pow_hint = if pow_bits > 0 {
+            let digest = channel.digest.as_ref().to_vec();
+            channel.digest = BWSSha256Hash::from(hash_with_nonce(&digest, proof.pow_nonce));
+            PowGadget::push_pow_hint(digest, proof.pow_nonce, pow_bits)
+        } else {
+            script! {}
+        };
+
+        let res = channel.draw_n_queries(proof.num_queries(), logn);
+        let queries_hints = res.1;
 
         script! {
             for hint in factors_hints.iter() {
                 { Sha256ChannelGadget::push_draw_hint(hint) }
             }
-            { Sha256ChannelGadget::push_draw_hint(&queries_hint) }
+            { pow_hint }
+            { Sha256ChannelGadget::push_draw_n_numbers_hint(&queries_hints) }
+            { proof.final_digest }
+        }
+    }
+
+    /// Push the witness for `check_fri`: all the hints, in the order that they are consumed, and
+    /// then the proof body.
+    pub fn push_fri_witness(
+        channel_init_state: BWSSha256Hash,
+        logn: usize,
+        proof: &FriProof,
+        pow_bits: usize,
+    ) -> Script {
+        script! {
+            // push all the hints
+            { Self::push_fiat_shamir_hints(&mut Sha256Channel::new(channel_init_state), logn, proof, pow_bits) }
+            { Self::push_twiddle_merkle_tree_proof(proof) }
+            for i in 0..proof.num_queries() {
+                { Self::push_single_query_merkle_tree_proof(i, proof) }
+            }
+
+            { Self::push_proof_body(proof) }
+        }
+    }
+
+    /// Push the witness for `check_fri_deduplicated`, as `push_fri_witness` does, but with the
+    /// hints of the twiddle tree and Merkle tree proofs replaced by the distinct values of the
+    /// deduplicated witness, each pushed once.
+    pub fn push_fri_witness_deduplicated(
+        channel_init_state: BWSSha256Hash,
+        logn: usize,
+        proof: &FriProof,
+        pow_bits: usize,
+        witness: &DeduplicatedWitness,
+    ) -> Script {
+        script! {
+            { Self::push_fiat_shamir_hints(&mut Sha256Channel::new(channel_init_state), logn, proof, pow_bits) }
+            for value in witness.values.iter() {
+                { *value }
+            }
+            { Self::push_proof_body(proof) }
+        }
+    }
+
+    /// Push the proof body, i.e., the leaves, the last layer, and the commitments.
+    fn push_proof_body(proof: &FriProof) -> Script {
+        script! {
+            // leaves
+            for elem in proof.leaves.iter().rev() {
+                { *elem }
+            }
+            // last layer
+            for elem in proof.last_layer.iter().rev() {
+                { *elem }
+            }
+            // commitments
+            for c in proof.commitments.iter().rev() {
+                { *c }
+            }
         }
     }
 
-    /// Check the Fiat-Shamir computation.
-    pub fn check_fiat_shamir(channel_init_state: &[u8], logn: usize, n_layers: usize) -> Script {
+    /// Check the Fiat-Shamir computation, which draws `num_queries` queries and ends with the
+    /// final digest of the proof, see `assert_final_digest_gadget`.
+    ///
+    /// If `pow_bits` is nonzero, the proof-of-work is verified after the last layer is absorbed,
+    /// as in `fri_verify`, so that the queries are drawn from the channel after grinding.
+    pub fn check_fiat_shamir(
+        channel_init_state: &[u8],
+        logn: usize,
+        n_layers: usize,
+        num_queries: usize,
+        pow_bits: usize,
+    ) -> Script {
         assert_eq!(channel_init_state.len(), 32);
-        let n_last_layer = 1 << (logn - n_layers);
         script! {
             { channel_init_state.to_vec() }
+            { Self::check_fiat_shamir_on_channel(logn, n_layers, num_queries, pow_bits) }
+        }
+    }
 
+    /// Check the Fiat-Shamir computation as `check_fiat_shamir` does, but starting from the
+    /// channel on the top of the stack, so that it continues the transcript of another verifier.
+    ///
+    /// input:
+    ///   last layer (some qm31), commitments (n_layers)
+    ///   channel
+    ///
+    /// output:
+    ///   queries (num_queries)
+    ///   factors (n_layers) qm31
+    pub fn check_fiat_shamir_on_channel(
+        logn: usize,
+        n_layers: usize,
+        num_queries: usize,
+        pow_bits: usize,
+    ) -> Script {
+        let n_last_layer = 1 << (logn - n_layers);
+        script! {
             for _ in 0..n_layers {
                 { Sha256ChannelGadget::mix_digest() }
                 { Sha256ChannelGadget::draw_felt_with_hint() }
                 qm31_toaltstack
             }
 
-            for _ in 0..n_last_layer {
-                { Sha256ChannelGadget::mix_felt() }
+            { Sha256ChannelGadget::mix_felts(n_last_layer) }
+
+            if pow_bits > 0 {
+                { verify_multi_pow_gadget(&[pow_bits]) }
             }
 
-            { Sha256ChannelGadget::draw_5numbers_with_hint(logn) }
+            { Sha256ChannelGadget::draw_n_numbers_with_hint(num_queries, logn) }
 
-            // remove the channel
-            5 OP_ROLL OP_DROP
+            // check the channel against the final digest of the proof
+            { num_queries } OP_ROLL
+            assert_final_digest_gadget
 
             for _ in 0..n_layers {
                 qm31_fromaltstack
@@ -80,22 +194,38 @@ impl FRIGadget {
 
     /// Check all the twiddle Merkle tree proofs.
     ///
-    /// hint: twiddle proof * 5 (as hints)
-    /// input: pos * 5
-    /// output: leaves * 5
+    /// hint: twiddle proof * num_queries (as hints)
+    /// input: pos * num_queries
+    /// output: leaves * num_queries
     pub fn check_twiddle_merkle_tree_proof(
         logn: usize,
+        num_queries: usize,
+        twiddle_merkle_tree_root: [u8; 32],
+    ) -> Script {
+        Self::check_twiddle_merkle_tree_proof_with_hints(
+            logn,
+            num_queries,
+            twiddle_merkle_tree_root,
+            &mut pull_hint_gadget,
+        )
+    }
+
+    /// As `check_twiddle_merkle_tree_proof`, but each hint is obtained with `pull_hint`.
+    fn check_twiddle_merkle_tree_proof_with_hints(
+        logn: usize,
+        num_queries: usize,
         twiddle_merkle_tree_root: [u8; 32],
+        pull_hint: &mut dyn FnMut() -> Script,
     ) -> Script {
         script! {
-            for _ in 0..N_QUERIES {
+            for _ in 0..num_queries {
                 OP_TOALTSTACK
             }
 
-            for _ in 0..N_QUERIES {
+            for _ in 0..num_queries {
                 { twiddle_merkle_tree_root.to_vec() }
                 OP_FROMALTSTACK
-                { TwiddleMerkleTreeGadget::query_and_verify(logn) }
+                { TwiddleMerkleTreeGadget::query_and_verify_with_hints(logn, pull_hint) }
             }
         }
     }
@@ -124,6 +254,14 @@ impl FRIGadget {
     /// output:
     ///   elems
     pub fn check_single_query_merkle_tree_proof(logn: usize) -> Script {
+        Self::check_single_query_merkle_tree_proof_with_hints(logn, &mut pull_hint_gadget)
+    }
+
+    /// As `check_single_query_merkle_tree_proof`, but each hint is obtained with `pull_hint`.
+    fn check_single_query_merkle_tree_proof_with_hints(
+        logn: usize,
+        pull_hint: &mut dyn FnMut() -> Script,
+    ) -> Script {
         script! {
             // convert query into bits
             { limb_to_be_bits(logn as u32) }
@@ -136,7 +274,7 @@ impl FRIGadget {
                 // copy the root
                 { logn } OP_ROLL
 
-                { MerkleTreeGadget::query_and_verify_internal(i, true) }
+                { MerkleTreeGadget::query_and_verify_internal_with_hints(i, true, pull_hint) }
 
                 qm31_toaltstack
             }
@@ -223,163 +361,981 @@ impl FRIGadget {
             qm31_equalverify
         }
     }
-}
-
-/// Gadget for FFT.
-pub struct FFTGadget;
 
-impl FFTGadget {
-    /// Perform inverse butterfly in Bitcoin script.
+    /// Verify a FRI proof, whose proof-of-work must have at least `pow_bits` bits, which is
+    /// assembled from the gadgets above.
+    ///
+    /// hints:
+    ///   as pushed by `push_fiat_shamir_hints`, `push_twiddle_merkle_tree_proof`, and then
+    ///   `push_single_query_merkle_tree_proof` for each query
+    ///
     /// input:
-    ///  v0 (qm31)
-    ///  v1 (qm31)
-    ///  itwid (m31)
+    ///   leaves (num_queries qm31), last layer (2 qm31), commitments (logn - 1)
     ///
     /// output:
-    ///  v0' (qm31)
-    ///  v1' (qm31)
-    pub fn ibutterfly() -> Script {
+    ///   last layer (2 qm31), commitments (logn - 1)
+    ///   queries (num_queries)
+    ///   alphas (logn - 1) qm31
+    ///   twiddle factors num_queries * (logn - 1) m31
+    pub fn check_fri(
+        channel_init_state: &[u8],
+        logn: usize,
+        num_queries: usize,
+        pow_bits: usize,
+        twiddle_merkle_tree_root: [u8; 32],
+    ) -> Script {
+        assert_eq!(channel_init_state.len(), 32);
         script! {
-            OP_TOALTSTACK
-
-            qm31_over
-            qm31_over
-            qm31_sub
+            { channel_init_state.to_vec() }
+            { Self::check_fri_on_channel(logn, num_queries, pow_bits, twiddle_merkle_tree_root) }
+        }
+    }
 
-            OP_FROMALTSTACK
-            qm31_mul_m31
+    /// Verify a FRI proof as `check_fri` does, from the witness of `push_fri_witness_deduplicated`.
+    ///
+    /// The distinct hint values stay at the bottom of the stack, where every hint of the twiddle
+    /// tree and Merkle tree proofs is copied from with `OP_PICK` at the position given by the
+    /// deduplicated witness, and they are dropped at the end. The script therefore depends on
+    /// the queries of the proof, as `verify_multiproof_gadget` does.
+    ///
+    /// This mode is for measuring the size of the deduplicated witness only, and is not a
+    /// verifier: the positions come from the witness, so the script is only known once the proof
+    /// is, and cannot be committed to in a Taproot leaf beforehand. A deployed verifier must use
+    /// `check_fri`, whose script only depends on logn and the number of queries.
+    ///
+    /// hints:
+    ///   as pushed by `push_fiat_shamir_hints`, and then the distinct values (which stay)
+    ///
+    /// input:
+    ///   as in `check_fri`
+    ///
+    /// output:
+    ///   as in `check_fri`
+    pub fn check_fri_deduplicated(
+        channel_init_state: &[u8],
+        logn: usize,
+        num_queries: usize,
+        pow_bits: usize,
+        twiddle_merkle_tree_root: [u8; 32],
+        witness: &DeduplicatedWitness,
+    ) -> Script {
+        assert_eq!(channel_init_state.len(), 32);
 
-            qm31_toaltstack
+        let mut indices = witness.indices.iter();
+        let check_fri = Self::check_fri_on_channel_with_hints(
+            logn,
+            num_queries,
+            pow_bits,
+            twiddle_merkle_tree_root,
+            &mut || pick_hint_gadget(*indices.next().unwrap()),
+        );
+        assert!(indices.next().is_none());
 
-            qm31_add
+        script! {
+            { channel_init_state.to_vec() }
+            { check_fri }
 
-            qm31_fromaltstack
+            // drop the distinct values
+            for _ in 0..witness.values.len() {
+                OP_DEPTH OP_1SUB OP_ROLL OP_DROP
+            }
         }
     }
-}
-
-#[cfg(test)]
-mod test {
-    use crate::channel::{ChannelWithHint, Sha256Channel};
-    use crate::fri;
-    use crate::fri::{FFTGadget, FRIGadget, N_QUERIES};
-    use crate::tests_utils::report::report_bitcoin_script_size;
-    use crate::treepp::*;
-    use crate::twiddle_merkle_tree::{TwiddleMerkleTree, TWIDDLE_MERKLE_TREE_ROOT_18};
-    use crate::utils::permute_eval;
-    use bitcoin::hashes::Hash;
-    use bitcoin::{TapLeafHash, Transaction};
-    use bitcoin_scriptexec::{Exec, ExecCtx, Experimental, Options, TxTemplate};
-    use num_traits::One;
-    use rand::{Rng, RngCore, SeedableRng};
-    use rand_chacha::ChaCha20Rng;
-    use rust_bitcoin_m31::qm31_equalverify;
-    use stwo_prover::core::channel::Channel;
-    use stwo_prover::core::circle::CirclePointIndex;
-    use stwo_prover::core::fft::ibutterfly;
-    use stwo_prover::core::fields::m31::M31;
-    use stwo_prover::core::fields::qm31::QM31;
-    use stwo_prover::core::fields::FieldExpOps;
-    use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
 
-    #[test]
-    fn test_fiat_shamir() {
-        let channel_init_state = {
-            let mut prng = ChaCha20Rng::seed_from_u64(0);
+    /// Verify a FRI proof as `check_fri` does, but starting from the channel on the top of the
+    /// stack instead of a channel init state that is fixed in the script, so that the FRI proof
+    /// continues the transcript of another verifier.
+    ///
+    /// hints:
+    ///   as in `check_fri`
+    ///
+    /// input:
+    ///   leaves (num_queries qm31), last layer (2 qm31), commitments (logn - 1)
+    ///   channel
+    ///
+    /// output:
+    ///   as in `check_fri`
+    pub fn check_fri_on_channel(
+        logn: usize,
+        num_queries: usize,
+        pow_bits: usize,
+        twiddle_merkle_tree_root: [u8; 32],
+    ) -> Script {
+        Self::check_fri_on_channel_with_hints(
+            logn,
+            num_queries,
+            pow_bits,
+            twiddle_merkle_tree_root,
+            &mut pull_hint_gadget,
+        )
+    }
 
-            let mut channel_init_state = [0u8; 32];
-            channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
-            BWSSha256Hash::from(channel_init_state.to_vec())
-        };
+    /// As `check_fri_on_channel`, but each hint of the twiddle tree and Merkle tree proofs is
+    /// obtained with `pull_hint`.
+    fn check_fri_on_channel_with_hints(
+        logn: usize,
+        num_queries: usize,
+        pow_bits: usize,
+        twiddle_merkle_tree_root: [u8; 32],
+        pull_hint: &mut dyn FnMut() -> Script,
+    ) -> Script {
+        let n_layers = logn - 1;
+        let n_last_layer = 1 << (logn - n_layers);
+        let q = num_queries;
+        script! {
+            { Self::check_fri_fiat_shamir(logn, q, pow_bits) }
 
-        let mut channel = Sha256Channel::new(channel_init_state);
-        let logn = 19;
+            // stack:
+            //    proof body -- leaves (q qm31), last layer (some qm31), commitments (logn - 1)
+            //    q queries
+            //    factors (logn - 1) qm31
 
-        let proof = {
-            let p = CirclePointIndex::subgroup_gen(logn as u32 + 1).to_point();
+            // copy the input for check_twiddle_merkle_tree_proof
+            for _ in 0..q {
+                { q + (logn - 1) * 4 - 1 } OP_PICK
+            }
 
-            let mut prng = ChaCha20Rng::seed_from_u64(0);
+            { Self::check_twiddle_merkle_tree_proof_with_hints(logn, q, twiddle_merkle_tree_root, pull_hint) }
 
-            let mut channel_init_state = [0u8; 32];
-            channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
-            let channel_init_state = BWSSha256Hash::from(channel_init_state.to_vec());
+            // stack:
+            //    proof body -- leaves (q qm31), last layer (some qm31), commitments (logn - 1)
+            //    q queries
+            //    alphas (logn - 1) qm31
+            //    twiddle factors q * (logn - 1) m31
 
-            let evaluation = (0..(1 << logn))
-                .map(|i| (p.mul(i * 2 + 1).x.square().square() + M31::one()).into())
-                .collect();
-            let evaluation = permute_eval(evaluation);
+            // now handle the 1st query, start with the Merkle trees to obtain the siblings
+            for i in 0..q {
+                // copy the input for check_single_query_merkle_tree
+                for _ in 0..logn - 1 {
+                    { q * (logn - 1) + (logn - 1) * 4 + q + (logn - 1) - 1 } OP_PICK
+                }
 
-            fri::fri_prove(&mut Sha256Channel::new(channel_init_state), evaluation)
-        };
+                // copy the query
+                { (logn - 1) + q * (logn - 1) + (logn - 1) * 4 + (q - 1) - i } OP_PICK
 
-        let expected = {
-            let mut channel = Sha256Channel::new(channel_init_state);
-            let mut expected_1 = vec![];
+                { Self::check_single_query_merkle_tree_proof_with_hints(logn, pull_hint) }
 
-            for c in proof.commitments.iter() {
-                channel.mix_digest(*c);
-                let res = channel.draw_felt_and_hints();
-                expected_1.push(res.0);
-            }
-            channel.mix_felts(&proof.last_layer);
+                // stack:
+                //    proof body -- leaves (q qm31), last layer (some qm31), commitments (logn - 1)
+                //    q queries
+                //    alphas (logn - 1) qm31
+                //    twiddle factors q * (logn - 1) m31
+                //    siblings (logn - 1) qm31
 
-            let res = channel.draw_5queries(logn);
+                // copy the input for check
+                // move siblings to alt stack
+                { qm31_save(logn - 1) }
+                // twiddle factors
+                for _ in 0..(logn - 1) {
+                    { (q - 1 - i) * (logn - 1) + (logn - 1) - 1 } OP_PICK
+                }
+                // alphas
+                for _ in 0..(logn - 1) * 4 {
+                    { (logn - 1) + q * (logn - 1) + (logn - 1) * 4 - 1 } OP_PICK
+                }
+                // siblings
+                { qm31_restore(logn - 1) }
+                // leaf
+                for _ in 0..4 {
+                    { n_last_layer * 4 + (logn - 1) * (4 + 4 + 4 + 1) + (q + 1) * (logn - 1) + q + 4 - 1 } OP_ROLL
+                }
+                // position
+                { (logn - 1) * (4 + 4 + 1 + 4 + q) + 4 + (q - 1 - i) } OP_PICK
 
-            let expected_2 = res.0;
+                { Self::check_single_query_ibutterfly(logn, (q + 4 + 1) * (logn - 1) + q + n_last_layer * 4) }
 
-            (expected_1, expected_2)
-        };
+                // stack:
+                //    proof body -- leaves (q - i qm31, disappearing), last layer (some qm31), commitments (logn - 1)
+                //    q queries
+                //    alphas (logn - 1) qm31
+                //    twiddle factors q * (logn - 1) m31
+            }
+        }
+    }
 
-        let script = script! {
-            { FRIGadget::push_fiat_shamir_hints(&mut channel, logn, &proof) }
+    /// The first part of `check_fri_on_channel`, which checks the degree of the last layer, copies
+    /// the proof body below the channel, and checks the Fiat-Shamir computation.
+    pub(crate) fn check_fri_fiat_shamir(
+        logn: usize,
+        num_queries: usize,
+        pow_bits: usize,
+    ) -> Script {
+        let n_layers = logn - 1;
+        let n_last_layer = 1 << (logn - n_layers);
+        script! {
+            OP_TOALTSTACK
 
-            for elem in proof.last_layer.iter().rev() {
-                { *elem }
-            }
-            for c in proof.commitments.iter().rev() {
-                { *c }
+            // check that the last layer is of half degree, i.e., its two elements are equal, as in
+            // `fri_verify`, since the queries only check the element that they fold into
+            for _ in 0..(n_last_layer * 4) {
+                { n_last_layer * 4 + n_layers - 1 } OP_PICK
             }
+            qm31_equalverify
 
-            { FRIGadget::check_fiat_shamir(channel_init_state.as_ref(), logn, logn - 1) }
-            for elem in expected.0.iter() {
-                { *elem }
-                qm31_equalverify
-            }
-            for elem in expected.1.iter().rev() {
-                { *elem }
-                OP_EQUALVERIFY
+            // copy the input for check_fiat_shamir
+            for _ in 0..(n_last_layer * 4 + n_layers) {
+                { n_last_layer * 4 + n_layers - 1 } OP_PICK
             }
-            OP_TRUE
-        };
 
-        report_bitcoin_script_size("FRI", "Fiat-Shamir", script.len());
+            // do the check_fiat_shamir
+            OP_FROMALTSTACK
+            { Self::check_fiat_shamir_on_channel(logn, logn - 1, num_queries, pow_bits) }
+        }
+    }
+}
 
-        let exec_result = execute_script(script);
-        assert!(exec_result.success);
+/// Compute the index of a query in the next FRI layer, which drops the lowest bit, the one that
+/// distinguishes a point from its sibling.
+///
+/// The bits are extracted from the highest one, which also checks that the index is within the
+/// layer of size 2^logn.
+///
+/// input:
+///  pos
+///
+/// output:
+///  pos >> 1
+pub fn query_fold_index_gadget(logn: usize) -> Script {
+    assert!((1..31).contains(&logn));
+
+    script! {
+        { assert_index_in_range_gadget(logn) }
+
+        0 OP_SWAP
+        for i in (1..logn).rev() {
+            OP_DUP { 1 << i } OP_GREATERTHANOREQUAL
+            OP_IF
+                { 1 << i } OP_SUB
+                OP_SWAP { 1 << (i - 1) } OP_ADD OP_SWAP
+            OP_ENDIF
+        }
+        OP_DROP
     }
+}
 
-    #[test]
-    fn test_twiddle_merkle_tree() {
-        let logn = 19;
+/// Fail the execution if the values opened by the `n` queries do not hash to the accumulator
+/// that the prover committed to, see `accumulate_query_responses`, so that they are checked
+/// with a single comparison and the openings can be verified later.
+///
+/// input:
+///  accumulator (32 bytes)
+///  v_0, ..., v_{n-1} (qm31)
+///
+/// output:
+///  v_0, ..., v_{n-1} (qm31)
+pub fn accumulate_query_responses_gadget(n: usize) -> Script {
+    assert!(n > 0);
+
+    script! {
+        { qm31_copy(n - 1) }
+        hash_felt_gadget
+        for i in 1..n {
+            for k in 0..4 {
+                { 4 * (n - 1 - i) + 1 + k } OP_PICK
+                OP_SWAP OP_CAT OP_SHA256
+            }
+        }
+        { 4 * n + 1 } OP_ROLL
+        OP_EQUALVERIFY
+    }
+}
 
-        let mut prng = ChaCha20Rng::seed_from_u64(0);
-        let mut channel_init_state = [0u8; 32];
-        channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
-        let channel_init_state = BWSSha256Hash::from(channel_init_state.to_vec());
+/// Fail the execution if the value opened at the first FRI layer differs from the quotient value
+/// derived from the trace at the same query, which binds FRI to the committed trace.
+///
+/// input:
+///  quotient (qm31)
+///  v (qm31), the value opened at the first FRI layer
+///
+/// output:
+///  v (qm31)
+pub fn verify_first_layer_binding_gadget() -> Script {
+    script! {
+        qm31_over
+        qm31_equalverify
+    }
+}
 
-        let proof = {
-            let p = CirclePointIndex::subgroup_gen(logn as u32 + 1).to_point();
+/// Verify a query of an aggregated proof, which runs a single FRI verification on the
+/// combination of the DEEP evaluations of several proofs, see `aggregate_evaluations`: absorb the
+/// commitments of the proofs, draw the combiner, as in `draw_aggregation_combiner`, and check the
+/// value opened at the first FRI layer against the combination of the values of the proofs at the
+/// same query, as in `verify_first_layer_binding_gadget`, before it is folded.
+///
+/// The combiner is left on the stack for the other queries, which are combined with
+/// `alpha_powers_batch_gadget`.
+///
+/// hint:
+///  the draw hint of the combiner, see `Sha256ChannelGadget::push_draw_hint`
+///
+/// input:
+///  v_0, ..., v_{num_proofs - 1} (qm31), the values of the proofs at the query
+///  v (qm31), the value opened at the first FRI layer
+///  commitment_{num_proofs - 1}
+///  ...
+///  commitment_0
+///  channel
+///
+/// output:
+///  v (qm31)
+///  channel'
+///  combiner (qm31)
+/// mark the transaction as invalid if v is not the combination of the values of the proofs
+pub fn verify_aggregated_gadget(num_proofs: usize) -> Script {
+    assert!(num_proofs > 0);
+
+    script! {
+        for _ in 0..num_proofs {
+            { Sha256ChannelGadget::mix_digest() }
+        }
+        { Sha256ChannelGadget::draw_felt_with_hint() }
+
+        // altstack: combiner, channel', v
+        qm31_dup
+        qm31_toaltstack
+        4 OP_ROLL OP_TOALTSTACK
+        qm31_swap
+        qm31_toaltstack
+
+        // stack: combiner, v_0, ..., v_{num_proofs - 1}
+        for _ in 0..num_proofs {
+            { qm31_roll(num_proofs) }
+        }
+        { alpha_powers_batch_gadget(num_proofs) }
 
-            let evaluation = (0..(1 << logn))
-                .map(|i| (p.mul(i * 2 + 1).x.square().square() + M31::one()).into())
-                .collect();
-            let evaluation = permute_eval(evaluation);
+        qm31_fromaltstack
+        verify_first_layer_binding_gadget
 
-            fri::fri_prove(&mut Sha256Channel::new(channel_init_state), evaluation)
-        };
+        OP_FROMALTSTACK
+        qm31_fromaltstack
+    }
+}
 
-        let queries = {
-            let mut channel = Sha256Channel::new(channel_init_state);
+/// Verify the opening of a query at a FRI layer and fold it into the next layer: the sibling of
+/// the queried value is checked against the root of the layer, and the pair is folded with the
+/// factor of the layer.
+///
+/// The Merkle tree of the layer has 2^(logn - layer) leaves.
+///
+/// hint:
+///  the Merkle tree proof of the sibling, see `MerkleTreeGadget::push_merkle_tree_proof`
+///
+/// input:
+///  alpha (qm31)
+///  itwid (m31)
+///  root
+///  pos
+///  v (qm31), the value at pos
+///
+/// output:
+///  v' (qm31), the folded value at pos >> 1 in the next layer
+///  pos >> 1
+pub fn verify_layer_opening_gadget(logn: usize, layer: usize) -> Script {
+    let layer_logn = logn - layer;
+
+    script! {
+        qm31_toaltstack
+        OP_DUP OP_TOALTSTACK
+
+        // stack: alpha, itwid, root, pos
+        // altstack: v, pos
+        { MerkleTreeGadget::query_and_verify_sibling(layer_logn) }
+
+        // stack: alpha, itwid, sibling
+        OP_FROMALTSTACK
+        qm31_fromaltstack
+        4 OP_ROLL
+
+        // compute pos >> 1, and the lowest bit of pos, which tells whether v is on the right
+        OP_DUP { query_fold_index_gadget(layer_logn) }
+        OP_DUP OP_TOALTSTACK
+        OP_DUP OP_ADD OP_SUB
+
+        // stack: alpha, itwid, sibling, v, pos & 1
+        // altstack: pos >> 1
+        OP_NOTIF
+            qm31_swap
+        OP_ENDIF
+
+        // pull the twiddle factor
+        8 OP_ROLL
+        { FFTGadget::ibutterfly() }
+
+        // pull the alpha
+        { qm31_roll(2) }
+        qm31_mul
+        qm31_add
+
+        OP_FROMALTSTACK
+    }
+}
+
+/// Verify the openings of a pair of siblings at a FRI layer, which are folded together: both are
+/// checked against the root of the layer, and their positions are checked to be those of a point
+/// and its image under the involution, not two arbitrary leaves.
+///
+/// As the layers are in the bit-reversed order, the image of the point at pos is at pos ^ 1,
+/// which is checked as the two positions being distinct and having the same index pos >> 1 in
+/// the next layer.
+///
+/// The Merkle tree of the layer has 2^(logn - layer) leaves.
+///
+/// hint:
+///  the Merkle tree proof of v, and then that of sibling_v, see
+///  `MerkleTreeGadget::push_merkle_tree_proof`
+///
+/// input:
+///  root
+///  pos
+///  v (qm31)
+///  sibling_pos
+///  sibling_v (qm31)
+///
+/// output:
+///  none
+/// mark the transaction as invalid if the check fails
+pub fn verify_sibling_pair_gadget(logn: usize, layer: usize) -> Script {
+    let layer_logn = logn - layer;
+
+    script! {
+        qm31_toaltstack
+        OP_TOALTSTACK
+        qm31_toaltstack
+
+        // stack: root, pos
+        // altstack: sibling_v, sibling_pos, v
+        OP_2DUP
+        { MerkleTreeGadget::query_and_verify(layer_logn) }
+        qm31_fromaltstack
+        qm31_equalverify
+
+        // check that the positions are distinct and fold to the same index
+        OP_FROMALTSTACK
+        OP_2DUP OP_NUMNOTEQUAL OP_VERIFY
+        OP_DUP { query_fold_index_gadget(layer_logn) }
+        2 OP_PICK { query_fold_index_gadget(layer_logn) }
+        OP_EQUALVERIFY
+
+        // stack: root, pos, sibling_pos
+        OP_ROT OP_SWAP
+        { MerkleTreeGadget::query_and_verify(layer_logn) }
+        qm31_fromaltstack
+        qm31_equalverify
+
+        OP_DROP
+    }
+}
+
+/// Fail the execution unless the positions at which the prover opened the Merkle trees are
+/// exactly the queries drawn from the channel, in the same order, so that the prover cannot open
+/// positions of its choice.
+///
+/// input:
+///  q_0, ..., q_{n - 1}, the queries drawn from the channel
+///  o_0, ..., o_{n - 1}, the positions of the openings
+///
+/// output:
+///  q_0, ..., q_{n - 1}
+pub fn bind_queries_to_openings_gadget(n: usize) -> Script {
+    script! {
+        // with k openings left, o_{k - 1} is on the top and q_{k - 1} is always n elements below
+        for _ in 0..n {
+            { n } OP_PICK OP_EQUALVERIFY
+        }
+    }
+}
+
+/// Spot-check, at one drawn index, that the trace opens to the same value that seeds the first
+/// FRI layer. This is much cheaper than the full FRI verification and catches a trace that is not
+/// the one committed to FRI, but it says nothing about the degree.
+///
+/// Both Merkle trees have 2^logn leaves.
+///
+/// hint:
+///  the Merkle tree proof of the trace at pos, and then that of the first FRI layer at pos, see
+///  `MerkleTreeGadget::push_merkle_tree_proof`
+///
+/// input:
+///  trace_root
+///  fri_root
+///  pos
+///
+/// output:
+///  none
+/// mark the transaction as invalid if the check fails
+pub fn spot_check_gadget(logn: usize) -> Script {
+    script! {
+        { assert_index_in_range_gadget(logn) }
+
+        // open the trace at pos
+        OP_ROT OP_OVER
+        { MerkleTreeGadget::query_and_verify(logn) }
+        qm31_toaltstack
+
+        // stack: fri_root, pos
+        // altstack: the trace value
+        { MerkleTreeGadget::query_and_verify(logn) }
+        qm31_fromaltstack
+        qm31_swap
+
+        { verify_first_layer_binding_gadget() }
+        OP_2DROP OP_2DROP
+    }
+}
+
+/// Run the commit phase of FRI: absorb the root of each layer and draw the folding factor of
+/// that layer, so that the factors are derived in the script rather than supplied as hints.
+///
+/// This enforces the Fiat-Shamir order: each factor is drawn from the digest after the root of
+/// its layer is absorbed, and the draw hints only split that digest into limbs, which is checked
+/// against it, so a factor drawn before the root is absorbed does not pass.
+///
+/// The roots are kept on the stack for the query phase, in the order of the layers, and the
+/// factors are left on the altstack, with the factor of the last layer on the top.
+///
+/// hint:
+///  the draw hints of the folding factors, see `push_commit_phase_hint`
+///
+/// input:
+///  root_{num_layers - 1}
+///  ...
+///  root_0
+///  channel
+///
+/// output:
+///  root_0
+///  ...
+///  root_{num_layers - 1}
+///  channel'
+///
+/// altstack output:
+///  alpha_0
+///  ...
+///  alpha_{num_layers - 1}
+pub fn commit_phase_gadget(num_layers: usize) -> Script {
+    script! {
+        for i in 0..num_layers {
+            // move root_i, which is below the roots of the previous layers, next to the channel
+            if i > 0 {
+                { i + 1 } OP_ROLL OP_SWAP
+            }
+            { Sha256ChannelGadget::mix_digest_keep() }
+            { Sha256ChannelGadget::draw_felt_with_hint() }
+            qm31_toaltstack
+        }
+    }
+}
+
+/// Push the hints for `commit_phase_gadget`, which are the draw hints of the folding factors.
+pub fn push_commit_phase_hint(channel: &mut Sha256Channel, proof: &FriProof) -> Script {
+    let mut factors_hints = Vec::<DrawQM31Hints>::new();
+
+    for c in proof.commitments.iter() {
+        channel.mix_digest(*c);
+        factors_hints.push(channel.draw_felt_and_hints().1);
+    }
+
+    script! {
+        for hint in factors_hints.iter() {
+            { Sha256ChannelGadget::push_draw_hint(hint) }
+        }
+    }
+}
+
+/// Check that the proof body has as many layer roots as a FRI proof for an evaluation of size
+/// 2^logn commits to, see `num_fri_layers`, by checking that the top `num_layers` items are hashes
+/// and that the item below them, the first limb of the last layer, is not.
+///
+/// input:
+///  last layer (qm31)
+///  root_{num_layers - 1}
+///  ...
+///  root_0
+///
+/// output:
+///  last layer (qm31)
+///  root_{num_layers - 1}
+///  ...
+///  root_0
+/// mark the transaction as invalid if the number of roots is not the expected one
+pub fn assert_layer_count_gadget(logn: usize) -> Script {
+    let num_layers = num_fri_layers(logn);
+
+    script! {
+        for i in 0..num_layers {
+            { i } OP_PICK OP_SIZE 32 OP_EQUALVERIFY OP_DROP
+        }
+        { num_layers } OP_PICK OP_SIZE 32 OP_LESSTHAN OP_VERIFY OP_DROP
+    }
+}
+
+/// Check that the proof body of the FRI proof of the composition polynomial has the number of
+/// layers that the constraints require, as in `check_composition_degree`, with
+/// `assert_layer_count_gadget`.
+///
+/// input:
+///  same as `assert_layer_count_gadget`
+///
+/// output:
+///  same as `assert_layer_count_gadget`
+/// mark the transaction as invalid if the proof claims a different degree
+pub fn assert_composition_degree_gadget(
+    trace_log_size: usize,
+    max_constraint_degree: usize,
+    log_blowup: usize,
+) -> Script {
+    assert_layer_count_gadget(composition_fri_logn(
+        trace_log_size,
+        max_constraint_degree,
+        log_blowup,
+    ))
+}
+
+/// Check that the proof bodies of the FRI proofs of the trace and of the composition polynomial
+/// are both over the canonic circle domain of size 2^logn, as in `check_same_domain`, with
+/// `assert_layer_count_gadget` on each of them.
+///
+/// input:
+///  trace last layer (qm31)
+///  trace root_{num_layers - 1}
+///  ...
+///  trace root_0
+///  composition last layer (qm31)
+///  composition root_{num_layers - 1}
+///  ...
+///  composition root_0
+///
+/// output:
+///  same as the input
+/// mark the transaction as invalid if either proof is over a different domain
+pub fn assert_same_domain_gadget(logn: usize) -> Script {
+    let body_len = num_fri_layers(logn) + 4 * (1 << LAST_LAYER_LOG_SIZE);
+
+    script! {
+        { assert_layer_count_gadget(logn) }
+        for _ in 0..body_len {
+            OP_TOALTSTACK
+        }
+        { assert_layer_count_gadget(logn) }
+        for _ in 0..body_len {
+            OP_FROMALTSTACK
+        }
+    }
+}
+
+/// Bind the inverse twiddle factor that a query uses to fold the `layer`-th FRI layer, as in
+/// `FFTGadget::ibutterfly`, to the one authenticated by the twiddle Merkle tree opening of the
+/// query, see `TwiddleMerkleTreeGadget::query_and_verify`.
+///
+/// hint:
+///  the twiddle Merkle tree path of pos
+///
+/// input:
+///  root_hash
+///  pos
+///  itwid (m31 -- the inverse twiddle factor supplied for folding)
+///
+/// output:
+///  itwid (m31)
+/// mark the transaction as invalid if the twiddle factor is not the authenticated one
+pub fn bind_twiddle_gadget(logn: usize, layer: usize) -> Script {
+    let num_layers = logn - 1;
+    assert!(layer < num_layers);
+
+    script! {
+        OP_TOALTSTACK
+        { TwiddleMerkleTreeGadget::query_and_verify(logn) }
+
+        // the inverse twiddle factors of the layers, the first layer on the top
+        { layer } OP_ROLL
+        OP_TOALTSTACK
+        for _ in 0..(num_layers - 1) / 2 {
+            OP_2DROP
+        }
+        if (num_layers - 1) % 2 == 1 {
+            OP_DROP
+        }
+
+        OP_FROMALTSTACK
+        OP_FROMALTSTACK
+        OP_OVER OP_EQUALVERIFY
+    }
+}
+
+/// Absorb the committed coefficients of the last layer polynomial into the channel, evaluate the
+/// polynomial at the query point with `qm31_horner_gadget`, and fail the execution if it differs
+/// from the folded value of the query.
+///
+/// input:
+///  v (qm31 -- the folded value)
+///  x (qm31 -- the query point)
+///  c_0, ..., c_degree (qm31 -- absorbed in this order)
+///  channel
+///
+/// output:
+///  channel'
+pub fn verify_last_layer_eval_gadget(degree: usize) -> Script {
+    script! {
+        OP_TOALTSTACK
+        for i in 0..=degree {
+            { qm31_copy(degree - i) }
+            OP_FROMALTSTACK
+            { Sha256ChannelGadget::mix_felt() }
+            OP_TOALTSTACK
+        }
+
+        { qm31_horner_gadget(degree) }
+        qm31_equalverify
+        OP_FROMALTSTACK
+    }
+}
+
+/// Check that a small line evaluation of size 2^log_size, such as a last FRI layer, is of degree
+/// at most `degree_bound`, by interpolating it in the script with a line IFFT and checking that
+/// the coefficients above the bound are zero.
+///
+/// The evaluation is in the order of the FRI layers after the first one, i.e., the values at x
+/// and -x are adjacent, where x runs over the line twiddles of `get_twiddles(log_size + 1)`, which
+/// are the same for any FRI layer of that size. The i-th coefficient is of the basis element that
+/// is the product of pi^j(x) over the set bits j of i, where pi(x) = 2x^2 - 1, which is of degree
+/// i. The coefficients are scaled by 2^log_size, which does not change which ones are zero.
+///
+/// This is only practical for tiny layers, so log_size is at most 3.
+///
+/// input:
+///  v_0, ..., v_{2^log_size - 1} (qm31)
+///
+/// output:
+///  none
+/// mark the transaction as invalid if the degree exceeds the bound
+pub fn last_layer_ifft_check_gadget(log_size: usize, degree_bound: usize) -> Script {
+    assert!((1..=3).contains(&log_size));
+    assert!(degree_bound < (1 << log_size));
+
+    let twiddles = get_twiddles(log_size + 1);
+
+    // the ids of the elements on the stack, the top last
+    let mut stack: Vec<usize> = (0..(1 << log_size)).collect();
+    let roll_to_top = |stack: &mut Vec<usize>, id: usize| {
+        let idx = stack.iter().position(|&v| v == id).unwrap();
+        stack.remove(idx);
+        stack.push(id);
+        qm31_roll(stack.len() - 1 - idx)
+    };
+
+    // each group is the set of basis bits decided so far, with the ids of its evaluation
+    let mut groups = vec![(0usize, (0..(1 << log_size)).collect::<Vec<usize>>())];
+    let mut steps = vec![];
+    for (layer, layer_twiddles) in twiddles.iter().skip(1).enumerate() {
+        let mut next_groups = Vec::with_capacity(groups.len() * 2);
+        for (bits, ids) in groups.iter() {
+            let mut evens = Vec::with_capacity(ids.len() / 2);
+            let mut odds = Vec::with_capacity(ids.len() / 2);
+            for (pair, twiddle) in ids.chunks_exact(2).zip(layer_twiddles.iter()) {
+                steps.push(roll_to_top(&mut stack, pair[0]));
+                steps.push(roll_to_top(&mut stack, pair[1]));
+                steps.push(script! {
+                    { twiddle.inverse() }
+                    { FFTGadget::ibutterfly() }
+                });
+                evens.push(pair[0]);
+                odds.push(pair[1]);
+            }
+            next_groups.push((*bits, evens));
+            next_groups.push((*bits | (1 << layer), odds));
+        }
+        groups = next_groups;
+    }
+
+    // check the coefficients above the bound
+    for (bits, ids) in groups.iter() {
+        if *bits > degree_bound {
+            steps.push(roll_to_top(&mut stack, ids[0]));
+            steps.push(qm31_is_zero_verify());
+            stack.pop();
+        }
+    }
+
+    script! {
+        for step in steps.iter() {
+            { step.clone() }
+        }
+        for _ in 0..stack.len() {
+            OP_2DROP OP_2DROP
+        }
+    }
+}
+
+/// Push the parameters of the domain of the `layer`-th FRI layer for an evaluation of size 2^logn,
+/// see `layer_domain`, as script constants.
+///
+/// input:
+///  none
+///
+/// output:
+///  initial.x, initial.y (m31 -- the first point of the domain)
+///  step.x, step.y (m31 -- the generator of the domain)
+pub fn layer_domain_gadget(logn: usize, layer: usize) -> Script {
+    let domain = layer_domain(logn, layer);
+    script! {
+        { domain.initial.x }
+        { domain.initial.y }
+        { domain.step.x }
+        { domain.step.y }
+    }
+}
+
+/// Multiply the value folded by the `layer`-th FRI layer by its degree-correction factor, see
+/// `degree_correction_factor`, which is a script constant rather than a hint.
+///
+/// input:
+///  v (qm31 -- the folded value)
+///
+/// output:
+///  v * 2^-(layer + 1) (qm31)
+pub fn apply_degree_correction_gadget(layer: usize) -> Script {
+    script! {
+        { degree_correction_factor(layer) }
+        qm31_mul_m31
+    }
+}
+
+/// Gadget for FFT.
+pub struct FFTGadget;
+
+impl FFTGadget {
+    /// Perform inverse butterfly in Bitcoin script.
+    /// input:
+    ///  v0 (qm31)
+    ///  v1 (qm31)
+    ///  itwid (m31)
+    ///
+    /// output:
+    ///  v0' (qm31)
+    ///  v1' (qm31)
+    pub fn ibutterfly() -> Script {
+        script! {
+            OP_TOALTSTACK
+
+            qm31_over
+            qm31_over
+            qm31_sub
+
+            OP_FROMALTSTACK
+            qm31_mul_m31
+
+            qm31_toaltstack
+
+            qm31_add
+
+            qm31_fromaltstack
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::channel::{ChannelWithHint, Sha256Channel, Sha256ChannelGadget};
+    use crate::constraints::composition_log_degree_bound;
+    use crate::fri;
+    use crate::fri::{
+        accumulate_query_responses, accumulate_query_responses_gadget, aggregate_evaluations,
+        apply_degree_correction_gadget, assert_composition_degree_gadget,
+        assert_layer_count_gadget, assert_same_domain_gadget, bind_queries_to_openings_gadget,
+        bind_twiddle_gadget, check_composition_degree, check_same_domain, commit_phase_gadget,
+        degree_correction_factor, draw_aggregation_combiner, last_layer_ifft_check_gadget,
+        layer_domain_gadget, push_commit_phase_hint, query_fold_index_gadget, spot_check_gadget,
+        verify_aggregated_gadget, verify_first_layer_binding_gadget, verify_last_layer_eval_gadget,
+        verify_layer_opening_gadget, verify_sibling_pair_gadget, FFTGadget, FRIGadget, FriError,
+        HintValue, TamperKind, N_QUERIES,
+    };
+    use crate::merkle_tree::{MerkleTree, MerkleTreeGadget};
+    use crate::tests_utils::fixtures::{fri_proof_fixture, random_qm31};
+    use crate::tests_utils::report::report_bitcoin_script_size;
+    use crate::tests_utils::run::{run_proof, run_script};
+    use crate::treepp::*;
+    use crate::twiddle_merkle_tree::{
+        TwiddleMerkleTree, TwiddleMerkleTreeGadget, TWIDDLE_MERKLE_TREE_ROOT_18,
+        TWIDDLE_MERKLE_TREE_ROOT_4,
+    };
+    use crate::utils::{get_twiddles, permute_eval};
+    use bitcoin::hashes::Hash;
+    use bitcoin::{TapLeafHash, Transaction};
+    use bitcoin_scriptexec::{Exec, ExecCtx, Experimental, Options, TxTemplate};
+    use num_traits::{One, Zero};
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use rust_bitcoin_m31::{qm31_equalverify, qm31_fromaltstack};
+    use stwo_prover::core::channel::Channel;
+    use stwo_prover::core::circle::CirclePointIndex;
+    use stwo_prover::core::fft::ibutterfly;
+    use stwo_prover::core::fields::m31::M31;
+    use stwo_prover::core::fields::qm31::QM31;
+    use stwo_prover::core::fields::FieldExpOps;
+    use stwo_prover::core::poly::circle::CanonicCoset;
+    use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+
+    #[test]
+    fn test_fiat_shamir() {
+        let logn = 19;
+
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+        let mut channel = Sha256Channel::new(channel_init_state);
+
+        let expected = {
+            let mut channel = Sha256Channel::new(channel_init_state);
+            let mut expected_1 = vec![];
+
+            for c in proof.commitments.iter() {
+                channel.mix_digest(*c);
+                let res = channel.draw_felt_and_hints();
+                expected_1.push(res.0);
+            }
+            channel.mix_felts(&proof.last_layer);
+
+            let res = channel.draw_n_queries(N_QUERIES, logn);
+
+            let expected_2 = res.0;
+
+            (expected_1, expected_2)
+        };
+
+        let script = script! {
+            { FRIGadget::push_fiat_shamir_hints(&mut channel, logn, &proof, 0) }
+
+            for elem in proof.last_layer.iter().rev() {
+                { *elem }
+            }
+            for c in proof.commitments.iter().rev() {
+                { *c }
+            }
+
+            { FRIGadget::check_fiat_shamir(channel_init_state.as_ref(), logn, logn - 1, N_QUERIES, 0) }
+            for elem in expected.0.iter() {
+                { *elem }
+                qm31_equalverify
+            }
+            for elem in expected.1.iter().rev() {
+                { *elem }
+                OP_EQUALVERIFY
+            }
+            OP_TRUE
+        };
+
+        report_bitcoin_script_size("FRI", "Fiat-Shamir", script.len());
+
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+    }
+
+    #[test]
+    fn test_twiddle_merkle_tree() {
+        let logn = 19;
+
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+
+        let queries = {
+            let mut channel = Sha256Channel::new(channel_init_state);
 
             for c in proof.commitments.iter() {
                 channel.mix_digest(*c);
@@ -387,7 +1343,7 @@ mod test {
             }
 
             channel.mix_felts(&proof.last_layer);
-            channel.draw_5queries(logn).0
+            channel.draw_n_queries(N_QUERIES, logn).0
         };
 
         let expected = {
@@ -406,7 +1362,7 @@ mod test {
             for query in queries.iter() {
                 { *query }
             }
-            { FRIGadget::check_twiddle_merkle_tree_proof(logn, TWIDDLE_MERKLE_TREE_ROOT_18) }
+            { FRIGadget::check_twiddle_merkle_tree_proof(logn, N_QUERIES, TWIDDLE_MERKLE_TREE_ROOT_18) }
             for elem in expected.iter().rev() {
                 { *elem }
                 OP_EQUALVERIFY
@@ -424,21 +1380,7 @@ mod test {
     fn test_single_query_merkle_tree() {
         let logn = 19;
 
-        let mut prng = ChaCha20Rng::seed_from_u64(0);
-        let mut channel_init_state = [0u8; 32];
-        channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
-        let channel_init_state = BWSSha256Hash::from(channel_init_state.to_vec());
-
-        let proof = {
-            let p = CirclePointIndex::subgroup_gen(logn as u32 + 1).to_point();
-
-            let evaluation = (0..(1 << logn))
-                .map(|i| (p.mul(i * 2 + 1).x.square().square() + M31::one()).into())
-                .collect();
-            let evaluation = permute_eval(evaluation);
-
-            fri::fri_prove(&mut Sha256Channel::new(channel_init_state), evaluation)
-        };
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
 
         let queries = {
             let mut channel = Sha256Channel::new(channel_init_state);
@@ -448,7 +1390,7 @@ mod test {
                 let _ = channel.draw_felt_and_hints();
             }
             channel.mix_felts(&proof.last_layer);
-            channel.draw_5queries(logn).0
+            channel.draw_n_queries(N_QUERIES, logn).0
         };
 
         let expected = {
@@ -484,21 +1426,7 @@ mod test {
     fn test_single_query_butterfly() {
         let logn = 19;
 
-        let mut prng = ChaCha20Rng::seed_from_u64(0);
-        let mut channel_init_state = [0u8; 32];
-        channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
-        let channel_init_state = BWSSha256Hash::from(channel_init_state.to_vec());
-
-        let proof = {
-            let p = CirclePointIndex::subgroup_gen(logn as u32 + 1).to_point();
-
-            let evaluation = (0..(1 << logn))
-                .map(|i| (p.mul(i * 2 + 1).x.square().square() + M31::one()).into())
-                .collect();
-            let evaluation = permute_eval(evaluation);
-
-            fri::fri_prove(&mut Sha256Channel::new(channel_init_state), evaluation)
-        };
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
 
         let (alphas, queries) = {
             let mut alphas = vec![];
@@ -512,7 +1440,7 @@ mod test {
             }
             channel.mix_felts(&proof.last_layer);
 
-            let queries = channel.draw_5queries(logn).0;
+            let queries = channel.draw_n_queries(N_QUERIES, logn).0;
 
             (alphas, queries)
         };
@@ -556,34 +1484,9 @@ mod test {
 
     #[test]
     fn test_end_to_end() {
-        let channel_init_state = {
-            let mut prng = ChaCha20Rng::seed_from_u64(0);
-
-            let mut channel_init_state = [0u8; 32];
-            channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
-            BWSSha256Hash::from(channel_init_state.to_vec())
-        };
-
-        let mut channel = Sha256Channel::new(channel_init_state);
         let logn = 19;
 
-        let proof = {
-            let p = CirclePointIndex::subgroup_gen(logn as u32 + 1).to_point();
-
-            let mut prng = ChaCha20Rng::seed_from_u64(0);
-
-            let mut channel_init_state = [0u8; 32];
-            channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
-
-            let channel_init_state = BWSSha256Hash::from(channel_init_state.to_vec());
-
-            let evaluation = (0..(1 << logn))
-                .map(|i| (p.mul(i * 2 + 1).x.square().square() + M31::one()).into())
-                .collect();
-            let evaluation = permute_eval(evaluation);
-
-            fri::fri_prove(&mut Sha256Channel::new(channel_init_state), evaluation)
-        };
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
 
         let expected_fiat_shamir = {
             let mut channel = Sha256Channel::new(channel_init_state);
@@ -596,7 +1499,7 @@ mod test {
             }
             channel.mix_felts(&proof.last_layer);
 
-            let res = channel.draw_5queries(logn);
+            let res = channel.draw_n_queries(N_QUERIES, logn);
 
             let expected_2 = res.0;
 
@@ -614,131 +1517,61 @@ mod test {
             expected
         };
 
-        let witness = script! {
-            // push all the hints
-            { FRIGadget::push_fiat_shamir_hints(&mut channel, logn, &proof) }
-            { FRIGadget::push_twiddle_merkle_tree_proof(&proof) }
-            for i in 0..N_QUERIES {
-                { FRIGadget::push_single_query_merkle_tree_proof(i, &proof) }
-            }
+        let witness = FRIGadget::push_fri_witness(channel_init_state, logn, &proof, 0);
 
-            // push the proof body
+        let script = script! {
+            { FRIGadget::check_fri(channel_init_state.as_ref(), logn, N_QUERIES, 0, TWIDDLE_MERKLE_TREE_ROOT_18) }
 
-            // leaves
-            for elem in proof.leaves.iter().rev() {
+            for elem in expected_twiddle_tree.iter().rev() {
                 { *elem }
+                OP_EQUALVERIFY
             }
-            // last layer
-            for elem in proof.last_layer.iter().rev() {
+            for elem in expected_fiat_shamir.0.iter() {
                 { *elem }
+                qm31_equalverify
             }
-            // commitments
-            for c in proof.commitments.iter().rev() {
-                { *c }
-            }
-        };
-
-        let script = script! {
-            // copy the input for check_fiat_shamir
-            for _ in 0..(proof.last_layer.len() * 4 + proof.commitments.len()) {
-                { proof.last_layer.len() * 4 + proof.commitments.len() - 1 } OP_PICK
+            for elem in expected_fiat_shamir.1.iter().rev() {
+                { *elem }
+                OP_EQUALVERIFY
             }
 
-            // do the check_fiat_shamir
-            { FRIGadget::check_fiat_shamir(channel_init_state.as_ref(), logn, logn - 1) }
-
-            // stack:
-            //    proof body -- leaves (n_queries qm31), last layer (some qm31), commitments (logn - 1)
-            //    5 queries
-            //    factors (logn - 1) qm31
-
-            // copy the input for check_twiddle_merkle_tree_proof
-            for _ in 0..5 {
-                { 5 + (logn - 1) * 4 - 1 } OP_PICK
+            // drop last layer, and commitments
+            for _ in 0..((proof.last_layer.len()) * 4 + proof.commitments.len()) {
+                OP_DROP
             }
 
-            { FRIGadget::check_twiddle_merkle_tree_proof(logn, TWIDDLE_MERKLE_TREE_ROOT_18) }
-
-            // stack:
-            //    proof body -- leaves (n_queries qm31), last layer (some qm31), commitments (logn - 1)
-            //    5 queries
-            //    alphas (logn - 1) qm31
-            //    twiddle factors 5 * (logn - 1) m31
-
-            // now handle the 1st query, start with the Merkle trees to obtain the siblings
-            for i in 0..N_QUERIES {
-                // copy the input for check_single_query_merkle_tree
-                for _ in 0..logn - 1 {
-                    { 5 * (logn - 1) + (logn - 1) * 4 + 5 + (logn - 1) - 1 } OP_PICK
-                }
+            OP_TRUE
+        };
 
-                // copy the query
-                { (logn - 1) + 5 * (logn - 1) + (logn - 1) * 4 + 4 - i } OP_PICK
+        report_bitcoin_script_size("FRI", "End-to-End", script.len());
 
-                { FRIGadget::check_single_query_merkle_tree_proof(logn) }
-
-                // stack:
-                //    proof body -- leaves (n_queries qm31), last layer (some qm31), commitments (logn - 1)
-                //    5 queries
-                //    alphas (logn - 1) qm31
-                //    twiddle factors 5 * (logn - 1) m31
-                //    siblings (logn - 1) qm31
-
-                // copy the input for check
-                // move siblings to alt stack
-                for _ in 0..(logn - 1) * 4 {
-                    OP_TOALTSTACK
-                }
-                // twiddle factors
-                for _ in 0..(logn - 1) {
-                    { (4 - i) * (logn - 1) + (logn - 1) - 1 } OP_PICK
-                }
-                // alphas
-                for _ in 0..(logn - 1) * 4 {
-                    { (logn - 1) + 5 * (logn - 1) + (logn - 1) * 4 - 1 } OP_PICK
-                }
-                // siblings
-                for _ in 0..(logn - 1) * 4 {
-                    OP_FROMALTSTACK
-                }
-                // leaf
-                for _ in 0..4 {
-                    { proof.last_layer.len() * 4 + (logn - 1) * (4 + 4 + 4 + 1) + (5 + 1) * (logn - 1) + 5 + 4 - 1 } OP_ROLL
-                }
-                // position
-                { (logn - 1) * (4 + 4 + 1 + 4 + 5) + 4 + (4 - i) } OP_PICK
-
-                { FRIGadget::check_single_query_ibutterfly(logn, (5 + 4 + 1) * (logn - 1) + 5 + proof.last_layer.len() * 4) }
-
-                // stack:
-                //    proof body -- leaves (n_queries - i qm31, disappearing), last layer (some qm31), commitments (logn - 1)
-                //    5 queries
-                //    alphas (logn - 1) qm31
-                //    twiddle factors 5 * (logn - 1) m31
-            }
-
-            for elem in expected_twiddle_tree.iter().rev() {
-                { *elem }
-                OP_EQUALVERIFY
-            }
-            for elem in expected_fiat_shamir.0.iter() {
-                { *elem }
-                qm31_equalverify
-            }
-            for elem in expected_fiat_shamir.1.iter().rev() {
-                { *elem }
-                OP_EQUALVERIFY
-            }
-
-            // drop last layer, and commitments
-            for _ in 0..((proof.last_layer.len()) * 4 + proof.commitments.len()) {
-                OP_DROP
-            }
-
-            OP_TRUE
-        };
-
-        report_bitcoin_script_size("FRI", "End-to-End", script.len());
+        let costs = fri::estimate_costs(logn, N_QUERIES, 2);
+        assert_eq!(
+            costs.script_bytes,
+            FRIGadget::check_fri(
+                channel_init_state.as_ref(),
+                logn,
+                N_QUERIES,
+                0,
+                TWIDDLE_MERKLE_TREE_ROOT_18
+            )
+            .len()
+        );
+        assert!(script.len() - costs.script_bytes < script.len() / 20);
+        assert_eq!(
+            costs.merkle_hashes,
+            proof
+                .merkle_proofs
+                .iter()
+                .flatten()
+                .map(|p| p.siblings.len() + 1)
+                .sum::<usize>()
+                + proof
+                    .twiddle_merkle_proofs
+                    .iter()
+                    .map(|p| p.siblings.len() + 1)
+                    .sum::<usize>()
+        );
 
         let mut exec = Exec::new(
             ExecCtx::Tapscript,
@@ -781,22 +1614,206 @@ mod test {
     }
 
     #[test]
-    fn test_ibutterfly() {
+    fn test_end_to_end_high_degree() {
+        let channel_init_state = BWSSha256Hash::from(vec![0u8; 32]);
+        let logn = 6;
+        let twiddle_merkle_tree_root = TwiddleMerkleTree::new(logn - 1).root_hash;
+
+        // an evaluation that is not of low degree, which folds honestly into a last layer whose
+        // two elements differ
         let mut prng = ChaCha20Rng::seed_from_u64(0);
+        let evaluation = (0..(1 << logn))
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<QM31>>();
+        let proof = fri::fri_prove(
+            &mut Sha256Channel::new(channel_init_state),
+            evaluation,
+            N_QUERIES,
+            0,
+        );
+
+        assert_eq!(
+            fri::fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn,
+                proof.clone(),
+                N_QUERIES,
+                0,
+                twiddle_merkle_tree_root
+            ),
+            Err(FriError::LastLayerDegree)
+        );
+        assert!(!run_proof(
+            channel_init_state,
+            &proof,
+            logn,
+            0,
+            twiddle_merkle_tree_root
+        ));
+    }
+
+    #[test]
+    fn test_end_to_end_pow() {
+        let logn = 5;
+        let pow_bits = 8;
+
+        let (channel_init_state, evaluation, proof) = fri_proof_fixture(logn, N_QUERIES, pow_bits);
+
+        report_bitcoin_script_size(
+            "FRI",
+            format!("End-to-End with PoW ({} bits)", pow_bits).as_str(),
+            FRIGadget::check_fri(
+                channel_init_state.as_ref(),
+                logn,
+                N_QUERIES,
+                pow_bits,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            )
+            .len(),
+        );
 
-        let a = QM31::from_m31(
-            M31::reduce(prng.next_u64()),
-            M31::reduce(prng.next_u64()),
-            M31::reduce(prng.next_u64()),
-            M31::reduce(prng.next_u64()),
+        // a correctly ground proof passes
+        assert!(run_proof(
+            channel_init_state,
+            &proof,
+            logn,
+            pow_bits,
+            TWIDDLE_MERKLE_TREE_ROOT_4
+        ));
+
+        // a bad nonce is rejected
+        let tampered = proof.tamper(TamperKind::WrongPowNonce);
+        assert_eq!(
+            fri::fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn,
+                tampered.clone(),
+                N_QUERIES,
+                pow_bits,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Err(FriError::InsufficientPow)
+        );
+        assert!(!run_proof(
+            channel_init_state,
+            &tampered,
+            logn,
+            pow_bits,
+            TWIDDLE_MERKLE_TREE_ROOT_4
+        ));
+
+        // a proof that is not ground at all is rejected
+        let unground_proof = fri::fri_prove(
+            &mut Sha256Channel::new(channel_init_state),
+            evaluation,
+            N_QUERIES,
+            0,
         );
+        assert!(!run_proof(
+            channel_init_state,
+            &unground_proof,
+            logn,
+            pow_bits,
+            TWIDDLE_MERKLE_TREE_ROOT_4
+        ));
+
+        // the queries are drawn after the proof of work, so the proof does not verify without it
+        assert!(!run_proof(
+            channel_init_state,
+            &proof,
+            logn,
+            0,
+            TWIDDLE_MERKLE_TREE_ROOT_4
+        ));
+    }
 
-        let b = QM31::from_m31(
-            M31::reduce(prng.next_u64()),
-            M31::reduce(prng.next_u64()),
-            M31::reduce(prng.next_u64()),
-            M31::reduce(prng.next_u64()),
+    #[test]
+    fn test_end_to_end_deduplicated() {
+        let logn = 10;
+        let n_layers = logn - 1;
+        let num_queries = 20;
+
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, num_queries, 0);
+        let twiddle_merkle_tree_root = TwiddleMerkleTree::new(n_layers).root_hash;
+
+        let witness = FRIGadget::push_fri_witness(channel_init_state, logn, &proof, 0);
+        let deduplicated = proof.to_witness_deduplicated();
+        let deduplicated_witness = FRIGadget::push_fri_witness_deduplicated(
+            channel_init_state,
+            logn,
+            &proof,
+            0,
+            &deduplicated,
+        );
+        report_bitcoin_script_size(
+            "FRI",
+            format!("witness ({} queries)", num_queries).as_str(),
+            witness.len(),
         );
+        report_bitcoin_script_size(
+            "FRI",
+            format!("witness deduplicated ({} queries)", num_queries).as_str(),
+            deduplicated_witness.len(),
+        );
+        assert!(deduplicated_witness.len() < witness.len());
+
+        let check_fri = FRIGadget::check_fri_deduplicated(
+            channel_init_state.as_ref(),
+            logn,
+            num_queries,
+            0,
+            twiddle_merkle_tree_root,
+            &deduplicated,
+        );
+        report_bitcoin_script_size(
+            "FRI",
+            format!("End-to-End deduplicated ({} queries)", num_queries).as_str(),
+            check_fri.len(),
+        );
+
+        // the outputs are as in `check_fri`
+        let num_outputs = 2 * 4 + n_layers + num_queries + n_layers * 4 + num_queries * n_layers;
+        let run = |witness: Script| {
+            run_script(script! {
+                { witness }
+                { check_fri.clone() }
+                for _ in 0..num_outputs {
+                    OP_DROP
+                }
+                OP_TRUE
+            })
+        };
+
+        assert!(run(deduplicated_witness));
+
+        // a sibling that several queries share is only pushed once, so tampering with it
+        // affects all of them, and is rejected
+        let mut tampered = deduplicated.clone();
+        let shared = (0..tampered.values.len())
+            .find(|&k| {
+                matches!(tampered.values[k], HintValue::Hash(_))
+                    && tampered.indices.iter().filter(|&&i| i == k).count() > 1
+            })
+            .unwrap();
+        if let HintValue::Hash(h) = &mut tampered.values[shared] {
+            h[0] ^= 1;
+        }
+        assert!(!run(FRIGadget::push_fri_witness_deduplicated(
+            channel_init_state,
+            logn,
+            &proof,
+            0,
+            &tampered,
+        )));
+    }
+
+    #[test]
+    fn test_ibutterfly() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let a = random_qm31(&mut prng);
+
+        let b = random_qm31(&mut prng);
 
         let itwid = M31::reduce(prng.next_u64());
 
@@ -820,4 +1837,1009 @@ mod test {
         let exec_result = execute_script(script);
         assert!(exec_result.success);
     }
+
+    #[test]
+    fn test_query_fold_index() {
+        let logn = 5;
+
+        let fold_script = query_fold_index_gadget(logn);
+        report_bitcoin_script_size(
+            "FRI",
+            format!("query_fold_index(2^{})", logn).as_str(),
+            fold_script.len(),
+        );
+
+        for pos in 0..(1 << logn) {
+            // follow the query through all the layers, as in `fri_prove`
+            let mut query = pos;
+            let mut expected = vec![];
+            for _ in 1..logn {
+                query >>= 1;
+                expected.push(query);
+            }
+
+            let script = script! {
+                { pos }
+                for i in 0..(logn - 1) {
+                    { query_fold_index_gadget(logn - i) }
+                    OP_DUP { expected[i] } OP_EQUALVERIFY
+                }
+                OP_DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        let script = script! {
+            { 1 << logn }
+            { fold_script.clone() }
+            OP_DROP
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_sibling_pair() {
+        let logn = 6;
+        let layer = 1;
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let pair_script = verify_sibling_pair_gadget(logn, layer);
+        report_bitcoin_script_size(
+            "FRI",
+            format!("sibling_pair(logn={}, layer={})", logn, layer).as_str(),
+            pair_script.len(),
+        );
+
+        let values = (0..(1 << (logn - layer)))
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<QM31>>();
+        let tree = MerkleTree::new(values.clone());
+
+        let check = |pos: usize, sibling_pos: usize| {
+            let script = script! {
+                { MerkleTreeGadget::push_merkle_tree_proof(&tree.query(pos)) }
+                { MerkleTreeGadget::push_merkle_tree_proof(&tree.query(sibling_pos)) }
+                { tree.root_hash }
+                { pos }
+                { values[pos] }
+                { sibling_pos }
+                { values[sibling_pos] }
+                { pair_script.clone() }
+                OP_TRUE
+            };
+            execute_script(script).success
+        };
+
+        // a point and its image under the involution, in either order
+        assert!(check(6, 7));
+        assert!(check(7, 6));
+
+        // two valid openings that are not a pair
+        assert!(!check(6, 9));
+        assert!(!check(5, 6));
+        assert!(!check(6, 6));
+    }
+
+    #[test]
+    fn test_spot_check() {
+        let logn = 6;
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let spot_check_script = spot_check_gadget(logn);
+        report_bitcoin_script_size(
+            "FRI",
+            format!("spot_check(logn={})", logn).as_str(),
+            spot_check_script.len(),
+        );
+
+        let values = (0..(1 << logn))
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<QM31>>();
+        let fri_tree = MerkleTree::new(values.clone());
+
+        // a trace that agrees with the FRI input, and one that differs from it at a single position
+        let pos = prng.gen_range(0..(1 << logn));
+        let mut tampered_values = values.clone();
+        tampered_values[pos] += QM31::one();
+
+        for (trace_values, expected) in [(values, true), (tampered_values, false)] {
+            let trace_tree = MerkleTree::new(trace_values);
+
+            let script = script! {
+                { MerkleTreeGadget::push_merkle_tree_proof(&trace_tree.query(pos)) }
+                { MerkleTreeGadget::push_merkle_tree_proof(&fri_tree.query(pos)) }
+                { trace_tree.root_hash }
+                { fri_tree.root_hash }
+                { pos }
+                { spot_check_script.clone() }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
+
+    #[test]
+    fn test_bind_queries_to_openings() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+        let logn = 10;
+
+        let bind_script = bind_queries_to_openings_gadget(N_QUERIES);
+        report_bitcoin_script_size(
+            "FRI",
+            format!("bind_queries_to_openings({} queries)", N_QUERIES).as_str(),
+            bind_script.len(),
+        );
+
+        for _ in 0..10 {
+            let mut a = [0u8; 32];
+            a.iter_mut().for_each(|v| *v = prng.gen());
+            let a = BWSSha256Hash::from(a.to_vec());
+
+            let mut channel = Sha256Channel::new(a);
+            let (queries, hint) = channel.draw_5queries(logn);
+
+            // the honest openings, and openings where the prover moved one of them elsewhere
+            let honest = queries.to_vec();
+            let mut tampered = honest.clone();
+            let idx = prng.gen_range(0..N_QUERIES);
+            tampered[idx] = (tampered[idx] + 1 + prng.gen_range(0..(1 << logn) - 1)) % (1 << logn);
+
+            for (openings, expected) in [(honest, true), (tampered, false)] {
+                let script = script! {
+                    { Sha256ChannelGadget::push_draw_hint(&hint) }
+                    { a }
+                    { Sha256ChannelGadget::draw_5numbers_with_hint(logn) }
+                    for opening in openings.iter() {
+                        { *opening }
+                    }
+                    { bind_script.clone() }
+                    for query in queries.iter().rev() {
+                        { *query } OP_EQUALVERIFY
+                    }
+                    { channel.digest }
+                    OP_EQUAL
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_first_layer_binding() {
+        let logn = 5;
+
+        let (channel_init_state, evaluation, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+
+        let queries = {
+            let mut channel = Sha256Channel::new(channel_init_state);
+
+            for c in proof.commitments.iter() {
+                channel.mix_digest(*c);
+                let _ = channel.draw_felt_and_hints();
+            }
+
+            channel.mix_felts(&proof.last_layer);
+            channel.draw_n_queries(N_QUERIES, logn).0
+        };
+
+        let binding_script = verify_first_layer_binding_gadget();
+        report_bitcoin_script_size("FRI", "first_layer_binding", binding_script.len());
+
+        for (query, leaf) in queries.iter().zip(proof.leaves.iter()) {
+            let script = script! {
+                { evaluation[*query] }
+                { *leaf }
+                { binding_script.clone() }
+                { evaluation[*query] }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        // the prover runs FRI on a value that is unrelated to the trace
+        let tampered_proof = proof.tamper(TamperKind::BadFoldValue);
+
+        let script = script! {
+            { evaluation[queries[0]] }
+            { tampered_proof.leaves[0] }
+            { binding_script.clone() }
+            OP_2DROP OP_2DROP
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_commit_phase() {
+        let logn = 5;
+
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+        let num_layers = proof.commitments.len();
+
+        let mut channel = Sha256Channel::new(channel_init_state);
+        let mut factors = vec![];
+        for c in proof.commitments.iter() {
+            channel.mix_digest(*c);
+            factors.push(channel.draw_felt_and_hints().0);
+        }
+
+        let commit_phase_script = commit_phase_gadget(num_layers);
+        report_bitcoin_script_size(
+            "FRI",
+            format!("commit_phase(num_layers={})", num_layers).as_str(),
+            commit_phase_script.len(),
+        );
+
+        let script = script! {
+            { push_commit_phase_hint(&mut Sha256Channel::new(channel_init_state), &proof) }
+            for c in proof.commitments.iter().rev() {
+                { *c }
+            }
+            { channel_init_state }
+            { commit_phase_script.clone() }
+            { channel.digest }
+            OP_EQUALVERIFY
+            for c in proof.commitments.iter().rev() {
+                { *c }
+                OP_EQUALVERIFY
+            }
+            for factor in factors.iter().rev() {
+                qm31_fromaltstack
+                { *factor }
+                qm31_equalverify
+            }
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+    }
+
+    #[test]
+    fn test_commit_phase_pre_absorption() {
+        let logn = 5;
+
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+        let num_layers = proof.commitments.len();
+
+        // a prover that draws each factor before absorbing the root of its layer
+        let mut channel = Sha256Channel::new(channel_init_state);
+        let mut pre_absorption_hints = vec![];
+        for c in proof.commitments.iter() {
+            pre_absorption_hints.push(channel.draw_felt_and_hints().1);
+            channel.mix_digest(*c);
+        }
+
+        let script = script! {
+            for hint in pre_absorption_hints.iter() {
+                { Sha256ChannelGadget::push_draw_hint(hint) }
+            }
+            for c in proof.commitments.iter().rev() {
+                { *c }
+            }
+            { channel_init_state }
+            { commit_phase_gadget(num_layers) }
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_layer_opening() {
+        let logn = 5;
+        let n_layers = logn - 1;
+
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+
+        let (alphas, queries) = {
+            let mut channel = Sha256Channel::new(channel_init_state);
+            let mut alphas = vec![];
+            for c in proof.commitments.iter() {
+                channel.mix_digest(*c);
+                alphas.push(channel.draw_felt_and_hints().0);
+            }
+            channel.mix_felts(&proof.last_layer);
+            (alphas, channel.draw_n_queries(N_QUERIES, logn).0)
+        };
+
+        let mut pos = queries[0];
+        let mut v = proof.leaves[0];
+        let twiddles = &proof.twiddle_merkle_proofs[0].elements;
+
+        for layer in 0..n_layers {
+            let opening_script = verify_layer_opening_gadget(logn, layer);
+            report_bitcoin_script_size(
+                "FRI",
+                format!("layer_opening(logn={}, layer={})", logn, layer).as_str(),
+                opening_script.len(),
+            );
+
+            let merkle_proof = &proof.merkle_proofs[0][layer];
+            let itwid = twiddles[n_layers - 1 - layer];
+
+            let next_v = {
+                let (mut f0, mut f1) = if pos & 1 == 0 {
+                    (v, merkle_proof.leaf)
+                } else {
+                    (merkle_proof.leaf, v)
+                };
+                ibutterfly(&mut f0, &mut f1, itwid);
+                f0 + alphas[layer] * f1
+            };
+
+            let script = script! {
+                { MerkleTreeGadget::push_merkle_tree_proof(merkle_proof) }
+                { alphas[layer] }
+                { itwid }
+                { proof.commitments[layer] }
+                { pos }
+                { v }
+                { opening_script.clone() }
+                { pos >> 1 }
+                OP_EQUALVERIFY
+                { next_v }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+
+            // the queried value is presented in place of its sibling
+            let mut swapped_proof = merkle_proof.clone();
+            swapped_proof.leaf = v;
+
+            let script = script! {
+                { MerkleTreeGadget::push_merkle_tree_proof(&swapped_proof) }
+                { alphas[layer] }
+                { itwid }
+                { proof.commitments[layer] }
+                { pos }
+                { merkle_proof.leaf }
+                { opening_script.clone() }
+                OP_DROP
+                OP_2DROP OP_2DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+
+            pos >>= 1;
+            v = next_v;
+        }
+
+        assert_eq!(v, proof.last_layer[pos]);
+    }
+
+    #[test]
+    fn test_last_layer_eval() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let degree = 1;
+
+        let verify_script = verify_last_layer_eval_gadget(degree);
+        report_bitcoin_script_size(
+            "FRI",
+            format!("last_layer_eval(degree={})", degree).as_str(),
+            verify_script.len(),
+        );
+
+        let coeffs = [random_qm31(&mut prng), random_qm31(&mut prng)];
+        let x = random_qm31(&mut prng);
+        let v = coeffs[0] + coeffs[1] * x;
+
+        let mut channel_init_state = [0u8; 32];
+        channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
+        let channel_init_state = BWSSha256Hash::from(channel_init_state.to_vec());
+
+        let mut channel = Sha256Channel::new(channel_init_state);
+        channel.mix_felts(&coeffs);
+
+        let script = script! {
+            { v }
+            { x }
+            for c in coeffs.iter() {
+                { *c }
+            }
+            { channel_init_state }
+            { verify_script.clone() }
+            { channel.digest }
+            OP_EQUAL
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+
+        // a mismatched coefficient is rejected
+        let script = script! {
+            { v }
+            { x }
+            { coeffs[0] }
+            { coeffs[1] + QM31::one() }
+            { channel_init_state }
+            { verify_script }
+            OP_DROP
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_layer_domain() {
+        let logn = 8;
+
+        let twiddles = get_twiddles(logn);
+        let circle_domain = CanonicCoset::new(logn as u32).circle_domain();
+
+        for layer in 0..logn {
+            let layer_domain_script = layer_domain_gadget(logn, layer);
+            report_bitcoin_script_size(
+                "FRI",
+                format!("layer_domain(logn={}, layer={})", logn, layer).as_str(),
+                layer_domain_script.len(),
+            );
+
+            // each layer halves the domain, which squares its generator
+            let expected = circle_domain.half_coset.repeated_double(layer as u32);
+            assert_eq!(expected.log_size as usize, logn - 1 - layer);
+            assert_eq!(
+                expected.step,
+                CirclePointIndex::subgroup_gen(logn as u32 - 1 - layer as u32).to_point()
+            );
+
+            // the domain starts from the first twiddle of the layer
+            let twiddle = if layer == 0 {
+                expected.initial.y
+            } else {
+                expected.initial.x
+            };
+            assert_eq!(twiddles[layer][0], twiddle);
+
+            let script = script! {
+                { layer_domain_script }
+                { expected.step.y }
+                OP_EQUALVERIFY
+                { expected.step.x }
+                OP_EQUALVERIFY
+                { expected.initial.y }
+                OP_EQUALVERIFY
+                { expected.initial.x }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_last_layer_ifft_check() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        // evaluate sum_i c_i * prod_{j in bits(i)} pi^j(x) at the points of the layer
+        let evaluate = |log_size: usize, coeffs: &[QM31]| {
+            let twiddles = get_twiddles(log_size + 1);
+            let eval_at = |x: M31| {
+                coeffs.iter().enumerate().fold(QM31::zero(), |acc, (i, c)| {
+                    let mut basis = M31::one();
+                    let mut pi = x;
+                    for j in 0..log_size {
+                        if (i >> j) & 1 == 1 {
+                            basis *= pi;
+                        }
+                        pi = pi * pi + pi * pi - M31::one();
+                    }
+                    acc + *c * basis
+                })
+            };
+            twiddles[1]
+                .iter()
+                .flat_map(|&x| [eval_at(x), eval_at(-x)])
+                .collect::<Vec<QM31>>()
+        };
+
+        // the line twiddles of a layer of size 2^log_size are the same in a larger FRI
+        assert_eq!(get_twiddles(10)[8], get_twiddles(3)[1]);
+
+        for (log_size, degree_bound) in [(2, 2), (3, 3), (3, 5)] {
+            let check_script = last_layer_ifft_check_gadget(log_size, degree_bound);
+            report_bitcoin_script_size(
+                "FRI",
+                format!(
+                    "last_layer_ifft_check(log_size={}, degree_bound={})",
+                    log_size, degree_bound
+                )
+                .as_str(),
+                check_script.len(),
+            );
+
+            for degree in [degree_bound, degree_bound + 1] {
+                let mut coeffs = vec![QM31::zero(); 1 << log_size];
+                for c in coeffs.iter_mut().take(degree + 1) {
+                    *c = random_qm31(&mut prng);
+                }
+                let evaluation = evaluate(log_size, &coeffs);
+
+                let script = script! {
+                    for v in evaluation.iter() {
+                        { *v }
+                    }
+                    { check_script.clone() }
+                    OP_TRUE
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, degree <= degree_bound);
+            }
+        }
+    }
+
+    #[test]
+    fn test_final_digest() {
+        let logn = 5;
+
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+
+        // the final digest of a transcript that skips absorbing the last layer
+        let mut channel = Sha256Channel::new(channel_init_state);
+        for c in proof.commitments.iter() {
+            channel.mix_digest(*c);
+            channel.draw_felt_and_hints();
+        }
+        channel.draw_n_queries(N_QUERIES, logn);
+        assert_ne!(channel.digest, proof.final_digest);
+
+        let mut skipped = proof.clone();
+        skipped.final_digest = channel.digest;
+
+        assert!(run_proof(
+            channel_init_state,
+            &proof,
+            logn,
+            0,
+            TWIDDLE_MERKLE_TREE_ROOT_4
+        ));
+        assert!(!run_proof(
+            channel_init_state,
+            &skipped,
+            logn,
+            0,
+            TWIDDLE_MERKLE_TREE_ROOT_4
+        ));
+
+        assert_eq!(
+            fri::fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn,
+                skipped,
+                N_QUERIES,
+                0,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Err(fri::FriError::FinalDigestMismatch)
+        );
+    }
+
+    #[test]
+    fn test_bind_twiddle() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 6;
+        let n_layers = logn - 1;
+
+        let twiddle_merkle_tree = TwiddleMerkleTree::new(n_layers);
+
+        let mut pos: u32 = prng.gen();
+        pos &= (1 << logn) - 1;
+
+        let proof = twiddle_merkle_tree.query(pos as usize);
+
+        for layer in 0..n_layers {
+            let bind_script = bind_twiddle_gadget(logn, layer);
+            report_bitcoin_script_size(
+                "FRI",
+                format!("bind_twiddle(logn={}, layer={})", logn, layer).as_str(),
+                bind_script.len(),
+            );
+
+            let itwid = proof.elements[n_layers - 1 - layer];
+            assert_eq!(
+                itwid,
+                twiddle_merkle_tree.twiddles_inverse[layer][pos as usize >> (layer + 1)]
+            );
+
+            // the twiddle factor of another layer is swapped in
+            let swapped = proof.elements[n_layers - 1 - (layer + 1) % n_layers];
+
+            for (claimed, expected) in [(itwid, true), (swapped, false)] {
+                let script = script! {
+                    { TwiddleMerkleTreeGadget::push_twiddle_merkle_tree_proof(&proof) }
+                    { twiddle_merkle_tree.root_hash.to_vec() }
+                    { pos }
+                    { claimed }
+                    { bind_script.clone() }
+                    { itwid }
+                    OP_EQUAL
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_assert_layer_count() {
+        let logn = 5;
+
+        let (_, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+        let dropped = proof.tamper(TamperKind::DropLayer);
+
+        let layer_count_script = assert_layer_count_gadget(logn);
+        report_bitcoin_script_size(
+            "FRI",
+            format!("assert_layer_count(logn={})", logn).as_str(),
+            layer_count_script.len(),
+        );
+
+        // the proof has one layer too few, or one layer too many for a smaller evaluation
+        for (proof, logn, expected) in [
+            (&proof, logn, true),
+            (&dropped, logn, false),
+            (&proof, logn - 1, false),
+        ] {
+            let script = script! {
+                for elem in proof.last_layer.iter().rev() {
+                    { *elem }
+                }
+                for c in proof.commitments.iter().rev() {
+                    { *c }
+                }
+                { assert_layer_count_gadget(logn) }
+                for _ in 0..proof.commitments.len() {
+                    OP_DROP
+                }
+                for _ in 0..proof.last_layer.len() {
+                    OP_2DROP OP_2DROP
+                }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
+
+    #[test]
+    fn test_accumulate_query_responses() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let n = N_QUERIES;
+
+        let accumulate_script = accumulate_query_responses_gadget(n);
+        report_bitcoin_script_size(
+            "FRI",
+            format!("accumulate_query_responses({} queries)", n).as_str(),
+            accumulate_script.len(),
+        );
+
+        let values = (0..n)
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<QM31>>();
+        let accumulator = accumulate_query_responses(&values);
+
+        // the values are kept for the openings
+        let script = script! {
+            { accumulator }
+            for v in values.iter() {
+                { *v }
+            }
+            { accumulate_script.clone() }
+            for v in values.iter().rev() {
+                { *v }
+                qm31_equalverify
+            }
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+
+        // tampering with any one response changes the accumulator
+        for i in 0..n {
+            let mut tampered = values.clone();
+            tampered[i] += QM31::one();
+            assert_ne!(accumulate_query_responses(&tampered), accumulator);
+
+            let script = script! {
+                { accumulator }
+                for v in tampered.iter() {
+                    { *v }
+                }
+                { accumulate_script.clone() }
+                for _ in 0..n {
+                    OP_2DROP OP_2DROP
+                }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_apply_degree_correction() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 8;
+        let n_layers = logn - 1;
+
+        for layer in 0..n_layers {
+            assert_eq!(
+                degree_correction_factor(layer) * M31::from(1u32 << (layer + 1)),
+                M31::one()
+            );
+        }
+
+        // each layer of stwo's folding doubles a constant evaluation, whatever the folding factors
+        let c = random_qm31(&mut prng);
+
+        let mut channel_init_state = [0u8; 32];
+        channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
+        let channel_init_state = BWSSha256Hash::from(channel_init_state.to_vec());
+
+        let proof = fri::fri_prove(
+            &mut Sha256Channel::new(channel_init_state),
+            vec![c; 1 << logn],
+            N_QUERIES,
+            0,
+        );
+        let folded = proof.last_layer[0];
+        assert_ne!(folded, c);
+
+        let correction_script = apply_degree_correction_gadget(n_layers - 1);
+        report_bitcoin_script_size(
+            "FRI",
+            format!("apply_degree_correction(layer={})", n_layers - 1).as_str(),
+            correction_script.len(),
+        );
+
+        // omitting the correction leaves the value off by 2^n_layers
+        for (correction, expected) in [(correction_script.clone(), true), (script! {}, false)] {
+            let script = script! {
+                { folded }
+                { correction }
+                { c }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
+
+    #[test]
+    fn test_assert_composition_degree() {
+        // constraints of degree 3 over a trace of size 2^3 need a composition degree bound of 2^5
+        let trace_log_size = 3;
+        let max_constraint_degree = 3;
+        let log_blowup = 1;
+        assert_eq!(
+            composition_log_degree_bound(trace_log_size, max_constraint_degree),
+            5
+        );
+
+        let prove = |logn: usize| fri_proof_fixture(logn, N_QUERIES, 0).2;
+
+        let composition_script =
+            assert_composition_degree_gadget(trace_log_size, max_constraint_degree, log_blowup);
+        report_bitcoin_script_size(
+            "FRI",
+            format!(
+                "assert_composition_degree(trace_log_size={}, degree={})",
+                trace_log_size, max_constraint_degree
+            )
+            .as_str(),
+            composition_script.len(),
+        );
+
+        // the second proof claims a composition degree bound of 2^4
+        for (logn, expected) in [(6, true), (5, false)] {
+            let proof = prove(logn);
+
+            assert_eq!(
+                check_composition_degree(&proof, trace_log_size, max_constraint_degree, log_blowup)
+                    .is_ok(),
+                expected
+            );
+
+            let script = script! {
+                for elem in proof.last_layer.iter().rev() {
+                    { *elem }
+                }
+                for c in proof.commitments.iter().rev() {
+                    { *c }
+                }
+                { composition_script.clone() }
+                for _ in 0..proof.commitments.len() {
+                    OP_DROP
+                }
+                for _ in 0..proof.last_layer.len() {
+                    OP_2DROP OP_2DROP
+                }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
+
+    #[test]
+    fn test_assert_same_domain() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 5;
+
+        let mut channel_init_state = [0u8; 32];
+        channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
+        let channel_init_state = BWSSha256Hash::from(channel_init_state.to_vec());
+
+        let prove = |logn: usize, offset: u32| {
+            let p = CirclePointIndex::subgroup_gen(logn as u32 + 1).to_point();
+            let evaluation = (0..(1 << logn))
+                .map(|i| (p.mul(i * 2 + 1).x.square() + M31::from_u32_unchecked(offset)).into())
+                .collect::<Vec<QM31>>();
+            fri::fri_prove(
+                &mut Sha256Channel::new(channel_init_state),
+                permute_eval(evaluation),
+                N_QUERIES,
+                0,
+            )
+        };
+
+        let trace_proof = prove(logn, 1);
+
+        let same_domain_script = assert_same_domain_gadget(logn);
+        report_bitcoin_script_size(
+            "FRI",
+            format!("assert_same_domain(logn={})", logn).as_str(),
+            same_domain_script.len(),
+        );
+
+        // the second composition proof is over a domain twice as large
+        for (composition_logn, expected) in [(logn, true), (logn + 1, false)] {
+            let composition_proof = prove(composition_logn, 2);
+
+            assert_eq!(
+                check_same_domain(&trace_proof, &composition_proof).is_ok(),
+                expected
+            );
+
+            let script = script! {
+                for proof in [&trace_proof, &composition_proof] {
+                    for elem in proof.last_layer.iter().rev() {
+                        { *elem }
+                    }
+                    for c in proof.commitments.iter().rev() {
+                        { *c }
+                    }
+                }
+                { same_domain_script.clone() }
+                for proof in [&composition_proof, &trace_proof] {
+                    for _ in 0..proof.commitments.len() {
+                        OP_DROP
+                    }
+                    for _ in 0..proof.last_layer.len() {
+                        OP_2DROP OP_2DROP
+                    }
+                }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
+
+    #[test]
+    fn test_verify_aggregated() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 5;
+        let num_proofs = 2;
+
+        let mut channel_init_state = [0u8; 32];
+        channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
+        let channel_init_state = BWSSha256Hash::from(channel_init_state.to_vec());
+
+        let p = CirclePointIndex::subgroup_gen(logn as u32 + 1).to_point();
+        let low_degree = |offset: u32| {
+            permute_eval(
+                (0..(1 << logn))
+                    .map(|i| (p.mul(i * 2 + 1).x.square() + M31::from_u32_unchecked(offset)).into())
+                    .collect::<Vec<QM31>>(),
+            )
+        };
+        let high_degree = (0..(1 << logn))
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<QM31>>();
+
+        let aggregate = |evaluations: &[Vec<QM31>]| {
+            let commitments = evaluations
+                .iter()
+                .map(|evaluation| MerkleTree::new(evaluation.clone()).root_hash)
+                .collect::<Vec<_>>();
+
+            let mut channel = Sha256Channel::new(channel_init_state);
+            let (combiner, hint) = draw_aggregation_combiner(&mut channel, &commitments);
+            let aggregated = aggregate_evaluations(combiner, evaluations);
+            let proof = fri::fri_prove(&mut channel, aggregated.clone(), N_QUERIES, 0);
+
+            let mut channel = Sha256Channel::new(channel_init_state);
+            draw_aggregation_combiner(&mut channel, &commitments);
+            let result = fri::fri_verify(
+                &mut channel,
+                logn,
+                proof,
+                N_QUERIES,
+                0,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            );
+
+            (commitments, combiner, hint, aggregated, result)
+        };
+
+        // two valid proofs pass, while including an invalid one fails FRI
+        let valid = vec![low_degree(1), low_degree(2)];
+        let (commitments, combiner, hint, aggregated, result) = aggregate(&valid);
+        assert!(result.is_ok());
+
+        let invalid = vec![low_degree(1), high_degree.clone()];
+        assert!(aggregate(&invalid).4.is_err());
+
+        let aggregated_script = verify_aggregated_gadget(num_proofs);
+        report_bitcoin_script_size(
+            "FRI",
+            format!("verify_aggregated({} proofs)", num_proofs).as_str(),
+            aggregated_script.len(),
+        );
+
+        let mut channel = Sha256Channel::new(channel_init_state);
+        draw_aggregation_combiner(&mut channel, &commitments);
+        let channel_after_draw = channel.digest;
+
+        for pos in 0..4 {
+            // the values of the invalid proof do not combine to the aggregated value
+            for (values, expected) in [
+                ([valid[0][pos], valid[1][pos]], true),
+                ([valid[0][pos], high_degree[pos]], false),
+            ] {
+                let script = script! {
+                    { Sha256ChannelGadget::push_draw_hint(&hint) }
+                    for value in values.iter() {
+                        { *value }
+                    }
+                    { aggregated[pos] }
+                    for commitment in commitments.iter().rev() {
+                        { *commitment }
+                    }
+                    { channel_init_state }
+                    { aggregated_script.clone() }
+                    { combiner }
+                    qm31_equalverify
+                    { channel_after_draw }
+                    OP_EQUALVERIFY
+                    { aggregated[pos] }
+                    qm31_equalverify
+                    OP_TRUE
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, expected);
+            }
+        }
+    }
 }
@@ -1,9 +1,17 @@
-use crate::channel::{ChannelWithHint, Sha256Channel};
+use crate::channel::{ChannelWithHint, DrawQM31Hints, FriChannel, Sha256Channel};
+use crate::circle::CircleDomain;
+use crate::constraints::composition_log_degree_bound;
 use crate::merkle_tree::{MerkleTree, MerkleTreeProof};
+use crate::pow::{check_leading_zeros, grind_find_nonce, hash_with_nonce};
+use crate::treepp::pushable::{Builder, Pushable};
 use crate::twiddle_merkle_tree::{TwiddleMerkleTree, TwiddleMerkleTreeProof};
-use crate::utils::get_twiddles;
+use crate::utils::{get_twiddles, hash_m31_vec};
+use num_traits::{One, Zero};
+use std::collections::HashMap;
 use stwo_prover::core::channel::Channel;
+use stwo_prover::core::circle::Coset;
 use stwo_prover::core::fft::ibutterfly;
+use stwo_prover::core::fields::m31::M31;
 use stwo_prover::core::fields::qm31::QM31;
 use stwo_prover::core::fields::FieldExpOps;
 use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
@@ -19,12 +27,406 @@ pub struct FriProof {
     leaves: Vec<QM31>,
     merkle_proofs: Vec<Vec<MerkleTreeProof>>,
     twiddle_merkle_proofs: Vec<TwiddleMerkleTreeProof>,
+    pow_nonce: u64,
+    final_digest: BWSSha256Hash,
 }
 
-const N_QUERIES: usize = 5; // cannot change. hardcoded in the Channel implementation
+/// A way to tamper with a valid FRI proof, used to exercise the rejection paths of the verifier.
+///
+/// Each variant touches exactly one field of the proof.
+#[derive(Clone, Copy, Debug)]
+pub enum TamperKind {
+    /// Flip a bit of the first sibling in the first-layer decommitment of the first query.
+    FlipSiblingBit,
+    /// Flip a bit of the first layer's Merkle root.
+    CorruptLayerRoot,
+    /// Change the first element of the last layer.
+    WrongLastLayer,
+    /// Change the leaf of the first query, so that folding leads to a wrong value.
+    BadFoldValue,
+    /// Drop the root of the last layer, so that the proof has one layer too few.
+    DropLayer,
+    /// Change the proof-of-work nonce, so that it does not lead to enough leading zero bits.
+    WrongPowNonce,
+}
+
+/// A hint of the twiddle tree and Merkle tree proofs in a FRI proof.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HintValue {
+    /// An m31 element, i.e., a twiddle factor or a limb of a leaf.
+    M31(u32),
+    /// A hash, i.e., a sibling.
+    Hash([u8; 32]),
+}
+
+impl Pushable for HintValue {
+    fn bitcoin_script_push(self, builder: Builder) -> Builder {
+        match self {
+            HintValue::M31(v) => v.bitcoin_script_push(builder),
+            HintValue::Hash(h) => h.to_vec().bitcoin_script_push(builder),
+        }
+    }
+}
+
+/// The hints of the twiddle tree and Merkle tree proofs in a FRI proof, where each distinct value
+/// appears once, see `FriProof::to_witness_deduplicated`.
+///
+/// The indices depend on the queries, so this is only used to measure the witness size, see
+/// `FRIGadget::check_fri_deduplicated`.
+#[derive(Clone, Debug)]
+pub struct DeduplicatedWitness {
+    /// The distinct values, in the order of their first use.
+    pub values: Vec<HintValue>,
+    /// The index in `values` of each hint, in the order that the verifier consumes them.
+    pub indices: Vec<usize>,
+}
+
+impl FriProof {
+    /// The leaves of the queries, i.e., the values of the evaluation at the queries, in the order
+    /// that the queries are drawn.
+    pub fn leaves(&self) -> &[QM31] {
+        &self.leaves
+    }
+
+    /// The number of queries that the proof opens.
+    pub fn num_queries(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Replay the Fiat-Shamir of the proof on the channel, i.e., absorb the commitments, the last
+    /// layer, and the proof-of-work nonce, as `fri_verify_with_queries` does, and draw the queries
+    /// without checking the proof, so that the openings of other commitments can be placed at the
+    /// same positions.
+    pub fn draw_queries<C: FriChannel>(
+        &self,
+        channel: &mut C,
+        logn: usize,
+        pow_bits: usize,
+    ) -> Vec<usize> {
+        for c in self.commitments.iter() {
+            channel.absorb_digest(*c);
+            channel.draw_challenge();
+        }
+        channel.absorb_felts(&self.last_layer);
+        if pow_bits > 0 {
+            let hash = hash_with_nonce(channel.state().as_ref(), self.pow_nonce);
+            channel.set_state(BWSSha256Hash::from(hash));
+        }
+        channel.draw_queries(self.num_queries(), logn)
+    }
+
+    /// The evaluation domain that the number of layers of the proof implies, i.e., the canonic
+    /// circle domain of size 2^(num_layers + LAST_LAYER_LOG_SIZE).
+    pub fn domain(&self) -> CircleDomain {
+        CircleDomain::new((self.commitments.len() + LAST_LAYER_LOG_SIZE) as u32)
+    }
+
+    /// The hints of the twiddle tree and Merkle tree proofs, in the order that
+    /// `FRIGadget::check_fri` consumes them: the twiddle tree proof of each query, as pushed by
+    /// `TwiddleMerkleTreeGadget::push_twiddle_merkle_tree_proof`, and then the Merkle tree
+    /// proofs of each query, as pushed by `FRIGadget::push_single_query_merkle_tree_proof`.
+    pub fn hint_values(&self) -> Vec<HintValue> {
+        let mut values = vec![];
+
+        for proof in self.twiddle_merkle_proofs.iter() {
+            values.push(HintValue::M31(proof.elements.last().unwrap().0));
+            for (element, sibling) in proof
+                .elements
+                .iter()
+                .rev()
+                .skip(1)
+                .zip(proof.siblings.iter())
+            {
+                values.push(HintValue::M31(element.0));
+                values.push(HintValue::Hash(*sibling));
+            }
+            values.push(HintValue::Hash(*proof.siblings.last().unwrap()));
+        }
+
+        for proof in self.merkle_proofs.iter().flatten() {
+            // the limbs of the leaf, in the order that they are pushed as a qm31 element
+            for limb in [
+                proof.leaf.1 .1,
+                proof.leaf.1 .0,
+                proof.leaf.0 .1,
+                proof.leaf.0 .0,
+            ] {
+                values.push(HintValue::M31(limb.0));
+            }
+            for sibling in proof.siblings.iter() {
+                values.push(HintValue::Hash(*sibling));
+            }
+        }
+
+        values
+    }
+
+    /// Deduplicate the hints of the twiddle tree and Merkle tree proofs, i.e., the twiddle
+    /// factors and the siblings near the roots that several queries share, so that each distinct
+    /// value is pushed only once, see `FRIGadget::push_fri_witness_deduplicated`.
+    pub fn to_witness_deduplicated(&self) -> DeduplicatedWitness {
+        let mut positions = HashMap::new();
+        let mut values = vec![];
+        let mut indices = vec![];
+
+        for value in self.hint_values() {
+            let index = *positions.entry(value).or_insert_with(|| {
+                values.push(value);
+                values.len() - 1
+            });
+            indices.push(index);
+        }
+
+        DeduplicatedWitness { values, indices }
+    }
+
+    /// Return a copy of the proof that is tampered with in the given way.
+    pub fn tamper(&self, kind: TamperKind) -> FriProof {
+        let mut proof = self.clone();
+        match kind {
+            TamperKind::FlipSiblingBit => {
+                proof.merkle_proofs[0][0].siblings[0][0] ^= 1;
+            }
+            TamperKind::CorruptLayerRoot => {
+                let mut root = proof.commitments[0].as_ref().to_vec();
+                root[0] ^= 1;
+                proof.commitments[0] = BWSSha256Hash::from(root);
+            }
+            TamperKind::WrongLastLayer => {
+                proof.last_layer[0] += QM31::one();
+            }
+            TamperKind::BadFoldValue => {
+                proof.leaves[0] += QM31::one();
+            }
+            TamperKind::DropLayer => {
+                proof.commitments.pop();
+            }
+            TamperKind::WrongPowNonce => {
+                proof.pow_nonce += 1;
+            }
+        }
+        proof
+    }
+}
+
+/// The reason a FRI proof is rejected by `fri_verify`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FriError {
+    /// A Merkle path, of a layer tree or of the twiddle tree, does not lead to the committed root.
+    MerkleMismatch,
+    /// Folding the queried values does not lead to the value in the last layer.
+    FoldMismatch,
+    /// The last layer is not of the expected degree.
+    LastLayerDegree,
+    /// A query drawn by the channel does not fall within the evaluation domain.
+    QueryOutOfRange,
+    /// The proof-of-work nonce does not lead to enough leading zero bits.
+    InsufficientPow,
+    /// The channel digest after the queries are drawn is not the final digest in the proof, which
+    /// is checked after all the other checks.
+    FinalDigestMismatch,
+    /// The proof does not have the expected shape.
+    MalformedProof,
+}
+
+/// The default number of queries.
+pub const N_QUERIES: usize = 5;
+
+/// The log size of the last layer, which the prover sends in the clear.
+pub const LAST_LAYER_LOG_SIZE: usize = 1;
+
+/// The number of layers that a FRI proof for an evaluation of size 2^logn commits to, since each
+/// layer folds the domain by a factor of 2 until it reaches the size of the last layer.
+pub fn num_fri_layers(logn: usize) -> usize {
+    assert!(logn > LAST_LAYER_LOG_SIZE);
+    logn - LAST_LAYER_LOG_SIZE
+}
+
+/// The log size of the evaluation that the FRI proof of the composition polynomial is over, for
+/// constraints of degree at most `max_constraint_degree` over a trace of size 2^trace_log_size,
+/// see `composition_log_degree_bound`, with a blowup factor of 2^log_blowup.
+pub fn composition_fri_logn(
+    trace_log_size: usize,
+    max_constraint_degree: usize,
+    log_blowup: usize,
+) -> usize {
+    composition_log_degree_bound(trace_log_size, max_constraint_degree) + log_blowup
+}
+
+/// Check that the FRI proof of the composition polynomial has the number of layers of an
+/// evaluation of size 2^composition_fri_logn, so that it does not claim a lower degree than the
+/// constraints require.
+pub fn check_composition_degree(
+    proof: &FriProof,
+    trace_log_size: usize,
+    max_constraint_degree: usize,
+    log_blowup: usize,
+) -> Result<(), FriError> {
+    let logn = composition_fri_logn(trace_log_size, max_constraint_degree, log_blowup);
+    if proof.commitments.len() != num_fri_layers(logn) {
+        return Err(FriError::MalformedProof);
+    }
+    Ok(())
+}
+
+/// Check that the FRI proofs of the trace and of the composition polynomial are over the same
+/// evaluation domain, which the DEEP quotient that mixes them assumes.
+///
+/// Both domains are the canonic circle domain of their size, so the same size implies the same
+/// coset.
+pub fn check_same_domain(
+    trace_proof: &FriProof,
+    composition_proof: &FriProof,
+) -> Result<(), FriError> {
+    if trace_proof.domain() != composition_proof.domain() {
+        return Err(FriError::MalformedProof);
+    }
+    Ok(())
+}
+
+/// The estimated costs of verifying a FRI proof in Bitcoin script.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProofCosts {
+    /// Number of Merkle tree nodes, including the leaves, that are hashed in the layer trees and
+    /// the twiddle tree.
+    pub merkle_hashes: usize,
+    /// Number of times that the channel is squeezed.
+    pub channel_squeezes: usize,
+    /// Size of the verifier script in bytes, excluding the hints and the proof body.
+    pub script_bytes: usize,
+}
+
+/// Estimate the costs of verifying a FRI proof for an evaluation of size 2^logn without
+/// generating a proof.
+///
+/// The script size is that of `FRIGadget::check_fri` without proof-of-work. The verifier folds by
+/// a factor of 2.
+pub fn estimate_costs(logn: usize, num_queries: usize, fold_factor: usize) -> ProofCosts {
+    assert_eq!(fold_factor, 2, "only folding by a factor of 2 is supported");
+    assert!(num_queries > 0);
+
+    let n_layers = logn - 1;
+
+    // each query opens the layers of sizes 2^logn, ..., 2^2, and a path of the twiddle tree
+    let merkle_hashes = num_queries * ((2..=logn).map(|i| i + 1).sum::<usize>() + logn);
+
+    // one squeeze for each folding factor, and then for the queries, which take 8 m31 per hash
+    let channel_squeezes = n_layers + num_queries.div_ceil(8);
+
+    let dummy = [0u8; 32];
+    let script_bytes = FRIGadget::check_fri(&dummy, logn, num_queries, 0, dummy).len();
+
+    ProofCosts {
+        merkle_hashes,
+        channel_squeezes,
+        script_bytes,
+    }
+}
+
+/// Accumulate the values opened by the queries into a single hash, which chains the limbs of
+/// all the values in order as `hash_m31_vec` does, so that it is `hash_qm31` for a single value.
+///
+/// The prover commits to it, and `accumulate_query_responses_gadget` checks the responses
+/// against it at once, before the openings are verified.
+pub fn accumulate_query_responses(values: &[QM31]) -> BWSSha256Hash {
+    let limbs = values
+        .iter()
+        .flat_map(|v| [v.0 .0, v.0 .1, v.1 .0, v.1 .1])
+        .collect::<Vec<M31>>();
+    BWSSha256Hash::from(hash_m31_vec(&limbs).to_vec())
+}
+
+/// Absorb the commitments to the DEEP evaluations of the aggregated proofs, commitment_0 first,
+/// and then draw the combiner, so that it depends on all the statements, together with the hint
+/// of the draw.
+pub fn draw_aggregation_combiner(
+    channel: &mut Sha256Channel,
+    commitments: &[BWSSha256Hash],
+) -> (QM31, DrawQM31Hints) {
+    for commitment in commitments.iter() {
+        channel.mix_digest(*commitment);
+    }
+    channel.draw_felt_and_hints()
+}
+
+/// Combine the DEEP evaluations of the aggregated proofs into the single evaluation that FRI
+/// proves, i.e., evaluation_0 + combiner * evaluation_1 + ... + combiner^{n-1} * evaluation_{n-1}.
+///
+/// The combination is of low degree, except with a negligible probability over the combiner, only
+/// if every evaluation is.
+pub fn aggregate_evaluations(combiner: QM31, evaluations: &[Vec<QM31>]) -> Vec<QM31> {
+    assert!(!evaluations.is_empty());
+    let len = evaluations[0].len();
+    assert!(evaluations.iter().all(|evaluation| evaluation.len() == len));
+
+    (0..len)
+        .map(|i| {
+            evaluations
+                .iter()
+                .rev()
+                .fold(QM31::zero(), |acc, evaluation| {
+                    acc * combiner + evaluation[i]
+                })
+        })
+        .collect()
+}
+
+/// The domain of the `layer`-th FRI layer for an evaluation of size 2^logn, whose points give the
+/// twiddles of the layer in `get_twiddles`: the y-coordinates for the first layer, which folds the
+/// circle domain onto a line, and the x-coordinates for the later layers.
+///
+/// It is the half coset of the canonic circle domain of size 2^logn, doubled once per layer.
+pub fn layer_domain(logn: usize, layer: usize) -> Coset {
+    assert!(logn >= 1 && layer < logn);
+    CircleDomain::new(logn as u32)
+        .half_coset()
+        .repeated_double(layer as u32)
+}
+
+/// The factor that corrects the value folded by the `layer`-th FRI layer to the fold of the
+/// original polynomial, i.e., 2^-(layer + 1).
+///
+/// As in stwo, each layer folds with the unnormalized inverse butterfly, which computes
+/// f(x) + f(-x) and (f(x) - f(-x)) / x, twice the even and odd parts of f, so that every layer,
+/// the circle to line fold as well as the line folds, doubles the degree-respecting fold.
+pub fn degree_correction_factor(layer: usize) -> M31 {
+    M31::from(2u32).pow(layer as u128 + 1).inverse()
+}
+
+/// Grind a proof-of-work nonce on the channel, whose digest becomes sha256(digest || nonce) as in
+/// `PowGadget::verify_pow`. Nothing is ground, and the channel is unchanged, if `pow_bits` is 0.
+fn grind_pow<C: FriChannel>(channel: &mut C, pow_bits: usize) -> u64 {
+    if pow_bits == 0 {
+        return 0;
+    }
+    let digest = channel.state();
+    let nonce = grind_find_nonce(digest.as_ref().to_vec(), pow_bits as u32);
+    channel.set_state(BWSSha256Hash::from(hash_with_nonce(digest.as_ref(), nonce)));
+    nonce
+}
+
+/// Generate a FRI proof with `num_queries` queries, which grinds `pow_bits` bits of proof-of-work
+/// after the commit phase, so that the queries are drawn from the digest after grinding.
+pub fn fri_prove<C: FriChannel>(
+    channel: &mut C,
+    evaluation: Vec<QM31>,
+    num_queries: usize,
+    pow_bits: usize,
+) -> FriProof {
+    fri_prove_with_queries(channel, evaluation, num_queries, pow_bits).0
+}
+
+/// Generate a FRI proof as `fri_prove` does, and also return the queries, i.e., the positions in
+/// the evaluation of the leaves of the proof, so that the caller can open its own commitments to
+/// the values that the evaluation is computed from at the same positions.
+pub fn fri_prove_with_queries<C: FriChannel>(
+    channel: &mut C,
+    evaluation: Vec<QM31>,
+    num_queries: usize,
+    pow_bits: usize,
+) -> (FriProof, Vec<usize>) {
+    assert!(num_queries > 0);
 
-/// Generate a FRI proof.
-pub fn fri_prove(channel: &mut Sha256Channel, evaluation: Vec<QM31>) -> FriProof {
     let logn = evaluation.len().ilog2() as usize;
     let n_layers = logn - 1;
     let twiddles = get_twiddles(logn);
@@ -40,12 +442,12 @@ pub fn fri_prove(channel: &mut Sha256Channel, evaluation: Vec<QM31>) -> FriProof
 
         let tree = MerkleTree::new(layer.clone());
 
-        channel.mix_digest(tree.root_hash);
+        channel.absorb_digest(tree.root_hash);
         commitments.push(tree.root_hash);
 
         trees.push(tree);
 
-        let (alpha, _) = channel.draw_felt_and_hints();
+        let alpha = channel.draw_challenge();
 
         layer = layer
             .chunks_exact(2)
@@ -60,19 +462,24 @@ pub fn fri_prove(channel: &mut Sha256Channel, evaluation: Vec<QM31>) -> FriProof
 
     // Last layer.
     let last_layer = layer;
-    channel.mix_felts(&last_layer);
+    channel.absorb_felts(&last_layer);
+
+    // Proof of work.
+    let pow_nonce = grind_pow(channel, pow_bits);
 
     // Queries.
-    let queries = channel.draw_5queries(logn).0.to_vec();
+    let queries = channel.draw_queries(num_queries, logn);
+    let final_digest = channel.state();
 
     // Decommit.
-    let mut leaves = Vec::with_capacity(N_QUERIES);
-    let mut merkle_proofs = Vec::with_capacity(N_QUERIES);
-    let mut twiddle_merkle_proofs = Vec::with_capacity(N_QUERIES);
+    let mut leaves = Vec::with_capacity(num_queries);
+    let mut merkle_proofs = Vec::with_capacity(num_queries);
+    let mut twiddle_merkle_proofs = Vec::with_capacity(num_queries);
 
     let twiddle_merkle_tree = TwiddleMerkleTree::new(n_layers);
 
-    for mut query in queries {
+    for &query in queries.iter() {
+        let mut query = query;
         leaves.push(layers[0][query]);
         twiddle_merkle_proofs.push(twiddle_merkle_tree.query(query));
         let mut layer_decommitments = Vec::with_capacity(n_layers);
@@ -82,80 +489,527 @@ pub fn fri_prove(channel: &mut Sha256Channel, evaluation: Vec<QM31>) -> FriProof
         }
         merkle_proofs.push(layer_decommitments);
     }
-    FriProof {
+    let proof = FriProof {
         commitments,
         last_layer,
         leaves,
         merkle_proofs,
         twiddle_merkle_proofs,
-    }
+        pow_nonce,
+        final_digest,
+    };
+    (proof, queries)
 }
 
-/// Verify the FRI proof.
-pub fn fri_verify(
-    channel: &mut Sha256Channel,
+/// Verify the FRI proof, which must open `num_queries` queries and whose proof-of-work must have
+/// at least `pow_bits` bits.
+pub fn fri_verify<C: FriChannel>(
+    channel: &mut C,
     logn: usize,
     proof: FriProof,
+    num_queries: usize,
+    pow_bits: usize,
     twiddle_merkle_tree_root: [u8; 32],
-) {
-    let n_layers = logn - 1;
+) -> Result<(), FriError> {
+    fri_verify_with_queries(
+        channel,
+        logn,
+        proof,
+        num_queries,
+        pow_bits,
+        twiddle_merkle_tree_root,
+    )
+    .map(|_| ())
+}
 
-    // Draw factors.
-    let mut factors = Vec::with_capacity(n_layers);
-    for c in proof.commitments.iter() {
-        channel.mix_digest(*c);
-        factors.push(channel.draw_felt_and_hints().0);
+/// Verify the FRI proof as `fri_verify` does, and return the queries, i.e., the positions in the
+/// evaluation of the leaves of the proof, so that the caller can check the leaves against the
+/// values that the evaluation is computed from.
+pub fn fri_verify_with_queries<C: FriChannel>(
+    channel: &mut C,
+    logn: usize,
+    proof: FriProof,
+    num_queries: usize,
+    pow_bits: usize,
+    twiddle_merkle_tree_root: [u8; 32],
+) -> Result<Vec<usize>, FriError> {
+    if logn <= LAST_LAYER_LOG_SIZE {
+        return Err(FriError::MalformedProof);
     }
-    // Last layer.
-    channel.mix_felts(&proof.last_layer);
-    // Check it's of half degree.
-    assert_eq!(proof.last_layer[0], proof.last_layer[1]);
-    // Queries.
-    let queries = channel.draw_5queries(logn).0.to_vec();
-    // Decommit.
-    for (mut query, ((mut leaf, merkle_proof), twiddle_merkle_tree_proof)) in
-        queries.iter().copied().zip(
-            proof
-                .leaves
-                .iter()
-                .copied()
-                .zip(proof.merkle_proofs.iter())
-                .zip(proof.twiddle_merkle_proofs.iter()),
-        )
+    let n_layers = num_fri_layers(logn);
+
+    // the number of layers is fixed by the claimed size of the evaluation
+    if proof.commitments.len() != n_layers
+        || proof.last_layer.len() != 1 << LAST_LAYER_LOG_SIZE
+        || proof.leaves.len() != num_queries
+        || proof.merkle_proofs.len() != num_queries
+        || proof.twiddle_merkle_proofs.len() != num_queries
+        || proof.merkle_proofs.iter().any(|v| {
+            v.len() != n_layers
+                || v.iter()
+                    .enumerate()
+                    .any(|(i, opening)| opening.siblings.len() != logn - i)
+        })
+        || proof
+            .twiddle_merkle_proofs
+            .iter()
+            .any(|v| v.elements.len() != n_layers || v.siblings.len() != n_layers)
     {
-        assert!(TwiddleMerkleTree::verify(
+        return Err(FriError::MalformedProof);
+    }
+
+    let mut verifier = FriVerifier::new(
+        channel,
+        logn,
+        num_queries,
+        pow_bits,
+        twiddle_merkle_tree_root,
+    );
+
+    for c in proof.commitments.iter() {
+        verifier.absorb_layer(*c)?;
+    }
+    verifier.absorb_last_layer(&proof.last_layer, proof.pow_nonce)?;
+    let queries = verifier.queries().to_vec();
+    verifier.open_queries(&proof.leaves, &proof.twiddle_merkle_proofs)?;
+    for i in 0..n_layers {
+        let openings = proof
+            .merkle_proofs
+            .iter()
+            .map(|v| v[i].clone())
+            .collect::<Vec<MerkleTreeProof>>();
+        verifier.query_layer(&openings)?;
+    }
+    verifier.finalize()?;
+
+    if channel.state() != proof.final_digest {
+        return Err(FriError::FinalDigestMismatch);
+    }
+    Ok(queries)
+}
+
+/// A FRI verifier that takes the proof one layer at a time, so that the openings of all the
+/// layers do not need to be in memory at once.
+///
+/// The proof is fed in the following order:
+/// - the root of each layer, with `absorb_layer`,
+/// - the last layer and the proof-of-work nonce, with `absorb_last_layer`, after which the
+///   queries are drawn,
+/// - the leaves and twiddle tree proofs of the queries, with `open_queries`,
+/// - the openings of each layer for all the queries, with `query_layer`,
+/// - and `finalize` checks the folded values against the last layer.
+///
+/// It accepts exactly the proofs that `fri_verify` accepts.
+pub struct FriVerifier<'a, C: FriChannel> {
+    channel: &'a mut C,
+    logn: usize,
+    num_queries: usize,
+    pow_bits: usize,
+    twiddle_merkle_tree_root: [u8; 32],
+    commitments: Vec<BWSSha256Hash>,
+    factors: Vec<QM31>,
+    last_layer: Vec<QM31>,
+    queries: Vec<usize>,
+    folded: Vec<QM31>,
+    twiddles: Vec<Vec<M31>>,
+    n_queried_layers: usize,
+}
+
+impl<'a, C: FriChannel> FriVerifier<'a, C> {
+    /// Start the verification of a FRI proof for an evaluation of size 2^logn, which opens
+    /// `num_queries` queries and whose proof-of-work must have at least `pow_bits` bits.
+    pub fn new(
+        channel: &'a mut C,
+        logn: usize,
+        num_queries: usize,
+        pow_bits: usize,
+        twiddle_merkle_tree_root: [u8; 32],
+    ) -> Self {
+        Self {
+            channel,
+            logn,
+            num_queries,
+            pow_bits,
             twiddle_merkle_tree_root,
-            logn - 1,
-            twiddle_merkle_tree_proof,
-            query
-        ));
-        for (i, (eval_proof, &alpha)) in merkle_proof.iter().zip(factors.iter()).enumerate() {
-            assert!(MerkleTree::verify(
-                &proof.commitments[i],
-                logn - i,
-                &merkle_proof[i],
-                query ^ 1
-            ));
+            commitments: Vec::with_capacity(logn - 1),
+            factors: Vec::with_capacity(logn - 1),
+            last_layer: vec![],
+            queries: vec![],
+            folded: vec![],
+            twiddles: vec![],
+            n_queried_layers: 0,
+        }
+    }
+
+    /// Absorb the root of the next layer and draw its folding factor.
+    pub fn absorb_layer(&mut self, root: BWSSha256Hash) -> Result<(), FriError> {
+        if self.commitments.len() == self.logn - 1 || !self.last_layer.is_empty() {
+            return Err(FriError::MalformedProof);
+        }
+
+        self.channel.absorb_digest(root);
+        self.factors.push(self.channel.draw_challenge());
+        self.commitments.push(root);
+        Ok(())
+    }
+
+    /// Absorb the last layer, check its degree and the proof-of-work, and draw the queries from
+    /// the digest after the proof-of-work.
+    pub fn absorb_last_layer(
+        &mut self,
+        last_layer: &[QM31],
+        pow_nonce: u64,
+    ) -> Result<(), FriError> {
+        if self.commitments.len() != self.logn - 1
+            || !self.last_layer.is_empty()
+            || last_layer.len() != 2
+        {
+            return Err(FriError::MalformedProof);
+        }
+
+        self.channel.absorb_felts(last_layer);
+        // Check it's of half degree.
+        if last_layer[0] != last_layer[1] {
+            return Err(FriError::LastLayerDegree);
+        }
+        if self.pow_bits > 0 {
+            let hash = hash_with_nonce(self.channel.state().as_ref(), pow_nonce);
+            if !check_leading_zeros(&hash, self.pow_bits as u32) {
+                return Err(FriError::InsufficientPow);
+            }
+            self.channel.set_state(BWSSha256Hash::from(hash));
+        }
+        self.last_layer = last_layer.to_vec();
+        self.queries = self.channel.draw_queries(self.num_queries, self.logn);
+        Ok(())
+    }
+
+    /// The queries, in the layer that is to be queried next, once they are drawn.
+    pub fn queries(&self) -> &[usize] {
+        &self.queries
+    }
+
+    /// Take the leaves of the queries in the first layer, and check the twiddle tree proofs of
+    /// the queries.
+    pub fn open_queries(
+        &mut self,
+        leaves: &[QM31],
+        twiddle_merkle_proofs: &[TwiddleMerkleTreeProof],
+    ) -> Result<(), FriError> {
+        if self.queries.len() != self.num_queries
+            || !self.folded.is_empty()
+            || leaves.len() != self.num_queries
+            || twiddle_merkle_proofs.len() != self.num_queries
+            || twiddle_merkle_proofs
+                .iter()
+                .any(|v| v.elements.len() != self.logn - 1 || v.siblings.len() != self.logn - 1)
+        {
+            return Err(FriError::MalformedProof);
+        }
+
+        for (&query, twiddle_merkle_proof) in self.queries.iter().zip(twiddle_merkle_proofs) {
+            if query >= 1 << self.logn {
+                return Err(FriError::QueryOutOfRange);
+            }
+            if !TwiddleMerkleTree::verify(
+                self.twiddle_merkle_tree_root,
+                self.logn - 1,
+                twiddle_merkle_proof,
+                query,
+            ) {
+                return Err(FriError::MerkleMismatch);
+            }
+        }
+
+        self.folded = leaves.to_vec();
+        self.twiddles = twiddle_merkle_proofs
+            .iter()
+            .map(|v| v.elements.clone())
+            .collect();
+        Ok(())
+    }
+
+    /// Check the openings of the siblings in the next layer, one for each query, and fold.
+    pub fn query_layer(&mut self, openings: &[MerkleTreeProof]) -> Result<(), FriError> {
+        let n_layers = self.logn - 1;
+        let i = self.n_queried_layers;
+
+        if self.folded.len() != self.num_queries
+            || i == n_layers
+            || openings.len() != self.num_queries
+            || openings.iter().any(|v| v.siblings.len() != self.logn - i)
+        {
+            return Err(FriError::MalformedProof);
+        }
+
+        for (((query, leaf), twiddles), eval_proof) in self
+            .queries
+            .iter_mut()
+            .zip(self.folded.iter_mut())
+            .zip(self.twiddles.iter())
+            .zip(openings.iter())
+        {
+            if !MerkleTree::verify(&self.commitments[i], self.logn - i, eval_proof, *query ^ 1) {
+                return Err(FriError::MerkleMismatch);
+            }
 
             let sibling = eval_proof.leaf;
 
-            let (mut f0, mut f1) = if query & 1 == 0 {
-                (leaf, sibling)
+            let (mut f0, mut f1) = if *query & 1 == 0 {
+                (*leaf, sibling)
             } else {
-                (sibling, leaf)
+                (sibling, *leaf)
             };
 
-            ibutterfly(
-                &mut f0,
-                &mut f1,
-                twiddle_merkle_tree_proof.elements[n_layers - 1 - i],
+            ibutterfly(&mut f0, &mut f1, twiddles[n_layers - 1 - i]);
+
+            *leaf = f0 + self.factors[i] * f1;
+
+            *query >>= 1;
+        }
+
+        self.n_queried_layers += 1;
+        Ok(())
+    }
+
+    /// Check the folded values against the last layer.
+    pub fn finalize(self) -> Result<(), FriError> {
+        if self.folded.len() != self.num_queries || self.n_queried_layers != self.logn - 1 {
+            return Err(FriError::MalformedProof);
+        }
+
+        for (&query, &leaf) in self.queries.iter().zip(self.folded.iter()) {
+            if leaf != self.last_layer[query] {
+                return Err(FriError::FoldMismatch);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::channel::Sha256Channel;
+    use crate::fri::{
+        fri_prove, fri_verify, num_fri_layers, FriError, FriProof, FriVerifier, TamperKind,
+        N_QUERIES,
+    };
+    use crate::merkle_tree::MerkleTreeProof;
+    use crate::tests_utils::fixtures::fri_proof_fixture;
+    use crate::twiddle_merkle_tree::TWIDDLE_MERKLE_TREE_ROOT_4;
+    use stwo_prover::core::channel::Channel;
+    use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+
+    #[test]
+    fn test_witness_deduplicated() {
+        let logn = 19;
+
+        let (_, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+
+        let hints = proof.hint_values();
+        let witness = proof.to_witness_deduplicated();
+
+        // the indices recover every hint, in order, from the distinct values
+        assert_eq!(witness.indices.len(), hints.len());
+        for (&index, hint) in witness.indices.iter().zip(hints.iter()) {
+            assert_eq!(witness.values[index], *hint);
+        }
+        for (i, value) in witness.values.iter().enumerate() {
+            assert!(!witness.values[..i].contains(value));
+        }
+
+        // with 5 queries, some of them must share the siblings near the roots of the smaller trees
+        assert!(witness.values.len() < hints.len());
+    }
+
+    fn verify_streaming(
+        channel_init_state: BWSSha256Hash,
+        logn: usize,
+        proof: &FriProof,
+    ) -> Result<(), FriError> {
+        let mut channel = Sha256Channel::new(channel_init_state);
+        let mut verifier =
+            FriVerifier::new(&mut channel, logn, N_QUERIES, 0, TWIDDLE_MERKLE_TREE_ROOT_4);
+
+        for c in proof.commitments.iter() {
+            verifier.absorb_layer(*c)?;
+        }
+        verifier.absorb_last_layer(&proof.last_layer, proof.pow_nonce)?;
+        assert_eq!(verifier.queries().len(), N_QUERIES);
+        verifier.open_queries(&proof.leaves, &proof.twiddle_merkle_proofs)?;
+        for i in 0..proof.commitments.len() {
+            let openings = proof
+                .merkle_proofs
+                .iter()
+                .map(|v| v[i].clone())
+                .collect::<Vec<MerkleTreeProof>>();
+            verifier.query_layer(&openings)?;
+        }
+        verifier.finalize()
+    }
+
+    #[test]
+    fn test_streaming_verifier() {
+        let logn = 5;
+
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+
+        for tamper in [
+            None,
+            Some(TamperKind::FlipSiblingBit),
+            Some(TamperKind::CorruptLayerRoot),
+            Some(TamperKind::WrongLastLayer),
+            Some(TamperKind::BadFoldValue),
+            Some(TamperKind::DropLayer),
+        ] {
+            let proof = match tamper {
+                None => proof.clone(),
+                Some(kind) => proof.tamper(kind),
+            };
+
+            let streaming_result = verify_streaming(channel_init_state, logn, &proof);
+
+            let result = fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn,
+                proof,
+                N_QUERIES,
+                0,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
             );
+            assert_eq!(streaming_result, result);
+            assert_eq!(result.is_ok(), tamper.is_none());
+        }
+    }
 
-            leaf = f0 + alpha * f1;
+    #[test]
+    fn test_layer_count() {
+        let logn = 5;
 
-            query >>= 1;
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+        assert_eq!(proof.commitments.len(), num_fri_layers(logn));
+
+        // a proof with one layer too few is rejected before any of its layers are checked
+        assert_eq!(
+            fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn,
+                proof.tamper(TamperKind::DropLayer),
+                N_QUERIES,
+                0,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Err(FriError::MalformedProof)
+        );
+
+        // so is the proof for a verifier that expects a larger evaluation
+        assert_eq!(
+            fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn + 1,
+                proof,
+                N_QUERIES,
+                0,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Err(FriError::MalformedProof)
+        );
+    }
+
+    #[test]
+    fn test_truncated_sibling_path() {
+        let logn = 5;
+
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+
+        // a path of a layer tree that is one sibling short, and one of the twiddle tree
+        let mut layer_path_proof = proof.clone();
+        layer_path_proof.merkle_proofs[0][1].siblings.pop();
+        let mut twiddle_path_proof = proof;
+        twiddle_path_proof.twiddle_merkle_proofs[N_QUERIES - 1]
+            .siblings
+            .pop();
+
+        for proof in [layer_path_proof, twiddle_path_proof] {
+            assert_eq!(
+                verify_streaming(channel_init_state, logn, &proof),
+                Err(FriError::MalformedProof)
+            );
+            assert_eq!(
+                fri_verify(
+                    &mut Sha256Channel::new(channel_init_state),
+                    logn,
+                    proof,
+                    N_QUERIES,
+                    0,
+                    TWIDDLE_MERKLE_TREE_ROOT_4,
+                ),
+                Err(FriError::MalformedProof)
+            );
         }
-        // Check against last layer
-        assert_eq!(leaf, proof.last_layer[query]);
+    }
+
+    #[test]
+    fn test_pow() {
+        let logn = 5;
+        let pow_bits = 8;
+
+        let (channel_init_state, evaluation, proof) = fri_proof_fixture(logn, N_QUERIES, pow_bits);
+
+        // a correctly ground proof passes
+        assert_eq!(
+            fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn,
+                proof.clone(),
+                N_QUERIES,
+                pow_bits,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Ok(())
+        );
+
+        // the proof is not ground enough for a verifier that requires more bits
+        assert_eq!(
+            fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn,
+                proof.clone(),
+                N_QUERIES,
+                pow_bits + 12,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Err(FriError::InsufficientPow)
+        );
+
+        // a proof that is not ground at all is rejected
+        let unground_proof = fri_prove(
+            &mut Sha256Channel::new(channel_init_state),
+            evaluation,
+            N_QUERIES,
+            0,
+        );
+        assert_eq!(
+            fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn,
+                unground_proof,
+                N_QUERIES,
+                pow_bits + 12,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Err(FriError::InsufficientPow)
+        );
+
+        // the queries are drawn after the proof of work, so the proof does not verify without it
+        assert!(fri_verify(
+            &mut Sha256Channel::new(channel_init_state),
+            logn,
+            proof,
+            N_QUERIES,
+            0,
+            TWIDDLE_MERKLE_TREE_ROOT_4,
+        )
+        .is_err());
     }
 }
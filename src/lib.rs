@@ -8,6 +8,8 @@ use stwo_prover::core::fields::m31::M31;
 use stwo_prover::core::fields::qm31::QM31;
 use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
 
+/// Module for the Blake3 hash.
+pub mod blake3;
 /// Module for absorbing and squeezing of the channel.
 pub mod channel;
 /// Module for the circle curve over the qm31 field.
@@ -22,8 +24,12 @@ pub mod fri;
 pub mod merkle_tree;
 /// Module for out-of-domain sampling.
 pub mod oods;
+/// Module for the Poseidon2 permutation and channel over m31.
+pub mod poseidon2;
 /// Module for PoW.
 pub mod pow;
+/// Module for a generic STARK prover and verifier over an AIR.
+pub mod prover;
 /// Module for test utils.
 pub mod tests_utils;
 /// Module for the twiddle Merkle tree.
@@ -65,21 +71,18 @@ impl Pushable for BWSSha256Hash {
 mod test {
     use crate::channel::Sha256Channel;
     use crate::fri;
+    use crate::fri::{FriError, TamperKind, N_QUERIES};
+    use crate::tests_utils::fixtures::{fri_proof_fixture, random_qm31};
+    use crate::tests_utils::run::run_proof;
     use crate::treepp::{
         pushable::{Builder, Pushable},
         *,
     };
     use crate::twiddle_merkle_tree::TWIDDLE_MERKLE_TREE_ROOT_4;
-    use crate::utils::permute_eval;
-    use num_traits::One;
-    use rand::{Rng, RngCore, SeedableRng};
+    use rand::{RngCore, SeedableRng};
     use rand_chacha::ChaCha20Rng;
     use stwo_prover::core::channel::Channel;
-    use stwo_prover::core::circle::CirclePointIndex;
     use stwo_prover::core::fields::m31::M31;
-    use stwo_prover::core::fields::qm31::QM31;
-    use stwo_prover::core::fields::FieldExpOps;
-    use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
 
     #[test]
     fn test_pushable() {
@@ -87,12 +90,7 @@ mod test {
 
         // m31
         let m31 = M31::reduce(prng.next_u64());
-        let qm31 = QM31::from_m31(
-            M31::reduce(prng.next_u64()),
-            M31::reduce(prng.next_u64()),
-            M31::reduce(prng.next_u64()),
-            M31::reduce(prng.next_u64()),
-        );
+        let qm31 = random_qm31(&mut prng);
 
         let mut builder = Builder::new();
         builder = m31.bitcoin_script_push(builder);
@@ -105,30 +103,130 @@ mod test {
 
     #[test]
     fn test_cfri_main() {
-        // Prepare a low degree evaluation
+        // Prove a low degree evaluation
         let logn = 5;
-        let p = CirclePointIndex::subgroup_gen(logn as u32 + 1).to_point();
-
-        let mut prng = ChaCha20Rng::seed_from_u64(0);
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
 
-        let mut channel_init_state = [0u8; 32];
-        channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
+        // the script verifier
+        assert!(run_proof(
+            channel_init_state,
+            &proof,
+            logn,
+            0,
+            TWIDDLE_MERKLE_TREE_ROOT_4
+        ));
+
+        assert_eq!(
+            fri::fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn,
+                proof,
+                N_QUERIES,
+                0,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Ok(())
+        );
+    }
 
-        let channel_init_state = BWSSha256Hash::from(channel_init_state.to_vec());
+    #[test]
+    fn test_cfri_num_queries() {
+        let logn = 5;
+        let num_queries = 12;
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, num_queries, 0);
+        assert_eq!(proof.num_queries(), num_queries);
+
+        // the script verifier
+        assert!(run_proof(
+            channel_init_state,
+            &proof,
+            logn,
+            0,
+            TWIDDLE_MERKLE_TREE_ROOT_4
+        ));
+        assert!(!run_proof(
+            channel_init_state,
+            &proof.tamper(TamperKind::BadFoldValue),
+            logn,
+            0,
+            TWIDDLE_MERKLE_TREE_ROOT_4
+        ));
+
+        assert_eq!(
+            fri::fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn,
+                proof.clone(),
+                num_queries,
+                0,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Ok(())
+        );
 
-        // Note: Add another .square() to make the proof fail.
-        let evaluation = (0..(1 << logn))
-            .map(|i| (p.mul(i * 2 + 1).x.square().square() + M31::one()).into())
-            .collect::<Vec<QM31>>();
-        let evaluation = permute_eval(evaluation);
+        // a verifier that expects another number of queries rejects the proof
+        assert_eq!(
+            fri::fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn,
+                proof,
+                N_QUERIES,
+                0,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Err(FriError::MalformedProof)
+        );
+    }
 
-        // FRI.
-        let proof = fri::fri_prove(&mut Sha256Channel::new(channel_init_state), evaluation);
-        fri::fri_verify(
-            &mut Sha256Channel::new(channel_init_state),
-            logn,
-            proof,
-            TWIDDLE_MERKLE_TREE_ROOT_4,
+    #[test]
+    fn test_cfri_tamper() {
+        let logn = 5;
+        let (channel_init_state, _, proof) = fri_proof_fixture(logn, N_QUERIES, 0);
+
+        for (kind, err) in [
+            (TamperKind::FlipSiblingBit, FriError::MerkleMismatch),
+            (TamperKind::CorruptLayerRoot, FriError::MerkleMismatch),
+            (TamperKind::WrongLastLayer, FriError::LastLayerDegree),
+            (TamperKind::BadFoldValue, FriError::FoldMismatch),
+        ] {
+            let tampered = proof.tamper(kind);
+            assert!(
+                !run_proof(
+                    channel_init_state,
+                    &tampered,
+                    logn,
+                    0,
+                    TWIDDLE_MERKLE_TREE_ROOT_4
+                ),
+                "the tampered proof ({:?}) is not rejected by the script verifier",
+                kind
+            );
+            assert_eq!(
+                fri::fri_verify(
+                    &mut Sha256Channel::new(channel_init_state),
+                    logn,
+                    tampered,
+                    N_QUERIES,
+                    0,
+                    TWIDDLE_MERKLE_TREE_ROOT_4,
+                ),
+                Err(err),
+                "the tampered proof ({:?}) is not rejected as expected",
+                kind
+            );
+        }
+
+        // a proof for a different size does not have the expected shape
+        assert_eq!(
+            fri::fri_verify(
+                &mut Sha256Channel::new(channel_init_state),
+                logn + 1,
+                proof,
+                N_QUERIES,
+                0,
+                TWIDDLE_MERKLE_TREE_ROOT_4,
+            ),
+            Err(FriError::MalformedProof)
         );
     }
 }
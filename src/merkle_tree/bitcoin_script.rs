@@ -1,6 +1,12 @@
-use crate::merkle_tree::MerkleTreeProof;
+use crate::merkle_tree::{
+    multiproof_steps, ColumnarMerkleTreeProof, MerkleMultiProof, MerkleTreeProof,
+};
 use crate::treepp::*;
-use crate::utils::{hash_felt_gadget, limb_to_be_bits_toaltstack};
+use crate::utils::{
+    assert_index_in_range_gadget, bit_reverse_index_gadget, hash_felt_gadget,
+    limb_to_be_bits_toaltstack, pull_hint_gadget, stwo_leaf_hash_gadget,
+};
+use rust_bitcoin_m31::{qm31_dup, qm31_fromaltstack, qm31_toaltstack};
 
 /// Gadget for verifying a regular binary Merkle tree.
 pub struct MerkleTreeGadget;
@@ -17,11 +23,21 @@ impl MerkleTreeGadget {
     }
 
     pub(crate) fn query_and_verify_internal(logn: usize, is_sibling: bool) -> Script {
+        Self::query_and_verify_internal_with_hints(logn, is_sibling, &mut pull_hint_gadget)
+    }
+
+    /// As `query_and_verify_internal`, but each hint is obtained with `pull_hint`, so that the
+    /// hints can also be picked from a deduplicated witness, see `FriProof::to_witness_deduplicated`.
+    pub(crate) fn query_and_verify_internal_with_hints(
+        logn: usize,
+        is_sibling: bool,
+        pull_hint: &mut dyn FnMut() -> Script,
+    ) -> Script {
         script! {
-            OP_DEPTH OP_1SUB OP_ROLL
-            OP_DEPTH OP_1SUB OP_ROLL
-            OP_DEPTH OP_1SUB OP_ROLL
-            OP_DEPTH OP_1SUB OP_ROLL
+            { pull_hint() }
+            { pull_hint() }
+            { pull_hint() }
+            { pull_hint() }
 
             // copy-paste the 4 elements
             //     ABCD -> CDAB -> CDABAB -> ABABCD-> ABABCDCD
@@ -37,18 +53,18 @@ impl MerkleTreeGadget {
             hash_felt_gadget
 
             if is_sibling {
-                OP_DEPTH OP_1SUB OP_ROLL
+                { pull_hint() }
                 OP_FROMALTSTACK OP_NOTIF OP_SWAP OP_ENDIF
                 OP_CAT OP_SHA256
 
                 for _ in 1..logn {
-                    OP_DEPTH OP_1SUB OP_ROLL
+                    { pull_hint() }
                     OP_FROMALTSTACK OP_IF OP_SWAP OP_ENDIF
                     OP_CAT OP_SHA256
                 }
             } else {
                 for _ in 0..logn {
-                    OP_DEPTH OP_1SUB OP_ROLL
+                    { pull_hint() }
                     OP_FROMALTSTACK OP_IF OP_SWAP OP_ENDIF
                     OP_CAT OP_SHA256
                 }
@@ -80,22 +96,285 @@ impl MerkleTreeGadget {
             { Self::query_and_verify_internal(logn, true) }
         }
     }
+
+    /// Push the Merkle tree proof of a Merkle tree over multiple columns into the stack (and used
+    /// as a hint).
+    pub fn push_columnar_merkle_tree_proof(merkle_proof: &ColumnarMerkleTreeProof) -> Script {
+        script! {
+            for elem in merkle_proof.leaf.iter() {
+                { *elem }
+            }
+            for salt in merkle_proof.salt.iter() {
+                { salt.to_vec() }
+            }
+            for elem in merkle_proof.siblings.iter() {
+                { elem.to_vec() }
+            }
+        }
+    }
+}
+
+/// Query and verify a leaf of a Merkle tree over `width` m31 columns using the Merkle path as a
+/// hint, where the leaf is hashed in the column order as stwo does, see `stwo_leaf_hash`.
+///
+/// input:
+///   root_hash
+///   pos
+///
+/// output:
+///   v_0, ..., v_{width - 1} (m31 -- `width` elements)
+pub fn verify_columnar_leaf_gadget(width: usize, logn: usize) -> Script {
+    verify_columnar_leaf_internal(width, logn, false)
+}
+
+/// Query and verify a leaf of a Merkle tree over `width` m31 columns that is blinded with a salt,
+/// as in `ColumnarMerkleTree::new_blinded`, using the salt and the Merkle path as a hint, where
+/// the salt is appended to the hash of the leaf before it is hashed again.
+///
+/// input:
+///   root_hash
+///   pos
+///
+/// output:
+///   v_0, ..., v_{width - 1} (m31 -- `width` elements)
+pub fn verify_columnar_leaf_blinded_gadget(width: usize, logn: usize) -> Script {
+    verify_columnar_leaf_internal(width, logn, true)
+}
+
+fn verify_columnar_leaf_internal(width: usize, logn: usize, is_blinded: bool) -> Script {
+    assert!(width > 0);
+
+    script! {
+        { limb_to_be_bits_toaltstack(logn as u32) }
+
+        for _ in 0..width {
+            OP_DEPTH OP_1SUB OP_ROLL
+        }
+        for _ in 0..width {
+            { width - 1 } OP_PICK
+        }
+        { stwo_leaf_hash_gadget(width) }
+
+        if is_blinded {
+            OP_DEPTH OP_1SUB OP_ROLL
+            OP_CAT OP_SHA256
+        }
+
+        for _ in 0..logn {
+            OP_DEPTH OP_1SUB OP_ROLL
+            OP_FROMALTSTACK OP_IF OP_SWAP OP_ENDIF
+            OP_CAT OP_SHA256
+        }
+
+        { width + 1 } OP_ROLL
+        OP_EQUALVERIFY
+    }
+}
+
+/// Query and verify a value committed by `commit_packed` with `pack` values per leaf, using the
+/// Merkle path of the leaf as a hint, where the leaf is at `pos / pack` and the value is selected
+/// by `pos % pack`.
+///
+/// input:
+///   root_hash
+///   pos
+///
+/// output:
+///   v (m31)
+pub fn verify_packed_leaf_gadget(pack: usize, logn: usize) -> Script {
+    assert!(pack.is_power_of_two() && pack > 1);
+    let log_pack = pack.ilog2() as usize;
+
+    script! {
+        { limb_to_be_bits_toaltstack((logn + log_pack) as u32) }
+
+        for _ in 0..pack {
+            OP_DEPTH OP_1SUB OP_ROLL
+        }
+        for _ in 0..pack {
+            { pack - 1 } OP_PICK
+        }
+        { stwo_leaf_hash_gadget(pack) }
+
+        // compute the remainder from the lowest bits and pick the value
+        OP_0
+        for i in 0..log_pack {
+            OP_FROMALTSTACK OP_IF { 1 << i } OP_ADD OP_ENDIF
+        }
+        { pack } OP_SWAP OP_SUB OP_PICK
+        OP_SWAP
+
+        for _ in 0..logn {
+            OP_DEPTH OP_1SUB OP_ROLL
+            OP_FROMALTSTACK OP_IF OP_SWAP OP_ENDIF
+            OP_CAT OP_SHA256
+        }
+
+        { pack + 2 } OP_ROLL
+        OP_EQUALVERIFY
+
+        OP_TOALTSTACK
+        for _ in 0..(pack / 2) {
+            OP_2DROP
+        }
+        OP_FROMALTSTACK
+    }
+}
+
+/// Query and verify a leaf of a Merkle tree over an evaluation permuted by `permute_eval`, using
+/// the Merkle path as a hint, where the caller gives the index i in the natural order, and the
+/// leaf is at `bit_reverse_index(i, logn)`.
+///
+/// The natural order is the one that `permute_eval` bit-reverses: index i holds evaluation[2i]
+/// for i < 2^(logn - 1), and evaluation[2^logn - 1 - 2(i - 2^(logn - 1))] otherwise.
+///
+/// input:
+///   root_hash
+///   i
+///
+/// output:
+///   v (qm31 -- 4 elements)
+pub fn verify_path_bitreversed_gadget(logn: usize) -> Script {
+    script! {
+        { assert_index_in_range_gadget(logn) }
+        { bit_reverse_index_gadget(logn) }
+        { MerkleTreeGadget::query_and_verify(logn) }
+    }
+}
+
+/// Query and verify the leaves of a Merkle tree at several sorted positions, using a multiproof
+/// as a hint, in which the sibling hashes that the leaves share are only provided once.
+///
+/// hint:
+///   leaves, in the order of the positions
+///   frontier, see `MerkleMultiProof`
+///
+/// input:
+///   root_hash
+///
+/// output:
+///   v_{n - 1}, ..., v_0 (qm31 -- one for each position, the first on the top)
+pub fn verify_multiproof_gadget(sorted_indices: &[usize], depth: usize) -> Script {
+    let steps = multiproof_steps(sorted_indices, depth);
+
+    script! {
+        // pull the leaves, keep a copy in the altstack, and hash them
+        for _ in 0..sorted_indices.len() {
+            OP_DEPTH OP_1SUB OP_ROLL
+            OP_DEPTH OP_1SUB OP_ROLL
+            OP_DEPTH OP_1SUB OP_ROLL
+            OP_DEPTH OP_1SUB OP_ROLL
+            qm31_dup qm31_toaltstack
+            hash_felt_gadget
+        }
+
+        // the known nodes of a layer are on the stack, the largest position on the top
+        for level_steps in steps.iter() {
+            for &(idx, paired) in level_steps.iter() {
+                if !paired {
+                    OP_DEPTH OP_1SUB OP_ROLL
+                    if idx & 1 == 1 {
+                        OP_SWAP
+                    }
+                }
+                OP_CAT OP_SHA256
+                OP_TOALTSTACK
+            }
+            for _ in 0..level_steps.len() {
+                OP_FROMALTSTACK
+            }
+        }
+
+        OP_EQUALVERIFY
+
+        for _ in 0..sorted_indices.len() {
+            qm31_fromaltstack
+        }
+    }
+}
+
+/// Query and verify a leaf of a Merkle tree that may be committed under any of a set of allowed
+/// roots, using the Merkle path as a hint, by reconstructing the root and checking that it equals
+/// one of them.
+///
+/// input:
+///   root_0, ..., root_{num_roots - 1}
+///   pos
+///
+/// output:
+///   v (qm31 -- 4 elements)
+pub fn verify_path_against_root_set_gadget(depth: usize, num_roots: usize) -> Script {
+    assert!(num_roots > 0);
+
+    script! {
+        // pull the leaf and keep a copy in the altstack, below the bits of pos
+        OP_DEPTH OP_1SUB OP_ROLL
+        OP_DEPTH OP_1SUB OP_ROLL
+        OP_DEPTH OP_1SUB OP_ROLL
+        OP_DEPTH OP_1SUB OP_ROLL
+        qm31_dup qm31_toaltstack
+
+        4 OP_ROLL
+        { limb_to_be_bits_toaltstack(depth as u32) }
+
+        hash_felt_gadget
+        for _ in 0..depth {
+            OP_DEPTH OP_1SUB OP_ROLL
+            OP_FROMALTSTACK OP_IF OP_SWAP OP_ENDIF
+            OP_CAT OP_SHA256
+        }
+
+        if num_roots == 1 {
+            OP_EQUALVERIFY
+        } else {
+            // stack: root_0, ..., root_{num_roots - 1}, root
+            OP_SWAP OP_OVER OP_EQUAL
+            for _ in 1..num_roots {
+                OP_ROT 2 OP_PICK OP_EQUAL OP_BOOLOR
+            }
+            OP_VERIFY
+            OP_DROP
+        }
+
+        qm31_fromaltstack
+    }
+}
+
+/// Push the Merkle multiproof into the stack (and used as a hint).
+pub fn push_merkle_multiproof(merkle_proof: &MerkleMultiProof) -> Script {
+    script! {
+        for leaf in merkle_proof.leaves.iter() {
+            { *leaf }
+        }
+        for elem in merkle_proof.frontier.iter() {
+            { elem.to_vec() }
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
 
+    use crate::tests_utils::fixtures::random_qm31;
     use crate::treepp::*;
     use crate::{
-        merkle_tree::{MerkleTree, MerkleTreeGadget},
+        merkle_tree::{
+            commit_packed, push_merkle_multiproof, verify_columnar_leaf_blinded_gadget,
+            verify_columnar_leaf_gadget, verify_multiproof_gadget, verify_packed_leaf_gadget,
+            verify_path_against_root_set_gadget, verify_path_bitreversed_gadget,
+            ColumnarMerkleTree, MerkleTree, MerkleTreeGadget,
+        },
         tests_utils::report::report_bitcoin_script_size,
+        utils::{bit_reverse_index, permute_eval, stwo_leaf_hash},
     };
+    use num_traits::One;
     use rand::{Rng, RngCore, SeedableRng};
     use rand_chacha::ChaCha20Rng;
     use rust_bitcoin_m31::qm31_equalverify;
-    use stwo_prover::core::fields::cm31::CM31;
+    use sha2::{Digest, Sha256};
     use stwo_prover::core::fields::m31::M31;
     use stwo_prover::core::fields::qm31::QM31;
+    use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
 
     #[test]
     fn test_merkle_tree_verify() {
@@ -112,10 +391,7 @@ mod test {
 
             let mut last_layer = vec![];
             for _ in 0..(1 << logn) {
-                last_layer.push(QM31(
-                    CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64())),
-                    CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64())),
-                ));
+                last_layer.push(random_qm31(&mut prng));
             }
 
             let merkle_tree = MerkleTree::new(last_layer.clone());
@@ -149,10 +425,7 @@ mod test {
 
             let mut last_layer = vec![];
             for _ in 0..(1 << logn) {
-                last_layer.push(QM31(
-                    CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64())),
-                    CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64())),
-                ));
+                last_layer.push(random_qm31(&mut prng));
             }
 
             let merkle_tree = MerkleTree::new(last_layer.clone());
@@ -176,4 +449,375 @@ mod test {
             assert!(exec_result.success);
         }
     }
+
+    #[test]
+    fn test_verify_columnar_leaf() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 10;
+
+        for width in [1, 4] {
+            let verify_script = verify_columnar_leaf_gadget(width, logn);
+
+            report_bitcoin_script_size(
+                "MerkleTree",
+                format!("verify_columnar_leaf({}, 2^{})", width, logn).as_str(),
+                verify_script.len(),
+            );
+
+            let columns = (0..width)
+                .map(|_| {
+                    (0..(1 << logn))
+                        .map(|_| M31::reduce(prng.next_u64()))
+                        .collect::<Vec<M31>>()
+                })
+                .collect::<Vec<Vec<M31>>>();
+
+            let merkle_tree = ColumnarMerkleTree::new(columns.clone());
+
+            // the leaves are the stwo leaf hashes of the rows, and a node hashes its children
+            let mut layer = (0..(1 << logn))
+                .map(|i| {
+                    stwo_leaf_hash(&columns.iter().map(|column| column[i]).collect::<Vec<M31>>())
+                })
+                .collect::<Vec<BWSSha256Hash>>();
+            while layer.len() > 1 {
+                layer = layer
+                    .chunks_exact(2)
+                    .map(|v| {
+                        let mut hasher = Sha256::new();
+                        Digest::update(&mut hasher, v[0].as_ref());
+                        Digest::update(&mut hasher, v[1].as_ref());
+                        BWSSha256Hash::from(hasher.finalize().to_vec())
+                    })
+                    .collect();
+            }
+            assert_eq!(merkle_tree.root_hash, layer[0]);
+
+            let mut pos: u32 = prng.gen();
+            pos &= (1 << logn) - 1;
+
+            let proof = merkle_tree.query(pos as usize);
+
+            let script = script! {
+                { MerkleTreeGadget::push_columnar_merkle_tree_proof(&proof) }
+                { merkle_tree.root_hash }
+                { pos }
+                { verify_script.clone() }
+                for i in (0..width).rev() {
+                    { columns[i][pos as usize] }
+                    OP_EQUALVERIFY
+                }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+
+            let mut bad_proof = proof.clone();
+            bad_proof.leaf[0] += M31::from(1);
+
+            let script = script! {
+                { MerkleTreeGadget::push_columnar_merkle_tree_proof(&bad_proof) }
+                { merkle_tree.root_hash }
+                { pos }
+                { verify_script.clone() }
+                for _ in 0..width {
+                    OP_DROP
+                }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_verify_packed_leaf() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 8;
+        let pack = 4;
+
+        let verify_script = verify_packed_leaf_gadget(pack, logn);
+        report_bitcoin_script_size(
+            "MerkleTree",
+            format!("verify_packed_leaf({}, 2^{})", pack, logn).as_str(),
+            verify_script.len(),
+        );
+
+        let values = (0..(pack << logn))
+            .map(|_| M31::reduce(prng.next_u64()))
+            .collect::<Vec<M31>>();
+        let merkle_tree = commit_packed(&values, pack);
+
+        for _ in 0..16 {
+            let mut pos: u32 = prng.gen();
+            pos &= (pack << logn) as u32 - 1;
+
+            let proof = merkle_tree.query(pos as usize / pack);
+            assert_eq!(proof.leaf[pos as usize % pack], values[pos as usize]);
+
+            // a leaf with another element of the leaf changed does not lead to the root
+            let mut tampered = proof.clone();
+            let other = (pos as usize + 1) % pack;
+            tampered.leaf[other] += M31::one();
+
+            for (proof, expected) in [(&proof, true), (&tampered, false)] {
+                let script = script! {
+                    { MerkleTreeGadget::push_columnar_merkle_tree_proof(proof) }
+                    { merkle_tree.root_hash }
+                    { pos }
+                    { verify_script.clone() }
+                    { values[pos as usize] }
+                    OP_EQUAL
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, expected);
+            }
+
+            // the value at the same leaf but another remainder is not the one selected
+            let script = script! {
+                { MerkleTreeGadget::push_columnar_merkle_tree_proof(&proof) }
+                { merkle_tree.root_hash }
+                { pos }
+                { verify_script.clone() }
+                { values[pos as usize / pack * pack + other] }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_verify_path_bitreversed() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+        let logn = 8;
+        let n = 1 << logn;
+
+        let verify_script = verify_path_bitreversed_gadget(logn);
+        report_bitcoin_script_size(
+            "MerkleTree",
+            format!("verify_path_bitreversed(2^{})", logn).as_str(),
+            verify_script.len(),
+        );
+
+        let evaluation = (0..n)
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<QM31>>();
+        let merkle_tree = MerkleTree::new(permute_eval(evaluation.clone()));
+
+        for i in [
+            0,
+            1,
+            n / 2 - 1,
+            n / 2,
+            n / 2 + 1,
+            n - 1,
+            prng.gen_range(0..n),
+        ] {
+            let expected = if i < n / 2 {
+                evaluation[2 * i]
+            } else {
+                evaluation[n - 1 - 2 * (i - n / 2)]
+            };
+
+            let script = script! {
+                { MerkleTreeGadget::push_merkle_tree_proof(&merkle_tree.query(bit_reverse_index(i, logn))) }
+                { merkle_tree.root_hash }
+                { i }
+                { verify_script.clone() }
+                { expected }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        // the path of the leaf at i itself, instead of at its bit reversal, does not verify
+        let i = 1;
+        let script = script! {
+            { MerkleTreeGadget::push_merkle_tree_proof(&merkle_tree.query(i)) }
+            { merkle_tree.root_hash }
+            { i }
+            { verify_script.clone() }
+            OP_2DROP OP_2DROP
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_merkle_multiproof() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let depth = 5;
+
+        let mut last_layer = vec![];
+        for _ in 0..(1 << depth) {
+            last_layer.push(random_qm31(&mut prng));
+        }
+
+        let merkle_tree = MerkleTree::new(last_layer.clone());
+
+        for sorted_indices in [vec![3, 4, 17], vec![0, 1, 9, 20, 31]] {
+            let proof = merkle_tree.query_multiproof(&sorted_indices);
+
+            let naive = sorted_indices.len() * depth;
+            println!(
+                "MerkleTree.multiproof({} positions) = {} hashes, instead of {} hashes",
+                sorted_indices.len(),
+                proof.frontier.len(),
+                naive
+            );
+            assert!(proof.frontier.len() < naive);
+
+            let verify_script = verify_multiproof_gadget(&sorted_indices, depth);
+            report_bitcoin_script_size(
+                "MerkleTree",
+                format!(
+                    "verify_multiproof({} positions, 2^{})",
+                    sorted_indices.len(),
+                    depth
+                )
+                .as_str(),
+                verify_script.len(),
+            );
+
+            let script = script! {
+                { push_merkle_multiproof(&proof) }
+                { merkle_tree.root_hash }
+                { verify_script.clone() }
+                for &i in sorted_indices.iter() {
+                    { last_layer[i] }
+                    qm31_equalverify
+                }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+
+            // a wrong frontier hash is rejected
+            let mut bad_proof = proof.clone();
+            bad_proof.frontier[0][0] ^= 1;
+
+            let script = script! {
+                { push_merkle_multiproof(&bad_proof) }
+                { merkle_tree.root_hash }
+                { verify_script.clone() }
+                for _ in 0..sorted_indices.len() {
+                    OP_2DROP OP_2DROP
+                }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_verify_path_against_root_set() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 10;
+
+        // three versions of the trace, each committed under its own root
+        let trees = (0..3)
+            .map(|_| MerkleTree::new((0..(1 << logn)).map(|_| random_qm31(&mut prng)).collect()))
+            .collect::<Vec<_>>();
+        let roots = trees.iter().map(|t| t.root_hash).collect::<Vec<_>>();
+
+        let mut pos: u32 = prng.gen();
+        pos &= (1 << logn) - 1;
+
+        for num_roots in [1, 3] {
+            let verify_script = verify_path_against_root_set_gadget(logn, num_roots);
+            report_bitcoin_script_size(
+                "MerkleTree",
+                format!("verify_path_against_root_set(2^{}, {})", logn, num_roots).as_str(),
+                verify_script.len(),
+            );
+
+            for (tree_idx, tree) in trees.iter().enumerate() {
+                let proof = tree.query(pos as usize);
+                let allowed = &roots[..num_roots];
+
+                let script = script! {
+                    { MerkleTreeGadget::push_merkle_tree_proof(&proof) }
+                    for root in allowed.iter() {
+                        { *root }
+                    }
+                    { pos }
+                    { verify_script.clone() }
+                    { proof.leaf }
+                    qm31_equalverify
+                    OP_TRUE
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, tree_idx < num_roots);
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_columnar_leaf_blinded() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 10;
+        let width = 4;
+
+        let verify_script = verify_columnar_leaf_blinded_gadget(width, logn);
+        report_bitcoin_script_size(
+            "MerkleTree",
+            format!("verify_columnar_leaf_blinded({}, 2^{})", width, logn).as_str(),
+            verify_script.len(),
+        );
+
+        let columns = (0..width)
+            .map(|_| {
+                (0..(1 << logn))
+                    .map(|_| M31::reduce(prng.next_u64()))
+                    .collect::<Vec<M31>>()
+            })
+            .collect::<Vec<Vec<M31>>>();
+        let salts = (0..(1 << logn))
+            .map(|_| {
+                let mut salt = [0u8; 32];
+                prng.fill_bytes(&mut salt);
+                salt
+            })
+            .collect::<Vec<[u8; 32]>>();
+
+        let merkle_tree = ColumnarMerkleTree::new_blinded(columns.clone(), salts);
+        assert_ne!(
+            merkle_tree.root_hash,
+            ColumnarMerkleTree::new(columns).root_hash
+        );
+
+        let mut pos: u32 = prng.gen();
+        pos &= (1 << logn) - 1;
+
+        let proof = merkle_tree.query(pos as usize);
+
+        let mut wrong_salt = proof.clone();
+        wrong_salt.salt.as_mut().unwrap()[0] ^= 1;
+
+        for (proof, expected) in [(&proof, true), (&wrong_salt, false)] {
+            let script = script! {
+                { MerkleTreeGadget::push_columnar_merkle_tree_proof(proof) }
+                { merkle_tree.root_hash }
+                { pos }
+                { verify_script.clone() }
+                for elem in proof.leaf.iter().rev() {
+                    { *elem }
+                    OP_EQUALVERIFY
+                }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
 }
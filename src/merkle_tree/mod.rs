@@ -1,9 +1,10 @@
 use sha2::{Digest, Sha256};
+use stwo_prover::core::fields::m31::M31;
 use stwo_prover::core::fields::qm31::QM31;
 use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
 
 mod bitcoin_script;
-use crate::utils::hash_qm31;
+use crate::utils::{hash_qm31, stwo_leaf_hash};
 pub use bitcoin_script::*;
 
 /// A Merkle tree.
@@ -84,6 +85,32 @@ impl MerkleTree {
         merkle_tree_proof
     }
 
+    /// Query the Merkle tree at several sorted positions and generate a proof that includes each
+    /// sibling hash that the verifier cannot compute by itself only once.
+    pub fn query_multiproof(&self, sorted_indices: &[usize]) -> MerkleMultiProof {
+        let depth = self.intermediate_layers.len();
+
+        let leaves = sorted_indices
+            .iter()
+            .map(|&i| self.leaf_layer[i])
+            .collect::<Vec<QM31>>();
+
+        let mut frontier = vec![];
+        for (level, steps) in multiproof_steps(sorted_indices, depth).iter().enumerate() {
+            for &(idx, paired) in steps.iter() {
+                if !paired {
+                    frontier.push(if level == 0 {
+                        hash_qm31(&self.leaf_layer[idx ^ 1])
+                    } else {
+                        self.intermediate_layers[level - 1][idx ^ 1]
+                    });
+                }
+            }
+        }
+
+        MerkleMultiProof { leaves, frontier }
+    }
+
     /// Verify a Merkle tree proof.
     pub fn verify(
         root_hash: &BWSSha256Hash,
@@ -123,14 +150,223 @@ pub struct MerkleTreeProof {
     pub siblings: Vec<[u8; 32]>,
 }
 
+/// A Merkle tree proof for several sorted positions.
+#[derive(Default, Clone, Debug)]
+pub struct MerkleMultiProof {
+    /// Leaves as qm31 elements, in the order of the positions.
+    pub leaves: Vec<QM31>,
+    /// The sibling nodes that cannot be computed from the leaves, layer by layer from the leaves,
+    /// and from the largest position to the smallest one within a layer.
+    pub frontier: Vec<[u8; 32]>,
+}
+
+/// Compute how the nodes of a multiproof are combined, layer by layer from the leaves.
+///
+/// For each layer, the known nodes are visited from the largest position to the smallest one,
+/// as `(idx, paired)` where `paired` is true if the node at `idx` is combined with its known
+/// sibling at `idx - 1`, which is then skipped, and false if the sibling is taken from the
+/// frontier.
+pub(crate) fn multiproof_steps(sorted_indices: &[usize], depth: usize) -> Vec<Vec<(usize, bool)>> {
+    assert!(!sorted_indices.is_empty());
+    assert!(sorted_indices.windows(2).all(|w| w[0] < w[1]));
+    assert!(sorted_indices[sorted_indices.len() - 1] < 1 << depth);
+
+    let mut known = sorted_indices.to_vec();
+    let mut steps = Vec::with_capacity(depth);
+
+    for _ in 0..depth {
+        let mut level_steps = vec![];
+        let mut parents = vec![];
+
+        let mut i = known.len();
+        while i > 0 {
+            let idx = known[i - 1];
+            let paired = idx & 1 == 1 && i > 1 && known[i - 2] == idx - 1;
+
+            level_steps.push((idx, paired));
+            parents.push(idx >> 1);
+
+            i -= if paired { 2 } else { 1 };
+        }
+
+        parents.reverse();
+        known = parents;
+        steps.push(level_steps);
+    }
+
+    steps
+}
+
+/// Commit to a sequence of m31 values by packing `pack` consecutive values into one leaf, so that
+/// the value at index `i` is the `i % pack`-th element of the leaf at `i / pack`.
+pub fn commit_packed(values: &[M31], pack: usize) -> ColumnarMerkleTree {
+    assert!(pack.is_power_of_two() && pack > 1);
+    assert_eq!(values.len() % pack, 0);
+
+    ColumnarMerkleTree::new(
+        (0..pack)
+            .map(|j| values.iter().skip(j).step_by(pack).copied().collect())
+            .collect(),
+    )
+}
+
+/// A Merkle tree over multiple m31 columns, where each leaf consists of the values of all the
+/// columns at the same row, in the column order, laid out as stwo commits the columns of the same
+/// size: the leaf hash is `stwo_leaf_hash` of the row, and a node hashes its two children.
+pub struct ColumnarMerkleTree {
+    /// The columns.
+    pub columns: Vec<Vec<M31>>,
+    /// The salts of the leaves, empty if the leaves are not blinded.
+    pub salts: Vec<[u8; 32]>,
+    /// Layers of the tree, starting from the hashes of the leaves, excluding the root.
+    pub layers: Vec<Vec<[u8; 32]>>,
+    /// Root hash.
+    pub root_hash: BWSSha256Hash,
+}
+
+impl ColumnarMerkleTree {
+    /// Create a new Merkle tree over the columns.
+    pub fn new(columns: Vec<Vec<M31>>) -> Self {
+        Self::new_internal(columns, vec![])
+    }
+
+    /// Create a new Merkle tree over the columns, where each leaf is blinded with its own salt,
+    /// i.e., the leaf hash is sha256(stwo_leaf_hash(row) || salt), and the salt is revealed in the
+    /// proof of the leaf.
+    pub fn new_blinded(columns: Vec<Vec<M31>>, salts: Vec<[u8; 32]>) -> Self {
+        assert_eq!(salts.len(), columns[0].len());
+        Self::new_internal(columns, salts)
+    }
+
+    fn new_internal(columns: Vec<Vec<M31>>, salts: Vec<[u8; 32]>) -> Self {
+        assert!(!columns.is_empty());
+
+        let n = columns[0].len();
+        assert!(n.is_power_of_two() && n > 1);
+        assert!(columns.iter().all(|column| column.len() == n));
+
+        let mut layers = vec![];
+        let mut cur = (0..n)
+            .map(|i| {
+                let mut leaf_hash = [0u8; 32];
+                leaf_hash.copy_from_slice(
+                    stwo_leaf_hash(&columns.iter().map(|column| column[i]).collect::<Vec<M31>>())
+                        .as_ref(),
+                );
+                if salts.is_empty() {
+                    leaf_hash
+                } else {
+                    blind_leaf_hash(&leaf_hash, &salts[i])
+                }
+            })
+            .collect::<Vec<[u8; 32]>>();
+
+        while cur.len() > 1 {
+            layers.push(cur.clone());
+            cur = cur
+                .chunks_exact(2)
+                .map(|v| {
+                    let mut hash_result = [0u8; 32];
+                    let mut hasher = Sha256::new();
+                    Digest::update(&mut hasher, v[0]);
+                    Digest::update(&mut hasher, v[1]);
+                    hash_result.copy_from_slice(hasher.finalize().as_slice());
+                    hash_result
+                })
+                .collect::<Vec<[u8; 32]>>();
+        }
+
+        Self {
+            columns,
+            salts,
+            layers,
+            root_hash: BWSSha256Hash::from(cur[0].to_vec()),
+        }
+    }
+
+    /// Query the Merkle tree and generate a corresponding proof.
+    pub fn query(&self, mut pos: usize) -> ColumnarMerkleTreeProof {
+        let mut merkle_tree_proof = ColumnarMerkleTreeProof {
+            leaf: self.columns.iter().map(|column| column[pos]).collect(),
+            salt: self.salts.get(pos).copied(),
+            ..Default::default()
+        };
+
+        for layer in self.layers.iter() {
+            merkle_tree_proof.siblings.push(layer[pos ^ 1]);
+            pos >>= 1;
+        }
+
+        merkle_tree_proof
+    }
+
+    /// Verify a Merkle tree proof of the row at the query.
+    pub fn verify(
+        root_hash: &BWSSha256Hash,
+        logn: usize,
+        proof: &ColumnarMerkleTreeProof,
+        mut query: usize,
+    ) -> bool {
+        assert_eq!(proof.siblings.len(), logn);
+
+        let mut leaf_hash = [0u8; 32];
+        leaf_hash.copy_from_slice(stwo_leaf_hash(&proof.leaf).as_ref());
+        if let Some(salt) = proof.salt {
+            leaf_hash = blind_leaf_hash(&leaf_hash, &salt);
+        }
+
+        for i in 0..logn {
+            let (f0, f1) = if query & 1 == 0 {
+                (leaf_hash, proof.siblings[i])
+            } else {
+                (proof.siblings[i], leaf_hash)
+            };
+
+            let mut hasher = Sha256::new();
+            Digest::update(&mut hasher, f0);
+            Digest::update(&mut hasher, f1);
+            leaf_hash.copy_from_slice(hasher.finalize().as_slice());
+
+            query >>= 1;
+        }
+
+        leaf_hash == root_hash.as_ref()
+    }
+}
+
+/// Blind the hash of a leaf with its salt, i.e., sha256(leaf_hash || salt).
+pub fn blind_leaf_hash(leaf_hash: &[u8; 32], salt: &[u8; 32]) -> [u8; 32] {
+    let mut hash_result = [0u8; 32];
+    let mut hasher = Sha256::new();
+    Digest::update(&mut hasher, leaf_hash);
+    Digest::update(&mut hasher, salt);
+    hash_result.copy_from_slice(hasher.finalize().as_slice());
+    hash_result
+}
+
+/// A Merkle tree proof for a Merkle tree over multiple m31 columns.
+#[derive(Default, Clone, Debug)]
+pub struct ColumnarMerkleTreeProof {
+    /// Leaf as the m31 values of all the columns.
+    pub leaf: Vec<M31>,
+    /// The salt of the leaf, if the tree is blinded.
+    pub salt: Option<[u8; 32]>,
+    /// All the intermediate sibling nodes.
+    pub siblings: Vec<[u8; 32]>,
+}
+
 #[cfg(test)]
 mod test {
-    use crate::merkle_tree::MerkleTree;
+    use crate::fibonacci::{fibonacci_channel_init_state, fibonacci_claim};
+    use crate::merkle_tree::{ColumnarMerkleTree, MerkleTree};
+    use crate::tests_utils::fixtures::random_qm31;
     use rand::{Rng, RngCore, SeedableRng};
     use rand_chacha::ChaCha20Rng;
-    use stwo_prover::core::fields::cm31::CM31;
+    use stwo_prover::core::channel::{BWSSha256Channel, Channel};
     use stwo_prover::core::fields::m31::M31;
-    use stwo_prover::core::fields::qm31::QM31;
+    use stwo_prover::core::poly::circle::CanonicCoset;
+    use stwo_prover::core::prover::prove;
+    use stwo_prover::examples::fibonacci::Fibonacci;
 
     #[test]
     fn test_merkle_tree() {
@@ -138,10 +374,7 @@ mod test {
 
         let mut last_layer = vec![];
         for _ in 0..1 << 12 {
-            last_layer.push(QM31(
-                CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64())),
-                CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64())),
-            ));
+            last_layer.push(random_qm31(&mut prng));
         }
 
         let merkle_tree = MerkleTree::new(last_layer.clone());
@@ -158,4 +391,63 @@ mod test {
             ));
         }
     }
+
+    #[test]
+    fn test_columnar_merkle_tree() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let columns = (0..3)
+            .map(|_| {
+                (0..1 << 10)
+                    .map(|_| M31::reduce(prng.next_u64()))
+                    .collect::<Vec<M31>>()
+            })
+            .collect::<Vec<Vec<M31>>>();
+
+        let merkle_tree = ColumnarMerkleTree::new(columns);
+
+        for _ in 0..10 {
+            let query = (prng.gen::<u32>() % (1 << 10)) as usize;
+
+            let mut proof = merkle_tree.query(query);
+            assert!(ColumnarMerkleTree::verify(
+                &merkle_tree.root_hash,
+                10,
+                &proof,
+                query
+            ));
+
+            proof.leaf[1] += M31::from_u32_unchecked(1);
+            assert!(!ColumnarMerkleTree::verify(
+                &merkle_tree.root_hash,
+                10,
+                &proof,
+                query
+            ));
+        }
+    }
+
+    #[test]
+    fn test_columnar_merkle_tree_stwo_root() {
+        let log_size = 5;
+        let claim = fibonacci_claim(log_size);
+        let fib = Fibonacci::new(log_size, claim);
+
+        let proof = prove(
+            &fib.air,
+            &mut BWSSha256Channel::new(fibonacci_channel_init_state(claim)),
+            vec![fib.get_trace()],
+        )
+        .unwrap();
+
+        // stwo commits the trace column evaluated on the domain with a blowup factor of 2, in the
+        // bit-reversed order
+        let evaluation = fib
+            .get_trace()
+            .interpolate()
+            .evaluate(CanonicCoset::new(log_size + 1).circle_domain());
+
+        let merkle_tree = ColumnarMerkleTree::new(vec![evaluation.values.to_vec()]);
+        assert_eq!(merkle_tree.root_hash, proof.commitments[0]);
+    }
 }
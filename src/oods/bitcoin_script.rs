@@ -1,10 +1,15 @@
 use crate::channel::Sha256ChannelGadget;
+use crate::circle::coset_shift_gadget;
+use crate::constraints::{verify_trace_value_at_oods_gadget, ConstraintsGadget};
 use crate::treepp::*;
+use crate::utils::alpha_powers_batch_gadget;
 use rust_bitcoin_m31::{
-    m31_add_n31, m31_sub, push_m31_one, push_n31_one, qm31_double, qm31_dup, qm31_equalverify,
-    qm31_from_bottom, qm31_mul, qm31_neg, qm31_roll, qm31_rot, qm31_square, qm31_swap,
+    m31_add, m31_add_n31, m31_mul, m31_sub, push_m31_one, push_n31_one, qm31_copy, qm31_double,
+    qm31_dup, qm31_equalverify, qm31_from_bottom, qm31_fromaltstack, qm31_mul, qm31_neg, qm31_roll,
+    qm31_rot, qm31_square, qm31_swap, qm31_toaltstack,
 };
-use stwo_prover::core::circle::CirclePoint;
+use stwo_prover::core::circle::{CirclePoint, Coset, M31_CIRCLE_LOG_ORDER};
+use stwo_prover::core::fields::m31::P;
 use stwo_prover::core::fields::qm31::QM31;
 
 /// Gadget for out-of-domain sampling.
@@ -70,6 +75,29 @@ impl OODSGadget {
         }
     }
 
+    /// Absorb the composition commitment into the live channel state and then sample a random
+    /// point from it, as in `get_random_point`, so that the point can only be drawn after both the
+    /// trace and the composition commitments are absorbed, see
+    /// `OODS::get_random_point_after_composition_with_hint`.
+    ///
+    /// hint:
+    ///  same as `get_random_point`
+    ///
+    /// input:
+    ///  composition commitment
+    ///  channel (after absorbing the trace commitment)
+    ///
+    /// output:
+    ///  channel'
+    ///  x
+    ///  y
+    pub fn get_random_point_after_composition() -> Script {
+        script! {
+            { Sha256ChannelGadget::mix_digest() }
+            { Self::get_random_point() }
+        }
+    }
+
     /// Push the hint for sampling a random circle curve point over qm31.
     pub fn push_random_point_hint(p: &CirclePoint<QM31>) -> Script {
         script! {
@@ -79,18 +107,283 @@ impl OODSGadget {
     }
 }
 
+/// Draw a random point, as in `OODSGadget::get_random_point`, to be used as a domain offset, and
+/// fail the execution if it lies in the canonic evaluation domain of size 2^logn, over which
+/// the quotients would divide by zero.
+///
+/// hint:
+///  same as `OODSGadget::get_random_point`
+///
+/// input:
+///  channel
+///
+/// output:
+///  channel'=sha256(channel)
+///  x
+///  y
+pub fn draw_coset_offset_gadget(logn: u32) -> Script {
+    script! {
+        { OODSGadget::get_random_point() }
+        { assert_not_in_domain_gadget(logn) }
+    }
+}
+
+/// Fail the execution if the point lies in the canonic evaluation domain of size 2^logn, i.e.,
+/// if the vanishing polynomial of the domain is zero at the point.
+///
+/// input:
+///  x
+///  y
+///
+/// output:
+///  x
+///  y
+pub fn assert_not_in_domain_gadget(logn: u32) -> Script {
+    script! {
+        { qm31_copy(1) }
+        { qm31_copy(1) }
+        { ConstraintsGadget::coset_vanishing(Coset::odds(logn)) }
+        OP_BOOLOR OP_BOOLOR OP_BOOLOR
+        OP_VERIFY
+    }
+}
+
+/// Fail the execution if the x-coordinate of the point has no component outside m31, which is a
+/// cheaper check than `assert_not_in_domain_gadget`, since every evaluation domain, including the
+/// one of size 2^logn, consists of m31 points, while an out-of-domain sample almost never has
+/// such an x-coordinate.
+///
+/// input:
+///  x
+///  y
+///
+/// output:
+///  x
+///  y
+pub fn assert_oods_out_of_domain_gadget(logn: u32) -> Script {
+    assert!(logn <= M31_CIRCLE_LOG_ORDER);
+
+    script! {
+        { qm31_copy(1) }
+        OP_DROP
+        OP_BOOLOR OP_BOOLOR
+        OP_VERIFY
+    }
+}
+
+/// Fail the execution unless the m31 point is in the canonic coset of size 2^logn, i.e., it is
+/// one of the representatives g^(2i + 1) of the cosets of the subgroup of size 2^logn within the
+/// subgroup of size 2^(logn + 1), where g generates the latter.
+///
+/// As the circle group over m31 is cyclic of order 2^31, these are exactly the points of order
+/// 2^(logn + 1), and the only point of order 2 is (-1, 0). The condition checked is therefore:
+///  x^2 + y^2 = 1, and
+///  T(T(...T(x))) = -1 with logn applications of the doubling map T(x) = 2x^2 - 1.
+///
+/// input:
+///  x (m31)
+///  y (m31)
+///
+/// output:
+///  x (m31)
+///  y (m31)
+pub fn assert_coset_representative_gadget(logn: u32) -> Script {
+    assert!(logn < M31_CIRCLE_LOG_ORDER);
+
+    script! {
+        // the point is on the circle
+        OP_2DUP
+        OP_DUP m31_mul
+        OP_SWAP OP_DUP m31_mul
+        m31_add
+        OP_1 OP_EQUALVERIFY
+
+        // doubling it logn times gives (-1, 0)
+        OP_OVER
+        for _ in 0..logn {
+            OP_DUP m31_mul
+            OP_DUP m31_add
+            OP_1 m31_sub
+        }
+        { P - 1 } OP_EQUALVERIFY
+    }
+}
+
+/// Check the claimed evaluations of a trace column at the OODS point z and at the neighboring row
+/// z + G, where G is the generator of the trace domain of size 2^log_size, for transition
+/// constraints that relate a row to the next one.
+///
+/// The two evaluations are independent OODS openings: neither is derived from the other, and each
+/// is tied to the trace with its own quotient at a domain point p, as in
+/// `verify_trace_value_at_oods_gadget`. The shifted point z + G is computed in the script with
+/// `coset_shift_gadget`, so that the prover cannot open the second evaluation elsewhere.
+///
+/// input:
+///  z.x, z.y (QM31)
+///  f(z), f(z + G) (QM31)
+///  p.x, p.y (QM31)
+///  f(p) (QM31)
+///  q_0(p), the quotient for f(z) (QM31)
+///  q_1(p), the quotient for f(z + G) (QM31)
+///
+/// output:
+///  z.x, z.y (QM31)
+///  f(z), f(z + G) (QM31)
+/// mark the transaction as invalid if the check fails
+pub fn verify_shifted_evals_gadget(log_size: u32) -> Script {
+    script! {
+        // check f(z) against q_0(p)
+        for _ in 0..3 {
+            { qm31_copy(8) }
+        }
+        for _ in 0..4 {
+            { qm31_copy(7) }
+        }
+        verify_trace_value_at_oods_gadget
+
+        // check f(z + G) against q_1(p)
+        { qm31_copy(8) }
+        { qm31_copy(8) }
+        { coset_shift_gadget(log_size) }
+        { qm31_copy(7) }
+        { qm31_roll(7) }
+        { qm31_roll(7) }
+        { qm31_roll(7) }
+        { qm31_roll(6) }
+        verify_trace_value_at_oods_gadget
+
+        // drop q_0(p)
+        OP_2DROP OP_2DROP
+    }
+}
+
+/// Check the OODS evaluations of several trace columns against their quotients at a domain point
+/// p, and batch the quotients with the powers of alpha into the single DEEP value that FRI opens,
+/// as in `batched_column_quotient`.
+///
+/// Each quotient is checked on its own with `verify_trace_value_at_oods_gadget`, so a claimed
+/// f_i(z) that is inconsistent with the trace cannot hide behind the batching.
+///
+/// input:
+///  alpha (QM31)
+///  z.x, z.y (QM31)
+///  p.x, p.y (QM31)
+///  f_0(z), f_0(p), q_0(p) (QM31)
+///  ...
+///  f_{n-1}(z), f_{n-1}(p), q_{n-1}(p) (QM31)
+///
+/// output:
+///  q_0(p) + alpha * q_1(p) + ... + alpha^{n-1} * q_{n-1}(p) (QM31)
+/// mark the transaction as invalid if any check fails
+pub fn verify_all_column_oods_gadget(num_columns: usize) -> Script {
+    assert!(num_columns > 0);
+    script! {
+        for i in (0..num_columns).rev() {
+            // keep q_i(p) for the batching
+            qm31_dup
+            qm31_toaltstack
+
+            // arrange z.x, z.y, f_i(z), p.x, p.y, f_i(p), q_i(p)
+            qm31_toaltstack
+            qm31_toaltstack
+            { qm31_copy(3 * i + 4) }
+            { qm31_copy(3 * i + 4) }
+            { qm31_roll(2) }
+            { qm31_copy(3 * i + 4) }
+            { qm31_copy(3 * i + 4) }
+            qm31_fromaltstack
+            qm31_fromaltstack
+            verify_trace_value_at_oods_gadget
+        }
+
+        // drop z and p
+        for _ in 0..8 {
+            OP_2DROP
+        }
+
+        for _ in 0..num_columns {
+            qm31_fromaltstack
+        }
+        { alpha_powers_batch_gadget(num_columns) }
+    }
+}
+
+/// Push the hint for `bind_hinted_eval_gadget`, which is the claimed evaluation f(z).
+pub fn push_bind_hinted_eval_hint(fz: QM31) -> Script {
+    script! {
+        { fz }
+    }
+}
+
+/// Take an evaluation f(z) of a committed column at the OODS point from the hint, and bind it to
+/// the column with a single DEEP opening at a queried domain point p, i.e., check that the
+/// quotient q(p) = (f(p) - L(p)) / V(p) from `trace_quotient_at_point`, where L is the line
+/// through (z, f(z)) and (conj(z), conj(f(z))) and V vanishes at z and conj(z), is consistent
+/// with the opened f(p), with `verify_trace_value_at_oods_gadget`.
+///
+/// On the circle, this quotient plays the role of (f(p) - f(z)) / (p - z), and a wrong f(z) makes
+/// it a high-degree polynomial, which FRI rejects once q(p) is the value that FRI opens. The same
+/// gadget applies to every column, each with its own hint.
+///
+/// hint:
+///  f(z) (QM31)
+///
+/// input:
+///  z.x, z.y (QM31)
+///  p.x, p.y (QM31)
+///  f(p) (QM31)
+///  q(p) (QM31)
+///
+/// output:
+///  f(z) (QM31)
+/// mark the transaction as invalid if the check fails
+pub fn bind_hinted_eval_gadget() -> Script {
+    script! {
+        qm31_from_bottom
+        qm31_dup
+        qm31_toaltstack
+
+        // arrange z.x, z.y, f(z), p.x, p.y, f(p), q(p)
+        for _ in 0..4 {
+            { qm31_roll(4) }
+        }
+        verify_trace_value_at_oods_gadget
+
+        qm31_fromaltstack
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::channel::Sha256ChannelGadget;
-    use crate::oods::{OODSGadget, OODS};
+    use crate::circle::CircleDomain;
+    use crate::constraints::trace_quotient_at_point;
+    use crate::oods::{
+        assert_coset_representative_gadget, assert_not_in_domain_gadget,
+        assert_oods_out_of_domain_gadget, batched_column_quotient, bind_hinted_eval_gadget,
+        draw_coset_offset_gadget, push_bind_hinted_eval_hint, verify_all_column_oods_gadget,
+        verify_shifted_evals_gadget, OODSGadget, OODS,
+    };
+    use crate::tests_utils::fixtures::random_qm31;
     use crate::treepp::*;
-    use crate::{channel::Sha256Channel, tests_utils::report::report_bitcoin_script_size};
+    use crate::{
+        channel::Sha256Channel,
+        tests_utils::report::{assert_max_stack_depth, report_bitcoin_script_size},
+    };
+    use num_traits::One;
     use rand::{Rng, SeedableRng};
     use rand_chacha::ChaCha20Rng;
     use rust_bitcoin_m31::qm31_equalverify;
     use stwo_prover::core::channel::Channel;
-    use stwo_prover::core::circle::CirclePoint;
+    use stwo_prover::core::circle::{CirclePoint, Coset};
+    use stwo_prover::core::fields::m31::M31;
+    use stwo_prover::core::fields::qm31::QM31;
     use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+    use stwo_prover::examples::fibonacci::Fibonacci;
+
+    /// The maximum stack depth of `get_random_point`, including its 13 hint elements, which has
+    /// a lot of headroom, as the gadget keeps at most a few qm31 elements around.
+    const GET_RANDOM_POINT_MAX_STACK_DEPTH: usize = 64;
 
     #[test]
     fn test_get_random_point() {
@@ -124,7 +417,406 @@ mod test {
             OP_EQUALVERIFY // checking that indeed channel' = sha256(channel)
             OP_TRUE
         };
-        let exec_result = execute_script(script);
+        let exec_result = execute_script(script.clone());
         assert!(exec_result.success);
+
+        assert!(assert_max_stack_depth(
+            script,
+            GET_RANDOM_POINT_MAX_STACK_DEPTH
+        ));
+    }
+
+    #[test]
+    fn test_get_random_point_after_composition() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let get_random_point_script = OODSGadget::get_random_point_after_composition();
+        report_bitcoin_script_size(
+            "OODS",
+            "get_random_point_after_composition",
+            get_random_point_script.len(),
+        );
+
+        let mut a = [0u8; 32];
+        a.iter_mut().for_each(|v| *v = prng.gen());
+        let a = BWSSha256Hash::from(a.to_vec());
+
+        let mut composition_commitment = [0u8; 32];
+        composition_commitment
+            .iter_mut()
+            .for_each(|v| *v = prng.gen());
+        let composition_commitment = BWSSha256Hash::from(composition_commitment.to_vec());
+
+        let mut channel = Sha256Channel::new(a);
+        let (p, hint_t) = CirclePoint::get_random_point_after_composition_with_hint(
+            &mut channel,
+            composition_commitment,
+        );
+        let c = channel.digest;
+
+        // a proof that draws the point before absorbing the composition commitment
+        let mut early_channel = Sha256Channel::new(a);
+        let (early_p, early_hint_t) = CirclePoint::get_random_point_with_hint(&mut early_channel);
+        assert_ne!(early_p, p);
+
+        for (p, hint_t, expected) in [(p, hint_t, true), (early_p, early_hint_t, false)] {
+            let script = script! {
+                { Sha256ChannelGadget::push_draw_hint(&hint_t) }
+                { OODSGadget::push_random_point_hint(&p) }
+                { composition_commitment }
+                { a }
+                { get_random_point_script.clone() }
+                { p.y }
+                qm31_equalverify
+                { p.x }
+                qm31_equalverify
+                { c }
+                OP_EQUALVERIFY
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
+
+    #[test]
+    fn test_draw_coset_offset() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 5;
+
+        let draw_coset_offset_script = draw_coset_offset_gadget(logn);
+        report_bitcoin_script_size(
+            "OODS",
+            format!("draw_coset_offset(logn={})", logn).as_str(),
+            draw_coset_offset_script.len(),
+        );
+
+        for _ in 0..10 {
+            let mut a = [0u8; 32];
+            a.iter_mut().for_each(|v| *v = prng.gen());
+            let a = BWSSha256Hash::from(a.to_vec());
+
+            let mut channel = Sha256Channel::new(a);
+            let (p, hint_t) = CirclePoint::get_random_point_with_hint(&mut channel);
+
+            let c = channel.digest;
+
+            let script = script! {
+                { Sha256ChannelGadget::push_draw_hint(&hint_t) }
+                { OODSGadget::push_random_point_hint(&p) }
+                { a }
+                { draw_coset_offset_script.clone() }
+                { p.y }
+                qm31_equalverify
+                { p.x }
+                qm31_equalverify
+                { c }
+                OP_EQUALVERIFY
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        // a point that collides with the evaluation domain is rejected
+        let assert_script = assert_not_in_domain_gadget(logn);
+        for p in Coset::odds(logn).iter() {
+            let p: CirclePoint<QM31> = p.into_ef();
+
+            let script = script! {
+                { p.x }
+                { p.y }
+                { assert_script.clone() }
+                OP_2DROP OP_2DROP OP_2DROP OP_2DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+
+        // while the points of the trace domain, which is disjoint from the evaluation domain, are not
+        for p in Coset::subgroup(logn).iter() {
+            let p: CirclePoint<QM31> = p.into_ef();
+
+            let script = script! {
+                { p.x }
+                { p.y }
+                { assert_script.clone() }
+                OP_2DROP OP_2DROP OP_2DROP OP_2DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_assert_oods_out_of_domain() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 5;
+
+        let assert_script = assert_oods_out_of_domain_gadget(logn);
+        report_bitcoin_script_size(
+            "OODS",
+            format!("assert_oods_out_of_domain(logn={})", logn).as_str(),
+            assert_script.len(),
+        );
+
+        // a genuine out-of-domain sample passes
+        for _ in 0..10 {
+            let mut a = [0u8; 32];
+            a.iter_mut().for_each(|v| *v = prng.gen());
+            let a = BWSSha256Hash::from(a.to_vec());
+
+            let mut channel = Sha256Channel::new(a);
+            let (p, _) = CirclePoint::get_random_point_with_hint(&mut channel);
+
+            let script = script! {
+                { p.x }
+                { p.y }
+                { assert_script.clone() }
+                { p.y }
+                qm31_equalverify
+                { p.x }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        // a point aligned with an evaluation domain is rejected
+        for p in Coset::odds(logn).iter() {
+            let p: CirclePoint<QM31> = p.into_ef();
+
+            let script = script! {
+                { p.x }
+                { p.y }
+                { assert_script.clone() }
+                OP_2DROP OP_2DROP OP_2DROP OP_2DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_assert_coset_representative() {
+        let logn = 5;
+
+        let assert_script = assert_coset_representative_gadget(logn);
+        report_bitcoin_script_size(
+            "OODS",
+            format!("assert_coset_representative(logn={})", logn).as_str(),
+            assert_script.len(),
+        );
+
+        let check = |x: M31, y: M31| {
+            let script = script! {
+                { x }
+                { y }
+                { assert_script.clone() }
+                { y }
+                OP_EQUALVERIFY
+                { x }
+                OP_EQUAL
+            };
+            execute_script(script).success
+        };
+
+        // the points of the canonic coset
+        for p in Coset::odds(logn).iter() {
+            assert!(check(p.x, p.y));
+        }
+
+        // points of a smaller order, which form the subgroup of the same size, and of a larger order
+        for p in Coset::subgroup(logn).iter() {
+            assert!(!check(p.x, p.y));
+        }
+        for p in Coset::odds(logn + 1).iter() {
+            assert!(!check(p.x, p.y));
+        }
+
+        // a point that is not on the circle, but whose x-coordinate is that of a coset point
+        let p = Coset::odds(logn).at(3);
+        assert!(!check(p.x, p.y + M31::one()));
+    }
+
+    #[test]
+    fn test_shifted_evals() {
+        let log_size = 5;
+        let claim = M31::reduce(443693538);
+
+        let fib = Fibonacci::new(log_size, claim);
+        let trace_poly = fib.get_trace().interpolate();
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let shifted_script = verify_shifted_evals_gadget(log_size);
+        report_bitcoin_script_size("OODS", "verify_shifted_evals", shifted_script.len());
+
+        let z = CirclePoint {
+            x: random_qm31(&mut prng),
+            y: random_qm31(&mut prng),
+        };
+        let z_next = z + CircleDomain::new(log_size).generator().into_ef::<QM31>();
+
+        // the evaluations of two consecutive rows
+        let fz = trace_poly.eval_at_point(z);
+        let fz_next = trace_poly.eval_at_point(z_next);
+
+        let p = Coset::odds(log_size + 1).at(3).into_ef::<QM31>();
+        let fp = trace_poly.eval_at_point(p);
+
+        let q0 = trace_quotient_at_point(z, fz, p, fp);
+        let q1 = trace_quotient_at_point(z_next, fz_next, p, fp);
+
+        // a wrong f(z + G), or a wrong f(z), is rejected
+        for (claimed, claimed_next, expected) in [
+            (fz, fz_next, true),
+            (fz, fz_next + QM31::one(), false),
+            (fz, fz, false),
+            (fz + QM31::one(), fz_next, false),
+            (fz_next, fz_next, false),
+        ] {
+            let script = script! {
+                { z.x }
+                { z.y }
+                { claimed }
+                { claimed_next }
+                { p.x }
+                { p.y }
+                { fp }
+                { q0 }
+                { q1 }
+                { shifted_script.clone() }
+                { claimed_next }
+                qm31_equalverify
+                { claimed }
+                qm31_equalverify
+                { z.y }
+                qm31_equalverify
+                { z.x }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
+
+    #[test]
+    fn test_verify_all_column_oods() {
+        let log_size = 5;
+        let num_columns = 4;
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let script = verify_all_column_oods_gadget(num_columns);
+        report_bitcoin_script_size(
+            "OODS",
+            format!("verify_all_column_oods({})", num_columns).as_str(),
+            script.len(),
+        );
+
+        let alpha = random_qm31(&mut prng);
+        let z = CirclePoint {
+            x: random_qm31(&mut prng),
+            y: random_qm31(&mut prng),
+        };
+        let p = Coset::odds(log_size + 1).at(3).into_ef::<QM31>();
+
+        let evals_at_z = (0..num_columns)
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<_>>();
+        let evals_at_p = (0..num_columns)
+            .map(|_| random_qm31(&mut prng))
+            .collect::<Vec<_>>();
+        let quotients = evals_at_z
+            .iter()
+            .zip(evals_at_p.iter())
+            .map(|(&fz, &fp)| trace_quotient_at_point(z, fz, p, fp))
+            .collect::<Vec<_>>();
+        let expected = batched_column_quotient(alpha, z, &evals_at_z, p, &evals_at_p);
+
+        let run = |evals_at_z: &[QM31], quotients: &[QM31], expected: QM31| {
+            let script = script! {
+                { alpha }
+                { z.x }
+                { z.y }
+                { p.x }
+                { p.y }
+                for i in 0..num_columns {
+                    { evals_at_z[i] }
+                    { evals_at_p[i] }
+                    { quotients[i] }
+                }
+                { script.clone() }
+                { expected }
+                qm31_equalverify
+                OP_TRUE
+            };
+            execute_script(script).success
+        };
+
+        assert!(run(&evals_at_z, &quotients, expected));
+
+        // corrupting one OODS evaluation without updating its quotient fails the check
+        let mut corrupted_evals_at_z = evals_at_z.clone();
+        corrupted_evals_at_z[2] += QM31::one();
+        assert!(!run(&corrupted_evals_at_z, &quotients, expected));
+
+        // updating the quotient as well changes the batched value
+        let mut corrupted_quotients = quotients.clone();
+        corrupted_quotients[2] =
+            trace_quotient_at_point(z, corrupted_evals_at_z[2], p, evals_at_p[2]);
+        let corrupted = batched_column_quotient(alpha, z, &corrupted_evals_at_z, p, &evals_at_p);
+        assert_ne!(corrupted, expected);
+        assert!(run(&corrupted_evals_at_z, &corrupted_quotients, corrupted));
+        assert!(!run(&corrupted_evals_at_z, &corrupted_quotients, expected));
+    }
+
+    #[test]
+    fn test_bind_hinted_eval() {
+        let log_size = 5;
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let bind_script = bind_hinted_eval_gadget();
+        report_bitcoin_script_size("OODS", "bind_hinted_eval", bind_script.len());
+
+        for i in 0..10 {
+            let z = CirclePoint {
+                x: random_qm31(&mut prng),
+                y: random_qm31(&mut prng),
+            };
+            let p = Coset::odds(log_size + 1).at(i).into_ef::<QM31>();
+            let (fz, fp) = (random_qm31(&mut prng), random_qm31(&mut prng));
+
+            // the quotient that the committed column opens at p
+            let qp = trace_quotient_at_point(z, fz, p, fp);
+
+            // a wrong hinted evaluation is caught by the same opening
+            for (hinted_fz, expected) in [(fz, true), (fz + QM31::one(), false)] {
+                let script = script! {
+                    { push_bind_hinted_eval_hint(hinted_fz) }
+                    { z.x }
+                    { z.y }
+                    { p.x }
+                    { p.y }
+                    { fp }
+                    { qp }
+                    { bind_script.clone() }
+                    { fz }
+                    qm31_equalverify
+                    OP_TRUE
+                };
+                let exec_result = execute_script(script);
+                assert_eq!(exec_result.success, expected);
+            }
+        }
     }
 }
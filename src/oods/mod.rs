@@ -1,10 +1,14 @@
 use crate::channel::Sha256Channel;
 use crate::channel::{ChannelWithHint, DrawHints};
+use crate::constraints::trace_quotient_at_point;
+use crate::utils::alpha_powers_batch;
 use num_traits::One;
 use std::ops::{Add, Mul, Neg};
+use stwo_prover::core::channel::Channel;
 use stwo_prover::core::circle::CirclePoint;
 use stwo_prover::core::fields::qm31::QM31;
 use stwo_prover::core::fields::{Field, FieldExpOps};
+use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
 
 mod bitcoin_script;
 pub use bitcoin_script::*;
@@ -13,6 +17,16 @@ pub use bitcoin_script::*;
 pub trait OODS: Sized {
     /// Obtain a random point from the channel and its hint.
     fn get_random_point_with_hint(channel: &mut Sha256Channel) -> (Self, DrawHints<4>);
+
+    /// Absorb the composition commitment and then obtain a random point from the channel and its
+    /// hint, so that the point depends on both the trace and the composition commitments.
+    fn get_random_point_after_composition_with_hint(
+        channel: &mut Sha256Channel,
+        composition_commitment: BWSSha256Hash,
+    ) -> (Self, DrawHints<4>) {
+        channel.mix_digest(composition_commitment);
+        Self::get_random_point_with_hint(channel)
+    }
 }
 
 impl OODS for CirclePoint<QM31> {
@@ -29,3 +43,22 @@ impl OODS for CirclePoint<QM31> {
         (CirclePoint { x, y }, hint)
     }
 }
+
+/// Compute the batched DEEP quotient of several trace columns at a domain point p, i.e.,
+/// sum_i alpha^i * q_i(p), where q_i is the quotient from `trace_quotient_at_point` for the
+/// column evaluations f_i(z) at the OODS point z and f_i(p) at p.
+pub fn batched_column_quotient(
+    alpha: QM31,
+    z: CirclePoint<QM31>,
+    evals_at_z: &[QM31],
+    p: CirclePoint<QM31>,
+    evals_at_p: &[QM31],
+) -> QM31 {
+    assert_eq!(evals_at_z.len(), evals_at_p.len());
+    let quotients = evals_at_z
+        .iter()
+        .zip(evals_at_p.iter())
+        .map(|(&fz, &fp)| trace_quotient_at_point(z, fz, p, fp))
+        .collect::<Vec<_>>();
+    alpha_powers_batch(alpha, &quotients)
+}
@@ -0,0 +1,299 @@
+use crate::poseidon2::{
+    EXTERNAL_ROUND_CONSTANTS, INTERNAL_DIAG, INTERNAL_ROUND_CONSTANTS, N_EXTERNAL_ROUNDS, WIDTH,
+};
+use crate::treepp::*;
+use rust_bitcoin_m31::{m31_add, m31_mul, m31_sub};
+use stwo_prover::core::fields::m31::M31;
+
+/// Gadget for a channel over the Poseidon2 permutation, matching `Poseidon2Channel`.
+pub struct Poseidon2ChannelGadget;
+
+impl Poseidon2ChannelGadget {
+    /// Apply the Poseidon2 permutation.
+    ///
+    /// input:
+    ///  s_0, ..., s_15 (m31 -- s_15 on top)
+    ///
+    /// output:
+    ///  s_0', ..., s_15'
+    pub fn permute() -> Script {
+        script! {
+            external_linear_layer
+            for round_constants in EXTERNAL_ROUND_CONSTANTS[..N_EXTERNAL_ROUNDS / 2].iter() {
+                { external_round(round_constants) }
+            }
+            for rc in INTERNAL_ROUND_CONSTANTS.iter() {
+                { internal_round(*rc) }
+            }
+            for round_constants in EXTERNAL_ROUND_CONSTANTS[N_EXTERNAL_ROUNDS / 2..].iter() {
+                { external_round(round_constants) }
+            }
+        }
+    }
+
+    /// Absorb a qm31 element.
+    ///
+    /// input:
+    ///  felt (qm31)
+    ///  channel (8 m31)
+    ///
+    /// output:
+    ///  channel'
+    pub fn mix_felt() -> Script {
+        script! {
+            for i in 0..4 {
+                { 8 + i } OP_ROLL
+            }
+            OP_0 OP_0 OP_0 OP_0
+            { Self::permute() }
+            OP_2DROP OP_2DROP OP_2DROP OP_2DROP
+        }
+    }
+
+    /// Squeeze a qm31 element.
+    ///
+    /// Unlike the sha256 channel, the output of the permutation is already in m31 elements, so no
+    /// hint is needed.
+    ///
+    /// input:
+    ///  channel (8 m31)
+    ///
+    /// output:
+    ///  channel'
+    ///  felt (qm31)
+    pub fn draw_felt() -> Script {
+        script! {
+            OP_0 OP_0 OP_0 OP_0 OP_0 OP_0 OP_0 OP_1
+            { Self::permute() }
+            OP_2DROP OP_2DROP
+            OP_SWAP OP_2SWAP OP_SWAP
+        }
+    }
+}
+
+fn sbox() -> Script {
+    script! {
+        OP_DUP OP_DUP m31_mul
+        OP_DUP m31_mul
+        m31_mul
+    }
+}
+
+/// Multiply a m31 element by a small constant (at least 2) by double-and-add.
+fn mul_small(c: u32) -> Script {
+    let bits = 32 - c.leading_zeros();
+    script! {
+        OP_DUP
+        for i in (0..bits - 1).rev() {
+            OP_DUP m31_add
+            if (c >> i) & 1 == 1 {
+                OP_OVER m31_add
+            }
+        }
+        OP_NIP
+    }
+}
+
+/// Multiply a m31 element by an entry of `INTERNAL_DIAG`, which is small up to its sign.
+fn mul_diag(d: i32) -> Script {
+    script! {
+        if d.unsigned_abs() > 1 {
+            { mul_small(d.unsigned_abs()) }
+        }
+        if d < 0 {
+            OP_0 OP_SWAP m31_sub
+        }
+    }
+}
+
+/// Apply the 4x4 matrix of `apply_m4` on the top four elements.
+fn m4() -> Script {
+    script! {
+        // t0 = x0 + x1, t1 = x2 + x3
+        3 OP_PICK 3 OP_PICK m31_add
+        2 OP_PICK 2 OP_PICK m31_add
+
+        // t2 = 2 * x1 + t1, t3 = 2 * x3 + t0
+        4 OP_PICK OP_DUP m31_add OP_OVER m31_add
+        3 OP_PICK OP_DUP m31_add 3 OP_PICK m31_add
+
+        OP_2ROT OP_2DROP OP_2ROT OP_2DROP
+
+        // t4 = 4 * t1 + t3, t5 = 4 * t0 + t2
+        2 OP_PICK OP_DUP m31_add OP_DUP m31_add OP_OVER m31_add
+        4 OP_PICK OP_DUP m31_add OP_DUP m31_add 3 OP_PICK m31_add
+
+        // t6 = t3 + t5, t7 = t2 + t4
+        OP_DUP 3 OP_PICK m31_add
+        4 OP_PICK 3 OP_PICK m31_add
+
+        OP_2ROT OP_2DROP OP_2ROT OP_2DROP
+
+        // t4, t5, t6, t7 => t6, t5, t7, t4
+        3 OP_ROLL
+        OP_2SWAP OP_SWAP OP_2SWAP
+    }
+}
+
+/// Apply the external matrix circ(2 * M4, M4, M4, M4) on the state.
+fn external_linear_layer() -> Script {
+    script! {
+        for _ in 0..4 {
+            15 OP_ROLL 15 OP_ROLL 15 OP_ROLL 15 OP_ROLL
+            m4
+        }
+
+        // the sums of y_r, y_{r+4}, y_{r+8}, y_{r+12}
+        for _ in 0..4 {
+            15 OP_PICK 12 OP_PICK m31_add 8 OP_PICK m31_add 4 OP_PICK m31_add
+        }
+
+        for i in 0..WIDTH {
+            19 OP_ROLL
+            { 4 - i % 4 + i } OP_PICK m31_add
+        }
+
+        for k in 0..4 {
+            { 19 - k } OP_ROLL OP_DROP
+        }
+    }
+}
+
+fn external_round(round_constants: &[M31; WIDTH]) -> Script {
+    script! {
+        for rc in round_constants.iter() {
+            15 OP_ROLL { *rc } m31_add
+            sbox
+        }
+        external_linear_layer
+    }
+}
+
+fn internal_round(rc: M31) -> Script {
+    script! {
+        15 OP_ROLL { rc } m31_add
+        sbox
+
+        // the state is now s_1, ..., s_15, s_0
+        OP_DUP
+        for k in 1..WIDTH {
+            { 17 - k } OP_PICK m31_add
+        }
+
+        OP_SWAP
+        { mul_diag(INTERNAL_DIAG[0]) }
+        1 OP_PICK m31_add
+
+        for i in 1..WIDTH {
+            16 OP_ROLL
+            { mul_diag(INTERNAL_DIAG[i]) }
+            { i + 1 } OP_PICK m31_add
+        }
+
+        16 OP_ROLL OP_DROP
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::poseidon2::{poseidon2_permute, Poseidon2Channel, Poseidon2ChannelGadget, WIDTH};
+    use crate::tests_utils::fixtures::random_qm31;
+    use crate::tests_utils::report::report_bitcoin_script_size;
+    use crate::treepp::*;
+    use rand::{RngCore, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use rust_bitcoin_m31::qm31_equalverify;
+    use stwo_prover::core::fields::m31::M31;
+
+    #[test]
+    fn test_permute() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let permute_script = Poseidon2ChannelGadget::permute();
+        report_bitcoin_script_size("Poseidon2", "permute", permute_script.len());
+
+        let mut state = [M31::default(); WIDTH];
+        state
+            .iter_mut()
+            .for_each(|v| *v = M31::reduce(prng.next_u64()));
+
+        let mut expected = state;
+        poseidon2_permute(&mut expected);
+
+        let script = script! {
+            for v in state.iter() {
+                { *v }
+            }
+            { permute_script.clone() }
+            for v in expected.iter().rev() {
+                { *v } OP_EQUALVERIFY
+            }
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+    }
+
+    #[test]
+    fn test_mix_felt() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let channel_script = Poseidon2ChannelGadget::mix_felt();
+        report_bitcoin_script_size("Poseidon2", "mix_felt", channel_script.len());
+
+        let mut init_state = [M31::default(); 8];
+        init_state
+            .iter_mut()
+            .for_each(|v| *v = M31::reduce(prng.next_u64()));
+
+        let elem = random_qm31(&mut prng);
+
+        let mut channel = Poseidon2Channel::new(init_state);
+        channel.mix_felts(&[elem]);
+
+        let script = script! {
+            { elem }
+            for v in init_state.iter() {
+                { *v }
+            }
+            { channel_script.clone() }
+            for v in channel.digest.iter().rev() {
+                { *v } OP_EQUALVERIFY
+            }
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+    }
+
+    #[test]
+    fn test_draw_felt() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let channel_script = Poseidon2ChannelGadget::draw_felt();
+        report_bitcoin_script_size("Poseidon2", "draw_felt", channel_script.len());
+
+        let mut init_state = [M31::default(); 8];
+        init_state
+            .iter_mut()
+            .for_each(|v| *v = M31::reduce(prng.next_u64()));
+
+        let mut channel = Poseidon2Channel::new(init_state);
+        let elem = channel.draw_felt();
+
+        let script = script! {
+            for v in init_state.iter() {
+                { *v }
+            }
+            { channel_script.clone() }
+            { elem }
+            qm31_equalverify
+            for v in channel.digest.iter().rev() {
+                { *v } OP_EQUALVERIFY
+            }
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+    }
+}
@@ -0,0 +1,497 @@
+mod bitcoin_script;
+pub use bitcoin_script::*;
+
+use num_traits::Zero;
+use stwo_prover::core::fields::cm31::CM31;
+use stwo_prover::core::fields::m31::{M31, P};
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::fields::FieldExpOps;
+
+/// The width of the Poseidon2 permutation.
+pub const WIDTH: usize = 16;
+
+/// The number of external (full) rounds, half of which are before the internal rounds.
+pub const N_EXTERNAL_ROUNDS: usize = 8;
+
+/// The number of internal (partial) rounds.
+pub const N_INTERNAL_ROUNDS: usize = 14;
+
+/// The number of m31 elements of the channel state, which is the rate part of the permutation.
+pub const DIGEST_SIZE: usize = 8;
+
+/// The diagonal D of the internal matrix D + 11^T, which maps x to `INTERNAL_DIAG[i] * x_i +
+/// sum(x)`.
+///
+/// It is the diagonal of the width-16 Mersenne31 instance of Plonky3, whose entries are -2 and
+/// powers of 2, so that the multiplications are a few doublings in Bitcoin script. The round
+/// constants are those of the reference, see `reference_round_constants`, so the instance as a
+/// whole is not a published one, and it relies on the internal matrix meeting the requirements of
+/// the Poseidon2 paper, which `test_internal_matrix` checks:
+/// - it is invertible, as det(D + 11^T) = det(D) * (1 + sum(1 / D_i)) is not zero,
+/// - for k = 1, ..., 2 * WIDTH, the characteristic polynomial of its k-th power is irreducible,
+///   so that no subspace is invariant under it, and no subspace trail goes through all the
+///   internal rounds.
+pub const INTERNAL_DIAG: [i32; WIDTH] = [
+    -2, 1, 2, 4, 8, 16, 32, 64, 128, 256, 1024, 4096, 8192, 16384, 32768, 65536,
+];
+
+lazy_static::lazy_static! {
+    /// The round constants of the external rounds, the first half of which are before the internal
+    /// rounds, see `reference_round_constants`.
+    pub static ref EXTERNAL_ROUND_CONSTANTS: [[M31; WIDTH]; N_EXTERNAL_ROUNDS] =
+        reference_round_constants().0;
+
+    /// The round constants of the internal rounds, see `reference_round_constants`.
+    pub static ref INTERNAL_ROUND_CONSTANTS: [M31; N_INTERNAL_ROUNDS] =
+        reference_round_constants().1;
+}
+
+/// The Grain LFSR that the reference implementation of Poseidon and Poseidon2 generates the round
+/// constants with, which is seeded with the parameters of the instance.
+pub struct GrainLfsr {
+    state: [bool; 80],
+}
+
+impl GrainLfsr {
+    /// Seed the LFSR for a prime field of `field_size` bits, the S-box x^alpha, the width, and the
+    /// numbers of full and partial rounds, and discard its first 160 bits.
+    pub fn new(
+        field_size: usize,
+        width: usize,
+        n_full_rounds: usize,
+        n_partial_rounds: usize,
+    ) -> Self {
+        // the field is a prime field (1 in 2 bits), and the S-box is x^alpha (0 in 4 bits)
+        let mut state = vec![];
+        for (v, n_bits) in [
+            (1, 2),
+            (0, 4),
+            (field_size, 12),
+            (width, 12),
+            (n_full_rounds, 10),
+            (n_partial_rounds, 10),
+        ] {
+            state.extend((0..n_bits).rev().map(|i| (v >> i) & 1 == 1));
+        }
+        state.extend([true; 30]);
+
+        let mut lfsr = Self {
+            state: state.try_into().unwrap(),
+        };
+        for _ in 0..160 {
+            lfsr.step();
+        }
+        lfsr
+    }
+
+    fn step(&mut self) -> bool {
+        let s = &self.state;
+        let bit = s[62] ^ s[51] ^ s[38] ^ s[23] ^ s[13] ^ s[0];
+        self.state.rotate_left(1);
+        self.state[79] = bit;
+        bit
+    }
+
+    /// The next bit, after the self-shrinking of the reference: the bits are taken in pairs, and the
+    /// second bit of a pair is output only if the first one is 1.
+    pub fn next_bit(&mut self) -> bool {
+        while !self.step() {
+            self.step();
+        }
+        self.step()
+    }
+
+    /// The next `n_bits` bits, the most significant first.
+    pub fn next_bits(&mut self, n_bits: usize) -> Vec<bool> {
+        (0..n_bits).map(|_| self.next_bit()).collect()
+    }
+
+    /// The next m31 element, which is the next 31-bit integer that is less than P.
+    pub fn next_m31(&mut self) -> M31 {
+        loop {
+            let v = self
+                .next_bits(31)
+                .iter()
+                .fold(0u32, |acc, bit| (acc << 1) | *bit as u32);
+            if v < P {
+                return M31::from_u32_unchecked(v);
+            }
+        }
+    }
+}
+
+/// The round constants of the reference implementation of Poseidon2 for m31 with the width, the
+/// S-box, and the numbers of rounds of this instance, as the external round constants and then the
+/// internal round constants.
+///
+/// The constants are drawn from `GrainLfsr` in the order that the rounds use them: a constant per
+/// element for each of the first half of the external rounds, then a constant for each internal
+/// round, and then a constant per element for each of the second half of the external rounds.
+pub fn reference_round_constants() -> ([[M31; WIDTH]; N_EXTERNAL_ROUNDS], [M31; N_INTERNAL_ROUNDS])
+{
+    let mut lfsr = GrainLfsr::new(31, WIDTH, N_EXTERNAL_ROUNDS, N_INTERNAL_ROUNDS);
+
+    let mut external = [[M31::zero(); WIDTH]; N_EXTERNAL_ROUNDS];
+    let mut internal = [M31::zero(); N_INTERNAL_ROUNDS];
+
+    let (first_half, second_half) = external.split_at_mut(N_EXTERNAL_ROUNDS / 2);
+    for round in first_half.iter_mut() {
+        round.iter_mut().for_each(|v| *v = lfsr.next_m31());
+    }
+    internal.iter_mut().for_each(|v| *v = lfsr.next_m31());
+    for round in second_half.iter_mut() {
+        round.iter_mut().for_each(|v| *v = lfsr.next_m31());
+    }
+
+    (external, internal)
+}
+
+/// Apply the 4x4 matrix
+///   [[5, 7, 1, 3], [4, 6, 1, 1], [1, 3, 5, 7], [1, 1, 4, 6]]
+/// from the Poseidon2 paper.
+fn apply_m4(x: [M31; 4]) -> [M31; 4] {
+    let t0 = x[0] + x[1];
+    let t1 = x[2] + x[3];
+    let t2 = x[1] + x[1] + t1;
+    let t3 = x[3] + x[3] + t0;
+    let t4 = M31::from(4) * t1 + t3;
+    let t5 = M31::from(4) * t0 + t2;
+    let t6 = t3 + t5;
+    let t7 = t2 + t4;
+    [t6, t5, t7, t4]
+}
+
+/// Apply the external matrix circ(2 * M4, M4, M4, M4).
+fn apply_external_linear_layer(state: &mut [M31; WIDTH]) {
+    for chunk in state.chunks_exact_mut(4) {
+        let y = apply_m4([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        chunk.copy_from_slice(&y);
+    }
+
+    let mut sums = [M31::zero(); 4];
+    for (i, v) in state.iter().enumerate() {
+        sums[i % 4] += *v;
+    }
+    for (i, v) in state.iter_mut().enumerate() {
+        *v += sums[i % 4];
+    }
+}
+
+/// The entries of `INTERNAL_DIAG` as m31 elements.
+fn internal_diag() -> [M31; WIDTH] {
+    INTERNAL_DIAG.map(|d| {
+        let v = M31::from(d.unsigned_abs());
+        if d < 0 {
+            -v
+        } else {
+            v
+        }
+    })
+}
+
+/// Apply the internal matrix, which maps x to `INTERNAL_DIAG[i] * x_i + sum(x)`.
+fn apply_internal_linear_layer(state: &mut [M31; WIDTH]) {
+    let sum = state.iter().fold(M31::zero(), |acc, v| acc + *v);
+    for (v, diag) in state.iter_mut().zip(internal_diag().iter()) {
+        *v = *v * *diag + sum;
+    }
+}
+
+fn sbox(v: M31) -> M31 {
+    v.square().square() * v
+}
+
+/// The Poseidon2 permutation over m31 with width 16 and the S-box x^5.
+pub fn poseidon2_permute(state: &mut [M31; WIDTH]) {
+    apply_external_linear_layer(state);
+
+    for round_constants in EXTERNAL_ROUND_CONSTANTS[..N_EXTERNAL_ROUNDS / 2].iter() {
+        for (v, rc) in state.iter_mut().zip(round_constants.iter()) {
+            *v = sbox(*v + *rc);
+        }
+        apply_external_linear_layer(state);
+    }
+
+    for rc in INTERNAL_ROUND_CONSTANTS.iter() {
+        state[0] = sbox(state[0] + *rc);
+        apply_internal_linear_layer(state);
+    }
+
+    for round_constants in EXTERNAL_ROUND_CONSTANTS[N_EXTERNAL_ROUNDS / 2..].iter() {
+        for (v, rc) in state.iter_mut().zip(round_constants.iter()) {
+            *v = sbox(*v + *rc);
+        }
+        apply_external_linear_layer(state);
+    }
+}
+
+/// A channel over the Poseidon2 permutation, whose state is 8 m31 elements.
+///
+/// To absorb a qm31 element, the permutation is applied to the state, followed by the four limbs
+/// of the element and four zeros, and the first 8 elements of the output become the new state.
+/// To squeeze a qm31 element, the permutation is applied to the state, followed by seven zeros and
+/// a one (which separates it from absorbing), and the next four elements of the output after the
+/// new state are the limbs of the element.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Poseidon2Channel {
+    /// The channel state.
+    pub digest: [M31; DIGEST_SIZE],
+}
+
+impl Poseidon2Channel {
+    /// Create a new channel from the state.
+    pub fn new(digest: [M31; DIGEST_SIZE]) -> Self {
+        Self { digest }
+    }
+
+    /// Absorb qm31 elements, one at a time.
+    pub fn mix_felts(&mut self, felts: &[QM31]) {
+        for felt in felts.iter() {
+            let mut state = [M31::zero(); WIDTH];
+            state[..DIGEST_SIZE].copy_from_slice(&self.digest);
+            state[DIGEST_SIZE..DIGEST_SIZE + 4]
+                .copy_from_slice(&[felt.0 .0, felt.0 .1, felt.1 .0, felt.1 .1]);
+
+            poseidon2_permute(&mut state);
+            self.digest.copy_from_slice(&state[..DIGEST_SIZE]);
+        }
+    }
+
+    /// Squeeze a qm31 element.
+    pub fn draw_felt(&mut self) -> QM31 {
+        let mut state = [M31::zero(); WIDTH];
+        state[..DIGEST_SIZE].copy_from_slice(&self.digest);
+        state[WIDTH - 1] = M31::from(1);
+
+        poseidon2_permute(&mut state);
+        self.digest.copy_from_slice(&state[..DIGEST_SIZE]);
+
+        QM31(
+            CM31(state[DIGEST_SIZE], state[DIGEST_SIZE + 1]),
+            CM31(state[DIGEST_SIZE + 2], state[DIGEST_SIZE + 3]),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::poseidon2::{
+        apply_internal_linear_layer, internal_diag, poseidon2_permute, GrainLfsr,
+        EXTERNAL_ROUND_CONSTANTS, INTERNAL_ROUND_CONSTANTS, WIDTH,
+    };
+    use num_traits::{One, Zero};
+    use stwo_prover::core::fields::m31::{M31, P};
+    use stwo_prover::core::fields::FieldExpOps;
+
+    type Matrix = [[M31; WIDTH]; WIDTH];
+
+    fn mat_mul(a: &Matrix, b: &Matrix) -> Matrix {
+        let mut c = [[M31::zero(); WIDTH]; WIDTH];
+        for (c_row, a_row) in c.iter_mut().zip(a.iter()) {
+            for (j, v) in c_row.iter_mut().enumerate() {
+                *v = a_row
+                    .iter()
+                    .zip(b.iter())
+                    .fold(M31::zero(), |acc, (x, b_row)| acc + *x * b_row[j]);
+            }
+        }
+        c
+    }
+
+    /// The characteristic polynomial of the matrix, lowest degree first, with the Faddeev-LeVerrier
+    /// algorithm.
+    fn char_poly(m: &Matrix) -> Vec<M31> {
+        let mut c = vec![M31::zero(); WIDTH + 1];
+        c[WIDTH] = M31::one();
+        let mut mk = [[M31::zero(); WIDTH]; WIDTH];
+        for k in 1..=WIDTH {
+            for (i, row) in mk.iter_mut().enumerate() {
+                row[i] += c[WIDTH + 1 - k];
+            }
+            mk = mat_mul(m, &mk);
+            let trace = (0..WIDTH).fold(M31::zero(), |acc, i| acc + mk[i][i]);
+            c[WIDTH - k] = -trace * M31::from(k as u32).inverse();
+        }
+        c
+    }
+
+    fn trim(mut a: Vec<M31>) -> Vec<M31> {
+        while a.last() == Some(&M31::zero()) {
+            a.pop();
+        }
+        a
+    }
+
+    /// The remainder of a divided by b, where b is trimmed and not zero.
+    fn poly_rem(a: &[M31], b: &[M31]) -> Vec<M31> {
+        let inv = b.last().unwrap().inverse();
+        let mut r = trim(a.to_vec());
+        while r.len() >= b.len() {
+            let coeff = *r.last().unwrap() * inv;
+            let shift = r.len() - b.len();
+            for (i, v) in b.iter().enumerate() {
+                r[shift + i] -= coeff * *v;
+            }
+            r = trim(r);
+        }
+        r
+    }
+
+    fn poly_mul_mod(a: &[M31], b: &[M31], f: &[M31]) -> Vec<M31> {
+        let mut c = vec![M31::zero(); a.len() + b.len() - 1];
+        for (i, x) in a.iter().enumerate() {
+            for (j, y) in b.iter().enumerate() {
+                c[i + j] += *x * *y;
+            }
+        }
+        poly_rem(&c, f)
+    }
+
+    fn poly_pow_mod(a: &[M31], mut e: u32, f: &[M31]) -> Vec<M31> {
+        let mut res = vec![M31::one()];
+        let mut a = poly_rem(a, f);
+        while e > 0 {
+            if e & 1 == 1 {
+                res = poly_mul_mod(&res, &a, f);
+            }
+            a = poly_mul_mod(&a, &a, f);
+            e >>= 1;
+        }
+        res
+    }
+
+    /// Whether a polynomial of degree 2^k is irreducible, i.e., x^(P^n) = x mod f and
+    /// gcd(x^(P^(n/2)) - x, f) = 1, where n is its degree, whose only prime factor is 2.
+    fn is_irreducible(f: &[M31]) -> bool {
+        let n = f.len() - 1;
+        assert!(n.is_power_of_two() && n > 1);
+
+        // x^(P^i) - x mod f, for i = n / 2 and i = n
+        let mut frobenius = vec![M31::zero(), M31::one()];
+        let mut minus_x = vec![];
+        for _ in 0..n {
+            frobenius = poly_pow_mod(&frobenius, P, f);
+            let mut g = frobenius.clone();
+            g.resize(n, M31::zero());
+            g[1] -= M31::one();
+            minus_x.push(trim(g));
+        }
+        if !minus_x[n - 1].is_empty() {
+            return false;
+        }
+
+        let (mut a, mut b) = (f.to_vec(), minus_x[n / 2 - 1].clone());
+        while !b.is_empty() {
+            let r = poly_rem(&a, &b);
+            a = b;
+            b = r;
+        }
+        a.len() == 1
+    }
+
+    #[test]
+    fn test_internal_matrix() {
+        // the matrix of the internal linear layer, whose columns are the images of the unit
+        // vectors
+        let columns = (0..WIDTH)
+            .map(|j| {
+                let mut e = [M31::zero(); WIDTH];
+                e[j] = M31::one();
+                apply_internal_linear_layer(&mut e);
+                e
+            })
+            .collect::<Vec<[M31; WIDTH]>>();
+        let mut m = [[M31::zero(); WIDTH]; WIDTH];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (v, column) in row.iter_mut().zip(columns.iter()) {
+                *v = column[i];
+            }
+        }
+
+        // det(D + 11^T) = det(D) * (1 + sum(1 / D_i))
+        let diag = internal_diag();
+        let det = diag.iter().fold(M31::one(), |acc, d| acc * *d)
+            * diag.iter().fold(M31::one(), |acc, d| acc + d.inverse());
+        assert_ne!(det, M31::zero());
+
+        let mut mk = m;
+        for _ in 1..=2 * WIDTH {
+            assert!(is_irreducible(&char_poly(&mk)));
+            mk = mat_mul(&mk, &m);
+        }
+    }
+
+    #[test]
+    fn test_irreducible() {
+        // x^2 + 1 is irreducible, as P = 3 mod 4, while x^2 - 1 = (x - 1)(x + 1) is not
+        assert!(is_irreducible(&[M31::one(), M31::zero(), M31::one()]));
+        assert!(!is_irreducible(&[-M31::one(), M31::zero(), M31::one()]));
+    }
+
+    #[test]
+    fn test_grain_lfsr_bn254_round_constants() {
+        // the first round constants of Poseidon over the scalar field of BN254 with width 3, x^5,
+        // 8 full rounds, and 57 partial rounds, as published with the reference implementation
+        // and in circomlib
+        let modulus = "30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001";
+        let expected = [
+            "0ee9a592ba9a9518d05986d656f40c2114c4993c11bb29938d21d47304cd8e6e",
+            "00f1445235f2148c5986587169fc1bcd887b08d4d00868df5696fff40956e864",
+            "08dff3487e8ac99e1f29a058d0fa80b930c728730b7ab36ce879f3890ecf73f5",
+        ];
+
+        let to_bits = |hex: &str| {
+            hex.chars()
+                .flat_map(|c| {
+                    let v = c.to_digit(16).unwrap();
+                    (0..4).rev().map(move |i| (v >> i) & 1 == 1)
+                })
+                .skip(2)
+                .collect::<Vec<bool>>()
+        };
+        let modulus = to_bits(modulus);
+
+        let mut lfsr = GrainLfsr::new(254, 3, 8, 57);
+        for expected in expected.iter() {
+            let constant = loop {
+                let bits = lfsr.next_bits(254);
+                if bits < modulus {
+                    break bits;
+                }
+            };
+            assert_eq!(constant, to_bits(expected));
+        }
+    }
+
+    #[test]
+    fn test_poseidon2_known_answer() {
+        assert_eq!(
+            EXTERNAL_ROUND_CONSTANTS[0][..4],
+            [1988864850, 1893772157, 1025928330, 1839472709].map(M31::from_u32_unchecked)
+        );
+        assert_eq!(
+            INTERNAL_ROUND_CONSTANTS[..4],
+            [2139014335, 69309039, 1368974953, 886780232].map(M31::from_u32_unchecked)
+        );
+        assert_eq!(
+            EXTERNAL_ROUND_CONSTANTS[7][WIDTH - 4..],
+            [1331641456, 38121324, 595810771, 1234594393].map(M31::from_u32_unchecked)
+        );
+
+        let mut state = [M31::default(); WIDTH];
+        state
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, v)| *v = M31::from_u32_unchecked(i as u32));
+        poseidon2_permute(&mut state);
+        assert_eq!(
+            state,
+            [
+                1781279123, 2138219184, 1648008002, 1451549590, 1941576110, 558940464, 2036091834,
+                891233125, 789070634, 1427257847, 304937794, 605216657, 1263669768, 1370957136,
+                179561150, 672469867,
+            ]
+            .map(M31::from_u32_unchecked)
+        );
+    }
+}
@@ -6,6 +6,12 @@ pub struct PowGadget;
 
 impl PowGadget {
     /// Verify the PoW in Bitcoin script.
+    ///
+    /// The nonce must be in its canonical encoding, which is exactly 8 bytes in little-endian as
+    /// in `hash_with_nonce`, and is checked with `OP_SIZE` before hashing. Otherwise, the same
+    /// nonce could be encoded with more or fewer bytes, each of which leads to a different
+    /// channel', so that a prover could grind over the encodings as well.
+    ///
     /// input:
     ///  channel (32 bytes)
     ///  nonce (64-bit string, aka 8 bytes)
@@ -106,13 +112,55 @@ impl PowGadget {
     }
 }
 
+/// Verify several rounds of PoW in a row, each with its own number of bits, as in `grind_multi_pow`.
+///
+/// hint:
+///  for each round, the hint from `PowGadget::push_pow_hint`, see `push_multi_pow_hint`
+///
+/// input:
+///  channel
+///
+/// output:
+///  channel' = the channel after all the rounds
+pub fn verify_multi_pow_gadget(rounds: &[usize]) -> Script {
+    script! {
+        for &n_bits in rounds.iter() {
+            // pull the nonce, the suffix, and the msb (if applicable) from the hint stack
+            for _ in 0..(if n_bits % 8 == 0 { 2 } else { 3 }) {
+                OP_DEPTH OP_1SUB OP_ROLL
+            }
+            { PowGadget::verify_pow(n_bits) }
+        }
+    }
+}
+
+/// Push the hints for `verify_multi_pow_gadget`, one round after the other.
+pub fn push_multi_pow_hint(channel_digest: Vec<u8>, nonces: &[u64], rounds: &[usize]) -> Script {
+    assert_eq!(nonces.len(), rounds.len());
+
+    let mut digest = channel_digest;
+    let mut script = script! {};
+    for (&nonce, &n_bits) in nonces.iter().zip(rounds.iter()) {
+        script = script! {
+            { script }
+            { PowGadget::push_pow_hint(digest.clone(), nonce, n_bits) }
+        };
+        digest = hash_with_nonce(&digest, nonce);
+    }
+    script
+}
+
 #[cfg(test)]
 mod test {
     use crate::{tests_utils::report::report_bitcoin_script_size, treepp::*};
     use rand::{RngCore, SeedableRng};
     use rand_chacha::ChaCha20Rng;
+    use sha2::{Digest, Sha256};
 
-    use crate::pow::{bitcoin_script::PowGadget, grind_find_nonce, hash_with_nonce};
+    use crate::pow::{
+        bitcoin_script::PowGadget, check_leading_zeros, grind_find_nonce, grind_multi_pow,
+        hash_with_nonce, push_multi_pow_hint, verify_multi_pow_gadget,
+    };
 
     #[test]
     fn test_push_pow_hint() {
@@ -138,6 +186,73 @@ mod test {
         assert!(exec_result.success);
     }
 
+    #[test]
+    fn test_non_canonical_nonce() {
+        let n_bits = 8;
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let mut channel_digest = vec![0u8; 32];
+        prng.fill_bytes(&mut channel_digest);
+
+        // find an encoding of the given form that satisfies the difficulty
+        let grind_encoding = |encode: &dyn Fn(u64) -> Vec<u8>| {
+            let mut nonce = 0u64;
+            loop {
+                let encoding = encode(nonce);
+
+                let mut concat = channel_digest.clone();
+                concat.extend_from_slice(&encoding);
+                let hash = Sha256::digest(&concat).to_vec();
+
+                if check_leading_zeros(&hash, n_bits as u32) {
+                    return (encoding, hash);
+                }
+                nonce += 1;
+            }
+        };
+
+        for (encode, expected) in [
+            (
+                Box::new(|nonce: u64| nonce.to_le_bytes().to_vec()) as Box<dyn Fn(u64) -> Vec<u8>>,
+                true,
+            ),
+            // a trailing zero byte, which does not change the value in little-endian
+            (
+                Box::new(|nonce: u64| {
+                    let mut encoding = nonce.to_le_bytes().to_vec();
+                    encoding.push(0);
+                    encoding
+                }),
+                false,
+            ),
+            // the minimal encoding
+            (
+                Box::new(|nonce: u64| {
+                    let mut encoding = nonce.to_le_bytes().to_vec();
+                    while encoding.last() == Some(&0) {
+                        encoding.pop();
+                    }
+                    encoding
+                }),
+                false,
+            ),
+        ] {
+            let (encoding, hash) = grind_encoding(&*encode);
+
+            let script = script! {
+                { channel_digest.clone() }
+                { encoding }
+                { hash[(n_bits / 8)..].to_vec() }
+                { PowGadget::verify_pow(n_bits) }
+                { hash }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert_eq!(exec_result.success, expected);
+        }
+    }
+
     #[test]
     fn test_fail_verify() {
         let n_bits = 8;
@@ -243,4 +358,54 @@ mod test {
             PowGadget::verify_pow(78).len(),
         );
     }
+
+    #[test]
+    fn test_multi_pow() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let mut channel_digest = [0u8; 32].to_vec();
+        prng.fill_bytes(&mut channel_digest);
+
+        let rounds = [8, 13];
+        let (nonces, final_digest) = grind_multi_pow(channel_digest.clone(), &rounds);
+
+        let verify_multi_pow_script = verify_multi_pow_gadget(&rounds);
+        report_bitcoin_script_size(
+            "POW",
+            "verify_multi_pow(8 bits, 13 bits)",
+            verify_multi_pow_script.len(),
+        );
+
+        let script = script! {
+            { push_multi_pow_hint(channel_digest.clone(), &nonces, &rounds) }
+            { channel_digest.clone() }
+            { verify_multi_pow_script.clone() }
+            { final_digest.clone() }
+            OP_EQUALVERIFY
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+
+        // a nonce that is not valid for its round is rejected, whichever the round is
+        let mut digest = channel_digest.clone();
+        for (i, &n_bits) in rounds.iter().enumerate() {
+            let mut bad_nonces = nonces.clone();
+            bad_nonces[i] += 1;
+            while check_leading_zeros(&hash_with_nonce(&digest, bad_nonces[i]), n_bits as u32) {
+                bad_nonces[i] += 1;
+            }
+            digest = hash_with_nonce(&digest, nonces[i]);
+
+            let script = script! {
+                { push_multi_pow_hint(channel_digest.clone(), &bad_nonces, &rounds) }
+                { channel_digest.clone() }
+                { verify_multi_pow_script.clone() }
+                OP_DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+    }
 }
@@ -41,3 +41,20 @@ pub fn grind_find_nonce(channel_digest: Vec<u8>, n_bits: u32) -> u64 {
         nonce += 1;
     }
 }
+
+/// Grind for several rounds in a row, each with its own number of bits, where the channel digest
+/// of each round is sha256(digest || nonce) of the previous one.
+///
+/// Return the nonces and the final channel digest.
+pub fn grind_multi_pow(channel_digest: Vec<u8>, rounds: &[usize]) -> (Vec<u64>, Vec<u8>) {
+    let mut digest = channel_digest;
+    let mut nonces = Vec::with_capacity(rounds.len());
+
+    for &n_bits in rounds.iter() {
+        let nonce = grind_find_nonce(digest.clone(), n_bits as u32);
+        digest = hash_with_nonce(&digest, nonce);
+        nonces.push(nonce);
+    }
+
+    (nonces, digest)
+}
@@ -0,0 +1,501 @@
+use crate::channel::Sha256Channel;
+use crate::circle::CircleDomain;
+use crate::constraints::trace_quotient_at_point;
+use crate::fri::{
+    composition_fri_logn, fri_prove_with_queries, fri_verify_with_queries, num_fri_layers,
+    FriError, FriProof, N_QUERIES,
+};
+use crate::merkle_tree::{ColumnarMerkleTree, ColumnarMerkleTreeProof};
+use crate::oods::OODS;
+use crate::twiddle_merkle_tree::TwiddleMerkleTree;
+use crate::utils::{alpha_powers_batch, bit_reverse_index};
+use num_traits::{One, Zero};
+use stwo_prover::core::backend::CPUBackend;
+use stwo_prover::core::channel::Channel;
+use stwo_prover::core::circle::CirclePoint;
+use stwo_prover::core::constraints::coset_vanishing;
+use stwo_prover::core::fields::m31::{BaseField, M31};
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::fields::FieldExpOps;
+use stwo_prover::core::poly::circle::{CanonicCoset, CircleEvaluation, CirclePoly};
+use stwo_prover::core::poly::BitReversedOrder;
+use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+
+/// The log of the blowup factor of the evaluation that FRI is applied to, over the degree bound of
+/// the composition polynomial.
+pub const LOG_BLOWUP: usize = 1;
+
+/// The number of bits of proof-of-work that the FRI proof is ground with.
+pub const POW_BITS: usize = 10;
+
+/// An algebraic intermediate representation of a statement, i.e., the layout of its trace and the
+/// constraints that the trace satisfies, which `prove` and `verify` work over.
+///
+/// The trace has `n_columns` columns of 2^log_size rows, where the row i is at the point
+/// `trace_point(log_size, i)`, and the next row of a row is at its point shifted by
+/// `trace_step(log_size)`.
+pub trait Air {
+    /// The log of the number of rows of the trace.
+    fn log_size(&self) -> u32;
+
+    /// The number of columns of the trace.
+    fn n_columns(&self) -> usize;
+
+    /// The offsets of the rows, relative to the current row, that the constraints read in every
+    /// column.
+    fn mask_offsets(&self) -> Vec<usize>;
+
+    /// The maximum degree of the constraints as polynomials in the trace values, which bounds the
+    /// degree of the composition polynomial, see `composition_log_degree_bound`.
+    fn max_degree(&self) -> usize;
+
+    /// Evaluate the composition polynomial at the point, i.e., the random linear combination of the
+    /// constraint quotients with the powers of `random_coeff`, where mask[j][k] is the evaluation
+    /// of the column j at the point shifted by mask_offsets()[k] rows.
+    fn eval_constraints(
+        &self,
+        point: CirclePoint<QM31>,
+        mask: &[Vec<QM31>],
+        random_coeff: QM31,
+    ) -> QM31;
+}
+
+/// The point of the row `row` of a trace of 2^log_size rows, which is the row-th point of the
+/// canonic coset, as in stwo's `CircleEvaluation::new_canonical_ordered`.
+pub fn trace_point(log_size: u32, row: usize) -> CirclePoint<M31> {
+    CanonicCoset::new(log_size).coset().at(row)
+}
+
+/// The shift from the point of a row of a trace of 2^log_size rows to the point of the next row.
+pub fn trace_step(log_size: u32) -> CirclePoint<M31> {
+    CanonicCoset::new(log_size).coset().step
+}
+
+/// The polynomial that vanishes on the points of all the rows of a trace of 2^log_size rows.
+pub fn trace_vanishing(log_size: u32, point: CirclePoint<QM31>) -> QM31 {
+    coset_vanishing(CanonicCoset::new(log_size).coset(), point)
+}
+
+/// The polynomial of degree 1 that is v0 at b0 and v1 at b1, so that a column that is v0 at the
+/// row b0 and v1 at the row b1 minus it is divisible by `pair_vanishing(b0, b1, point)`.
+pub fn boundary_line(
+    b0: CirclePoint<M31>,
+    v0: M31,
+    b1: CirclePoint<M31>,
+    v1: M31,
+    point: CirclePoint<QM31>,
+) -> QM31 {
+    assert_ne!(b0, b1);
+    // two distinct points on the circle differ in at least one coordinate
+    if b0.y != b1.y {
+        (point.y - b0.y) * ((v1 - v0) * (b1.y - b0.y).inverse()) + v0
+    } else {
+        (point.x - b0.x) * ((v1 - v0) * (b1.x - b0.x).inverse()) + v0
+    }
+}
+
+/// A proof that a trace satisfies the constraints of an AIR, see `prove`.
+#[derive(Clone, Debug)]
+pub struct Proof {
+    /// The root of the trace evaluated on the evaluation domain, one column per trace column.
+    pub trace_root: BWSSha256Hash,
+    /// The root of the composition polynomial evaluated on the evaluation domain, one column per
+    /// coordinate.
+    pub composition_root: BWSSha256Hash,
+    /// The trace at the mask points of the OODS point, where trace_sampled_values[j][k] is the
+    /// column j at the OODS point shifted by mask_offsets()[k] rows.
+    pub trace_sampled_values: Vec<Vec<QM31>>,
+    /// The four coordinates of the composition polynomial at the OODS point.
+    pub composition_sampled_values: Vec<QM31>,
+    /// The openings of the trace at the queries of the FRI proof.
+    pub trace_openings: Vec<ColumnarMerkleTreeProof>,
+    /// The openings of the composition polynomial at the queries of the FRI proof.
+    pub composition_openings: Vec<ColumnarMerkleTreeProof>,
+    /// The FRI proof of the DEEP quotient.
+    pub fri_proof: FriProof,
+}
+
+/// The reason a proof is rejected by `verify`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerificationError {
+    /// The proof does not have the expected shape.
+    MalformedProof,
+    /// The composition polynomial at the OODS point does not follow from the trace at the mask
+    /// points.
+    OodsMismatch,
+    /// An opening of the trace or of the composition polynomial does not lead to its root.
+    MerkleMismatch,
+    /// The DEEP quotient at a query does not match the leaf of the FRI proof.
+    DeepMismatch,
+    /// The FRI proof is rejected.
+    Fri(FriError),
+}
+
+/// The log size of the evaluation domain of the trace, the composition polynomial, and the DEEP
+/// quotient, which is the canonic circle domain that FRI is applied to.
+pub fn evaluation_logn(air: &impl Air) -> usize {
+    composition_fri_logn(air.log_size() as usize, air.max_degree(), LOG_BLOWUP)
+}
+
+/// The points of the evaluation domain of size 2^logn, in the bit-reversed order that FRI expects.
+fn evaluation_points(logn: usize) -> Vec<CirclePoint<M31>> {
+    let domain = CircleDomain::new(logn as u32);
+    (0..domain.size())
+        .map(|i| domain.point_at(bit_reverse_index(i, logn)))
+        .collect()
+}
+
+/// The Merkle tree over the coordinates x and y of the points of the evaluation domain of size
+/// 2^logn, in the bit-reversed order that FRI expects, from which a script verifier opens the
+/// point of a query against a constant root instead of computing it.
+pub fn evaluation_point_tree(logn: usize) -> ColumnarMerkleTree {
+    let points = evaluation_points(logn);
+    ColumnarMerkleTree::new(vec![
+        points.iter().map(|point| point.x).collect(),
+        points.iter().map(|point| point.y).collect(),
+    ])
+}
+
+/// The points that the mask of the AIR reads at the point.
+fn mask_points(air: &impl Air, point: CirclePoint<QM31>) -> Vec<CirclePoint<QM31>> {
+    let step = trace_step(air.log_size());
+    air.mask_offsets()
+        .iter()
+        .map(|&offset| point + step.mul(offset as u128).into_ef::<QM31>())
+        .collect()
+}
+
+/// Evaluate every column at every mask point.
+fn eval_mask(
+    polys: &[CirclePoly<CPUBackend>],
+    mask_points: &[CirclePoint<QM31>],
+) -> Vec<Vec<QM31>> {
+    polys
+        .iter()
+        .map(|poly| {
+            mask_points
+                .iter()
+                .map(|&point| poly.eval_at_point(point))
+                .collect()
+        })
+        .collect()
+}
+
+/// Combine the four coordinates of the composition polynomial into its qm31 value, i.e.,
+/// e0 + e1 * i + e2 * u + e3 * iu.
+fn combine_coordinates(values: &[QM31]) -> QM31 {
+    let (zero, one) = (M31::zero(), M31::one());
+    let basis = [
+        QM31::from_m31(one, zero, zero, zero),
+        QM31::from_m31(zero, one, zero, zero),
+        QM31::from_m31(zero, zero, one, zero),
+        QM31::from_m31(zero, zero, zero, one),
+    ];
+    values
+        .iter()
+        .zip(basis.iter())
+        .fold(QM31::zero(), |acc, (&value, &b)| acc + value * b)
+}
+
+/// Compute the DEEP quotient at a point of the evaluation domain, i.e., the random linear
+/// combination with the powers of `deep_coeff` of the quotients from `trace_quotient_at_point`
+/// of every trace column at every mask point of the OODS point, and then of every coordinate of
+/// the composition polynomial at the OODS point, given their values at the point.
+#[allow(clippy::too_many_arguments)]
+fn deep_quotient(
+    oods_point: CirclePoint<QM31>,
+    oods_mask_points: &[CirclePoint<QM31>],
+    trace_sampled_values: &[Vec<QM31>],
+    composition_sampled_values: &[QM31],
+    point: CirclePoint<QM31>,
+    trace_row: &[M31],
+    composition_row: &[M31],
+    deep_coeff: QM31,
+) -> QM31 {
+    let mut quotients = vec![];
+    for (values, &value_at_point) in trace_sampled_values.iter().zip(trace_row.iter()) {
+        for (&sample_point, &value) in oods_mask_points.iter().zip(values.iter()) {
+            quotients.push(trace_quotient_at_point(
+                sample_point,
+                value,
+                point,
+                value_at_point.into(),
+            ));
+        }
+    }
+    for (&value, &value_at_point) in composition_sampled_values
+        .iter()
+        .zip(composition_row.iter())
+    {
+        quotients.push(trace_quotient_at_point(
+            oods_point,
+            value,
+            point,
+            value_at_point.into(),
+        ));
+    }
+    alpha_powers_batch(deep_coeff, &quotients)
+}
+
+/// The row of the columns of a tree at the position.
+fn row(tree: &ColumnarMerkleTree, pos: usize) -> Vec<M31> {
+    tree.columns.iter().map(|column| column[pos]).collect()
+}
+
+/// Prove that the trace, given as its columns, satisfies the constraints of the AIR.
+///
+/// The prover commits to the trace evaluated on the domain of size 2^evaluation_logn(air), draws
+/// the random coefficient of the constraints, commits to the coordinates of the composition
+/// polynomial evaluated on the same domain, draws the OODS point and absorbs the trace at its mask
+/// points and the composition polynomial at it, draws the DEEP coefficient, and proves with FRI
+/// that the DEEP quotient is of low degree, after which the trace and the composition polynomial
+/// are opened at the queries of FRI.
+///
+/// The composition polynomial and the mask are evaluated point by point, which is enough for the
+/// small traces that this prover is meant for.
+pub fn prove(air: &impl Air, trace: &[Vec<M31>], channel: &mut Sha256Channel) -> Proof {
+    let log_size = air.log_size();
+    assert_eq!(trace.len(), air.n_columns());
+    assert!(trace.iter().all(|column| column.len() == 1 << log_size));
+
+    let logn = evaluation_logn(air);
+    let domain = CanonicCoset::new(logn as u32).circle_domain();
+    let points = evaluation_points(logn);
+
+    // Commit to the trace.
+    let trace_polys = trace
+        .iter()
+        .map(|column| {
+            CircleEvaluation::<CPUBackend, BaseField, BitReversedOrder>::new_canonical_ordered(
+                CanonicCoset::new(log_size),
+                column.clone(),
+            )
+            .interpolate()
+        })
+        .collect::<Vec<CirclePoly<CPUBackend>>>();
+    let trace_tree = ColumnarMerkleTree::new(
+        trace_polys
+            .iter()
+            .map(|poly| poly.evaluate(domain).values)
+            .collect(),
+    );
+    channel.mix_digest(trace_tree.root_hash);
+    let random_coeff = channel.draw_felt();
+
+    // Commit to the composition polynomial.
+    let composition = points
+        .iter()
+        .map(|point| {
+            let point = point.into_ef::<QM31>();
+            let mask = eval_mask(&trace_polys, &mask_points(air, point));
+            air.eval_constraints(point, &mask, random_coeff)
+        })
+        .collect::<Vec<QM31>>();
+    let composition_tree = ColumnarMerkleTree::new(vec![
+        composition.iter().map(|v| v.0 .0).collect(),
+        composition.iter().map(|v| v.0 .1).collect(),
+        composition.iter().map(|v| v.1 .0).collect(),
+        composition.iter().map(|v| v.1 .1).collect(),
+    ]);
+    channel.mix_digest(composition_tree.root_hash);
+
+    // Sample the trace and the composition polynomial at the OODS point.
+    let (oods_point, _) = CirclePoint::<QM31>::get_random_point_with_hint(channel);
+    let oods_mask_points = mask_points(air, oods_point);
+    let trace_sampled_values = eval_mask(&trace_polys, &oods_mask_points);
+    let composition_sampled_values = composition_tree
+        .columns
+        .iter()
+        .map(|column| {
+            CircleEvaluation::<CPUBackend, BaseField, BitReversedOrder>::new(domain, column.clone())
+                .interpolate()
+                .eval_at_point(oods_point)
+        })
+        .collect::<Vec<QM31>>();
+    channel.mix_felts(&trace_sampled_values.concat());
+    channel.mix_felts(&composition_sampled_values);
+    let deep_coeff = channel.draw_felt();
+
+    // Prove that the DEEP quotient is of low degree.
+    let deep_evaluation = points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            deep_quotient(
+                oods_point,
+                &oods_mask_points,
+                &trace_sampled_values,
+                &composition_sampled_values,
+                point.into_ef(),
+                &row(&trace_tree, i),
+                &row(&composition_tree, i),
+                deep_coeff,
+            )
+        })
+        .collect::<Vec<QM31>>();
+    let (fri_proof, queries) =
+        fri_prove_with_queries(channel, deep_evaluation, N_QUERIES, POW_BITS);
+
+    Proof {
+        trace_root: trace_tree.root_hash,
+        composition_root: composition_tree.root_hash,
+        trace_sampled_values,
+        composition_sampled_values,
+        trace_openings: queries.iter().map(|&q| trace_tree.query(q)).collect(),
+        composition_openings: queries.iter().map(|&q| composition_tree.query(q)).collect(),
+        fri_proof,
+    }
+}
+
+/// Verify a proof from `prove` for the AIR, on a channel in the same state as the prover's.
+///
+/// The verifier replays the channel, checks the composition polynomial at the OODS point against
+/// the constraints of the AIR at the mask values, verifies the FRI proof, and checks that the
+/// leaf of each query is the DEEP quotient of the openings of the trace and of the composition
+/// polynomial at the query.
+pub fn verify(
+    air: &impl Air,
+    proof: Proof,
+    channel: &mut Sha256Channel,
+) -> Result<(), VerificationError> {
+    let logn = evaluation_logn(air);
+    let n_columns = air.n_columns();
+    let n_mask_points = air.mask_offsets().len();
+
+    if proof.trace_sampled_values.len() != n_columns
+        || proof
+            .trace_sampled_values
+            .iter()
+            .any(|values| values.len() != n_mask_points)
+        || proof.composition_sampled_values.len() != 4
+        || proof.trace_openings.len() != N_QUERIES
+        || proof.composition_openings.len() != N_QUERIES
+        || proof
+            .trace_openings
+            .iter()
+            .any(|opening| opening.leaf.len() != n_columns || opening.siblings.len() != logn)
+        || proof
+            .composition_openings
+            .iter()
+            .any(|opening| opening.leaf.len() != 4 || opening.siblings.len() != logn)
+    {
+        return Err(VerificationError::MalformedProof);
+    }
+
+    channel.mix_digest(proof.trace_root);
+    let random_coeff = channel.draw_felt();
+    channel.mix_digest(proof.composition_root);
+
+    // Check the composition polynomial at the OODS point.
+    let (oods_point, _) = CirclePoint::<QM31>::get_random_point_with_hint(channel);
+    if combine_coordinates(&proof.composition_sampled_values)
+        != air.eval_constraints(oods_point, &proof.trace_sampled_values, random_coeff)
+    {
+        return Err(VerificationError::OodsMismatch);
+    }
+    channel.mix_felts(&proof.trace_sampled_values.concat());
+    channel.mix_felts(&proof.composition_sampled_values);
+    let deep_coeff = channel.draw_felt();
+
+    // Verify FRI, and then check its leaves against the openings.
+    let leaves = proof.fri_proof.leaves().to_vec();
+    let queries = fri_verify_with_queries(
+        channel,
+        logn,
+        proof.fri_proof,
+        N_QUERIES,
+        POW_BITS,
+        TwiddleMerkleTree::new(num_fri_layers(logn)).root_hash,
+    )
+    .map_err(VerificationError::Fri)?;
+
+    let domain = CircleDomain::new(logn as u32);
+    let oods_mask_points = mask_points(air, oods_point);
+    for (((&query, &leaf), trace_opening), composition_opening) in queries
+        .iter()
+        .zip(leaves.iter())
+        .zip(proof.trace_openings.iter())
+        .zip(proof.composition_openings.iter())
+    {
+        if !ColumnarMerkleTree::verify(&proof.trace_root, logn, trace_opening, query)
+            || !ColumnarMerkleTree::verify(
+                &proof.composition_root,
+                logn,
+                composition_opening,
+                query,
+            )
+        {
+            return Err(VerificationError::MerkleMismatch);
+        }
+
+        let point = domain.point_at(bit_reverse_index(query, logn));
+        let expected = deep_quotient(
+            oods_point,
+            &oods_mask_points,
+            &proof.trace_sampled_values,
+            &proof.composition_sampled_values,
+            point.into_ef(),
+            &trace_opening.leaf,
+            &composition_opening.leaf,
+            deep_coeff,
+        );
+        if leaf != expected {
+            return Err(VerificationError::DeepMismatch);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::channel::Sha256Channel;
+    use crate::fibonacci::{
+        fibonacci_channel_init_state, fibonacci_claim, fibonacci_trace, FibonacciAir,
+    };
+    use crate::prover::{prove, verify, VerificationError};
+    use num_traits::One;
+    use stwo_prover::core::channel::Channel;
+    use stwo_prover::core::fields::m31::M31;
+
+    #[test]
+    fn test_prove_fibonacci_air() {
+        let log_size = 5;
+        let claim = fibonacci_claim(log_size);
+        let air = FibonacciAir::new(log_size, claim);
+        let trace = vec![fibonacci_trace(1 << log_size)];
+
+        let new_channel = || Sha256Channel::new(fibonacci_channel_init_state(claim));
+
+        let proof = prove(&air, &trace, &mut new_channel());
+        assert_eq!(verify(&air, proof.clone(), &mut new_channel()), Ok(()));
+
+        // the verifier of another claim rejects the proof
+        let other_air = FibonacciAir::new(log_size, claim + M31::one());
+        assert_eq!(
+            verify(&other_air, proof.clone(), &mut new_channel()),
+            Err(VerificationError::OodsMismatch)
+        );
+
+        // a sampled value that is not the trace at the mask point is caught
+        let mut tampered = proof.clone();
+        tampered.trace_sampled_values[0][1] += M31::one();
+        assert_eq!(
+            verify(&air, tampered, &mut new_channel()),
+            Err(VerificationError::OodsMismatch)
+        );
+
+        // an opening that is not in the trace commitment is caught
+        let mut tampered = proof;
+        tampered.trace_openings[0].leaf[0] += M31::one();
+        assert_eq!(
+            verify(&air, tampered, &mut new_channel()),
+            Err(VerificationError::MerkleMismatch)
+        );
+
+        // a trace that does not follow the transition cannot be proven
+        let mut bad_trace = trace.clone();
+        bad_trace[0][10] += M31::one();
+        let proof = prove(&air, &bad_trace, &mut new_channel());
+        assert!(verify(&air, proof, &mut new_channel()).is_err());
+    }
+}
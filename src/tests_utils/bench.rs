@@ -0,0 +1,123 @@
+//! This module measures the execution cost of the major gadgets, see `report_execution_cost`.
+use crate::channel::{ChannelWithHint, Sha256Channel, Sha256ChannelGadget};
+use crate::circle::CirclePointGadget;
+use crate::fibonacci::{build_full_verifier, fibonacci_claim, prove_fibonacci};
+use crate::merkle_tree::{MerkleTree, MerkleTreeGadget};
+use crate::tests_utils::fixtures::random_qm31;
+use crate::tests_utils::report::report_execution_cost;
+use crate::treepp::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use rust_bitcoin_m31::qm31_equalverify;
+use stwo_prover::core::channel::Channel;
+use stwo_prover::core::circle::CirclePoint;
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+
+fn random_hash(prng: &mut ChaCha20Rng) -> BWSSha256Hash {
+    let mut v = [0u8; 32];
+    v.iter_mut().for_each(|v| *v = prng.gen());
+    BWSSha256Hash::from(v.to_vec())
+}
+
+#[test]
+fn bench() {
+    let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+    // absorbing and squeezing the channel
+    let init_state = random_hash(&mut prng);
+    let elem = random_qm31(&mut prng);
+
+    let mut channel = Sha256Channel::new(init_state);
+    channel.mix_felts(&[elem]);
+    let mixed_state = channel.digest;
+
+    let script = script! {
+        { elem }
+        { init_state }
+        { Sha256ChannelGadget::mix_felt() }
+        { mixed_state }
+        OP_EQUAL
+    };
+    assert!(report_execution_cost("Channel", "mix_felt", script));
+
+    let (felt, hint) = channel.draw_felt_and_hints();
+    let script = script! {
+        { Sha256ChannelGadget::push_draw_hint(&hint) }
+        { mixed_state }
+        { Sha256ChannelGadget::draw_felt_with_hint() }
+        { felt }
+        qm31_equalverify
+        { channel.digest }
+        OP_EQUAL
+    };
+    assert!(report_execution_cost(
+        "Channel",
+        "draw_felt_with_hint",
+        script
+    ));
+
+    // circle point addition
+    let a = CirclePoint {
+        x: random_qm31(&mut prng),
+        y: random_qm31(&mut prng),
+    };
+    let b = CirclePoint {
+        x: random_qm31(&mut prng),
+        y: random_qm31(&mut prng),
+    };
+    let c = a + b;
+    let script = script! {
+        { a.x }
+        { a.y }
+        { b.x }
+        { b.y }
+        { CirclePointGadget::add() }
+        { c.x }
+        { c.y }
+        { CirclePointGadget::equalverify() }
+        OP_TRUE
+    };
+    assert!(report_execution_cost("CirclePoint", "add", script));
+
+    // Merkle path
+    let logn = 16;
+    let leaf_layer = (0..(1 << logn))
+        .map(|_| random_qm31(&mut prng))
+        .collect::<Vec<QM31>>();
+    let merkle_tree = MerkleTree::new(leaf_layer.clone());
+
+    let mut pos: u32 = prng.gen();
+    pos &= (1 << logn) - 1;
+    let proof = merkle_tree.query(pos as usize);
+
+    let script = script! {
+        { MerkleTreeGadget::push_merkle_tree_proof(&proof) }
+        { merkle_tree.root_hash }
+        { pos }
+        { MerkleTreeGadget::query_and_verify(logn) }
+        { leaf_layer[pos as usize] }
+        qm31_equalverify
+        OP_TRUE
+    };
+    assert!(report_execution_cost(
+        "MerkleTree",
+        format!("verify(2^{})", logn).as_str(),
+        script
+    ));
+
+    // the full Fibonacci verifier, which holds the most elements on the stack
+    let log_size = 5;
+    let claim = fibonacci_claim(log_size);
+
+    let (verifier_script, witness_builder) = build_full_verifier(log_size);
+    let script = script! {
+        { witness_builder.build(prove_fibonacci(log_size, claim)) }
+        { verifier_script }
+    };
+    assert!(report_execution_cost(
+        "Fibonacci",
+        format!("full_verifier(log_size={})", log_size).as_str(),
+        script
+    ));
+}
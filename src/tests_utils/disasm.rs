@@ -0,0 +1,130 @@
+//! This module renders scripts as one instruction per line, and executes scripts while keeping
+//! track of the instruction that fails, so that a failing gadget can be inspected around the
+//! failure instead of only reporting a boolean.
+use crate::tests_utils::report::new_exec;
+use crate::tests_utils::trace::hex;
+use crate::treepp::*;
+use bitcoin::script::Instruction;
+
+/// The number of instructions shown before and after the failing instruction.
+const DUMP_CONTEXT: usize = 8;
+
+/// Render a script as one instruction per line, each prefixed by its index. Opcodes are shown by
+/// their names, and data pushes by their bytes in hex.
+pub fn disassemble(script: &Script) -> String {
+    instructions(script)
+        .iter()
+        .enumerate()
+        .map(|(i, instruction)| format!("{:>6}: {}\n", i, instruction))
+        .collect()
+}
+
+fn instructions(script: &Script) -> Vec<String> {
+    script
+        .instructions()
+        .map(|instruction| match instruction {
+            Ok(Instruction::Op(op)) => op.to_string(),
+            Ok(Instruction::PushBytes(bytes)) if bytes.is_empty() => "OP_0".to_string(),
+            Ok(Instruction::PushBytes(bytes)) => format!("<{}>", hex(bytes.as_bytes())),
+            Err(e) => format!("<invalid: {}>", e),
+        })
+        .collect()
+}
+
+/// Execute a bitcoin script, and if it fails, return the instructions around the failing one,
+/// together with the stack when the execution stops.
+///
+/// The failing instruction is the first one that the executor cannot step through. If every
+/// instruction is stepped through, the execution fails at the end of the script, for example
+/// because the top of the stack is false.
+pub fn failure_dump(script: Script) -> Option<String> {
+    let lines = instructions(&script);
+
+    let mut exec = new_exec(script);
+    let mut stepped = 0;
+    while exec.exec_next().is_ok() {
+        stepped += 1;
+    }
+
+    if exec.result().unwrap().success {
+        return None;
+    }
+
+    let mut dump = String::new();
+    let start = stepped.saturating_sub(DUMP_CONTEXT);
+    let end = (stepped + DUMP_CONTEXT + 1).min(lines.len());
+    for (i, line) in lines.iter().enumerate().take(end).skip(start) {
+        let marker = if i == stepped { "=>" } else { "  " };
+        dump.push_str(&format!("{} {:>6}: {}\n", marker, i, line));
+    }
+    if stepped >= lines.len() {
+        dump.push_str("=> <end of script>\n");
+    }
+
+    dump.push_str("stack, with the top one last:\n");
+    for elem in exec.stack().iter_str() {
+        dump.push_str(&format!("  {}\n", hex(&elem)));
+    }
+
+    Some(dump)
+}
+
+/// Execute a bitcoin script, and if it fails, print the instructions around the failing one and
+/// the stack. Returns whether the execution succeeds.
+pub fn execute_and_dump(script: Script) -> bool {
+    match failure_dump(script) {
+        None => true,
+        Some(dump) => {
+            println!("execution fails at:\n{}", dump);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests_utils::disasm::{disassemble, execute_and_dump, failure_dump};
+    use crate::treepp::*;
+
+    #[test]
+    fn test_disassemble() {
+        let script = script! {
+            3 4 OP_ADD
+            { vec![0xabu8, 0xcd] }
+            OP_DROP
+        };
+        let disasm = disassemble(&script);
+
+        let lines = disasm.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[2].ends_with("OP_ADD"));
+        assert!(lines[3].ends_with("<abcd>"));
+        assert!(lines[4].ends_with("OP_DROP"));
+    }
+
+    #[test]
+    fn test_execute_and_dump() {
+        assert!(execute_and_dump(script! {
+            3 4 OP_ADD 7 OP_EQUAL
+        }));
+
+        // 3 + 4 is not 8, so the execution fails at OP_EQUALVERIFY, at index 4
+        let script = script! {
+            3 4 OP_ADD 8 OP_EQUALVERIFY
+            OP_TRUE
+        };
+        assert!(!execute_and_dump(script.clone()));
+
+        let dump = failure_dump(script).unwrap();
+        let failing = dump.lines().find(|line| line.starts_with("=>")).unwrap();
+        assert!(failing.contains("4: OP_EQUALVERIFY"));
+
+        // a script that steps through every instruction but leaves false on the stack fails at
+        // the end of the script
+        let dump = failure_dump(script! {
+            3 4 OP_ADD 8 OP_EQUAL
+        })
+        .unwrap();
+        assert!(dump.contains("=> <end of script>"));
+    }
+}
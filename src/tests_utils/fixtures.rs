@@ -0,0 +1,57 @@
+//! This module generates the random inputs and the FRI proof that the tests share.
+use crate::channel::Sha256Channel;
+use crate::fri::{fri_prove, FriProof};
+use crate::utils::permute_eval;
+use num_traits::One;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use stwo_prover::core::circle::CirclePointIndex;
+use stwo_prover::core::fields::m31::M31;
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::fields::FieldExpOps;
+use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+
+/// Draw a random m31 element.
+pub fn random_m31(prng: &mut ChaCha20Rng) -> M31 {
+    M31::reduce(prng.next_u64())
+}
+
+/// Draw a random qm31 element.
+pub fn random_qm31(prng: &mut ChaCha20Rng) -> QM31 {
+    QM31::from_m31(
+        random_m31(prng),
+        random_m31(prng),
+        random_m31(prng),
+        random_m31(prng),
+    )
+}
+
+/// Generate a FRI proof for the evaluation of `x^4 + 1` over a domain of size `2^logn`, with the
+/// channel initial state drawn from `ChaCha20Rng::seed_from_u64(0)`.
+///
+/// Return the channel initial state, the evaluation (as passed to `fri_prove`), and the proof.
+pub fn fri_proof_fixture(
+    logn: usize,
+    num_queries: usize,
+    pow_bits: usize,
+) -> (BWSSha256Hash, Vec<QM31>, FriProof) {
+    let mut prng = ChaCha20Rng::seed_from_u64(0);
+    let mut channel_init_state = [0u8; 32];
+    channel_init_state.iter_mut().for_each(|v| *v = prng.gen());
+    let channel_init_state = BWSSha256Hash::from(channel_init_state.to_vec());
+
+    let p = CirclePointIndex::subgroup_gen(logn as u32 + 1).to_point();
+    let evaluation = (0..(1 << logn))
+        .map(|i| (p.mul(i * 2 + 1).x.square().square() + M31::one()).into())
+        .collect::<Vec<QM31>>();
+    let evaluation = permute_eval(evaluation);
+
+    let proof = fri_prove(
+        &mut Sha256Channel::new(channel_init_state),
+        evaluation.clone(),
+        num_queries,
+        pow_bits,
+    );
+
+    (channel_init_state, evaluation, proof)
+}
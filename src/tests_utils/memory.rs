@@ -0,0 +1,53 @@
+//! This module measures the peak heap usage of a computation, see `measure_peak_heap`.
+//!
+//! The allocations are counted per thread, so that the tests running in parallel do not affect
+//! each other's measurements.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+/// The system allocator, which also counts the bytes allocated by the current thread.
+struct CountingAllocator;
+
+fn record(delta: isize) {
+    // the thread-local storage may already be destroyed when a thread exits
+    let _ = ALLOCATED.try_with(|allocated| {
+        let current = allocated.get() + delta;
+        allocated.set(current);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(current)));
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record(-(layout.size() as isize));
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Run the computation and return its result, together with the peak number of heap bytes that it
+/// holds at once on the current thread, on top of what was allocated before.
+pub fn measure_peak_heap<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let start = ALLOCATED.with(|allocated| allocated.get());
+    PEAK.with(|peak| peak.set(start));
+
+    let res = f();
+
+    let peak = PEAK.with(|peak| peak.get());
+    (res, (peak - start) as usize)
+}
@@ -1,3 +1,26 @@
 #[cfg(not(tarpaulin_include))]
 /// This module contains functions for reporting test results to a CSV file.
 pub mod report;
+
+#[cfg(test)]
+mod bench;
+
+/// This module measures the peak heap usage of the Rust-side computations.
+#[cfg(test)]
+pub mod memory;
+
+/// This module generates the random inputs and the FRI proof that the tests share.
+pub mod fixtures;
+
+/// This module disassembles scripts and dumps the instructions around a failure for debugging.
+#[cfg(test)]
+pub mod disasm;
+
+/// This module runs the script verifiers end-to-end on typed proofs.
+pub mod run;
+
+/// This module annotates the gadgets with stack checkpoints for debugging.
+pub mod trace;
+
+/// This module generates deterministic test vectors for the major gadgets.
+pub mod vectors;
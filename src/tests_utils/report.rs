@@ -1,6 +1,11 @@
 //! This module contains functions for reporting test results to a CSV file.
 //!
-//! The CSV file is used to track the size of bitcoin scripts.
+//! The CSV file is used to track the size of bitcoin scripts, and, for the scripts whose execution
+//! is reported, the number of executed opcodes and the maximum stack depth.
+use crate::treepp::*;
+use bitcoin::hashes::Hash;
+use bitcoin::{TapLeafHash, Transaction};
+use bitcoin_scriptexec::{Exec, ExecCtx, Experimental, Options, TxTemplate};
 use std::io::{BufRead, Write};
 use std::sync::Mutex;
 use std::{
@@ -27,7 +32,11 @@ fn setup() {
         .write(true)
         .open("target/bitcoin_scripts_performance_report.csv")
         .unwrap();
-    writeln!(file, "category,name,script_size_bytes").unwrap();
+    writeln!(
+        file,
+        "category,name,script_size_bytes,opcodes_executed,max_stack_depth"
+    )
+    .unwrap();
 }
 
 // Ensure this runs after all tests have completed
@@ -44,7 +53,106 @@ fn finalize() {
 pub fn report_bitcoin_script_size(category: &str, name: &str, script_size_bytes: usize) {
     let mut file = REPORT_FILE.lock().unwrap();
     println!("{}.{}() = {} bytes", category, name, script_size_bytes);
-    writeln!(file, "{},{},{}", category, name, script_size_bytes).unwrap();
+    writeln!(file, "{},{},{},,", category, name, script_size_bytes).unwrap();
+}
+
+/// Execute a bitcoin script and report its size, the number of executed opcodes (including the
+/// pushes), and the maximum stack depth to a CSV file. Returns whether the execution succeeds.
+///
+/// The stack limit of 1000 elements is not enforced, so that scripts exceeding it can still be
+/// measured.
+/// # Arguments
+/// * `category` - A descriptive category for the script.
+/// * `name` - The name of the script.
+/// * `script` - The script, including the hints.
+pub fn report_execution_cost(category: &str, name: &str, script: Script) -> bool {
+    let script_size_bytes = script.len();
+    let (success, opcodes_executed, max_stack_depth) = execute_script_with_stats(script);
+
+    let mut file = REPORT_FILE.lock().unwrap();
+    println!(
+        "{}.{}() = {} bytes, {} opcodes, {} max stack depth",
+        category, name, script_size_bytes, opcodes_executed, max_stack_depth
+    );
+    writeln!(
+        file,
+        "{},{},{},{},{}",
+        category, name, script_size_bytes, opcodes_executed, max_stack_depth
+    )
+    .unwrap();
+
+    success
+}
+
+/// Execute a bitcoin script and panic if the maximum stack depth exceeds `limit`. Returns whether
+/// the execution succeeds.
+///
+/// Bitcoin caps the stack (together with the altstack) at 1000 elements, so `limit` should be at
+/// most 1000, and a gadget whose depth is close to its limit is a candidate for moving elements to
+/// the altstack.
+/// # Arguments
+/// * `script` - The script, including the hints.
+/// * `limit` - The maximum allowed stack depth.
+pub fn assert_max_stack_depth(script: Script, limit: usize) -> bool {
+    let (success, _, max_stack_depth) = execute_script_with_stats(script);
+    assert!(
+        max_stack_depth <= limit,
+        "max stack depth {} exceeds the limit {}",
+        max_stack_depth,
+        limit
+    );
+    success
+}
+
+// Execute a bitcoin script without enforcing the stack limit, and return whether it succeeds, the
+// number of executed opcodes, and the maximum stack depth.
+fn execute_script_with_stats(script: Script) -> (bool, usize, usize) {
+    let mut exec = new_exec(script);
+
+    let mut opcodes_executed = 0;
+    while exec.exec_next().is_ok() {
+        opcodes_executed += 1;
+    }
+
+    (
+        exec.result().unwrap().success,
+        opcodes_executed,
+        exec.stats().max_nb_stack_items,
+    )
+}
+
+/// Create an execution of a bitcoin script that can be stepped through, without enforcing the
+/// stack limit.
+pub(crate) fn new_exec(script: Script) -> Exec {
+    Exec::new(
+        ExecCtx::Tapscript,
+        Options {
+            require_minimal: true,
+            verify_cltv: true,
+            verify_csv: true,
+            verify_minimal_if: true,
+            enforce_stack_limit: false,
+            experimental: Experimental {
+                op_cat: true,
+                op_mul: false,
+                op_div: false,
+            },
+        },
+        TxTemplate {
+            tx: Transaction {
+                version: bitcoin::transaction::Version::TWO,
+                lock_time: bitcoin::locktime::absolute::LockTime::ZERO,
+                input: vec![],
+                output: vec![],
+            },
+            prevouts: vec![],
+            input_idx: 0,
+            taproot_annex_scriptleaf: Some((TapLeafHash::all_zeros(), None)),
+        },
+        script,
+        vec![],
+    )
+    .expect("error creating exec")
 }
 
 // Function to sort the CSV file by the first column
@@ -68,8 +176,12 @@ fn sort_csv_file(file_path: &str) {
         .open(file_path)
         .unwrap();
 
-    writeln!(file, "category,primitive,script_size_bytes").unwrap();
+    writeln!(
+        file,
+        "category,primitive,script_size_bytes,opcodes_executed,max_stack_depth"
+    )
+    .unwrap();
     for row in rows {
-        writeln!(file, "{},{},{}", row[0], row[1], row[2]).unwrap();
+        writeln!(file, "{}", row.join(",")).unwrap();
     }
 }
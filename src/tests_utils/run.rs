@@ -0,0 +1,50 @@
+//! This module runs the script verifiers end-to-end on typed proofs, so that the order in which
+//! the witness is assembled is kept in one place instead of being repeated in every test.
+use crate::fri::{FRIGadget, FriProof};
+use crate::tests_utils::report::new_exec;
+use crate::treepp::*;
+use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+
+/// Run the FRI verifier script, `FRIGadget::check_fri`, on a proof for an evaluation of size
+/// 2^logn with `pow_bits` bits of proof-of-work, with the witness from
+/// `FRIGadget::push_fri_witness`, and return whether it succeeds.
+///
+/// The outputs of the verifier are dropped, so the result only depends on the checks in it. The
+/// stack limit is not enforced, so that large proofs can be run as well.
+pub fn run_proof(
+    channel_init_state: BWSSha256Hash,
+    proof: &FriProof,
+    logn: usize,
+    pow_bits: usize,
+    twiddle_merkle_tree_root: [u8; 32],
+) -> bool {
+    let n_layers = logn - 1;
+    let num_queries = proof.num_queries();
+
+    // the last layer (2 qm31), the commitments, the queries, the folding factors (qm31), and the
+    // twiddle factors of each query
+    let num_outputs = 2 * 4 + n_layers + num_queries + n_layers * 4 + num_queries * n_layers;
+
+    let script = script! {
+        { FRIGadget::push_fri_witness(channel_init_state, logn, proof, pow_bits) }
+        { FRIGadget::check_fri(channel_init_state.as_ref(), logn, num_queries, pow_bits, twiddle_merkle_tree_root) }
+        for _ in 0..num_outputs / 2 {
+            OP_2DROP
+        }
+        if num_outputs % 2 == 1 {
+            OP_DROP
+        }
+        OP_TRUE
+    };
+
+    run_script(script)
+}
+
+/// Run a script, including its witness, and return whether it succeeds.
+///
+/// The stack limit is not enforced, so that the verifiers of large proofs can be run as well.
+pub fn run_script(script: Script) -> bool {
+    let mut exec = new_exec(script);
+    while exec.exec_next().is_ok() {}
+    exec.result().unwrap().success
+}
@@ -0,0 +1,211 @@
+//! This module annotates the gadgets with the expected stack contents at checkpoints, and executes
+//! scripts while recording the stack at each checkpoint, so that a failing script can be compared
+//! against the expectations at the nearest checkpoint before the failure.
+//!
+//! The checkpoints are only inserted with the `trace` feature, and otherwise cost nothing.
+use crate::tests_utils::report::new_exec;
+use crate::treepp::*;
+use std::fmt::{Display, Formatter};
+
+/// The prefix of the data pushed by a checkpoint.
+const CHECKPOINT_MAGIC: &[u8] = b"\x00trace\x00";
+
+/// Annotate the expected stack contents at this point of a gadget, as the names of the elements
+/// at the top of the stack, with the top one last.
+///
+/// Without the `trace` feature, this is an empty script.
+pub fn trace_checkpoint(label: &str, expected: &[&str]) -> Script {
+    if cfg!(feature = "trace") {
+        checkpoint_script(label, expected)
+    } else {
+        script! {}
+    }
+}
+
+// Push the checkpoint as data and drop it, which leaves the stack unchanged.
+fn checkpoint_script(label: &str, expected: &[&str]) -> Script {
+    let mut data = CHECKPOINT_MAGIC.to_vec();
+    data.extend_from_slice(label.as_bytes());
+    for name in expected.iter() {
+        data.push(0);
+        data.extend_from_slice(name.as_bytes());
+    }
+    assert!(data.len() <= 520);
+
+    script! {
+        { data }
+        OP_DROP
+    }
+}
+
+/// The stack recorded at a checkpoint.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    /// The label of the checkpoint.
+    pub label: String,
+    /// The names of the expected elements at the top of the stack, with the top one last.
+    pub expected: Vec<String>,
+    /// The stack at the checkpoint, with the top one last.
+    pub stack: Vec<Vec<u8>>,
+}
+
+impl Checkpoint {
+    fn parse(data: &[u8], stack: Vec<Vec<u8>>) -> Option<Self> {
+        let data = data.strip_prefix(CHECKPOINT_MAGIC)?;
+        let mut parts = data
+            .split(|&b| b == 0)
+            .map(|part| String::from_utf8_lossy(part).to_string());
+
+        Some(Self {
+            label: parts.next()?,
+            expected: parts.collect(),
+            stack,
+        })
+    }
+}
+
+/// The result of `execute_script_with_trace`.
+#[derive(Clone, Debug)]
+pub struct TraceResult {
+    /// Whether the execution succeeds.
+    pub success: bool,
+    /// The last checkpoint that the execution reaches, if any.
+    pub last_checkpoint: Option<Checkpoint>,
+    /// The stack when the execution stops, with the top one last.
+    pub final_stack: Vec<Vec<u8>>,
+}
+
+impl Display for TraceResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "execution {}",
+            if self.success { "succeeds" } else { "fails" }
+        )?;
+
+        match &self.last_checkpoint {
+            None => writeln!(f, "no checkpoint is reached")?,
+            Some(checkpoint) => {
+                writeln!(
+                    f,
+                    "at checkpoint \"{}\", the stack has {} elements, expected at the top:",
+                    checkpoint.label,
+                    checkpoint.stack.len()
+                )?;
+                let n = checkpoint.expected.len().max(checkpoint.stack.len());
+                for i in 0..n {
+                    let expected = checkpoint
+                        .expected
+                        .len()
+                        .checked_sub(i + 1)
+                        .map_or("-", |j| checkpoint.expected[j].as_str());
+                    let actual = checkpoint
+                        .stack
+                        .len()
+                        .checked_sub(i + 1)
+                        .map_or("-".to_string(), |j| hex(&checkpoint.stack[j]));
+                    writeln!(f, "  [{}] {}: {}", i, expected, actual)?;
+                }
+            }
+        }
+
+        writeln!(f, "final stack, with the top one last:")?;
+        for elem in self.final_stack.iter() {
+            writeln!(f, "  {}", hex(elem))?;
+        }
+        Ok(())
+    }
+}
+
+/// Render bytes as lowercase hex, which is empty for an empty stack element.
+pub(crate) fn hex(v: &[u8]) -> String {
+    v.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Execute a bitcoin script and record the stack at each checkpoint from `trace_checkpoint`. On
+/// failure, the result can be printed to compare the stack against the expectations of the
+/// nearest checkpoint before the failure.
+pub fn execute_script_with_trace(script: Script) -> TraceResult {
+    let mut exec = new_exec(script);
+
+    let mut last_checkpoint = None;
+    while exec.exec_next().is_ok() {
+        let mut stack = exec.stack().iter_str().collect::<Vec<Vec<u8>>>();
+        if let Some(top) = stack.last() {
+            if top.starts_with(CHECKPOINT_MAGIC) {
+                let top = stack.pop().unwrap();
+                if let Some(checkpoint) = Checkpoint::parse(&top, stack) {
+                    last_checkpoint = Some(checkpoint);
+                }
+            }
+        }
+    }
+
+    TraceResult {
+        success: exec.result().unwrap().success,
+        last_checkpoint,
+        final_stack: exec.stack().iter_str().collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests_utils::trace::{execute_script_with_trace, trace_checkpoint};
+    use crate::treepp::*;
+
+    // A gadget that computes (a + b) * 2 == c, with checkpoints, where `buggy` forgets to
+    // duplicate the sum before adding it to itself.
+    fn double_sum_gadget(buggy: bool) -> Script {
+        script! {
+            { trace_checkpoint("start", &["a", "b", "c"]) }
+            OP_ROT OP_ROT OP_ADD
+            { trace_checkpoint("after sum", &["c", "a + b"]) }
+            if !buggy {
+                OP_DUP
+            }
+            OP_ADD
+            { trace_checkpoint("after double", &["c", "(a + b) * 2"]) }
+            OP_EQUAL
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "trace")]
+    fn test_execute_script_with_trace() {
+        let script = script! {
+            3 4 14
+            { double_sum_gadget(false) }
+        };
+        let result = execute_script_with_trace(script);
+        assert!(result.success);
+        assert_eq!(result.last_checkpoint.unwrap().label, "after double");
+
+        let script = script! {
+            3 4 14
+            { double_sum_gadget(true) }
+        };
+        let result = execute_script_with_trace(script);
+        println!("{}", result);
+        assert!(!result.success);
+
+        // the stack at the nearest checkpoint shows one element fewer than expected
+        let checkpoint = result.last_checkpoint.unwrap();
+        assert_eq!(checkpoint.label, "after double");
+        assert_eq!(checkpoint.expected.len(), 2);
+        assert_eq!(checkpoint.stack.len(), 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "trace"))]
+    fn test_trace_checkpoint_disabled() {
+        // without the feature, the checkpoints are empty and no checkpoint is reached
+        assert_eq!(trace_checkpoint("start", &["a", "b", "c"]).len(), 0);
+
+        let result = execute_script_with_trace(script! {
+            3 4 14
+            { double_sum_gadget(false) }
+        });
+        assert!(result.success);
+        assert!(result.last_checkpoint.is_none());
+    }
+}
@@ -0,0 +1,271 @@
+//! This module generates deterministic test vectors for the major gadgets.
+//!
+//! The vectors are meant to be consumed by an external conformance checker that validates
+//! stwo's Rust verifier against the Bitcoin script one.
+use crate::channel::{BitcoinIntegerEncodedData, ChannelWithHint, Sha256Channel};
+use crate::merkle_tree::MerkleTree;
+use crate::tests_utils::fixtures::{random_m31, random_qm31};
+use crate::tests_utils::trace::hex;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::fs;
+use std::path::Path;
+use stwo_prover::core::channel::Channel;
+use stwo_prover::core::fft::ibutterfly;
+use stwo_prover::core::fields::m31::M31;
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+
+/// Number of vectors generated for each gadget.
+const N_VECTORS: usize = 4;
+
+/// Height of the Merkle tree used for the Merkle path vectors.
+const MERKLE_LOGN: usize = 4;
+
+/// Generate the test vectors for `mix_felt`, `draw_felt`, the FRI fold step, and the Merkle path,
+/// and write them into `dir` as `<name>.json`.
+///
+/// Each file is a JSON array, one entry per vector, with a fixed seed (`ChaCha20Rng::seed_from_u64(0)`
+/// for every file) so that the output is reproducible. Each entry has the form
+/// `{"inputs": {..}, "hints": {..}, "expected_outputs": {..}}` where:
+/// - a byte string (channel state, hash) is lowercase hex,
+/// - an m31 element is a decimal integer,
+/// - a qm31 element `QM31::from_m31(a, b, c, d)` is the array `[a, b, c, d]`,
+/// - a hint that is pushed as a Bitcoin integer is the hex of its pushed bytes (`""` for zero and
+///   `"80"` for the negative zero).
+///
+/// The files are:
+/// - `mix_felt.json`: inputs `channel`, `felt`; expected `channel`.
+/// - `draw_felt.json`: inputs `channel`; hints `m31_limbs` (4 integers), `residue` (the remaining
+///   16 bytes of the hash); expected `felt`, `channel`.
+/// - `fri_fold_step.json`: inputs `f0`, `f1`, `itwid`, `alpha`; expected `folded`, which is
+///   `f0' + alpha * f1'` where `(f0', f1') = ibutterfly(f0, f1, itwid)`.
+/// - `merkle_path.json`: inputs `root`, `logn`, `pos` (distinct across the vectors); hints `leaf`,
+///   `siblings`; expected `valid`, whether the leaf and the siblings hash to the root. Every other
+///   vector has a tampered leaf, so that `valid` is false.
+pub fn generate_vectors(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (name, content) in all_vectors() {
+        fs::write(dir.join(format!("{}.json", name)), content)?;
+    }
+    Ok(())
+}
+
+/// Compute all the test vectors as (name, JSON content) pairs.
+pub fn all_vectors() -> Vec<(&'static str, String)> {
+    vec![
+        ("mix_felt", mix_felt_vectors()),
+        ("draw_felt", draw_felt_vectors()),
+        ("fri_fold_step", fri_fold_step_vectors()),
+        ("merkle_path", merkle_path_vectors()),
+    ]
+}
+
+fn mix_felt_vectors() -> String {
+    let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+    let mut entries = vec![];
+    for _ in 0..N_VECTORS {
+        let init_state = random_digest(&mut prng);
+        let felt = random_qm31(&mut prng);
+
+        let mut channel = Sha256Channel::new(init_state);
+        channel.mix_felts(&[felt]);
+
+        entries.push(format_entry(
+            format!(
+                "{{\"channel\": \"{}\", \"felt\": {}}}",
+                hex(init_state.as_ref()),
+                qm31_json(felt)
+            ),
+            "{}".to_string(),
+            format!("{{\"channel\": \"{}\"}}", hex(channel.digest.as_ref())),
+        ));
+    }
+    format_file(&entries)
+}
+
+fn draw_felt_vectors() -> String {
+    let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+    let mut entries = vec![];
+    for _ in 0..N_VECTORS {
+        let init_state = random_digest(&mut prng);
+
+        let mut channel = Sha256Channel::new(init_state);
+        let (felt, hint) = channel.draw_felt_and_hints();
+
+        let limbs = hint
+            .0
+            .iter()
+            .map(|v| format!("\"{}\"", hex(&encoded_bytes(v))))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        entries.push(format_entry(
+            format!("{{\"channel\": \"{}\"}}", hex(init_state.as_ref())),
+            format!(
+                "{{\"m31_limbs\": [{}], \"residue\": \"{}\"}}",
+                limbs,
+                hex(&hint.1)
+            ),
+            format!(
+                "{{\"felt\": {}, \"channel\": \"{}\"}}",
+                qm31_json(felt),
+                hex(channel.digest.as_ref())
+            ),
+        ));
+    }
+    format_file(&entries)
+}
+
+fn fri_fold_step_vectors() -> String {
+    let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+    let mut entries = vec![];
+    for _ in 0..N_VECTORS {
+        let f0 = random_qm31(&mut prng);
+        let f1 = random_qm31(&mut prng);
+        let itwid = random_m31(&mut prng);
+        let alpha = random_qm31(&mut prng);
+
+        let (mut v0, mut v1) = (f0, f1);
+        ibutterfly(&mut v0, &mut v1, itwid);
+        let folded = v0 + alpha * v1;
+
+        entries.push(format_entry(
+            format!(
+                "{{\"f0\": {}, \"f1\": {}, \"itwid\": {}, \"alpha\": {}}}",
+                qm31_json(f0),
+                qm31_json(f1),
+                itwid.0,
+                qm31_json(alpha)
+            ),
+            "{}".to_string(),
+            format!("{{\"folded\": {}}}", qm31_json(folded)),
+        ));
+    }
+    format_file(&entries)
+}
+
+fn merkle_path_vectors() -> String {
+    let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+    let leaves = (0..(1 << MERKLE_LOGN))
+        .map(|_| random_qm31(&mut prng))
+        .collect::<Vec<QM31>>();
+    let merkle_tree = MerkleTree::new(leaves);
+
+    let mut positions = vec![];
+    while positions.len() < N_VECTORS {
+        let pos = (prng.next_u32() % (1 << MERKLE_LOGN)) as usize;
+        if !positions.contains(&pos) {
+            positions.push(pos);
+        }
+    }
+
+    let mut entries = vec![];
+    for (i, &pos) in positions.iter().enumerate() {
+        let mut proof = merkle_tree.query(pos);
+        if i % 2 == 1 {
+            proof.leaf.0 .0 += M31::from_u32_unchecked(1);
+        }
+        let valid = MerkleTree::verify(&merkle_tree.root_hash, MERKLE_LOGN, &proof, pos);
+
+        let siblings = proof
+            .siblings
+            .iter()
+            .map(|v| format!("\"{}\"", hex(v)))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        entries.push(format_entry(
+            format!(
+                "{{\"root\": \"{}\", \"logn\": {}, \"pos\": {}}}",
+                hex(merkle_tree.root_hash.as_ref()),
+                MERKLE_LOGN,
+                pos
+            ),
+            format!(
+                "{{\"leaf\": {}, \"siblings\": [{}]}}",
+                qm31_json(proof.leaf),
+                siblings
+            ),
+            format!("{{\"valid\": {}}}", valid),
+        ));
+    }
+    format_file(&entries)
+}
+
+fn random_digest(prng: &mut ChaCha20Rng) -> BWSSha256Hash {
+    let mut bytes = [0u8; 32];
+    prng.fill_bytes(&mut bytes);
+    BWSSha256Hash::from(bytes.to_vec())
+}
+
+fn qm31_json(v: QM31) -> String {
+    format!(
+        "[{}, {}, {}, {}]",
+        v.0 .0 .0, v.0 .1 .0, v.1 .0 .0, v.1 .1 .0
+    )
+}
+
+/// The bytes that are pushed to the stack for a Bitcoin integer hint.
+fn encoded_bytes(v: &BitcoinIntegerEncodedData) -> Vec<u8> {
+    match v {
+        BitcoinIntegerEncodedData::NegativeZero => vec![0x80],
+        BitcoinIntegerEncodedData::Other(v) => {
+            let mut bytes = vec![];
+            let mut abs = v.unsigned_abs();
+            while abs > 0 {
+                bytes.push((abs & 0xff) as u8);
+                abs >>= 8;
+            }
+            match bytes.last().copied() {
+                Some(last) if last & 0x80 != 0 => bytes.push(if *v < 0 { 0x80 } else { 0 }),
+                Some(_) if *v < 0 => *bytes.last_mut().unwrap() |= 0x80,
+                _ => {}
+            }
+            bytes
+        }
+    }
+}
+
+fn format_entry(inputs: String, hints: String, expected_outputs: String) -> String {
+    format!(
+        "  {{\"inputs\": {}, \"hints\": {}, \"expected_outputs\": {}}}",
+        inputs, hints, expected_outputs
+    )
+}
+
+fn format_file(entries: &[String]) -> String {
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests_utils::vectors::all_vectors;
+
+    #[test]
+    fn test_vectors_are_up_to_date() {
+        let committed = [
+            ("mix_felt", include_str!("vectors/mix_felt.json")),
+            ("draw_felt", include_str!("vectors/draw_felt.json")),
+            ("fri_fold_step", include_str!("vectors/fri_fold_step.json")),
+            ("merkle_path", include_str!("vectors/merkle_path.json")),
+        ];
+
+        let generated = all_vectors();
+        assert_eq!(generated.len(), committed.len());
+        for ((name, generated), (committed_name, expected)) in
+            generated.iter().zip(committed.iter())
+        {
+            assert_eq!(name, committed_name);
+            assert_eq!(
+                generated, expected,
+                "the test vectors for {} differ from the committed ones",
+                name
+            );
+        }
+    }
+}
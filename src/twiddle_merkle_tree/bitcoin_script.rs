@@ -1,6 +1,6 @@
 use crate::treepp::*;
 use crate::twiddle_merkle_tree::TwiddleMerkleTreeProof;
-use crate::utils::limb_to_le_bits;
+use crate::utils::{limb_to_le_bits, pull_hint_gadget};
 
 /// Gadget for verifying a Merkle tree path in a twiddle tree.
 pub struct TwiddleMerkleTreeGadget;
@@ -32,6 +32,15 @@ impl TwiddleMerkleTreeGadget {
     /// output:
     ///   v (m31 -- [num_layer] elements)
     pub fn query_and_verify(logn: usize) -> Script {
+        Self::query_and_verify_with_hints(logn, &mut pull_hint_gadget)
+    }
+
+    /// As `query_and_verify`, but each hint is obtained with `pull_hint`, so that the hints can
+    /// also be picked from a deduplicated witness, see `FriProof::to_witness_deduplicated`.
+    pub(crate) fn query_and_verify_with_hints(
+        logn: usize,
+        pull_hint: &mut dyn FnMut() -> Script,
+    ) -> Script {
         let num_layer = logn - 1;
         script! {
             // convert pos into bits and drop the LSB
@@ -39,7 +48,7 @@ impl TwiddleMerkleTreeGadget {
             OP_DROP
 
             // obtain the leaf element v
-            OP_DEPTH OP_1SUB OP_ROLL
+            { pull_hint() }
             OP_DUP OP_TOALTSTACK
 
             // compute the current element's hash
@@ -51,14 +60,14 @@ impl TwiddleMerkleTreeGadget {
             // for every layer
             for _ in 0..num_layer - 1 {
                 // pull the middle element and copy to the altstack
-                OP_DEPTH OP_1SUB OP_ROLL
+                { pull_hint() }
                 OP_DUP OP_TOALTSTACK
 
                 // stack: root_hash, <bits>, leaf-hash, middle-element
                 // altstack: leaf, middle-element
 
                 // pull the sibling
-                OP_DEPTH OP_1SUB OP_ROLL
+                { pull_hint() }
 
                 // stack: root_hash, <bits>, leaf-hash, middle-element, sibling
                 // altstack: leaf, middle-element
@@ -73,7 +82,7 @@ impl TwiddleMerkleTreeGadget {
             }
 
             // pull the sibling
-            OP_DEPTH OP_1SUB OP_ROLL
+            { pull_hint() }
 
             // stack: root_hash, <bit>, leaf-hash, sibling
 
@@ -1,5 +1,6 @@
+use crate::circle::CircleDomain;
 use crate::utils::get_twiddles;
-use crate::utils::num_to_bytes;
+use crate::utils::{bit_reverse_index, num_to_bytes};
 use sha2::{Digest, Sha256};
 use stwo_prover::core::fields::m31::M31;
 use stwo_prover::core::fields::FieldExpOps;
@@ -112,6 +113,71 @@ impl TwiddleMerkleTree {
         TwiddleMerkleTreeProof { elements, siblings }
     }
 
+    /// Generate the proof for a query without constructing the twiddle Merkle tree.
+    ///
+    /// The sibling subtrees are hashed depth-first with the inverse twiddle factors computed on
+    /// demand, so that only O(logn) hashes are kept in memory, at the cost of recomputing the
+    /// O(2^logn) nodes for every query.
+    pub fn open_lazy(logn: usize, mut pos: usize) -> TwiddleMerkleTreeProof {
+        let mut elements = Vec::with_capacity(logn);
+        let mut siblings = Vec::with_capacity(logn);
+
+        for i in 0..logn {
+            pos >>= 1;
+
+            elements.push(Self::twiddle_inverse(logn, i, pos));
+            siblings.push(Self::subtree_hash(logn, i, pos ^ 1));
+        }
+
+        elements.reverse();
+
+        TwiddleMerkleTreeProof { elements, siblings }
+    }
+
+    /// Compute the inverse twiddle factor at the given layer and index, as in `get_twiddles`.
+    fn twiddle_inverse(logn: usize, layer: usize, i: usize) -> M31 {
+        let n = logn + 1;
+
+        let domain = CircleDomain::new(n as u32);
+        let mut p = domain.initial();
+        let mut step = domain.half_coset_step();
+
+        if layer == 0 {
+            (p + step.mul(bit_reverse_index(i, n - 1) as u128))
+                .y
+                .inverse()
+        } else {
+            for _ in 1..layer {
+                p = p.double();
+                step = step.double();
+            }
+            (p + step.mul(bit_reverse_index(i, n - layer - 1) as u128))
+                .x
+                .inverse()
+        }
+    }
+
+    /// Compute the hash of the node at the given layer and index.
+    fn subtree_hash(logn: usize, layer: usize, i: usize) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        if layer == 0 {
+            Digest::update(&mut hasher, num_to_bytes(Self::twiddle_inverse(logn, 0, i)));
+        } else {
+            Digest::update(&mut hasher, Self::subtree_hash(logn, layer - 1, i * 2));
+            if layer != logn {
+                Digest::update(
+                    &mut hasher,
+                    num_to_bytes(Self::twiddle_inverse(logn, layer, i)),
+                );
+            }
+            Digest::update(&mut hasher, Self::subtree_hash(logn, layer - 1, i * 2 + 1));
+        }
+
+        let mut hash_result = [0u8; 32];
+        hash_result.copy_from_slice(hasher.finalize().as_slice());
+        hash_result
+    }
+
     /// Verify a twiddle Merkle tree proof.
     pub fn verify(
         root_hash: [u8; 32],
@@ -166,6 +232,7 @@ pub struct TwiddleMerkleTreeProof {
 
 #[cfg(test)]
 mod test {
+    use crate::tests_utils::memory::measure_peak_heap;
     use crate::twiddle_merkle_tree::TwiddleMerkleTree;
     use rand::{Rng, SeedableRng};
     use rand_chacha::ChaCha20Rng;
@@ -188,4 +255,59 @@ mod test {
             ));
         }
     }
+
+    #[test]
+    fn test_twiddle_merkle_tree_open_lazy() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 10;
+        let twiddle_merkle_tree = TwiddleMerkleTree::new(logn);
+
+        for _ in 0..10 {
+            let query = (prng.gen::<u32>() % (1 << (logn + 1))) as usize;
+
+            let proof = twiddle_merkle_tree.query(query);
+            let lazy_proof = TwiddleMerkleTree::open_lazy(logn, query);
+
+            assert_eq!(proof.elements, lazy_proof.elements);
+            assert_eq!(proof.siblings, lazy_proof.siblings);
+            assert!(TwiddleMerkleTree::verify(
+                twiddle_merkle_tree.root_hash,
+                logn,
+                &lazy_proof,
+                query
+            ));
+        }
+    }
+
+    #[test]
+    fn test_twiddle_merkle_tree_open_lazy_memory() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let logn = 20;
+        let query = (prng.gen::<u32>() % (1 << (logn + 1))) as usize;
+
+        let (twiddle_merkle_tree, eager_bytes) = measure_peak_heap(|| TwiddleMerkleTree::new(logn));
+        let (lazy_proof, lazy_bytes) =
+            measure_peak_heap(|| TwiddleMerkleTree::open_lazy(logn, query));
+        println!(
+            "TwiddleMerkleTree.peak_heap(logn = {}): eager = {} bytes, lazy = {} bytes",
+            logn, eager_bytes, lazy_bytes
+        );
+
+        // the eager tree holds all the 2^(logn + 1) - 1 nodes, while the lazy opening only holds
+        // the path and the transient hashes, i.e., O(logn) nodes
+        assert!(eager_bytes >= ((1 << (logn + 1)) - 1) * 32);
+        assert!(lazy_bytes <= 64 * logn * 32);
+
+        let proof = twiddle_merkle_tree.query(query);
+        assert_eq!(proof.elements, lazy_proof.elements);
+        assert_eq!(proof.siblings, lazy_proof.siblings);
+        assert!(TwiddleMerkleTree::verify(
+            twiddle_merkle_tree.root_hash,
+            logn,
+            &lazy_proof,
+            query
+        ));
+    }
 }
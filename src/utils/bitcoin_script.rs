@@ -1,4 +1,13 @@
 use crate::treepp::*;
+use crate::utils::limb_to_be_bits_toaltstack;
+use num_traits::{One, Zero};
+use rust_bitcoin_m31::{
+    m31_add, m31_mul, m31_sub, qm31_add, qm31_copy, qm31_dup, qm31_fromaltstack, qm31_mul,
+    qm31_neg, qm31_over, qm31_roll, qm31_swap, qm31_toaltstack,
+};
+use stwo_prover::core::fields::m31::{M31, P};
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::fields::Field;
 
 /// Gadget for trimming away a m31 element to keep only logn bits.
 pub fn trim_m31_gadget(logn: usize) -> Script {
@@ -21,6 +30,75 @@ pub fn trim_m31_gadget(logn: usize) -> Script {
     }
 }
 
+/// Gadget for embedding a circle point over m31 into a circle point over qm31, as in
+/// `CirclePoint::into_ef`, by padding each coordinate with three zero limbs.
+///
+/// input:
+///  x, y (m31)
+///
+/// output:
+///  x, y (qm31)
+pub fn m31_point_to_qm31_gadget() -> Script {
+    script! {
+        OP_TOALTSTACK
+        OP_0 OP_0 OP_0
+        3 OP_ROLL
+        OP_0 OP_0 OP_0
+        OP_FROMALTSTACK
+    }
+}
+
+/// Gadget for computing the bit reversal of an index of logn bits, as in `bit_reverse_index`.
+///
+/// input:
+///  i (smaller than 2^logn)
+///
+/// output:
+///  bit_reverse_index(i, logn)
+pub fn bit_reverse_index_gadget(logn: usize) -> Script {
+    script! {
+        { limb_to_be_bits_toaltstack(logn as u32) }
+
+        // the bits come out from the lowest one, which becomes the highest one
+        OP_FROMALTSTACK
+        for _ in 1..logn {
+            OP_DUP OP_ADD
+            OP_FROMALTSTACK OP_ADD
+        }
+    }
+}
+
+/// Fail the execution if the index is not in a domain of size 2^logn, i.e., 0 <= index < 2^logn,
+/// which is checked with a comparison instead of relying on the index having been masked.
+///
+/// input:
+///  index
+///
+/// output:
+///  index
+pub fn assert_index_in_range_gadget(logn: usize) -> Script {
+    assert!(logn < 31);
+    script! {
+        OP_DUP 0 { 1 << logn } OP_WITHIN OP_VERIFY
+    }
+}
+
+/// Pull the next hint, which is the element at the bottom of the stack.
+pub fn pull_hint_gadget() -> Script {
+    script! {
+        OP_DEPTH OP_1SUB OP_ROLL
+    }
+}
+
+/// Copy the k-th element from the bottom of the stack, where the distinct values of a
+/// deduplicated witness stay until the end of the verification, see
+/// `FriProof::to_witness_deduplicated`.
+pub fn pick_hint_gadget(k: usize) -> Script {
+    script! {
+        OP_DEPTH { k + 1 } OP_SUB OP_PICK
+    }
+}
+
 /// Copy some stack elements to the altstack, where the stack top is being inserted first.
 pub fn copy_to_altstack_top_item_first_in(n: usize) -> Script {
     script! {
@@ -43,13 +121,597 @@ pub fn hash_felt_gadget() -> Script {
     }
 }
 
+/// Gadget for checking that each of the four limbs of a qm31 element is a canonical m31 value, in
+/// [0, 2^31-1), as otherwise two different encodings of the same element hash differently. In
+/// particular, a limb of 2^31-1, which is zero modulo p, is rejected.
+///
+/// input:
+///  a (qm31)
+///
+/// output:
+///  a (qm31)
+pub fn assert_qm31_canonical_gadget() -> Script {
+    script! {
+        for i in 0..4 {
+            { i } OP_PICK 0 { 0x7fffffff } OP_WITHIN OP_VERIFY
+        }
+    }
+}
+
+/// Gadget for hashing a qm31 element in the script, as `hash_felt_gadget`, after checking that it
+/// is canonical with `assert_qm31_canonical_gadget`.
+pub fn hash_felt_checked_gadget() -> Script {
+    script! {
+        assert_qm31_canonical_gadget
+        hash_felt_gadget
+    }
+}
+
+/// Gadget for turning a m31 element into its 4-byte little-endian encoding, by padding its
+/// minimal Bitcoin integer encoding with zero bytes. Since the element is less than 2^31, the
+/// minimal encoding has at most 4 bytes and never needs a sign byte beyond those.
+///
+/// input:
+///  v (m31)
+///
+/// output:
+///  v as 4 bytes, little-endian
+pub fn m31_to_le_bytes_gadget() -> Script {
+    script! {
+        OP_SIZE
+        OP_DUP 0 OP_EQUAL
+        OP_IF
+            OP_DROP { vec![0u8; 4] } OP_CAT
+        OP_ELSE
+            OP_DUP 1 OP_EQUAL
+            OP_IF
+                OP_DROP { vec![0u8; 3] } OP_CAT
+            OP_ELSE
+                OP_DUP 2 OP_EQUAL
+                OP_IF
+                    OP_DROP { vec![0u8; 2] } OP_CAT
+                OP_ELSE
+                    3 OP_EQUAL
+                    OP_IF
+                        { vec![0u8; 1] } OP_CAT
+                    OP_ENDIF
+                OP_ENDIF
+            OP_ENDIF
+        OP_ENDIF
+    }
+}
+
+/// Gadget for hashing a sequence of m31 elements as stwo hashes a Merkle leaf, see
+/// `stwo_leaf_hash`.
+///
+/// input:
+///  v_0 (m31)
+///  ...
+///  v_{num_m31 - 1} (m31)
+///
+/// output:
+///  sha256(le(v_0) || ... || le(v_{num_m31 - 1}))
+pub fn stwo_leaf_hash_gadget(num_m31: usize) -> Script {
+    assert!(num_m31 > 0);
+    script! {
+        m31_to_le_bytes_gadget
+        for _ in 1..num_m31 {
+            OP_SWAP m31_to_le_bytes_gadget OP_SWAP OP_CAT
+        }
+        OP_SHA256
+    }
+}
+
+/// A secure extension field of M31 that the verifier works over.
+///
+/// The Rust arithmetic (add, mul, inverse) comes from `Field`, and the base field embeds with
+/// `from_base`. The gadgets describe the shape of an element on the stack, so that the combiners
+/// can be written once for any extension. QM31 is the only implementation for now.
+pub trait SecureField: Field + From<M31> {
+    /// The number of m31 limbs of an element on the stack.
+    const N_LIMBS: usize;
+
+    /// Embed a base field element.
+    fn from_base(v: M31) -> Self {
+        Self::from(v)
+    }
+
+    /// Push an element.
+    fn push(&self) -> Script;
+
+    /// Add the two elements on the top of the stack.
+    fn add_gadget() -> Script;
+
+    /// Multiply the two elements on the top of the stack.
+    fn mul_gadget() -> Script;
+
+    /// Duplicate the element on the top of the stack.
+    fn dup_gadget() -> Script;
+
+    /// Copy the second element to the top of the stack.
+    fn over_gadget() -> Script;
+
+    /// Swap the two elements on the top of the stack.
+    fn swap_gadget() -> Script;
+
+    /// Copy the n-th element (0 being the top) to the top of the stack.
+    fn copy_gadget(n: usize) -> Script;
+
+    /// Move the n-th element (0 being the top) to the top of the stack.
+    fn roll_gadget(n: usize) -> Script;
+
+    /// Drop the element on the top of the stack.
+    fn drop_gadget() -> Script;
+
+    /// Move the element on the top of the stack to the altstack.
+    fn toaltstack_gadget() -> Script;
+
+    /// Move the element on the top of the altstack to the stack.
+    fn fromaltstack_gadget() -> Script;
+}
+
+impl SecureField for QM31 {
+    const N_LIMBS: usize = 4;
+
+    fn push(&self) -> Script {
+        script! {
+            { *self }
+        }
+    }
+
+    fn add_gadget() -> Script {
+        qm31_add()
+    }
+
+    fn mul_gadget() -> Script {
+        qm31_mul()
+    }
+
+    fn dup_gadget() -> Script {
+        qm31_dup()
+    }
+
+    fn over_gadget() -> Script {
+        qm31_over()
+    }
+
+    fn swap_gadget() -> Script {
+        qm31_swap()
+    }
+
+    fn copy_gadget(n: usize) -> Script {
+        qm31_copy(n)
+    }
+
+    fn roll_gadget(n: usize) -> Script {
+        qm31_roll(n)
+    }
+
+    fn drop_gadget() -> Script {
+        script! {
+            OP_2DROP OP_2DROP
+        }
+    }
+
+    fn toaltstack_gadget() -> Script {
+        qm31_toaltstack()
+    }
+
+    fn fromaltstack_gadget() -> Script {
+        qm31_fromaltstack()
+    }
+}
+
+/// Gadget for evaluating a polynomial over a secure field at a point with Horner's method.
+///
+/// input:
+///  x (F)
+///  c_0 (F)
+///  ...
+///  c_degree (F)
+///
+/// output:
+///  c_0 + c_1 * x + ... + c_degree * x^degree (F)
+pub fn horner_gadget<F: SecureField>(degree: usize) -> Script {
+    script! {
+        for i in (1..=degree).rev() {
+            { F::copy_gadget(i + 1) }
+            { F::mul_gadget() }
+            { F::add_gadget() }
+        }
+        { F::swap_gadget() }
+        { F::drop_gadget() }
+    }
+}
+
+/// Gadget for evaluating a polynomial over qm31 at a point with Horner's method, see
+/// `horner_gadget`.
+pub fn qm31_horner_gadget(degree: usize) -> Script {
+    horner_gadget::<QM31>(degree)
+}
+
+/// Gadget for batching n values of a secure field with the powers of a single challenge, as in
+/// `alpha_powers_batch`, which keeps a running alpha^i instead of using independent coefficients.
+///
+/// input:
+///  alpha (F)
+///  v_0 (F)
+///  ...
+///  v_{n-1} (F)
+///
+/// output:
+///  v_0 + alpha * v_1 + ... + alpha^{n-1} * v_{n-1} (F)
+pub fn powers_batch_gadget<F: SecureField>(n: usize) -> Script {
+    assert!(n > 0);
+    script! {
+        { F::roll_gadget(n) }
+        { F::dup_gadget() }
+        { F::roll_gadget(n + 1) }
+
+        // the stack is now alpha, alpha^i, sum
+        for i in 1..n {
+            { F::roll_gadget(n + 2 - i) }
+            { F::copy_gadget(2) }
+            { F::mul_gadget() }
+            { F::add_gadget() }
+
+            if i < n - 1 {
+                { F::toaltstack_gadget() }
+                { F::over_gadget() }
+                { F::mul_gadget() }
+                { F::fromaltstack_gadget() }
+            }
+        }
+
+        { F::swap_gadget() }
+        { F::drop_gadget() }
+        { F::swap_gadget() }
+        { F::drop_gadget() }
+    }
+}
+
+/// Gadget for batching n qm31 values with the powers of a single challenge, see
+/// `powers_batch_gadget`.
+pub fn alpha_powers_batch_gadget(n: usize) -> Script {
+    powers_batch_gadget::<QM31>(n)
+}
+
+/// Push a constant qm31 element with the minimal push sequence.
+///
+/// The limbs are pushed in the same order as the `Pushable` implementation of QM31. Limbs from 0
+/// to 16 are pushed with a single opcode, and a larger limb that has already been pushed for the
+/// same element is copied with `OP_DUP`, `OP_OVER`, or `OP_PICK` instead of being pushed again.
+pub fn push_qm31_constant(v: QM31) -> Script {
+    let limbs = [v.1 .1 .0, v.1 .0 .0, v.0 .1 .0, v.0 .0 .0];
+
+    // the distance, in stack elements, to a previously pushed copy of each limb
+    let mut copy_from = [None; 4];
+    for i in 1..4 {
+        if limbs[i] > 16 {
+            copy_from[i] = (0..i)
+                .rev()
+                .find(|&j| limbs[j] == limbs[i])
+                .map(|j| i - 1 - j);
+        }
+    }
+
+    let is_2dup = limbs[2] == limbs[0] && limbs[3] == limbs[1];
+
+    script! {
+        for i in 0..2 {
+            { push_limb(limbs[i], copy_from[i]) }
+        }
+        if is_2dup {
+            OP_2DUP
+        } else {
+            for i in 2..4 {
+                { push_limb(limbs[i], copy_from[i]) }
+            }
+        }
+    }
+}
+
+/// Push the qm31 element zero.
+pub fn push_qm31_zero() -> Script {
+    push_qm31_constant(QM31::zero())
+}
+
+/// Push the qm31 element one.
+pub fn push_qm31_one() -> Script {
+    push_qm31_constant(QM31::one())
+}
+
+/// Fail the execution if the qm31 element is not zero, which is shorter than pushing zero and
+/// using `qm31_equalverify`.
+///
+/// input:
+///  a (qm31)
+///
+/// output:
+///  none
+pub fn qm31_is_zero_verify() -> Script {
+    script! {
+        OP_BOOLOR OP_BOOLOR OP_BOOLOR
+        OP_NOT OP_VERIFY
+    }
+}
+
+/// Fail the execution if the qm31 element is not one, which is shorter than pushing one and using
+/// `qm31_equalverify`.
+///
+/// input:
+///  a (qm31)
+///
+/// output:
+///  none
+pub fn qm31_is_one_verify() -> Script {
+    script! {
+        OP_1 OP_EQUALVERIFY
+        OP_BOOLOR OP_BOOLOR
+        OP_NOT OP_VERIFY
+    }
+}
+
+/// Gadget for negating a qm31 element and making each limb canonical, so that the result can be
+/// hashed or concatenated and match `hash_qm31` of the negation.
+///
+/// A zero limb must come out as the empty push: the negation of zero may otherwise be left as p,
+/// and a limb may be the "negative zero" 0x80, which is handled as in the `reconstruct` helper of
+/// the channel. Both are numerically zero but hash differently.
+///
+/// input:
+///  a (qm31)
+///
+/// output:
+///  -a (qm31)
+pub fn qm31_neg_canonical_gadget() -> Script {
+    script! {
+        qm31_neg
+        for _ in 0..4 {
+            OP_DUP OP_PUSHBYTES_1 OP_LEFT OP_EQUAL
+            OP_IF
+                OP_DROP OP_PUSHBYTES_0
+            OP_ENDIF
+            OP_DUP { P } OP_EQUAL
+            OP_IF
+                OP_DROP OP_PUSHBYTES_0
+            OP_ENDIF
+            OP_TOALTSTACK
+        }
+        for _ in 0..4 {
+            OP_FROMALTSTACK
+        }
+    }
+}
+
+/// Gadget for adding two cm31 elements, each of which is pushed as its imaginary part and then
+/// its real part, in the same order as the limbs of a qm31 element.
+///
+/// input:
+///  a (cm31)
+///  b (cm31)
+///
+/// output:
+///  a + b (cm31)
+pub fn cm31_add_gadget() -> Script {
+    script! {
+        OP_ROT m31_add
+        OP_ROT OP_ROT m31_add
+        OP_SWAP
+    }
+}
+
+/// Gadget for multiplying two cm31 elements, with three m31 multiplications by Karatsuba:
+/// (a0 + a1 i)(b0 + b1 i) = (a0 b0 - a1 b1) + ((a0 + a1)(b0 + b1) - a0 b0 - a1 b1) i.
+///
+/// input:
+///  a (cm31)
+///  b (cm31)
+///
+/// output:
+///  a * b (cm31)
+pub fn cm31_mul_gadget() -> Script {
+    script! {
+        // stack: a1, a0, b1, b0
+        OP_2DUP m31_add OP_TOALTSTACK
+        3 OP_PICK 3 OP_PICK m31_add
+        OP_FROMALTSTACK m31_mul OP_TOALTSTACK
+
+        // altstack: (a0 + a1)(b0 + b1)
+        OP_ROT m31_mul
+        OP_ROT OP_ROT m31_mul
+
+        // stack: a0 b0, a1 b1
+        OP_2DUP m31_add
+        OP_FROMALTSTACK OP_SWAP m31_sub OP_TOALTSTACK
+        m31_sub
+        OP_FROMALTSTACK OP_SWAP
+    }
+}
+
+/// Fail the execution if the two cm31 elements are not equal.
+///
+/// input:
+///  a (cm31)
+///  b (cm31)
+///
+/// output:
+///  none
+pub fn cm31_equalverify_gadget() -> Script {
+    script! {
+        OP_ROT OP_EQUALVERIFY
+        OP_EQUALVERIFY
+    }
+}
+
+/// Move the top n qm31 elements to the altstack, to be brought back with `qm31_restore(n)` in the
+/// same order.
+pub fn qm31_save(n: usize) -> Script {
+    script! {
+        for _ in 0..n {
+            qm31_toaltstack
+        }
+    }
+}
+
+/// Move n qm31 elements back from the altstack, which were moved there with `qm31_save(n)`.
+pub fn qm31_restore(n: usize) -> Script {
+    script! {
+        for _ in 0..n {
+            qm31_fromaltstack
+        }
+    }
+}
+
+fn push_limb(limb: u32, copy_from: Option<usize>) -> Script {
+    match copy_from {
+        Some(0) => script! { OP_DUP },
+        Some(1) => script! { OP_OVER },
+        Some(depth) => script! { { depth } OP_PICK },
+        None => script! { { limb } },
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use crate::fibonacci::{fibonacci_channel_init_state, fibonacci_claim};
+    use crate::tests_utils::fixtures::random_qm31;
+    use crate::tests_utils::report::report_bitcoin_script_size;
     use crate::treepp::*;
-    use crate::utils::{trim_m31, trim_m31_gadget};
+    use crate::twiddle_merkle_tree::{TwiddleMerkleTree, TWIDDLE_MERKLE_TREE_ROOT_4};
+    use crate::utils::{
+        alpha_powers_batch, alpha_powers_batch_gadget, assert_index_in_range_gadget,
+        assert_qm31_canonical_gadget, bit_reverse_index, bit_reverse_index_gadget, cm31_add_gadget,
+        cm31_equalverify_gadget, cm31_mul_gadget, compute_itwiddles, compute_twiddles,
+        hash_felt_checked_gadget, hash_felt_gadget, hash_qm31, m31_point_to_qm31_gadget,
+        push_qm31_constant, push_qm31_one, push_qm31_zero, qm31_from_le_bytes, qm31_horner_gadget,
+        qm31_is_one_verify, qm31_is_zero_verify, qm31_neg_canonical_gadget, qm31_restore,
+        qm31_save, qm31_to_le_bytes, stwo_leaf_hash, stwo_leaf_hash_gadget, trim_m31,
+        trim_m31_gadget, SecureField,
+    };
+    use num_traits::{One, Zero};
     use rand::{RngCore, SeedableRng};
     use rand_chacha::ChaCha20Rng;
-    use stwo_prover::core::fields::m31::M31;
+    use rust_bitcoin_m31::{qm31_equalverify, qm31_toaltstack};
+    use sha2::{Digest, Sha256};
+    use stwo_prover::core::channel::{BWSSha256Channel, Channel};
+    use stwo_prover::core::circle::{CirclePoint, Coset};
+    use stwo_prover::core::fields::cm31::CM31;
+    use stwo_prover::core::fields::m31::{M31, P};
+    use stwo_prover::core::fields::qm31::QM31;
+    use stwo_prover::core::poly::circle::CanonicCoset;
+    use stwo_prover::core::prover::prove;
+    use stwo_prover::core::vcs::bws_sha256_hash::BWSSha256Hash;
+    use stwo_prover::examples::fibonacci::Fibonacci;
+
+    #[test]
+    fn test_m31_point_to_qm31() {
+        let embed_script = m31_point_to_qm31_gadget();
+        report_bitcoin_script_size("Utils", "m31_point_to_qm31", embed_script.len());
+
+        for p in Coset::odds(4).iter() {
+            let p_ef: CirclePoint<QM31> = p.into_ef();
+
+            let script = script! {
+                { p.x }
+                { p.y }
+                { embed_script.clone() }
+                { p_ef.y }
+                qm31_equalverify
+                { p_ef.x }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_assert_index_in_range() {
+        for logn in [1, 5, 20, 30] {
+            let range_script = assert_index_in_range_gadget(logn);
+            report_bitcoin_script_size(
+                "Utils",
+                format!("assert_index_in_range(logn={})", logn).as_str(),
+                range_script.len(),
+            );
+
+            for index in [0, (1 << logn) - 1] {
+                let script = script! {
+                    { index }
+                    { range_script.clone() }
+                    { index }
+                    OP_EQUAL
+                };
+                let exec_result = execute_script(script);
+                assert!(exec_result.success);
+            }
+
+            let script = script! {
+                { 1 << logn }
+                { range_script.clone() }
+                OP_DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+
+            let script = script! {
+                OP_1NEGATE
+                { range_script.clone() }
+                OP_DROP
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(!exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_bit_reverse_index() {
+        let logn = 6;
+
+        let bit_reverse_script = bit_reverse_index_gadget(logn);
+        report_bitcoin_script_size(
+            "Utils",
+            format!("bit_reverse_index(logn={})", logn).as_str(),
+            bit_reverse_script.len(),
+        );
+
+        for i in 0..(1 << logn) {
+            let script = script! {
+                { i }
+                { bit_reverse_script.clone() }
+                { bit_reverse_index(i, logn) }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        let logn = 20;
+        let bit_reverse_script = bit_reverse_index_gadget(logn);
+        report_bitcoin_script_size(
+            "Utils",
+            format!("bit_reverse_index(logn={})", logn).as_str(),
+            bit_reverse_script.len(),
+        );
+
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+        for _ in 0..10 {
+            let i = prng.next_u32() as usize & ((1 << logn) - 1);
+
+            let script = script! {
+                { i }
+                { bit_reverse_script.clone() }
+                { bit_reverse_index(i, logn) }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
 
     #[test]
     fn test_trim_m31() {
@@ -72,4 +734,542 @@ mod test {
             assert!(exec_result.success);
         }
     }
+
+    #[test]
+    fn test_qm31_horner() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        for degree in 1..=8 {
+            let horner_script = qm31_horner_gadget(degree);
+            report_bitcoin_script_size(
+                "QM31",
+                format!("horner({})", degree).as_str(),
+                horner_script.len(),
+            );
+
+            let x = random_qm31(&mut prng);
+            let coeffs = (0..=degree)
+                .map(|_| random_qm31(&mut prng))
+                .collect::<Vec<QM31>>();
+
+            let mut expected = QM31::zero();
+            let mut power = QM31::from(M31::from(1));
+            for coeff in coeffs.iter() {
+                expected += *coeff * power;
+                power *= x;
+            }
+
+            let script = script! {
+                { x }
+                for coeff in coeffs.iter() {
+                    { *coeff }
+                }
+                { horner_script.clone() }
+                { expected }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_alpha_powers_batch() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        for n in 1..=8 {
+            let batch_script = alpha_powers_batch_gadget(n);
+            report_bitcoin_script_size(
+                "QM31",
+                format!("alpha_powers_batch({})", n).as_str(),
+                batch_script.len(),
+            );
+
+            let alpha = random_qm31(&mut prng);
+            let values = (0..n)
+                .map(|_| random_qm31(&mut prng))
+                .collect::<Vec<QM31>>();
+
+            let expected = alpha_powers_batch(alpha, &values);
+
+            let script = script! {
+                { alpha }
+                for v in values.iter() {
+                    { *v }
+                }
+                { batch_script.clone() }
+                { expected }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_push_qm31_constant() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let a = M31::reduce(prng.next_u64());
+        let b = M31::reduce(prng.next_u64());
+        let c = M31::reduce(prng.next_u64());
+        let d = M31::reduce(prng.next_u64());
+
+        let test_cases = [
+            ("zero", QM31::zero()),
+            ("one", QM31::one()),
+            (
+                "m31",
+                QM31::from_m31(a, M31::zero(), M31::zero(), M31::zero()),
+            ),
+            ("repeated", QM31::from_m31(a, b, a, b)),
+            ("same", QM31::from_m31(a, a, a, a)),
+            ("random", QM31::from_m31(a, b, c, d)),
+        ];
+
+        for (name, v) in test_cases.iter() {
+            let push_script = push_qm31_constant(*v);
+            let naive_script = script! { { *v } };
+
+            report_bitcoin_script_size(
+                "QM31",
+                format!("push_constant({}, before)", name).as_str(),
+                naive_script.len(),
+            );
+            report_bitcoin_script_size(
+                "QM31",
+                format!("push_constant({}, after)", name).as_str(),
+                push_script.len(),
+            );
+            assert!(push_script.len() <= naive_script.len());
+
+            let script = script! {
+                { push_script.clone() }
+                { *v }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        let script = script! {
+            { push_qm31_zero() }
+            { QM31::zero() }
+            qm31_equalverify
+            { push_qm31_one() }
+            { QM31::one() }
+            qm31_equalverify
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+    }
+
+    #[test]
+    fn test_qm31_is_constant_verify() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let a = M31::reduce(prng.next_u64());
+
+        for (name, gadget, generic, constant) in [
+            (
+                "zero",
+                qm31_is_zero_verify(),
+                script! { { push_qm31_zero() } qm31_equalverify },
+                QM31::zero(),
+            ),
+            (
+                "one",
+                qm31_is_one_verify(),
+                script! { { push_qm31_one() } qm31_equalverify },
+                QM31::one(),
+            ),
+        ] {
+            report_bitcoin_script_size(
+                "QM31",
+                format!("is_{}_verify(before)", name).as_str(),
+                generic.len(),
+            );
+            report_bitcoin_script_size(
+                "QM31",
+                format!("is_{}_verify(after)", name).as_str(),
+                gadget.len(),
+            );
+            assert!(gadget.len() < generic.len());
+
+            let script = script! {
+                { constant }
+                { gadget.clone() }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+
+            // any other element, differing in any limb, is rejected
+            for i in 0..4 {
+                let mut limbs = [constant.0 .0, constant.0 .1, constant.1 .0, constant.1 .1];
+                limbs[i] += a;
+                let script = script! {
+                    { QM31::from_m31_array(limbs) }
+                    { gadget.clone() }
+                    OP_TRUE
+                };
+                let exec_result = execute_script(script);
+                assert!(!exec_result.success);
+            }
+        }
+    }
+
+    #[test]
+    fn test_qm31_le_bytes() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        for _ in 0..100 {
+            let v = random_qm31(&mut prng);
+
+            let bytes = qm31_to_le_bytes(v);
+            assert_eq!(qm31_from_le_bytes(bytes), v);
+
+            // pushing the limbs in the order of the bytes gives the same stack as pushing v
+            let script = script! {
+                for chunk in bytes.chunks_exact(4) {
+                    { u32::from_le_bytes(chunk.try_into().unwrap()) }
+                }
+                { v }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_stwo_leaf_hash() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        // the claim hash of a Fibonacci proof is a stwo leaf hash of a single element
+        let claim = M31::reduce(prng.next_u64());
+        assert_eq!(
+            stwo_leaf_hash(&[claim]),
+            fibonacci_channel_init_state(claim)
+        );
+
+        // values whose minimal Bitcoin encoding has each possible length, including those
+        // that need a sign byte
+        let edge_cases = [
+            0,
+            1,
+            0x7f,
+            0x80,
+            0xff,
+            0x7fff,
+            0x8000,
+            0x7fffff,
+            0x800000,
+            P - 1,
+        ]
+        .into_iter()
+        .map(M31::from)
+        .collect::<Vec<_>>();
+
+        for num_m31 in 1..=edge_cases.len() {
+            let mut values = edge_cases[..num_m31].to_vec();
+            values.rotate_left(prng.next_u32() as usize % num_m31);
+            for v in values.iter_mut().step_by(3) {
+                *v = M31::reduce(prng.next_u64());
+            }
+
+            let hash = stwo_leaf_hash(&values);
+
+            let leaf_hash_script = stwo_leaf_hash_gadget(num_m31);
+            if num_m31 == 8 {
+                report_bitcoin_script_size(
+                    "Utils",
+                    format!("stwo_leaf_hash(num_m31={})", num_m31).as_str(),
+                    leaf_hash_script.len(),
+                );
+            }
+
+            let script = script! {
+                for v in values.iter() {
+                    { *v }
+                }
+                { leaf_hash_script.clone() }
+                { hash }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_stwo_leaf_hash_against_stwo_root() {
+        let log_size = 5;
+        let claim = fibonacci_claim(log_size);
+        let fib = Fibonacci::new(log_size, claim);
+
+        let proof = prove(
+            &fib.air,
+            &mut BWSSha256Channel::new(fibonacci_channel_init_state(claim)),
+            vec![fib.get_trace()],
+        )
+        .unwrap();
+
+        // stwo commits the trace evaluated on the domain with a blowup factor of 2, in the
+        // bit-reversed order, with a leaf per row and a node hashing its two children
+        let evaluation = fib
+            .get_trace()
+            .interpolate()
+            .evaluate(CanonicCoset::new(log_size + 1).circle_domain());
+
+        let mut layer = evaluation
+            .values
+            .iter()
+            .map(|v| stwo_leaf_hash(&[*v]))
+            .collect::<Vec<BWSSha256Hash>>();
+        while layer.len() > 1 {
+            layer = layer
+                .chunks_exact(2)
+                .map(|v| {
+                    let mut hasher = Sha256::new();
+                    Digest::update(&mut hasher, v[0].as_ref());
+                    Digest::update(&mut hasher, v[1].as_ref());
+                    BWSSha256Hash::from(hasher.finalize().to_vec())
+                })
+                .collect();
+        }
+
+        assert_eq!(layer[0], proof.commitments[0]);
+    }
+
+    #[test]
+    fn test_qm31_neg_canonical() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let neg_script = qm31_neg_canonical_gadget();
+        report_bitcoin_script_size("QM31", "neg_canonical", neg_script.len());
+
+        for mask in 0..16u32 {
+            // zero out the limbs selected by the mask, including the all-zero element
+            let limbs: [M31; 4] = std::array::from_fn(|i| {
+                if mask & (1 << i) != 0 {
+                    M31::zero()
+                } else {
+                    M31::reduce(prng.next_u64())
+                }
+            });
+            let a = QM31::from_m31_array(limbs);
+
+            let script = script! {
+                { a }
+                { neg_script.clone() }
+                hash_felt_gadget
+                { hash_qm31(&(-a)).to_vec() }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_hash_felt_checked() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let canonical_script = assert_qm31_canonical_gadget();
+        report_bitcoin_script_size("QM31", "assert_canonical", canonical_script.len());
+
+        let hash_script = hash_felt_checked_gadget();
+        report_bitcoin_script_size("QM31", "hash_felt_checked", hash_script.len());
+
+        for _ in 0..10 {
+            let a = random_qm31(&mut prng);
+
+            let script = script! {
+                { a }
+                { hash_script.clone() }
+                { hash_qm31(&a).to_vec() }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+
+            // replace each limb, in the order 1.1, 1.0, 0.1, 0.0 as pushed, by 2^31-1, which is
+            // zero modulo p, or by a negative number
+            let limbs = [a.1 .1, a.1 .0, a.0 .1, a.0 .0].map(|v| v.0 as i64);
+            for i in 0..4 {
+                for bad in [P as i64, -1] {
+                    let mut bad_limbs = limbs;
+                    bad_limbs[i] = bad;
+
+                    let script = script! {
+                        for limb in bad_limbs.iter() {
+                            { *limb }
+                        }
+                        { hash_script.clone() }
+                        OP_DROP
+                        OP_TRUE
+                    };
+                    let exec_result = execute_script(script);
+                    assert!(!exec_result.success);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cm31_arithmetic() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let add_script = cm31_add_gadget();
+        let mul_script = cm31_mul_gadget();
+        let equalverify_script = cm31_equalverify_gadget();
+        report_bitcoin_script_size("CM31", "add", add_script.len());
+        report_bitcoin_script_size("CM31", "mul", mul_script.len());
+        report_bitcoin_script_size("CM31", "equalverify", equalverify_script.len());
+
+        for _ in 0..100 {
+            let a = CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64()));
+            let b = CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64()));
+
+            let script = script! {
+                { a.1 } { a.0 }
+                { b.1 } { b.0 }
+                { add_script.clone() }
+                { (a + b).1 } { (a + b).0 }
+                { equalverify_script.clone() }
+
+                { a.1 } { a.0 }
+                { b.1 } { b.0 }
+                { mul_script.clone() }
+                { (a * b).1 } { (a * b).0 }
+                { equalverify_script.clone() }
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+
+        // two elements that only differ in the imaginary part are not equal
+        let a = CM31(M31::reduce(prng.next_u64()), M31::reduce(prng.next_u64()));
+        let script = script! {
+            { a.1 } { a.0 }
+            { a.1 + M31::one() } { a.0 }
+            { equalverify_script.clone() }
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(!exec_result.success);
+    }
+
+    #[test]
+    fn test_compute_twiddles() {
+        let logn = 5;
+
+        let twiddles = compute_twiddles(logn);
+        let itwiddles = compute_itwiddles(logn);
+        assert_eq!(twiddles.len(), (1 << logn) - 1);
+        assert_eq!(itwiddles.len(), twiddles.len());
+        for (twiddle, itwiddle) in twiddles.iter().zip(itwiddles.iter()) {
+            assert_eq!(*twiddle * *itwiddle, M31::one());
+        }
+
+        // the twiddle tree of 4 layers, for evaluations of size 2^5, commits to the same values
+        let tree = TwiddleMerkleTree::new(logn - 1);
+        assert_eq!(tree.root_hash, TWIDDLE_MERKLE_TREE_ROOT_4);
+        assert_eq!(tree.twiddles_inverse.concat(), itwiddles);
+    }
+
+    #[test]
+    fn test_qm31_save_restore() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        for n in 1..=4 {
+            let manual_save = script! {
+                for _ in 0..n * 4 {
+                    OP_TOALTSTACK
+                }
+            };
+            let manual_restore = script! {
+                for _ in 0..n * 4 {
+                    OP_FROMALTSTACK
+                }
+            };
+            assert_eq!(qm31_save(n).as_bytes(), manual_save.as_bytes());
+            assert_eq!(
+                qm31_save(n).as_bytes(),
+                script! { for _ in 0..n { qm31_toaltstack } }.as_bytes()
+            );
+            assert_eq!(qm31_restore(n).as_bytes(), manual_restore.as_bytes());
+
+            let elements = (0..n)
+                .map(|_| random_qm31(&mut prng))
+                .collect::<Vec<QM31>>();
+
+            let script = script! {
+                for elem in elements.iter() {
+                    { *elem }
+                }
+                { qm31_save(n) }
+                { 42 }
+                { qm31_restore(n) }
+                for elem in elements.iter().rev() {
+                    { *elem }
+                    qm31_equalverify
+                }
+                { 42 }
+                OP_EQUAL
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_secure_field() {
+        let mut prng = ChaCha20Rng::seed_from_u64(0);
+
+        let v = M31::reduce(prng.next_u64());
+        assert_eq!(
+            QM31::from_base(v),
+            QM31::from_m31(v, M31::zero(), M31::zero(), M31::zero())
+        );
+
+        let a = random_qm31(&mut prng);
+        let b = QM31::from_base(v);
+
+        // an element takes N_LIMBS stack elements
+        let script = script! {
+            { a.push() }
+            OP_DEPTH
+            { QM31::N_LIMBS }
+            OP_EQUALVERIFY
+            { QM31::drop_gadget() }
+            OP_DEPTH
+            OP_NOT
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+
+        let script = script! {
+            { a.push() }
+            { b.push() }
+            { QM31::over_gadget() }
+            { QM31::over_gadget() }
+            { QM31::mul_gadget() }
+            { a * b }
+            qm31_equalverify
+            { QM31::add_gadget() }
+            { a + b }
+            qm31_equalverify
+            OP_TRUE
+        };
+        let exec_result = execute_script(script);
+        assert!(exec_result.success);
+    }
 }
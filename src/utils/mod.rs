@@ -1,13 +1,16 @@
 mod bitcoin_script;
 
+use crate::circle::CircleDomain;
 use crate::treepp::*;
 pub use bitcoin_script::*;
 use num_traits::Zero;
 use sha2::{Digest, Sha256};
 use std::cmp::min;
-use stwo_prover::core::circle::CirclePointIndex;
-use stwo_prover::core::fields::m31::M31;
+use stwo_prover::core::fields::m31::{M31, P};
 use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::fields::{FieldExpOps, IntoSlice};
+use stwo_prover::core::vcs::bws_sha256_hash::{BWSSha256Hash, BWSSha256Hasher};
+use stwo_prover::core::vcs::hasher::Hasher;
 
 /// Convert a m31 element to its Bitcoin integer representation.
 pub fn num_to_bytes(v: M31) -> Vec<u8> {
@@ -26,6 +29,34 @@ pub fn num_to_bytes(v: M31) -> Vec<u8> {
     bytes
 }
 
+/// Serialize a qm31 element into 16 bytes, with the limbs in the order in which they are pushed to
+/// the stack (1.1, 1.0, 0.1, 0.0), each of which in little-endian.
+pub fn qm31_to_le_bytes(v: QM31) -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    for (chunk, limb) in bytes
+        .chunks_exact_mut(4)
+        .zip([v.1 .1, v.1 .0, v.0 .1, v.0 .0])
+    {
+        chunk.copy_from_slice(&limb.0.to_le_bytes());
+    }
+    bytes
+}
+
+/// Deserialize a qm31 element from the 16 bytes produced by `qm31_to_le_bytes`.
+///
+/// Panics if a limb is not a canonical m31 element.
+pub fn qm31_from_le_bytes(bytes: [u8; 16]) -> QM31 {
+    let limbs = bytes
+        .chunks_exact(4)
+        .map(|chunk| {
+            let limb = u32::from_le_bytes(chunk.try_into().unwrap());
+            assert!(limb < P, "the limb is not a canonical m31 element");
+            M31::from_u32_unchecked(limb)
+        })
+        .collect::<Vec<M31>>();
+    QM31::from_m31(limbs[3], limbs[2], limbs[1], limbs[0])
+}
+
 /// Compute the bit reversed index.
 pub fn bit_reverse_index(i: usize, log_size: usize) -> usize {
     if i == 0 {
@@ -46,6 +77,17 @@ pub fn permute_eval(evaluation: Vec<QM31>) -> Vec<QM31> {
     layer
 }
 
+/// Batch the values with the powers of a single challenge, i.e., sum_i alpha^i * v_i.
+pub fn alpha_powers_batch<F: SecureField>(alpha: F, values: &[F]) -> F {
+    let mut sum = F::zero();
+    let mut power = F::from_base(M31::from(1));
+    for v in values.iter() {
+        sum += *v * power;
+        power *= alpha;
+    }
+    sum
+}
+
 /// Compute the Bitcoin-friendly hash of a single QM31 element.
 pub fn hash_qm31(v: &QM31) -> [u8; 32] {
     let mut res = [0u8; 32];
@@ -72,6 +114,34 @@ pub fn hash_qm31(v: &QM31) -> [u8; 32] {
     res
 }
 
+/// Compute the Bitcoin-friendly hash of a sequence of m31 elements, in the same manner as
+/// `hash_qm31`, so that hashing the four limbs of a qm31 element gives the same result.
+pub fn hash_m31_vec(v: &[M31]) -> [u8; 32] {
+    assert!(!v.is_empty());
+
+    let mut res = [0u8; 32];
+
+    let mut hasher = Sha256::new();
+    Digest::update(&mut hasher, num_to_bytes(v[0]));
+    res.copy_from_slice(hasher.finalize().as_slice());
+
+    for elem in v.iter().skip(1) {
+        let mut hasher = Sha256::new();
+        Digest::update(&mut hasher, num_to_bytes(*elem));
+        Digest::update(&mut hasher, res);
+        res.copy_from_slice(hasher.finalize().as_slice());
+    }
+
+    res
+}
+
+/// Compute the hash of a sequence of m31 elements in the same way as stwo's `BWSSha256Hasher`
+/// does for a leaf, i.e., a single SHA256 over the concatenation of the 4-byte little-endian
+/// encodings of the elements, `v[0]` first, without any padding or length prefix.
+pub fn stwo_leaf_hash(v: &[M31]) -> BWSSha256Hash {
+    BWSSha256Hasher::hash(M31::into_slice(v))
+}
+
 /// Trim a m31 element to have only logn bits.
 pub fn trim_m31(v: u32, logn: usize) -> u32 {
     v & ((1 << logn) - 1)
@@ -205,8 +275,9 @@ pub fn limb_to_be_bits_toaltstack(num_bits: u32) -> Script {
 pub fn get_twiddles(mut logn: usize) -> Vec<Vec<M31>> {
     let mut twiddles = Vec::with_capacity(logn);
 
-    let mut p = CirclePointIndex::subgroup_gen(logn as u32 + 1).to_point();
-    let mut step = CirclePointIndex::subgroup_gen(logn as u32 - 1).to_point();
+    let domain = CircleDomain::new(logn as u32);
+    let mut p = domain.initial();
+    let mut step = domain.half_coset_step();
 
     let mut layer = Vec::with_capacity(1 << logn);
     for i in 0..(1 << (logn - 1)) {
@@ -226,3 +297,20 @@ pub fn get_twiddles(mut logn: usize) -> Vec<Vec<M31>> {
 
     twiddles
 }
+
+/// Compute the twiddle factors for an evaluation of size 2^logn as a flat list, which is
+/// `get_twiddles` with the layers concatenated: the y-coordinates of the first layer, which
+/// folds the circle domain onto a line, and then the x-coordinates of the later layers, each in
+/// the bit-reversed order used by the folding in `fri`.
+pub fn compute_twiddles(logn: usize) -> Vec<M31> {
+    get_twiddles(logn).concat()
+}
+
+/// Compute the inverses of the twiddle factors, in the same order as `compute_twiddles`, which
+/// are the values that the twiddle Merkle tree of `logn - 1` layers commits to.
+pub fn compute_itwiddles(logn: usize) -> Vec<M31> {
+    compute_twiddles(logn)
+        .into_iter()
+        .map(|v| v.inverse())
+        .collect()
+}